@@ -1,6 +1,5 @@
-use std::collections::HashMap;
-
-use lazy_static::lazy_static;
+use crate::collections::HashMap;
+use crate::lazy::Lazy;
 
 const N: usize = 256;
 const VT100_CHARS: [u32; N] = [
@@ -108,13 +107,11 @@ pub const LAT1_MAP: [char; N] = {
     a
 };
 
-lazy_static! {
-    pub static ref MAPS: HashMap<&'static str, [char; 256]> = {
-        let mut m = HashMap::new();
-        m.insert("B", LAT1_MAP);
-        m.insert("0", VT100_MAP);
-        m.insert("U", IBMPC_MAP);
-        m.insert("V", VAX42_MAP);
-        m
-    };
-}
+pub static MAPS: Lazy<HashMap<&'static str, [char; 256]>> = Lazy::new(|| {
+    let mut m = HashMap::new();
+    m.insert("B", LAT1_MAP);
+    m.insert("0", VT100_MAP);
+    m.insert("U", IBMPC_MAP);
+    m.insert("V", VAX42_MAP);
+    m
+});
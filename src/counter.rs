@@ -1,12 +1,13 @@
-use std::collections::HashMap;
-
+use crate::collections::HashMap;
 use crate::parser_listener::ParserListener;
+use crate::prelude::*;
 
 pub struct Counter {
     pub counts: HashMap<&'static str, i32>,
     pub last_params: HashMap<&'static str, Vec<u32>>, // Store numeric parameters
     pub last_strings: HashMap<&'static str, String>,  // Store string parameters
     pub last_private: Option<bool>,                   // Store private flag
+    pub last_intermediates: Option<String>,           // Store CSI intermediates
 }
 
 impl Counter {
@@ -16,6 +17,7 @@ impl Counter {
             last_params: HashMap::new(),
             last_strings: HashMap::new(),
             last_private: None,
+            last_intermediates: None,
         }
     }
 
@@ -46,9 +48,30 @@ impl Counter {
     pub fn get_last_private(&self) -> Option<bool> {
         self.last_private
     }
+
+    pub fn get_last_intermediates(&self) -> Option<&str> {
+        self.last_intermediates.as_deref()
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ParserListener for Counter {
+    fn csi_dispatch(
+        &mut self,
+        csi_command: &str,
+        params: &[u32],
+        private_marker: Option<char>,
+        intermediates: &str,
+    ) {
+        self.last_intermediates = Some(intermediates.to_owned());
+        self.route_csi(csi_command, params, private_marker, intermediates);
+    }
+
     fn alignment_display(&mut self) {
         self.increment("alignment_display");
     }
@@ -61,10 +84,26 @@ impl ParserListener for Counter {
         self.increment("bell");
     }
 
+    fn enquiry(&mut self) {
+        self.increment("enquiry");
+    }
+
+    fn keypad_application_mode(&mut self) {
+        self.increment("keypad_application_mode");
+    }
+
+    fn keypad_numeric_mode(&mut self) {
+        self.increment("keypad_numeric_mode");
+    }
+
     fn linefeed(&mut self) {
         self.increment("linefeed");
     }
 
+    fn next_line(&mut self) {
+        self.increment("next_line");
+    }
+
     fn cursor_back(&mut self, count: Option<u32>) {
         self.increment("cursor_back");
         self.save_params("cursor_back", &[count.unwrap_or(1)]);
@@ -88,12 +127,12 @@ impl ParserListener for Counter {
     fn cursor_position(&mut self, line: Option<u32>, column: Option<u32>) {
         self.increment("cursor_position");
         let mut params = vec![];
-        if line.is_some() {
-            params.push(line.unwrap());
+        if let Some(line) = line {
+            params.push(line);
         }
 
-        if column.is_some() {
-            params.push(column.unwrap());
+        if let Some(column) = column {
+            params.push(column);
         }
 
         self.save_params("cursor_position", params.as_slice());
@@ -129,6 +168,16 @@ impl ParserListener for Counter {
         self.save_params("delete_lines", &[count.unwrap_or(1)]);
     }
 
+    fn scroll_up(&mut self, count: Option<u32>) {
+        self.increment("scroll_up");
+        self.save_params("scroll_up", &[count.unwrap_or(1)]);
+    }
+
+    fn scroll_down(&mut self, count: Option<u32>) {
+        self.increment("scroll_down");
+        self.save_params("scroll_down", &[count.unwrap_or(1)]);
+    }
+
     fn draw(&mut self, string: &str) {
         self.increment("draw");
         self.save_string("draw", string);
@@ -171,10 +220,101 @@ impl ParserListener for Counter {
         self.last_private = private;
     }
 
+    fn apc_dispatch(&mut self, data: &str) {
+        self.increment("apc_dispatch");
+        self.save_string("apc_dispatch", data);
+    }
+
+    fn report_secondary_device_attributes(&mut self, mode: Option<u32>) {
+        self.increment("report_secondary_device_attributes");
+        self.save_params("report_secondary_device_attributes", &[mode.unwrap_or(0)]);
+    }
+
+    fn report_tertiary_device_attributes(&mut self, mode: Option<u32>) {
+        self.increment("report_tertiary_device_attributes");
+        self.save_params("report_tertiary_device_attributes", &[mode.unwrap_or(0)]);
+    }
+
+    fn set_modify_other_keys(&mut self, level: u32) {
+        self.increment("set_modify_other_keys");
+        self.save_params("set_modify_other_keys", &[level]);
+    }
+
+    fn window_op(&mut self, params: &[u32]) {
+        self.increment("window_op");
+        self.save_params("window_op", params);
+    }
+
+    fn change_attributes_in_rectangle(&mut self, params: &[u32]) {
+        self.increment("change_attributes_in_rectangle");
+        self.save_params("change_attributes_in_rectangle", params);
+    }
+
+    fn reverse_attributes_in_rectangle(&mut self, params: &[u32]) {
+        self.increment("reverse_attributes_in_rectangle");
+        self.save_params("reverse_attributes_in_rectangle", params);
+    }
+
+    fn push_keyboard_flags(&mut self, flags: u32) {
+        self.increment("push_keyboard_flags");
+        self.save_params("push_keyboard_flags", &[flags]);
+    }
+
+    fn pop_keyboard_flags(&mut self, count: Option<u32>) {
+        self.increment("pop_keyboard_flags");
+        self.save_params("pop_keyboard_flags", &[count.unwrap_or(0)]);
+    }
+
+    fn report_keyboard_flags(&mut self) {
+        self.increment("report_keyboard_flags");
+    }
+
+    fn set_dynamic_color(&mut self, code: u32, value: &str) {
+        self.increment("set_dynamic_color");
+        self.save_params("set_dynamic_color", &[code]);
+        self.save_string("set_dynamic_color", value);
+    }
+
+    fn reset_dynamic_color(&mut self, code: u32) {
+        self.increment("reset_dynamic_color");
+        self.save_params("reset_dynamic_color", &[code]);
+    }
+
+    fn media_copy(&mut self, mode: Option<u32>, private: Option<bool>) {
+        self.increment("media_copy");
+        self.save_params("media_copy", &[mode.unwrap_or(0)]);
+        self.last_private = private;
+    }
+
+    fn dcs_dispatch(&mut self, data: &str) {
+        self.increment("dcs_dispatch");
+        self.save_string("dcs_dispatch", data);
+    }
+
+    fn report_mode(&mut self, mode: u32, is_private: bool) {
+        self.increment("report_mode");
+        self.save_params("report_mode", &[mode]);
+        self.last_private = Some(is_private);
+    }
+
+    fn report_device_status(&mut self, mode: Option<u32>, private: Option<bool>) {
+        self.increment("report_device_status");
+        self.save_params("report_device_status", &[mode.unwrap_or(0)]);
+        self.last_private = private;
+    }
+
     fn reverse_index(&mut self) {
         self.increment("reverse_index");
     }
 
+    fn back_index(&mut self) {
+        self.increment("back_index");
+    }
+
+    fn forward_index(&mut self) {
+        self.increment("forward_index");
+    }
+
     fn save_cursor(&mut self) {
         self.increment("save_cursor");
     }
@@ -193,6 +333,17 @@ impl ParserListener for Counter {
         self.save_string("set_title", title);
     }
 
+    fn set_hyperlink(&mut self, params: &str, uri: &str) {
+        self.increment("set_hyperlink");
+        self.save_string("set_hyperlink_params", params);
+        self.save_string("set_hyperlink", uri);
+    }
+
+    fn set_warning_bell_volume(&mut self, volume: Option<u32>) {
+        self.increment("set_warning_bell_volume");
+        self.save_params("set_warning_bell_volume", &[volume.unwrap_or(0)]);
+    }
+
     fn tab(&mut self) {
         self.increment("tab");
     }
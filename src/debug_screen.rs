@@ -1,161 +1,343 @@
+use crate::modes::mode_name;
 use crate::parser_listener::ParserListener;
 
-pub struct DebugScreen {}
+/// A listener which prints each event it receives, for debugging
+/// captured sessions. The lines are also kept in `log`.
+#[derive(Default)]
+pub struct DebugScreen {
+    pub log: Vec<String>,
+}
+
+impl DebugScreen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn log(&mut self, line: impl Into<String>) {
+        let line = line.into();
+        println!("{}", line);
+        self.log.push(line);
+    }
+}
+
+/// Formats `modes` as in the sequence setting them, followed by their
+/// names when known, e.g. `?1000 (mouse tracking)`.
+fn describe_modes(modes: &[u32], private: bool) -> String {
+    let prefix = if private { "?" } else { "" };
+    modes
+        .iter()
+        .map(|&mode| match mode_name(mode, private) {
+            Some(name) => format!("{}{} ({})", prefix, mode, name),
+            None => format!("{}{}", prefix, mode),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
 impl ParserListener for DebugScreen {
+    fn csi_dispatch(
+        &mut self,
+        csi_command: &str,
+        params: &[u32],
+        private_marker: Option<char>,
+        intermediates: &str,
+    ) {
+        if !intermediates.is_empty() {
+            self.log(format!(
+                "csi intermediates {:?} final {:?} params {:?}",
+                intermediates, csi_command, params
+            ));
+        }
+        self.route_csi(csi_command, params, private_marker, intermediates);
+    }
+
     fn alignment_display(&mut self) {
-        println!("alignment display");
+        self.log("alignment display");
     }
 
     fn define_charset(&mut self, code: &str, mode: &str) {
-        println!("defining charset code {} mode {}", code, mode);
+        self.log(format!("defining charset code {} mode {}", code, mode));
     }
 
     fn reset(&mut self) {
-        println!("reset");
+        self.log("reset");
     }
 
     fn index(&mut self) {
-        println!("index");
+        self.log("index");
     }
 
     fn linefeed(&mut self) {
-        println!("linefeed");
+        self.log("linefeed");
+    }
+
+    fn next_line(&mut self) {
+        self.log("next_line");
     }
 
     fn reverse_index(&mut self) {
-        println!("reverse_index");
+        self.log("reverse_index");
+    }
+
+    fn back_index(&mut self) {
+        self.log("back_index");
+    }
+
+    fn forward_index(&mut self) {
+        self.log("forward_index");
     }
 
     fn set_tab_stop(&mut self) {
-        println!("set_tab_stop");
+        self.log("set_tab_stop");
     }
 
     fn save_cursor(&mut self) {
-        println!("save_cursor");
+        self.log("save_cursor");
     }
 
     fn restore_cursor(&mut self) {
-        println!("restore_cursor");
+        self.log("restore_cursor");
     }
 
     fn bell(&mut self) {
-        println!("bell");
+        self.log("bell");
+    }
+
+    fn enquiry(&mut self) {
+        self.log("enquiry");
+    }
+
+    fn keypad_application_mode(&mut self) {
+        self.log("keypad_application_mode");
+    }
+
+    fn keypad_numeric_mode(&mut self) {
+        self.log("keypad_numeric_mode");
     }
 
     fn backspace(&mut self) {
-        println!("backspace");
+        self.log("backspace");
     }
 
     fn tab(&mut self) {
-        println!("tab");
+        self.log("tab");
     }
 
     fn cariage_return(&mut self) {
-        println!("carriage return")
+        self.log("carriage return")
     }
 
     fn draw(&mut self, input: &str) {
-        println!("draw input {}", input);
+        self.log(format!("draw input {}", input));
     }
 
     fn insert_characters(&mut self, count: Option<u32>) {
-        println!("insert_characters count {:?}", count);
+        self.log(format!("insert_characters count {:?}", count));
     }
 
     fn cursor_up(&mut self, count: Option<u32>) {
-        println!("cursor up count {:?} ", count);
+        self.log(format!("cursor up count {:?} ", count));
     }
 
     fn cursor_down(&mut self, count: Option<u32>) {
-        println!("cursor down count {:?}", count);
+        self.log(format!("cursor down count {:?}", count));
     }
 
     fn cursor_forward(&mut self, count: Option<u32>) {
-        println!("cursor forward count {:?}", count);
+        self.log(format!("cursor forward count {:?}", count));
     }
 
     fn cursor_back(&mut self, count: Option<u32>) {
-        println!("cursor back count {:?}", count);
+        self.log(format!("cursor back count {:?}", count));
     }
 
     fn cursor_down1(&mut self, count: Option<u32>) {
-        println!("cursor down count {:?}", count);
+        self.log(format!("cursor down count {:?}", count));
     }
 
     fn cursor_up1(&mut self, count: Option<u32>) {
-        println!("cursor up1 count {:?}", count);
+        self.log(format!("cursor up1 count {:?}", count));
     }
 
     fn cursor_to_column(&mut self, character: Option<u32>) {
-        println!("cursor to column character {:?}", character);
+        self.log(format!("cursor to column character {:?}", character));
     }
 
     fn cursor_position(&mut self, _line: Option<u32>, _character: Option<u32>) {
-        println!("cursor position");
+        self.log("cursor position");
     }
 
     fn erase_in_display(&mut self, _how: Option<u32>, _private: Option<bool>) {
-        println!("erase in display");
+        self.log("erase in display");
     }
 
     fn erase_in_line(&mut self, _how: Option<u32>, _private: Option<bool>) {
-        println!("erase in line");
+        self.log("erase in line");
     }
 
     fn insert_lines(&mut self, _count: Option<u32>) {
-        println!("insert lines")
+        self.log("insert lines")
     }
 
     fn delete_lines(&mut self, _count: Option<u32>) {
-        println!("delete lines");
+        self.log("delete lines");
+    }
+
+    fn scroll_up(&mut self, count: Option<u32>) {
+        self.log(format!("scroll up count {:?}", count));
+    }
+
+    fn scroll_down(&mut self, count: Option<u32>) {
+        self.log(format!("scroll down count {:?}", count));
     }
 
     fn delete_characters(&mut self, _count: Option<u32>) {
-        println!("delete characters");
+        self.log("delete characters");
     }
 
     fn erase_characters(&mut self, _count: Option<u32>) {
-        println!("erase characters");
+        self.log("erase characters");
     }
 
     fn report_device_attributes(&mut self, _mode: Option<u32>, _private: Option<bool>) {
-        println!("report device attributes");
+        self.log("report device attributes");
+    }
+
+    fn report_mode(&mut self, _mode: u32, _is_private: bool) {
+        self.log("report mode");
+    }
+
+    fn report_device_status(&mut self, _mode: Option<u32>, _private: Option<bool>) {
+        self.log("report device status");
     }
 
     fn cursor_to_line(&mut self, _line: Option<u32>) {
-        println!("cursor to line");
+        self.log("cursor to line");
     }
 
     fn clear_tab_stop(&mut self, _how: Option<u32>) {
-        println!("clear tab stop");
+        self.log("clear tab stop");
     }
 
-    fn set_mode(&mut self, _modes: &[u32], _is_private: bool) {
-        println!("set mode");
+    fn set_mode(&mut self, modes: &[u32], is_private: bool) {
+        self.log(format!("set mode {}", describe_modes(modes, is_private)));
     }
 
-    fn reset_mode(&mut self, _modes: &[u32], _is_private: bool) {
-        println!("reset mode");
+    fn reset_mode(&mut self, modes: &[u32], is_private: bool) {
+        self.log(format!("reset mode {}", describe_modes(modes, is_private)));
     }
 
     fn select_graphic_rendition(&mut self, _modes: &[u32]) {
-        println!("select graphic rendition");
+        self.log("select graphic rendition");
     }
 
     fn shift_out(&mut self) {
-        println!("shift out");
+        self.log("shift out");
     }
 
     fn shift_in(&mut self) {
-        println!("shift in");
+        self.log("shift in");
     }
 
     fn set_title(&mut self, title: &str) {
-        println!("set_title {}", title);
+        self.log(format!("set_title {}", title));
     }
 
     fn set_icon_name(&mut self, icon_name: &str) {
-        println!("set icon_name {}", icon_name);
+        self.log(format!("set icon_name {}", icon_name));
+    }
+
+    fn set_hyperlink(&mut self, params: &str, uri: &str) {
+        self.log(format!("set hyperlink params {} uri {}", params, uri));
+    }
+
+    fn set_warning_bell_volume(&mut self, volume: Option<u32>) {
+        self.log(format!("set warning bell volume {:?}", volume));
+    }
+
+    fn report_secondary_device_attributes(&mut self, _mode: Option<u32>) {
+        self.log("report secondary device attributes");
+    }
+
+    fn report_tertiary_device_attributes(&mut self, _mode: Option<u32>) {
+        self.log("report tertiary device attributes");
+    }
+
+    fn set_modify_other_keys(&mut self, level: u32) {
+        self.log(format!("set modify other keys {}", level));
+    }
+
+    fn window_op(&mut self, params: &[u32]) {
+        self.log(format!("window op {:?}", params));
+    }
+
+    fn change_attributes_in_rectangle(&mut self, params: &[u32]) {
+        self.log(format!("change attributes in rectangle {:?}", params));
+    }
+
+    fn reverse_attributes_in_rectangle(&mut self, params: &[u32]) {
+        self.log(format!("reverse attributes in rectangle {:?}", params));
+    }
+
+    fn push_keyboard_flags(&mut self, flags: u32) {
+        self.log(format!("push keyboard flags {}", flags));
+    }
+
+    fn pop_keyboard_flags(&mut self, count: Option<u32>) {
+        self.log(format!("pop keyboard flags {:?}", count));
+    }
+
+    fn report_keyboard_flags(&mut self) {
+        self.log("report keyboard flags");
+    }
+
+    fn set_dynamic_color(&mut self, code: u32, value: &str) {
+        self.log(format!("set dynamic color {} {}", code, value));
+    }
+
+    fn reset_dynamic_color(&mut self, code: u32) {
+        self.log(format!("reset dynamic color {}", code));
+    }
+
+    fn media_copy(&mut self, mode: Option<u32>, private: Option<bool>) {
+        self.log(format!("media copy mode {:?} private {:?}", mode, private));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DebugScreen;
+    use crate::parser::StreamParser;
+
+    #[test]
+    fn mode_names() {
+        let mut screen = DebugScreen::new();
+        let mut parser = StreamParser::new();
+        parser.feed(&mut screen, "\x1b[?1000;1006h\x1b[?2004l\x1b[4h\x1b[?7777h");
+        assert_eq!(
+            screen.log,
+            vec![
+                "set mode ?1000 (mouse tracking), ?1006 (SGR mouse encoding)",
+                "reset mode ?2004 (bracketed paste)",
+                "set mode 4 (IRM)",
+                "set mode ?7777",
+            ]
+        );
+    }
+
+    #[test]
+    fn csi_intermediates() {
+        let mut screen = DebugScreen::new();
+        let mut parser = StreamParser::new();
+        parser.feed(&mut screen, "\x1b[?25$p\x1b[!p\x1b[2A");
+        assert_eq!(
+            screen.log,
+            vec![
+                "csi intermediates \"$\" final \"p\" params [25]",
+                "report mode",
+                "csi intermediates \"!\" final \"p\" params [0]",
+                "cursor up count Some(2) ",
+            ]
+        );
     }
 }
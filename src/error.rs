@@ -0,0 +1,47 @@
+use std::{fmt, io};
+
+use crate::parser::ParseError;
+
+/// An error from `Screen::try_feed`.
+#[derive(Debug)]
+pub enum TermError {
+    /// A reply couldn't be written to the host, see
+    /// `Reporter::try_report`.
+    Output(io::Error),
+    /// The input had errors, only reported in strict mode, see
+    /// `StreamParser::set_strict`.
+    Parse(Vec<ParseError>),
+}
+
+impl fmt::Display for TermError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TermError::Output(error) => write!(f, "failed to write a reply: {}", error),
+            TermError::Parse(errors) => {
+                write!(f, "invalid input: ")?;
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TermError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TermError::Output(error) => Some(error),
+            TermError::Parse(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for TermError {
+    fn from(error: io::Error) -> Self {
+        TermError::Output(error)
+    }
+}
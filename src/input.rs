@@ -0,0 +1,210 @@
+use crate::modes::{DECCKM, LNM};
+use crate::prelude::*;
+use crate::screen::Screen;
+
+/// A key which sends input to the host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// A key producing a character, e.g. `a` or `é`.
+    Char(char),
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// A function key, `F(1)` to `F(12)`.
+    F(u8),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    /// A key on the numeric keypad: a digit, one of `. + - * / =`, or
+    /// `\r` for the keypad Enter.
+    Keypad(char),
+}
+
+/// The modifier keys held down with a key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, alt: false, ctrl: false };
+
+    /// The xterm modifier parameter, e.g. `5` in `CSI 1 ; 5 A` for
+    /// Ctrl, or `None` without modifiers.
+    fn param(&self) -> Option<u8> {
+        let param = 1 + self.shift as u8 + 2 * self.alt as u8 + 4 * self.ctrl as u8;
+        (param > 1).then_some(param)
+    }
+}
+
+/// Returns the bytes xterm sends to the host when `key` is pressed with
+/// `modifiers`. The cursor keys (DECCKM), keypad (DECKPAM) and new line
+/// (LNM) modes of `screen` select between the alternative encodings.
+pub fn encode(key: Key, modifiers: Modifiers, screen: &Screen) -> Vec<u8> {
+    let cursor_final = match key {
+        Key::Up => Some('A'),
+        Key::Down => Some('B'),
+        Key::Right => Some('C'),
+        Key::Left => Some('D'),
+        Key::Home => Some('H'),
+        Key::End => Some('F'),
+        Key::F(n @ 1..=4) => Some((b'P' + n - 1) as char),
+        _ => None,
+    };
+    if let Some(final_char) = cursor_final {
+        return match modifiers.param() {
+            Some(param) => format!("\x1B[1;{}{}", param, final_char),
+            // F1-F4 always use SS3, the cursor keys only in application
+            // mode.
+            None if matches!(key, Key::F(_)) || screen.mode.contains(&DECCKM) => {
+                format!("\x1BO{}", final_char)
+            }
+            None => format!("\x1B[{}", final_char),
+        }
+        .into_bytes();
+    }
+
+    let tilde_code = match key {
+        Key::Insert => Some(2),
+        Key::Delete => Some(3),
+        Key::PageUp => Some(5),
+        Key::PageDown => Some(6),
+        Key::F(n @ 5..=12) => Some([15, 17, 18, 19, 20, 21, 23, 24][n as usize - 5]),
+        _ => None,
+    };
+    if let Some(code) = tilde_code {
+        return match modifiers.param() {
+            Some(param) => format!("\x1B[{};{}~", code, param),
+            None => format!("\x1B[{}~", code),
+        }
+        .into_bytes();
+    }
+
+    let mut bytes = match key {
+        Key::Char(c) if modifiers.ctrl => match c {
+            'a'..='z' | 'A'..='Z' => vec![c.to_ascii_uppercase() as u8 - 0x40],
+            '@' | ' ' | '2' => vec![0x00],
+            '[' | '3' => vec![0x1B],
+            '\\' | '4' => vec![0x1C],
+            ']' | '5' => vec![0x1D],
+            '^' | '6' => vec![0x1E],
+            '_' | '/' | '7' => vec![0x1F],
+            '?' | '8' => vec![0x7F],
+            c => c.to_string().into_bytes(),
+        },
+        Key::Char(c) => c.to_string().into_bytes(),
+        Key::Enter if screen.mode.contains(&LNM) => b"\r\n".to_vec(),
+        Key::Enter => b"\r".to_vec(),
+        Key::Tab if modifiers.shift => return b"\x1B[Z".to_vec(),
+        Key::Tab => b"\t".to_vec(),
+        Key::Backspace if modifiers.ctrl => vec![0x08],
+        Key::Backspace => vec![0x7F],
+        Key::Keypad(c) if screen.keypad_application => {
+            let final_char = match c {
+                '0'..='9' => (b'p' + (c as u8 - b'0')) as char,
+                '.' => 'n',
+                '+' => 'k',
+                '-' => 'm',
+                '*' => 'j',
+                '/' => 'o',
+                '=' => 'X',
+                '\r' => 'M',
+                c => c,
+            };
+            return format!("\x1BO{}", final_char).into_bytes();
+        }
+        Key::Keypad('\r') => return encode(Key::Enter, modifiers, screen),
+        Key::Keypad(c) => c.to_string().into_bytes(),
+        _ => Vec::new(),
+    };
+    // Alt sends the key prefixed with ESC.
+    if modifiers.alt {
+        bytes.insert(0, 0x1B);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::{encode, Key, Modifiers};
+    use crate::screen::Screen;
+
+    #[test]
+    fn arrows() {
+        let mut screen = Screen::new(10, 1);
+        assert_eq!(encode(Key::Up, Modifiers::NONE, &screen), b"\x1B[A");
+        assert_eq!(encode(Key::Left, Modifiers::NONE, &screen), b"\x1B[D");
+
+        // Application cursor keys use SS3.
+        screen.feed("\x1b[?1h");
+        assert_eq!(encode(Key::Up, Modifiers::NONE, &screen), b"\x1BOA");
+        assert_eq!(encode(Key::Left, Modifiers::NONE, &screen), b"\x1BOD");
+        assert_eq!(encode(Key::Home, Modifiers::NONE, &screen), b"\x1BOH");
+
+        // Modified keys are the same in both modes.
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::NONE };
+        assert_eq!(encode(Key::Right, ctrl, &screen), b"\x1B[1;5C");
+    }
+
+    #[test]
+    fn function_keys() {
+        let screen = Screen::new(10, 1);
+        let shift = Modifiers { shift: true, ..Modifiers::NONE };
+        assert_eq!(encode(Key::F(1), Modifiers::NONE, &screen), b"\x1BOP");
+        assert_eq!(encode(Key::F(4), shift, &screen), b"\x1B[1;2S");
+        assert_eq!(encode(Key::F(5), Modifiers::NONE, &screen), b"\x1B[15~");
+        assert_eq!(encode(Key::F(12), shift, &screen), b"\x1B[24;2~");
+        assert_eq!(encode(Key::PageDown, Modifiers::NONE, &screen), b"\x1B[6~");
+        assert_eq!(encode(Key::Delete, Modifiers::NONE, &screen), b"\x1B[3~");
+    }
+
+    #[test]
+    fn characters() {
+        let mut screen = Screen::new(10, 1);
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::NONE };
+        let alt = Modifiers { alt: true, ..Modifiers::NONE };
+        let shift = Modifiers { shift: true, ..Modifiers::NONE };
+        assert_eq!(
+            encode(Key::Char('é'), Modifiers::NONE, &screen),
+            "é".as_bytes()
+        );
+        assert_eq!(encode(Key::Char('c'), ctrl, &screen), b"\x03");
+        assert_eq!(encode(Key::Char('x'), alt, &screen), b"\x1Bx");
+        assert_eq!(encode(Key::Backspace, Modifiers::NONE, &screen), b"\x7F");
+        assert_eq!(encode(Key::Tab, shift, &screen), b"\x1B[Z");
+
+        assert_eq!(encode(Key::Enter, Modifiers::NONE, &screen), b"\r");
+        screen.feed("\x1b[20h");
+        assert_eq!(encode(Key::Enter, Modifiers::NONE, &screen), b"\r\n");
+    }
+
+    #[test]
+    fn keypad() {
+        let mut screen = Screen::new(10, 1);
+        assert_eq!(encode(Key::Keypad('5'), Modifiers::NONE, &screen), b"5");
+        assert_eq!(encode(Key::Keypad('\r'), Modifiers::NONE, &screen), b"\r");
+
+        screen.feed("\x1b=");
+        assert_eq!(
+            encode(Key::Keypad('5'), Modifiers::NONE, &screen),
+            b"\x1BOu"
+        );
+        assert_eq!(
+            encode(Key::Keypad('-'), Modifiers::NONE, &screen),
+            b"\x1BOm"
+        );
+        assert_eq!(
+            encode(Key::Keypad('\r'), Modifiers::NONE, &screen),
+            b"\x1BOM"
+        );
+    }
+}
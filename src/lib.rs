@@ -1,18 +1,79 @@
-#![feature(iter_advance_by)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 macro_rules! ascii {
     ($($xx:literal/$yy:literal), *) => {
-        unsafe { std::str::from_utf8_unchecked(&[$(($xx << 4) + $yy),*]) }
+        unsafe { core::str::from_utf8_unchecked(&[$(($xx << 4) + $yy),*]) }
     };
 }
 
-pub(crate) use ascii;
+/// The `alloc` types the `std` prelude would otherwise provide.
+mod prelude {
+    pub use alloc::borrow::ToOwned;
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
+}
+
+/// The collections used by the crate, hash based ones come from
+/// `hashbrown` when `std` is not available.
+pub mod collections {
+    pub use alloc::collections::{BTreeMap, VecDeque};
+    #[cfg(feature = "std")]
+    pub use std::collections::{HashMap, HashSet};
+
+    #[cfg(not(feature = "std"))]
+    pub use hashbrown::{HashMap, HashSet};
+}
+
+/// A value computed on first use, for the lookup tables in statics.
+mod lazy {
+    use core::ops::Deref;
+
+    use once_cell::race::OnceBox;
+
+    use crate::prelude::*;
+
+    pub struct Lazy<T> {
+        cell: OnceBox<T>,
+        init: fn() -> T,
+    }
+
+    impl<T> Lazy<T> {
+        pub const fn new(init: fn() -> T) -> Self {
+            Self { cell: OnceBox::new(), init }
+        }
+    }
+
+    impl<T> Deref for Lazy<T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            self.cell.get_or_init(|| Box::new((self.init)()))
+        }
+    }
+}
+
 pub mod charset;
 pub mod control;
 pub mod counter;
+#[cfg(feature = "std")]
 pub mod debug_screen;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(test)]
+mod golden;
 pub mod graphics;
+pub mod input;
 pub mod modes;
+pub mod observer;
 pub mod parser;
 pub mod parser_listener;
+pub mod recording;
+pub mod reporter;
+pub mod rle;
 pub mod screen;
+#[cfg(feature = "std")]
+pub mod terminal;
@@ -12,10 +12,18 @@ pub const IRM: u32 = 4;
 
 //Private modes.
 //..............
+// *Cursor Keys Mode*: when set, the cursor keys send application
+// sequences (``SS3 A``) instead of ANSI ones (``CSI A``).
+pub const DECCKM: u32 = 1 << 5;
+
 // *Text Cursor Enable Mode*: determines if the text cursor is
 // visible.
 pub const DECTCEM: u32 = 25 << 5;
 
+// *Cursor Blink*: the text cursor blinks. Unlike the blink attribute
+// this is about the cursor only, not the text.
+pub const CURSOR_BLINK: u32 = 12 << 5;
+
 // *Screen Mode*: toggles screen-wide reverse-video mode.
 pub const DECSCNM: u32 = 5 << 5;
 
@@ -31,3 +39,61 @@ pub const DECAWM: u32 = 7 << 5;
 // *Column Mode*: selects the number of columns per line (80 or 132)
 // on the screen.
 pub const DECCOLM: u32 = 3 << 5;
+
+// *Reverse Wraparound Mode*: when enabled, a backspace at the left
+// margin moves the cursor to the last column of the previous line.
+pub const REVERSE_WRAP: u32 = 45 << 5;
+
+// *Alternate Screen*: shows a cleared alternate screen in place of
+// the primary one, which comes back when the mode is reset.
+pub const ALT_SCREEN: u32 = 1047 << 5;
+
+// *Save Cursor*: setting saves the cursor as DECSC does, resetting
+// restores it as DECRC does.
+pub const SAVE_CURSOR: u32 = 1048 << 5;
+
+// *Alternate Screen With Saved Cursor*: saves the cursor and then
+// shows the alternate screen, resetting reverses both.
+pub const ALT_SCREEN_SAVE_CURSOR: u32 = 1049 << 5;
+
+// *Synchronized Output*: while set the application is drawing a frame,
+// a renderer should hold back updates until it is reset.
+pub const SYNCHRONIZED_OUTPUT: u32 = 2026 << 5;
+
+/// Returns a readable name for the mode set by `CSI mode h`, or by
+/// `CSI ? mode h` if `private`, e.g. for debug output.
+pub fn mode_name(mode: u32, private: bool) -> Option<&'static str> {
+    let name = if private {
+        match mode {
+            1 => "DECCKM",
+            3 => "DECCOLM",
+            5 => "DECSCNM",
+            6 => "DECOM",
+            7 => "DECAWM",
+            9 => "X10 mouse",
+            12 => "cursor blink",
+            25 => "DECTCEM",
+            45 => "reverse wraparound",
+            47 | 1047 => "alternate screen",
+            1000 => "mouse tracking",
+            1002 => "mouse button event tracking",
+            1003 => "mouse any event tracking",
+            1004 => "focus events",
+            1005 => "UTF-8 mouse encoding",
+            1006 => "SGR mouse encoding",
+            1015 => "urxvt mouse encoding",
+            1048 => "save cursor",
+            1049 => "alternate screen with saved cursor",
+            2004 => "bracketed paste",
+            2026 => "synchronized output",
+            _ => return None,
+        }
+    } else {
+        match mode {
+            4 => "IRM",
+            20 => "LNM",
+            _ => return None,
+        }
+    };
+    Some(name)
+}
@@ -0,0 +1,500 @@
+//! A listener observing the events passed on to another one, e.g. to
+//! log or count them without changing the listener itself.
+
+use crate::parser_listener::ParserListener;
+
+/// An event received by a `ParserListener`, one per trait method, with
+/// its arguments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind<'a> {
+    AlignmentDisplay,
+    DefineCharset { code: &'a str, mode: &'a str },
+    Reset,
+    Index,
+    Linefeed,
+    NextLine,
+    ReverseIndex,
+    BackIndex,
+    ForwardIndex,
+    SetTabStop,
+    SaveCursor,
+    RestoreCursor,
+    ShiftOut,
+    ShiftIn,
+    KeypadApplicationMode,
+    KeypadNumericMode,
+    Bell,
+    Enquiry,
+    Backspace,
+    Tab,
+    CariageReturn,
+    Draw(&'a str),
+    InsertCharacters(Option<u32>),
+    CursorUp(Option<u32>),
+    CursorDown(Option<u32>),
+    CursorForward(Option<u32>),
+    CursorBack(Option<u32>),
+    CursorDown1(Option<u32>),
+    CursorUp1(Option<u32>),
+    CursorToColumn(Option<u32>),
+    CursorPosition { line: Option<u32>, character: Option<u32> },
+    EraseInDisplay { how: Option<u32>, private: Option<bool> },
+    EraseInLine { how: Option<u32>, private: Option<bool> },
+    InsertLines(Option<u32>),
+    DeleteLines(Option<u32>),
+    ScrollUp(Option<u32>),
+    ScrollDown(Option<u32>),
+    DeleteCharacters(Option<u32>),
+    EraseCharacters(Option<u32>),
+    ReportDeviceAttributes { mode: Option<u32>, private: Option<bool> },
+    ReportDeviceStatus { mode: Option<u32>, private: Option<bool> },
+    CursorToLine(Option<u32>),
+    ClearTabStop(Option<u32>),
+    SetMode { modes: &'a [u32], is_private: bool },
+    ReportMode { mode: u32, is_private: bool },
+    ResetMode { modes: &'a [u32], is_private: bool },
+    SelectGraphicRendition(&'a [u32]),
+    SetTitle(&'a str),
+    SetIconName(&'a str),
+    SetHyperlink { params: &'a str, uri: &'a str },
+    SetWarningBellVolume(Option<u32>),
+    DcsDispatch(&'a str),
+    ApcDispatch(&'a str),
+    MediaCopy { mode: Option<u32>, private: Option<bool> },
+    ReportSecondaryDeviceAttributes(Option<u32>),
+    ReportTertiaryDeviceAttributes(Option<u32>),
+    SetModifyOtherKeys(u32),
+    WindowOp(&'a [u32]),
+    ChangeAttributesInRectangle(&'a [u32]),
+    ReverseAttributesInRectangle(&'a [u32]),
+    PushKeyboardFlags(u32),
+    PopKeyboardFlags(Option<u32>),
+    ReportKeyboardFlags,
+    SetDynamicColor { code: u32, value: &'a str },
+    ResetDynamicColor(u32),
+}
+
+/// Wraps the listener `inner`, calling `on_event` with each event before
+/// passing it on.
+///
+/// Escape, control and CSI sequences are routed by the observer, so
+/// `on_event` sees e.g. `CursorUp` rather than the raw sequence, and
+/// `inner` receives the routed calls too. `on_event` may borrow local
+/// state, which is released with the observer.
+///
+/// ```
+/// use memterm::observer::ObserverListener;
+/// use memterm::parser::StreamParser;
+/// use memterm::screen::Screen;
+///
+/// let mut log = Vec::new();
+/// let mut observer = ObserverListener::new(Screen::new(10, 2), |event| {
+///     log.push(format!("{:?}", event));
+/// });
+/// StreamParser::new().feed(&mut observer, "hi\x1b[2D");
+/// assert_eq!(observer.into_inner().display()[0], "hi        ");
+/// assert_eq!(log, ["Draw(\"h\")", "Draw(\"i\")", "CursorBack(Some(2))"]);
+/// ```
+pub struct ObserverListener<T: ParserListener, F: FnMut(&EventKind<'_>)> {
+    pub inner: T,
+    on_event: F,
+}
+
+impl<T: ParserListener, F: FnMut(&EventKind<'_>)> ObserverListener<T, F> {
+    pub fn new(inner: T, on_event: F) -> Self {
+        Self { inner, on_event }
+    }
+
+    /// Returns the wrapped listener.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: ParserListener, F: FnMut(&EventKind<'_>)> ParserListener for ObserverListener<T, F> {
+    fn alignment_display(&mut self) {
+        (self.on_event)(&EventKind::AlignmentDisplay);
+        self.inner.alignment_display();
+    }
+
+    fn define_charset(&mut self, code: &str, mode: &str) {
+        (self.on_event)(&EventKind::DefineCharset { code, mode });
+        self.inner.define_charset(code, mode);
+    }
+
+    fn reset(&mut self) {
+        (self.on_event)(&EventKind::Reset);
+        self.inner.reset();
+    }
+
+    fn index(&mut self) {
+        (self.on_event)(&EventKind::Index);
+        self.inner.index();
+    }
+
+    fn linefeed(&mut self) {
+        (self.on_event)(&EventKind::Linefeed);
+        self.inner.linefeed();
+    }
+
+    fn next_line(&mut self) {
+        (self.on_event)(&EventKind::NextLine);
+        self.inner.next_line();
+    }
+
+    fn reverse_index(&mut self) {
+        (self.on_event)(&EventKind::ReverseIndex);
+        self.inner.reverse_index();
+    }
+
+    fn back_index(&mut self) {
+        (self.on_event)(&EventKind::BackIndex);
+        self.inner.back_index();
+    }
+
+    fn forward_index(&mut self) {
+        (self.on_event)(&EventKind::ForwardIndex);
+        self.inner.forward_index();
+    }
+
+    fn set_tab_stop(&mut self) {
+        (self.on_event)(&EventKind::SetTabStop);
+        self.inner.set_tab_stop();
+    }
+
+    fn save_cursor(&mut self) {
+        (self.on_event)(&EventKind::SaveCursor);
+        self.inner.save_cursor();
+    }
+
+    fn restore_cursor(&mut self) {
+        (self.on_event)(&EventKind::RestoreCursor);
+        self.inner.restore_cursor();
+    }
+
+    fn shift_out(&mut self) {
+        (self.on_event)(&EventKind::ShiftOut);
+        self.inner.shift_out();
+    }
+
+    fn shift_in(&mut self) {
+        (self.on_event)(&EventKind::ShiftIn);
+        self.inner.shift_in();
+    }
+
+    fn keypad_application_mode(&mut self) {
+        (self.on_event)(&EventKind::KeypadApplicationMode);
+        self.inner.keypad_application_mode();
+    }
+
+    fn keypad_numeric_mode(&mut self) {
+        (self.on_event)(&EventKind::KeypadNumericMode);
+        self.inner.keypad_numeric_mode();
+    }
+
+    fn bell(&mut self) {
+        (self.on_event)(&EventKind::Bell);
+        self.inner.bell();
+    }
+
+    fn enquiry(&mut self) {
+        (self.on_event)(&EventKind::Enquiry);
+        self.inner.enquiry();
+    }
+
+    fn backspace(&mut self) {
+        (self.on_event)(&EventKind::Backspace);
+        self.inner.backspace();
+    }
+
+    fn tab(&mut self) {
+        (self.on_event)(&EventKind::Tab);
+        self.inner.tab();
+    }
+
+    fn cariage_return(&mut self) {
+        (self.on_event)(&EventKind::CariageReturn);
+        self.inner.cariage_return();
+    }
+
+    fn draw(&mut self, input: &str) {
+        (self.on_event)(&EventKind::Draw(input));
+        self.inner.draw(input);
+    }
+
+    fn insert_characters(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::InsertCharacters(count));
+        self.inner.insert_characters(count);
+    }
+
+    fn cursor_up(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::CursorUp(count));
+        self.inner.cursor_up(count);
+    }
+
+    fn cursor_down(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::CursorDown(count));
+        self.inner.cursor_down(count);
+    }
+
+    fn cursor_forward(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::CursorForward(count));
+        self.inner.cursor_forward(count);
+    }
+
+    fn cursor_back(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::CursorBack(count));
+        self.inner.cursor_back(count);
+    }
+
+    fn cursor_down1(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::CursorDown1(count));
+        self.inner.cursor_down1(count);
+    }
+
+    fn cursor_up1(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::CursorUp1(count));
+        self.inner.cursor_up1(count);
+    }
+
+    fn cursor_to_column(&mut self, character: Option<u32>) {
+        (self.on_event)(&EventKind::CursorToColumn(character));
+        self.inner.cursor_to_column(character);
+    }
+
+    fn cursor_position(&mut self, line: Option<u32>, character: Option<u32>) {
+        (self.on_event)(&EventKind::CursorPosition { line, character });
+        self.inner.cursor_position(line, character);
+    }
+
+    fn erase_in_display(&mut self, how: Option<u32>, private: Option<bool>) {
+        (self.on_event)(&EventKind::EraseInDisplay { how, private });
+        self.inner.erase_in_display(how, private);
+    }
+
+    fn erase_in_line(&mut self, how: Option<u32>, private: Option<bool>) {
+        (self.on_event)(&EventKind::EraseInLine { how, private });
+        self.inner.erase_in_line(how, private);
+    }
+
+    fn insert_lines(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::InsertLines(count));
+        self.inner.insert_lines(count);
+    }
+
+    fn delete_lines(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::DeleteLines(count));
+        self.inner.delete_lines(count);
+    }
+
+    fn scroll_up(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::ScrollUp(count));
+        self.inner.scroll_up(count);
+    }
+
+    fn scroll_down(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::ScrollDown(count));
+        self.inner.scroll_down(count);
+    }
+
+    fn delete_characters(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::DeleteCharacters(count));
+        self.inner.delete_characters(count);
+    }
+
+    fn erase_characters(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::EraseCharacters(count));
+        self.inner.erase_characters(count);
+    }
+
+    fn report_device_attributes(&mut self, mode: Option<u32>, private: Option<bool>) {
+        (self.on_event)(&EventKind::ReportDeviceAttributes { mode, private });
+        self.inner.report_device_attributes(mode, private);
+    }
+
+    fn report_device_status(&mut self, mode: Option<u32>, private: Option<bool>) {
+        (self.on_event)(&EventKind::ReportDeviceStatus { mode, private });
+        self.inner.report_device_status(mode, private);
+    }
+
+    fn cursor_to_line(&mut self, line: Option<u32>) {
+        (self.on_event)(&EventKind::CursorToLine(line));
+        self.inner.cursor_to_line(line);
+    }
+
+    fn clear_tab_stop(&mut self, how: Option<u32>) {
+        (self.on_event)(&EventKind::ClearTabStop(how));
+        self.inner.clear_tab_stop(how);
+    }
+
+    fn set_mode(&mut self, modes: &[u32], is_private: bool) {
+        (self.on_event)(&EventKind::SetMode { modes, is_private });
+        self.inner.set_mode(modes, is_private);
+    }
+
+    fn report_mode(&mut self, mode: u32, is_private: bool) {
+        (self.on_event)(&EventKind::ReportMode { mode, is_private });
+        self.inner.report_mode(mode, is_private);
+    }
+
+    fn reset_mode(&mut self, modes: &[u32], is_private: bool) {
+        (self.on_event)(&EventKind::ResetMode { modes, is_private });
+        self.inner.reset_mode(modes, is_private);
+    }
+
+    fn select_graphic_rendition(&mut self, modes: &[u32]) {
+        (self.on_event)(&EventKind::SelectGraphicRendition(modes));
+        self.inner.select_graphic_rendition(modes);
+    }
+
+    fn set_title(&mut self, title: &str) {
+        (self.on_event)(&EventKind::SetTitle(title));
+        self.inner.set_title(title);
+    }
+
+    fn set_icon_name(&mut self, icon_name: &str) {
+        (self.on_event)(&EventKind::SetIconName(icon_name));
+        self.inner.set_icon_name(icon_name);
+    }
+
+    fn set_hyperlink(&mut self, params: &str, uri: &str) {
+        (self.on_event)(&EventKind::SetHyperlink { params, uri });
+        self.inner.set_hyperlink(params, uri);
+    }
+
+    fn set_warning_bell_volume(&mut self, volume: Option<u32>) {
+        (self.on_event)(&EventKind::SetWarningBellVolume(volume));
+        self.inner.set_warning_bell_volume(volume);
+    }
+
+    fn dcs_dispatch(&mut self, data: &str) {
+        (self.on_event)(&EventKind::DcsDispatch(data));
+        self.inner.dcs_dispatch(data);
+    }
+
+    fn apc_dispatch(&mut self, data: &str) {
+        (self.on_event)(&EventKind::ApcDispatch(data));
+        self.inner.apc_dispatch(data);
+    }
+
+    fn media_copy(&mut self, mode: Option<u32>, private: Option<bool>) {
+        (self.on_event)(&EventKind::MediaCopy { mode, private });
+        self.inner.media_copy(mode, private);
+    }
+
+    fn report_secondary_device_attributes(&mut self, mode: Option<u32>) {
+        (self.on_event)(&EventKind::ReportSecondaryDeviceAttributes(mode));
+        self.inner.report_secondary_device_attributes(mode);
+    }
+
+    fn report_tertiary_device_attributes(&mut self, mode: Option<u32>) {
+        (self.on_event)(&EventKind::ReportTertiaryDeviceAttributes(mode));
+        self.inner.report_tertiary_device_attributes(mode);
+    }
+
+    fn set_modify_other_keys(&mut self, level: u32) {
+        (self.on_event)(&EventKind::SetModifyOtherKeys(level));
+        self.inner.set_modify_other_keys(level);
+    }
+
+    fn window_op(&mut self, params: &[u32]) {
+        (self.on_event)(&EventKind::WindowOp(params));
+        self.inner.window_op(params);
+    }
+
+    fn change_attributes_in_rectangle(&mut self, params: &[u32]) {
+        (self.on_event)(&EventKind::ChangeAttributesInRectangle(params));
+        self.inner.change_attributes_in_rectangle(params);
+    }
+
+    fn reverse_attributes_in_rectangle(&mut self, params: &[u32]) {
+        (self.on_event)(&EventKind::ReverseAttributesInRectangle(params));
+        self.inner.reverse_attributes_in_rectangle(params);
+    }
+
+    fn push_keyboard_flags(&mut self, flags: u32) {
+        (self.on_event)(&EventKind::PushKeyboardFlags(flags));
+        self.inner.push_keyboard_flags(flags);
+    }
+
+    fn pop_keyboard_flags(&mut self, count: Option<u32>) {
+        (self.on_event)(&EventKind::PopKeyboardFlags(count));
+        self.inner.pop_keyboard_flags(count);
+    }
+
+    fn report_keyboard_flags(&mut self) {
+        (self.on_event)(&EventKind::ReportKeyboardFlags);
+        self.inner.report_keyboard_flags();
+    }
+
+    fn printer_controller_mode(&self) -> bool {
+        self.inner.printer_controller_mode()
+    }
+
+    fn set_dynamic_color(&mut self, code: u32, value: &str) {
+        (self.on_event)(&EventKind::SetDynamicColor { code, value });
+        self.inner.set_dynamic_color(code, value);
+    }
+
+    fn reset_dynamic_color(&mut self, code: u32) {
+        (self.on_event)(&EventKind::ResetDynamicColor(code));
+        self.inner.reset_dynamic_color(code);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{EventKind, ObserverListener};
+    use crate::parser::StreamParser;
+    use crate::screen::Screen;
+
+    #[test]
+    fn observe_screen() {
+        let mut counts = HashMap::new();
+        let mut sgr = Vec::new();
+        let mut observer = ObserverListener::new(Screen::new(6, 3), |event| {
+            let name = format!("{:?}", event);
+            let name = name.split(['(', ' ']).next().unwrap().to_owned();
+            *counts.entry(name).or_insert(0) += 1;
+            if let EventKind::SelectGraphicRendition(params) = event {
+                sgr.push(params.to_vec());
+            }
+        });
+
+        let mut parser = StreamParser::new();
+        parser.feed(
+            &mut observer,
+            "\x1b]2;title\x07\x1b[1;31mab\x1b[0m\r\ncd\x1b[3;2H\x1b[?25h\x1b[?25l\x07\x1bM",
+        );
+
+        // Releases the borrows of the callback.
+        let screen = observer.into_inner();
+        let expected = [
+            ("SetTitle", 1),
+            ("SelectGraphicRendition", 2),
+            ("Draw", 4),
+            ("CariageReturn", 1),
+            ("Linefeed", 1),
+            ("CursorPosition", 1),
+            ("SetMode", 1),
+            ("ResetMode", 1),
+            ("Bell", 1),
+            ("ReverseIndex", 1),
+        ];
+        assert_eq!(counts.len(), expected.len());
+        for (name, count) in expected {
+            assert_eq!(counts.get(name), Some(&count), "{}", name);
+        }
+        assert_eq!(sgr, vec![vec![1, 31], vec![0]]);
+
+        // The screen saw all of it as well.
+        assert_eq!(screen.title, "title");
+        assert_eq!(screen.display(), vec!["ab    ", "cd    ", "      "]);
+        assert_eq!(&*screen.buffer[0][0].fg, "red");
+        assert!(screen.buffer[0][0].bold);
+        assert!(screen.cursor.hidden);
+        assert_eq!((screen.cursor.x, screen.cursor.y), (1, 1));
+    }
+}
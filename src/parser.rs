@@ -1,202 +1,951 @@
 #![allow(clippy::cmp_owned)]
 
+#[cfg(feature = "std")]
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
 use std::sync::{Arc, Mutex};
 
-use generator::{Generator, Gn};
+pub use encoding_rs::Encoding;
+use encoding_rs::{Decoder, DecoderResult};
 
 use crate::control::*;
+use crate::graphics::{BG_256, BG_AIXTERM, BG_ANSI, FG_256, FG_AIXTERM, FG_ANSI, TEXT};
 use crate::parser_listener::ParserListener;
+use crate::prelude::*;
+
+/// A problem found in the input, recorded in strict mode.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    /// A CSI sequence with a final character which isn't handled.
+    UnknownCsi(String),
+    /// SGR parameters with an unknown attribute or a color out of range.
+    InvalidSgr(Vec<u32>),
+    /// A sequence cut short by a character which can't appear in it.
+    Truncated(String),
+    /// A CSI sequence with a character out of place, e.g. a private
+    /// marker after a parameter. It is ignored up to its final.
+    Malformed(String),
+    /// A control string longer than the limit, see
+    /// `Parser::set_max_string_length`.
+    TooLong,
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::UnknownCsi(final_char) => write!(f, "unknown CSI final {:?}", final_char),
+            ParseError::InvalidSgr(params) => write!(f, "invalid SGR parameters {:?}", params),
+            ParseError::Truncated(by) => write!(f, "sequence interrupted by {:?}", by),
+            ParseError::Malformed(at) => write!(f, "malformed sequence ignored from {:?}", at),
+            ParseError::TooLong => write!(f, "control string too long"),
+        }
+    }
+}
+
+/// What invalid input and unprintable control characters turn into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplacementPolicy {
+    /// U+FFFD REPLACEMENT CHARACTER, the default.
+    #[default]
+    Fffd,
+    /// Nothing, the input is dropped.
+    Skip,
+    /// The given character, e.g. `?`.
+    Custom(char),
+}
+
+impl ReplacementPolicy {
+    /// The character to draw in place of invalid input, if any.
+    pub fn replacement(self) -> Option<char> {
+        match self {
+            ReplacementPolicy::Fffd => Some('\u{FFFD}'),
+            ReplacementPolicy::Skip => None,
+            ReplacementPolicy::Custom(c) => Some(c),
+        }
+    }
+}
 
 pub struct ParserState {
     use_utf8: bool,
+    accept_c1: bool,
+    strict: bool,
+    errors: Vec<ParseError>,
+    max_params: usize,
+    max_string_length: usize,
+    replacement_policy: ReplacementPolicy,
+}
+
+impl ParserState {
+    /// Records `error` when in strict mode.
+    fn error(&mut self, error: ParseError) {
+        if self.strict {
+            self.errors.push(error);
+        }
+    }
+
+    /// Select other (non G0 or G1) charset, `ESC % code`.
+    ///
+    /// `@` selects the default ISO 2022 charsets, `G` and `8` select
+    /// UTF-8. Other codes are ignored.
+    fn select_other_charset(&mut self, code: &str) {
+        match code {
+            "@" => self.use_utf8 = false,
+            "G" | "8" => self.use_utf8 = true,
+            _ => {}
+        }
+    }
+}
+
+/// The parameters of a CSI sequence read so far.
+#[derive(Default)]
+struct CsiParams {
+    params: Vec<u32>,
+    /// The `?`, `>`, `=` or `<` prefix, if any.
+    private_marker: Option<char>,
+    /// Intermediate bytes (0x20-0x2F) before the final, e.g. `$` in
+    /// DECRQM.
+    intermediates: String,
+    current: String,
+}
+
+/// Where the parser is within an escape sequence.
+enum State {
+    Ground,
+    /// After `ESC`, or a C1 control code.
+    Escape,
+    /// `ESC #`, waiting for the final.
+    EscapeHash,
+    /// `ESC %`, waiting for the charset code.
+    EscapePercent,
+    /// `ESC (` or `ESC )`, waiting for the charset code.
+    Charset(String),
+    Csi(CsiParams),
+    /// The rest of a malformed CSI sequence, e.g. with `:` separated
+    /// parameters, thrown away up to its final.
+    CsiIgnore,
+    /// `OSC`, waiting for the first character.
+    OscStart,
+    /// Inside an OSC, DCS, APC or PM string, `escape` is set after an
+    /// `ESC` which may start an `ST`.
+    ControlString {
+        kind: &'static str,
+        string: String,
+        escape: bool,
+    },
+    /// The rest of a string which went over `max_string_length`, thrown
+    /// away up to its terminator.
+    IgnoreString {
+        escape: bool,
+    },
+}
+
+/// A parser which borrows its listener for the duration of each `feed`,
+/// for single threaded use without `Arc<Mutex<_>>`.
+///
+/// The parser state is kept between calls, so a sequence may be split
+/// across several `feed`s.
+pub struct StreamParser {
+    state: State,
+    parser_state: ParserState,
+    /// Leading bytes of a UTF-8 sequence split across `feed_bytes` calls.
+    partial: Vec<u8>,
+    /// Decodes bytes when UTF-8 is disabled, Latin-1 is used if unset.
+    decoder: Option<Decoder>,
+    /// In printer controller mode, the start of what may be the `CSI 4 i`
+    /// ending it.
+    printer_pending: String,
+}
+
+impl Default for StreamParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamParser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            parser_state: ParserState {
+                use_utf8: true,
+                accept_c1: true,
+                strict: false,
+                errors: Vec::new(),
+                max_params: 32,
+                max_string_length: 8192,
+                replacement_policy: ReplacementPolicy::default(),
+            },
+            partial: Vec::new(),
+            decoder: None,
+            printer_pending: String::new(),
+        }
+    }
+
+    /// Creates a parser decoding `feed_bytes` input with `encoding`, see
+    /// `set_encoding`.
+    pub fn with_encoding(encoding: &'static Encoding) -> Self {
+        let mut parser = Self::new();
+        parser.set_encoding(encoding);
+        parser
+    }
+
+    /// Decode `feed_bytes` input with `encoding`, e.g. Shift_JIS or
+    /// windows-1252. Any encoding other than UTF-8 disables UTF-8 mode,
+    /// which `ESC % G` switches back on and `ESC % @` off again.
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.partial.clear();
+        if encoding == encoding_rs::UTF_8 {
+            self.decoder = None;
+            self.parser_state.use_utf8 = true;
+        } else {
+            self.decoder = Some(encoding.new_decoder_without_bom_handling());
+            self.parser_state.use_utf8 = false;
+        }
+    }
+
+    /// Parse `data`, calling `listener` for each character and complete
+    /// sequence. Returns the number of bytes consumed, all of `data`.
+    pub fn feed<T: ParserListener + ?Sized>(&mut self, listener: &mut T, data: &str) -> usize {
+        let mut buffer = [0; 4];
+        for c in data.chars() {
+            self.advance(listener, c.encode_utf8(&mut buffer));
+        }
+        data.len()
+    }
+
+    /// Returns `true` when the parser is between sequences, so the
+    /// next character is either drawn or starts a new sequence. A host
+    /// can only inject sequences of its own safely at this point.
+    pub fn in_ground_state(&self) -> bool {
+        matches!(self.state, State::Ground)
+    }
+
+    /// Like `feed`, but returns whether the parser ended back in its
+    /// ground state, that is `false` when `data` stops partway through
+    /// a sequence.
+    pub fn feed_complete<T: ParserListener + ?Sized>(
+        &mut self,
+        listener: &mut T,
+        data: &str,
+    ) -> bool {
+        self.feed(listener, data);
+        self.in_ground_state()
+    }
+
+    /// Like `feed`, but stops after the first sequence for which
+    /// `predicate` returns `true`, e.g. `|seq| seq == "\x1b[?2026l"`.
+    /// Each printable character, control character and complete escape
+    /// sequence is passed to `predicate` in turn, a sequence started by
+    /// an earlier `feed` only with its remaining part.
+    ///
+    /// Returns the number of bytes consumed, so `&data[consumed..]` is
+    /// the rest of the input.
+    pub fn feed_until<T: ParserListener + ?Sized>(
+        &mut self,
+        listener: &mut T,
+        data: &str,
+        mut predicate: impl FnMut(&str) -> bool,
+    ) -> usize {
+        let mut buffer = [0; 4];
+        let mut start = 0;
+        for (offset, c) in data.char_indices() {
+            self.advance(listener, c.encode_utf8(&mut buffer));
+            let end = offset + c.len_utf8();
+            if self.in_ground_state() {
+                if predicate(&data[start..end]) {
+                    return end;
+                }
+                start = end;
+            }
+        }
+        data.len()
+    }
+
+    /// Like `feed`, but decodes `data` first: as UTF-8, or when UTF-8 is
+    /// disabled (see `set_use_utf8`) with the encoding given to
+    /// `set_encoding`, Latin-1 by default. Invalid input is replaced as
+    /// `set_replacement_policy` says, by U+FFFD by default.
+    pub fn feed_bytes<T: ParserListener + ?Sized>(
+        &mut self,
+        listener: &mut T,
+        data: &[u8],
+    ) -> usize {
+        for &byte in data {
+            self.advance_byte(listener, byte);
+        }
+        data.len()
+    }
+
+    fn advance_byte<T: ParserListener + ?Sized>(&mut self, listener: &mut T, byte: u8) {
+        let mut buffer = [0; 4];
+        if let Some(decoder) = self
+            .decoder
+            .as_mut()
+            .filter(|_| !self.parser_state.use_utf8)
+        {
+            // A single byte never decodes to more than a few characters.
+            // A byte ending a malformed sequence may be left unread, to
+            // be decoded on the next round. `None` marks the malformed
+            // sequences.
+            let mut decoded = [0; 32];
+            let mut input = &[byte][..];
+            let mut pieces = Vec::new();
+            loop {
+                let (result, read, written) =
+                    decoder.decode_to_utf8_without_replacement(input, &mut decoded, false);
+                let text = core::str::from_utf8(&decoded[..written]).unwrap_or_default();
+                pieces.push(Some(text.to_owned()));
+                input = &input[read..];
+                match result {
+                    DecoderResult::Malformed(..) => pieces.push(None),
+                    _ => break,
+                }
+            }
+            for piece in pieces {
+                match piece {
+                    Some(text) => {
+                        for c in text.chars() {
+                            self.advance(listener, c.encode_utf8(&mut buffer));
+                        }
+                    }
+                    None => self.replace_invalid(listener),
+                }
+            }
+            return;
+        }
+        if !self.parser_state.use_utf8 || (byte.is_ascii() && self.partial.is_empty()) {
+            self.advance(listener, char::from(byte).encode_utf8(&mut buffer));
+            return;
+        }
+
+        let mut bytes = core::mem::take(&mut self.partial);
+        bytes.push(byte);
+        match core::str::from_utf8(&bytes) {
+            Ok(char) => {
+                self.advance(listener, char);
+                bytes.clear();
+                self.partial = bytes;
+            }
+            Err(error) => match error.error_len() {
+                // Wait for the rest of the sequence.
+                None => self.partial = bytes,
+                Some(len) => {
+                    self.replace_invalid(listener);
+                    for &byte in &bytes[len..] {
+                        self.advance_byte(listener, byte);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Passes the replacement for undecodable input to `listener`, if
+    /// the policy has one.
+    fn replace_invalid<T: ParserListener + ?Sized>(&mut self, listener: &mut T) {
+        if let Some(replacement) = self.parser_state.replacement_policy.replacement() {
+            let mut buffer = [0; 4];
+            self.advance(listener, replacement.encode_utf8(&mut buffer));
+        }
+    }
+
+    pub fn set_use_utf8(&mut self, use_utf8: bool) {
+        self.parser_state.use_utf8 = use_utf8;
+    }
+
+    /// Choose what `feed_bytes` passes on for input which can't be
+    /// decoded, U+FFFD by default.
+    pub fn set_replacement_policy(&mut self, policy: ReplacementPolicy) {
+        self.parser_state.replacement_policy = policy;
+    }
+
+    /// Enable or disable strict mode, in which unknown sequences,
+    /// invalid SGR parameters and interrupted sequences are recorded as
+    /// errors, see `take_errors`. Parsing itself is not affected.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.parser_state.strict = strict;
+    }
+
+    /// Removes and returns the errors recorded in strict mode.
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        core::mem::take(&mut self.parser_state.errors)
+    }
+
+    /// Limit the number of CSI parameters, extra ones are dropped.
+    /// Defaults to 32.
+    pub fn set_max_params(&mut self, max_params: usize) {
+        self.parser_state.max_params = max_params;
+    }
+
+    /// Limit the length in bytes of OSC, DCS, APC and PM strings. A
+    /// longer string is dropped, along with the rest of it up to its
+    /// terminator. Defaults to 8192.
+    pub fn set_max_string_length(&mut self, max_string_length: usize) {
+        self.parser_state.max_string_length = max_string_length;
+    }
+
+    /// Enable or disable recognition of single byte C1 control codes
+    /// (0x80-0x9F). When disabled they are passed to `draw` as data.
+    /// Enabled by default.
+    pub fn set_accept_c1(&mut self, accept_c1: bool) {
+        self.parser_state.accept_c1 = accept_c1;
+    }
+
+    fn advance<T: ParserListener + ?Sized>(&mut self, listener: &mut T, char: &str) {
+        if !self.printer_pending.is_empty()
+            || matches!(self.state, State::Ground) && listener.printer_controller_mode()
+        {
+            self.print(listener, char);
+            return;
+        }
+        match core::mem::replace(&mut self.state, State::Ground) {
+            State::Ground => self.ground(listener, char),
+            // A sequence interrupted by the start of another is dropped,
+            // and the new one is parsed from the ground state.
+            State::Escape
+            | State::EscapeHash
+            | State::EscapePercent
+            | State::Charset(_)
+            | State::Csi(_)
+                if self.starts_sequence(char) =>
+            {
+                self.parser_state
+                    .error(ParseError::Truncated(char.to_owned()));
+                self.ground(listener, char);
+            }
+            State::Escape => self.escape(listener, char),
+            State::EscapeHash => {
+                if char == DECALN {
+                    listener.alignment_display();
+                }
+            }
+            State::EscapePercent => self.parser_state.select_other_charset(char),
+            State::Charset(mode) => {
+                // Like xterm, line drawing and ASCII can still be
+                // designated in UTF-8 mode, they don't touch the upper
+                // half which UTF-8 text may use.
+                if !self.parser_state.use_utf8 || char == "0" || char == "B" {
+                    listener.define_charset(char, &mode);
+                }
+            }
+            State::Csi(csi) => self.csi(listener, csi, char),
+            State::CsiIgnore => {
+                if self.starts_sequence(char) || char == CAN || char == SUB {
+                    self.ground(listener, char);
+                } else if !matches!(char.as_bytes(), [0x40..=0x7E]) {
+                    self.state = State::CsiIgnore;
+                }
+            }
+            State::OscStart => {
+                // Reset palette is not implemented.
+                if char != "R" && char != "p" {
+                    self.control_string(listener, OSC, String::new(), false, char);
+                }
+            }
+            State::ControlString { kind, string, escape } => {
+                self.control_string(listener, kind, string, escape, char)
+            }
+            State::IgnoreString { escape } => self.ignore_string(listener, escape, char),
+        }
+    }
+
+    /// In printer controller mode everything, sequences included, goes
+    /// to the printer through `draw`, up to the `CSI 4 i` ending it.
+    fn print<T: ParserListener + ?Sized>(&mut self, listener: &mut T, char: &str) {
+        let seven_bit = format!("{}[4i", ESC);
+        let eight_bit = format!("{}4i", CSI);
+        let ends = [
+            Some(seven_bit.as_str()),
+            Some(eight_bit.as_str()).filter(|_| self.parser_state.accept_c1),
+        ];
+        let starts_end = |text: &str| ends.iter().flatten().any(|end| end.starts_with(text));
+
+        self.printer_pending.push_str(char);
+        if ends
+            .iter()
+            .flatten()
+            .any(|&end| end == self.printer_pending)
+        {
+            self.printer_pending.clear();
+            listener.csi_dispatch(MC, &[4], None, "");
+        } else if !starts_end(&self.printer_pending) {
+            let pending = core::mem::take(&mut self.printer_pending);
+            // The last character may start the end itself.
+            let (text, last) = pending.split_at(pending.len() - char.len());
+            if !text.is_empty() {
+                listener.draw(text);
+            }
+            if starts_end(last) {
+                self.printer_pending.push_str(last);
+            } else {
+                listener.draw(last);
+            }
+        }
+    }
+
+    /// Returns `true` if `char` starts a sequence of its own: `ESC`, or
+    /// a C1 control code when those are accepted.
+    fn starts_sequence(&self, char: &str) -> bool {
+        char == ESC || (self.parser_state.accept_c1 && c1_final(char).is_some())
+    }
+
+    fn ground<T: ParserListener + ?Sized>(&mut self, listener: &mut T, char: &str) {
+        if let Some(final_char) = c1_final(char) {
+            if self.parser_state.accept_c1 {
+                // An 8-bit C1 code is handled as its `ESC` form.
+                self.escape(listener, &final_char);
+            } else {
+                // In a 7-bit environment 0x80-0x9F are data.
+                listener.draw(char);
+            }
+        } else if char == ESC {
+            self.state = State::Escape;
+        } else if char == NUL || char == DEL {
+            // Padding characters, neither drawn nor dispatched.
+        } else if char == CAN || char == SUB {
+            // A sequence was cancelled, SUB shows where.
+            if char == SUB {
+                listener.draw("\u{FFFD}");
+            }
+        } else if BASIC.contains(&char) {
+            if !((char == SI || char == SO) && self.parser_state.use_utf8) {
+                listener.basic_dispatch(char);
+            }
+        } else {
+            listener.draw(char);
+        }
+    }
+
+    fn escape<T: ParserListener + ?Sized>(&mut self, listener: &mut T, char: &str) {
+        match char {
+            _ if char == CAN || char == SUB => self.ground(listener, char),
+            "[" => self.state = State::Csi(CsiParams::default()),
+            "]" => self.state = State::OscStart,
+            "P" | "^" | "_" => {
+                self.state = State::ControlString {
+                    kind: match char {
+                        "P" => DCS,
+                        "^" => PM,
+                        _ => APC,
+                    },
+                    string: String::new(),
+                    escape: false,
+                }
+            }
+            "#" => self.state = State::EscapeHash,
+            "%" => self.state = State::EscapePercent,
+            "(" | ")" => self.state = State::Charset(char.to_owned()),
+            _ => listener.escape_dispatch(char),
+        }
+    }
+
+    fn csi<T: ParserListener + ?Sized>(
+        &mut self,
+        listener: &mut T,
+        mut csi: CsiParams,
+        char: &str,
+    ) {
+        if matches!(char, "?" | ">" | "=" | "<") {
+            // Only a private marker before the first parameter, anywhere
+            // else the sequence is malformed and dropped up to its final.
+            if csi.params.is_empty()
+                && csi.current.is_empty()
+                && csi.intermediates.is_empty()
+                && csi.private_marker.is_none()
+            {
+                csi.private_marker = char.chars().next();
+            } else {
+                self.parser_state
+                    .error(ParseError::Malformed(char.to_owned()));
+                self.state = State::CsiIgnore;
+                return;
+            }
+        } else if ALLOWED_IN_CSI.contains(&char) {
+            listener.basic_dispatch(char);
+        } else if char == NUL || char == DEL {
+            // Padding, ignored here as well.
+        } else if matches!(char.as_bytes(), [0x20..=0x2F]) {
+            csi.intermediates.push_str(char);
+        } else if char == CAN || char == SUB {
+            self.parser_state
+                .error(ParseError::Truncated(char.to_owned()));
+            self.ground(listener, char);
+            return;
+        } else if char.as_bytes()[0].is_ascii_digit() {
+            // Leading zeros are dropped so they don't count towards the
+            // limit, anything longer is clamped to 9999 anyway.
+            if csi.current == "0" {
+                csi.current.clear();
+            }
+            if csi.current.len() < 5 {
+                csi.current.push_str(char);
+            }
+        } else {
+            let current_param = u64::min(csi.current.parse().unwrap_or_default(), 9999);
+            if csi.params.len() < self.parser_state.max_params {
+                csi.params.push(current_param as u32);
+            }
+            if char == ";" {
+                csi.current.clear();
+            } else if matches!(char.as_bytes(), [0x40..=0x7E]) {
+                self.csi_dispatch(listener, &csi, char);
+                return;
+            } else {
+                // Not a final character, the sequence is dropped up to
+                // its final.
+                self.parser_state
+                    .error(ParseError::Malformed(char.to_owned()));
+                self.state = State::CsiIgnore;
+                return;
+            }
+        }
+        self.state = State::Csi(csi);
+    }
+
+    fn csi_dispatch<T: ParserListener + ?Sized>(
+        &mut self,
+        listener: &mut T,
+        csi: &CsiParams,
+        char: &str,
+    ) {
+        let known = match (csi.private_marker, csi.intermediates.as_str()) {
+            (Some('>' | '<' | '?'), "") if char == KITTY_KEYBOARD => true,
+            (None | Some('?'), "") => CSI_COMMANDS.contains_key(char),
+            (Some('>'), "") => char == DA || char == SGR || char == DSR,
+            (Some('='), "") => char == DA,
+            (_, " ") => char == DECSWBV,
+            (_, "$") => char == DECRQM || char == DECCARA || char == DECRARA,
+            _ => false,
+        };
+        if !known {
+            let marker = csi.private_marker.map(String::from).unwrap_or_default();
+            self.parser_state.error(ParseError::UnknownCsi(format!(
+                "{}{}{}",
+                marker, csi.intermediates, char
+            )));
+        } else if char == SGR && csi.intermediates.is_empty() && !valid_sgr(&csi.params) {
+            self.parser_state
+                .error(ParseError::InvalidSgr(csi.params.clone()));
+        }
+        listener.csi_dispatch(char, &csi.params, csi.private_marker, &csi.intermediates);
+    }
+
+    /// Adds `char` to a control string, dispatching it once terminated by
+    /// `BEL`, `ST` or `ESC \`. A string cut short is dropped and the
+    /// characters which ended it are processed from the ground state.
+    fn control_string<T: ParserListener + ?Sized>(
+        &mut self,
+        listener: &mut T,
+        kind: &'static str,
+        mut string: String,
+        escape: bool,
+        char: &str,
+    ) {
+        if escape {
+            if char == "\\" {
+                self.string_dispatch(listener, kind, &string);
+            } else {
+                self.parser_state
+                    .error(ParseError::Truncated(format!("{}{}", ESC, char)));
+                self.ground(listener, ESC);
+                self.advance(listener, char);
+            }
+        } else if char == ESC {
+            self.state = State::ControlString { kind, string, escape: true };
+        } else if OSC_TERMINATORS.contains(&char) {
+            self.string_dispatch(listener, kind, &string);
+        } else if char == CAN || char == SUB {
+            self.parser_state
+                .error(ParseError::Truncated(char.to_owned()));
+            self.ground(listener, char);
+        } else if string.len() + char.len() > self.parser_state.max_string_length {
+            self.parser_state.error(ParseError::TooLong);
+            self.state = State::IgnoreString { escape: false };
+        } else {
+            string.push_str(char);
+            self.state = State::ControlString { kind, string, escape: false };
+        }
+    }
+
+    /// Throws `char` away unless it ends the string: `BEL`, `ST`, `ESC \`,
+    /// `CAN` or `SUB`. An `ESC` starting another sequence ends it too.
+    fn ignore_string<T: ParserListener + ?Sized>(
+        &mut self,
+        listener: &mut T,
+        escape: bool,
+        char: &str,
+    ) {
+        if escape {
+            if char != "\\" {
+                self.ground(listener, ESC);
+                self.advance(listener, char);
+            }
+        } else if char == ESC {
+            self.state = State::IgnoreString { escape: true };
+        } else if char == CAN || char == SUB {
+            self.ground(listener, char);
+        } else if !OSC_TERMINATORS.contains(&char) {
+            self.state = State::IgnoreString { escape: false };
+        }
+    }
+
+    fn string_dispatch<T: ParserListener + ?Sized>(
+        &mut self,
+        listener: &mut T,
+        kind: &str,
+        string: &str,
+    ) {
+        if kind == DCS {
+            listener.dcs_dispatch(string);
+        } else if kind == APC {
+            listener.apc_dispatch(string);
+        } else if kind == OSC {
+            // Each further value sets the next color, e.g. `OSC 10 ; fg ;
+            // bg` sets both the foreground and the background.
+            if let Some((code @ ("10" | "11" | "12"), values)) = string.split_once(';') {
+                let first = code.parse::<u32>().unwrap_or_default();
+                for (code, value) in (first..=12).zip(values.split(';')) {
+                    listener.set_dynamic_color(code, value);
+                }
+                return;
+            }
+            // `OSC 110`, `OSC 111` and `OSC 112` restore them.
+            let code = string.split(';').next().unwrap_or_default();
+            if let "110" | "111" | "112" = code {
+                listener.reset_dynamic_color(code.parse::<u32>().unwrap_or_default() - 100);
+                return;
+            }
+
+            let mut chars = string.chars();
+            let code = chars.next().map(String::from).unwrap_or_default();
+            let param = chars.skip(1).collect::<String>();
+
+            if code.is_empty() {
+                return;
+            }
+            if "01".contains(&code) {
+                listener.set_icon_name(&param);
+            }
+            if "02".contains(&code) {
+                listener.set_title(&param);
+            }
+            if code == "8" {
+                let (params, uri) = param.split_once(';').unwrap_or(("", &param));
+                listener.set_hyperlink(params, uri);
+            }
+        }
+        // Privacy messages are swallowed.
+    }
 }
+
+/// A parser driving a listener shared behind `Arc<Mutex<_>>`, which is
+/// locked for the duration of each `feed`. See `StreamParser` for a
+/// parser which borrows the listener instead, which also works without
+/// the `std` feature.
+#[cfg(feature = "std")]
 pub struct Parser<'a, T>
 where
     T: ParserListener + Send + 'a,
 {
-    parser_fsm: Generator<'a, String, Option<bool>>,
-    parser_state: Arc<Mutex<ParserState>>,
-    taking_plain_text: bool,
+    stream: StreamParser,
     listener: Arc<Mutex<T>>,
+    _marker: PhantomData<&'a ()>,
 }
 
+#[cfg(feature = "std")]
 impl<'a, T> Parser<'a, T>
 where
     T: ParserListener + Send + 'a,
 {
     pub fn new(listener: Arc<Mutex<T>>) -> Self {
-        let parser_state = Arc::new(Mutex::new(ParserState { use_utf8: true }));
-        let parser_state_cloned = parser_state.clone();
-        let mut a = Self {
-            listener: listener.clone(),
-            taking_plain_text: true,
-            parser_fsm: Gn::<String>::new_scoped(move |mut co| {
-                loop {
-                    let mut char = co.yield_(Some(true)).unwrap_or_default();
-                    if ESC == char {
-                        char = co.yield_(None).unwrap_or_default();
-                        if char == "[" {
-                            char = CSI.to_owned();
-                        } else if char == "]" {
-                            char = OSC.to_owned();
-                        } else {
-                            if char == "#" {
-                                if co.yield_(None).unwrap_or_default() == DECALN {
-                                    listener.lock().unwrap().alignment_display();
-                                } else {
-                                    println!("unexpected escape character");
-                                }
-                            } else if char == "%" {
-                                // self.select_other_charset(yield_!(None));
-                            } else if "()".contains(&char) {
-                                let _code = co.yield_(None);
-                                if parser_state_cloned.lock().unwrap().use_utf8 {
-                                    continue;
-                                } else {
-                                    // listener.lock().unwrap().define_charset(code, char);
-                                }
-                            } else {
-                                listener.lock().unwrap().escape_dispatch(&char);
-                            }
-                            continue;
-                        }
-                    }
-                    if BASIC.iter().any(|cf| *cf == char) {
-                        println!("basic dispatch");
-                        if (char == SI || char == SO)
-                            && parser_state_cloned.lock().unwrap().use_utf8
-                        {
-                            continue;
-                        } else {
-                            listener.lock().unwrap().basic_dispatch(&char);
-                        }
-                    } else if char == CSI {
-                        let mut params: Vec<u32> = vec![];
-                        let mut private: bool = false;
-                        let mut current: String = "".to_owned();
-                        loop {
-                            char = co.yield_(None).unwrap_or_default();
-                            if char == "?" {
-                                private = true;
-                            } else if ALLOWED_IN_CSI.iter().any(|cf| *cf == char) {
-                                listener.lock().unwrap().basic_dispatch(&char);
-                            } else if char == SP || char == GREATER {
-                            } else if char == CAN || char == SUB {
-                                listener.lock().unwrap().draw(&char);
-                                break;
-                            } else if char.chars().next().unwrap().is_ascii_digit() {
-                                current.push(char.chars().next().unwrap());
-                            } else if char == "$" {
-                                co.yield_(None);
-                                break;
-                            } else {
-                                let mut current_param = match current.parse::<u64>() {
-                                    Ok(val) => val,
-                                    _ => 0,
-                                };
-                                current_param = u64::min(current_param, 9999);
-                                params.push(current_param as u32);
-                                if char == ";" {
-                                    current = "".to_owned();
-                                } else {
-                                    if private {
-                                        listener.lock().unwrap().csi_dispatch(
-                                            &char,
-                                            &params[..],
-                                            true,
-                                        );
-                                    } else {
-                                        listener.lock().unwrap().csi_dispatch(
-                                            &char,
-                                            &params[..],
-                                            false,
-                                        );
-                                    }
-                                    break;
-                                }
-                            }
-                        }
-                    } else if char == OSC {
-                        let code = co.yield_(None).unwrap_or_default();
-                        if code == "R" || code == "p" {
-                            continue; // reset palette not implemented
-                        }
-                        let mut param = "".to_owned();
-
-                        'param_loop: loop {
-                            let mut accu = co.yield_(None).unwrap_or_default();
-                            if accu == ESC {
-                                accu.push_str(&co.yield_(None).unwrap_or_default());
-                            }
-
-                            if OSC_TERMINATORS.contains(&accu.as_str()) {
-                                break 'param_loop;
-                            } else {
-                                param.push(accu.chars().next().unwrap());
-                            }
-                        }
-
-                        param = param.chars().skip(1).take(param.len() - 1).collect();
+        Self {
+            stream: StreamParser::new(),
+            listener,
+            _marker: PhantomData,
+        }
+    }
 
-                        if "01".contains(&code) {
-                            listener.lock().unwrap().set_icon_name(&param);
-                        }
-                        if "02".contains(&code) {
-                            listener.lock().unwrap().set_title(&param);
-                        }
-                    }
-                }
-            }),
-            parser_state,
-        };
+    /// Creates a parser decoding `feed_bytes` input with `encoding`, see
+    /// `StreamParser::set_encoding`.
+    pub fn new_with_encoding(listener: Arc<Mutex<T>>, encoding: &'static Encoding) -> Self {
+        Self {
+            stream: StreamParser::with_encoding(encoding),
+            listener,
+            _marker: PhantomData,
+        }
+    }
 
-        a.parser_fsm.send("".to_owned());
-        a
+    /// Decode `feed_bytes` input with `encoding`, see
+    /// `StreamParser::set_encoding`.
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.stream.set_encoding(encoding);
     }
 
     pub fn is_special_start(s: &str) -> bool {
-        SPECIAL.iter().any(|special| s.starts_with(special))
+        SPECIAL.iter().any(|special| s.starts_with(special)) || c1_final(s).is_some()
     }
 
-    pub fn feed(&mut self, data: String) {
-        for c in data.chars() {
-            let char_str = c.to_string();
+    /// Parse `data`, returns the number of bytes consumed, see
+    /// `StreamParser::feed`.
+    pub fn feed(&mut self, data: String) -> usize {
+        let mut listener = self.listener.lock().unwrap();
+        self.stream.feed(&mut *listener, &data)
+    }
 
-            // If we're in plain text mode and this is a special character
-            if self.taking_plain_text && Self::is_special_start(&char_str) {
-                self.taking_plain_text = false;
-            }
+    /// Returns `true` when the parser is between sequences, see
+    /// `StreamParser::in_ground_state`.
+    pub fn in_ground_state(&self) -> bool {
+        self.stream.in_ground_state()
+    }
 
-            if self.taking_plain_text {
-                // Feed plain text directly to listener
-                self.listener.lock().unwrap().draw(&char_str);
-            } else {
-                // Feed to parser FSM and update taking_plain_text state
-                self.taking_plain_text = self.parser_fsm.send(char_str).unwrap_or(false);
-            }
-        }
+    /// Parse `data`, returns whether it ended outside of any sequence,
+    /// see `StreamParser::feed_complete`.
+    pub fn feed_complete(&mut self, data: &str) -> bool {
+        let mut listener = self.listener.lock().unwrap();
+        self.stream.feed_complete(&mut *listener, data)
+    }
+
+    /// Parse `data` up to and including the first sequence matching
+    /// `predicate`, see `StreamParser::feed_until`.
+    pub fn feed_until(&mut self, data: &str, predicate: impl FnMut(&str) -> bool) -> usize {
+        let mut listener = self.listener.lock().unwrap();
+        self.stream.feed_until(&mut *listener, data, predicate)
+    }
+
+    /// Like `feed`, but takes undecoded input, see
+    /// `StreamParser::feed_bytes`.
+    pub fn feed_bytes(&mut self, data: &[u8]) -> usize {
+        let mut listener = self.listener.lock().unwrap();
+        self.stream.feed_bytes(&mut *listener, data)
     }
 
     pub fn set_use_utf8(&mut self, use_utf8: bool) {
-        self.parser_state.lock().unwrap().use_utf8 = use_utf8;
+        self.stream.set_use_utf8(use_utf8);
+    }
+
+    /// Choose what `feed_bytes` passes on for input which can't be
+    /// decoded, see `StreamParser::set_replacement_policy`.
+    pub fn set_replacement_policy(&mut self, policy: ReplacementPolicy) {
+        self.stream.set_replacement_policy(policy);
+    }
+
+    /// Enable or disable strict mode, in which unknown sequences,
+    /// invalid SGR parameters and interrupted sequences are recorded as
+    /// errors, see `take_errors`. Parsing itself is not affected.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.stream.set_strict(strict);
+    }
+
+    /// Removes and returns the errors recorded in strict mode.
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        self.stream.take_errors()
+    }
+
+    /// Limit the number of CSI parameters, extra ones are dropped.
+    /// Defaults to 32.
+    pub fn set_max_params(&mut self, max_params: usize) {
+        self.stream.set_max_params(max_params);
+    }
+
+    /// Limit the length in bytes of OSC, DCS, APC and PM strings. A
+    /// longer string is dropped, along with the rest of it up to its
+    /// terminator. Defaults to 8192.
+    pub fn set_max_string_length(&mut self, max_string_length: usize) {
+        self.stream.set_max_string_length(max_string_length);
+    }
+
+    /// Enable or disable recognition of single byte C1 control codes
+    /// (0x80-0x9F). When disabled they are passed to `draw` as data.
+    /// Enabled by default.
+    pub fn set_accept_c1(&mut self, accept_c1: bool) {
+        self.stream.set_accept_c1(accept_c1);
     }
 }
 
-// fn select_other_charset(&self, input: &str) {}
+/// Returns `false` if the SGR parameters contain an unknown attribute
+/// or a color out of range, e.g. `38;5;300`.
+fn valid_sgr(params: &[u32]) -> bool {
+    let mut params = params.iter();
+    while let Some(&attr) = params.next() {
+        if attr == FG_256 || attr == BG_256 {
+            let valid = match params.next() {
+                Some(5) => params.next().is_some_and(|&n| n <= 255),
+                Some(2) => (0..3).all(|_| params.next().is_some_and(|&n| n <= 255)),
+                _ => false,
+            };
+            if !valid {
+                return false;
+            }
+        } else if attr != 0
+            && ![&*TEXT, &*FG_ANSI, &*BG_ANSI, &*FG_AIXTERM, &*BG_AIXTERM]
+                .iter()
+                .any(|map| map.contains_key(&attr))
+        {
+            return false;
+        }
+    }
+    true
+}
 
-#[cfg(test)]
+/// Returns the `ESC` final equivalent to an 8-bit C1 control code, e.g.
+/// `"D"` for IND (0x84).
+fn c1_final(char: &str) -> Option<String> {
+    let mut chars = char.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c @ '\u{0080}'..='\u{009F}'), None) => {
+            char::from_u32(c as u32 - 0x40).map(String::from)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod test {
     use std::sync::{Arc, Mutex};
 
-    use super::{Parser, CSI_COMMANDS, DECRC, DECSC, ESC, HTS, IND, NEL, OSC, RI, RIS, ST, ST_C0};
+    use super::{
+        Parser,
+        CSI_COMMANDS,
+        DECBI,
+        DECFI,
+        DECID,
+        DECKPAM,
+        DECKPNM,
+        DECRC,
+        DECSC,
+        ESC,
+        HTS,
+        IND,
+        NEL,
+        OSC,
+        RI,
+        RIS,
+        ST,
+        ST_C0,
+    };
     use crate::counter::Counter;
     use crate::debug_screen::DebugScreen;
-    use crate::parser::{CSI, FF, HVP, LF, SI, SO, VT};
+    use crate::parser::{
+        ParseError,
+        StreamParser,
+        APC,
+        BEL,
+        CAN,
+        CSI,
+        DCS,
+        FF,
+        HVP,
+        LF,
+        PM,
+        SI,
+        SO,
+        SUB,
+        VT,
+    };
     use crate::screen::Screen;
 
     #[test]
     fn first_step() {
-        let listener = Arc::new(Mutex::new(DebugScreen {}));
+        let listener = Arc::new(Mutex::new(DebugScreen::new()));
         let mut parser = Parser::new(listener.clone());
         parser.feed(String::default());
         parser.feed(ESC.to_owned());
@@ -209,11 +958,16 @@ mod test {
         let escape_map = vec![
             (RIS, "reset"),
             (IND, "index"),
-            (NEL, "linefeed"),
+            (NEL, "next_line"),
             (RI, "reverse_index"),
             (HTS, "set_tab_stop"),
             (DECSC, "save_cursor"),
             (DECRC, "restore_cursor"),
+            (DECID, "report_device_attributes"),
+            (DECBI, "back_index"),
+            (DECFI, "forward_index"),
+            (DECKPAM, "keypad_application_mode"),
+            (DECKPNM, "keypad_numeric_mode"),
         ];
 
         for (cmd, event) in escape_map {
@@ -412,6 +1166,74 @@ mod test {
         );
     }
 
+    #[test]
+    fn c1_control_codes() {
+        let c1_map = vec![
+            ("\u{84}", "index"),
+            ("\u{85}", "next_line"),
+            ("\u{88}", "set_tab_stop"),
+            ("\u{8D}", "reverse_index"),
+        ];
+
+        for (code, event) in c1_map {
+            let counter = Arc::new(Mutex::new(Counter::new()));
+            let mut parser = Parser::new(counter.clone());
+            parser.feed(code.to_string());
+            assert_eq!(counter.lock().unwrap().get_count(event), 1, "{}", event);
+            assert_eq!(counter.lock().unwrap().get_count("draw"), 0);
+        }
+
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+        parser.feed("\u{9B}2A".to_string());
+        assert_eq!(counter.lock().unwrap().get_count("cursor_up"), 1);
+    }
+
+    #[test]
+    fn c1_control_codes_disabled() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        parser.set_accept_c1(false);
+        parser.feed("\u{9B}2A".to_string());
+        assert_eq!(counter.lock().unwrap().get_count("cursor_up"), 0);
+        assert_eq!(counter.lock().unwrap().get_count("draw"), 3);
+        assert_eq!(
+            counter.lock().unwrap().get_last_string("draw"),
+            Some(&"A".to_string())
+        );
+
+        parser.set_accept_c1(true);
+        parser.feed("\u{9B}2A".to_string());
+        assert_eq!(counter.lock().unwrap().get_count("cursor_up"), 1);
+    }
+
+    #[test]
+    fn brackets_are_text() {
+        let screen = Arc::new(Mutex::new(Screen::new(8, 1)));
+        let mut parser = Parser::new(screen.clone());
+
+        parser.feed("a[b]c\\d".to_string());
+        assert_eq!(
+            screen.lock().unwrap().display(),
+            vec!["a[b]c\\d ".to_string()]
+        );
+    }
+
+    #[test]
+    fn nul_and_del_are_ignored() {
+        let screen = Arc::new(Mutex::new(Screen::new(4, 1)));
+        let mut parser = Parser::new(screen.clone());
+
+        parser.feed("\u{0}\u{7f}".to_string());
+        assert_eq!(screen.lock().unwrap().display(), vec!["    ".to_string()]);
+        assert_eq!(screen.lock().unwrap().cursor.x, 0);
+
+        parser.feed("a\u{0}b\u{7f}c".to_string());
+        assert_eq!(screen.lock().unwrap().display(), vec!["abc ".to_string()]);
+        assert_eq!(screen.lock().unwrap().cursor.x, 3);
+    }
+
     #[test]
     fn set_title_icon_name() {
         let test_cases = vec![
@@ -445,10 +1267,87 @@ mod test {
 
             // e) test ➜ ('\xe2\x9e\x9c') symbol, that contains string terminator \x9c
             parser.feed("➜".to_string());
-            assert_eq!(screen.lock().unwrap().buffer[&0][&0].data, "➜");
+            assert_eq!(screen.lock().unwrap().buffer[0][0].data, "➜");
         }
     }
 
+    #[test]
+    fn osc_terminators() {
+        for st in [ST_C0, ST, BEL] {
+            let screen = Arc::new(Mutex::new(Screen::new(5, 1)));
+            let mut parser = Parser::new(screen.clone());
+
+            parser.feed(format!("{}]2;title{}ab", ESC, st));
+            let screen = screen.lock().unwrap();
+            assert_eq!(screen.title, "title");
+            assert_eq!(screen.display(), vec!["ab   ".to_string()]);
+        }
+
+        // An escape sequence interrupts the string, which is dropped.
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+        parser.feed(format!("{}]2;title{}[2Aab", ESC, ESC));
+        let counter = counter.lock().unwrap();
+        assert_eq!(counter.get_count("set_title"), 0);
+        assert_eq!(counter.get_count("cursor_up"), 1);
+        assert_eq!(counter.get_last_params("cursor_up"), Some(&vec![2]));
+        assert_eq!(counter.get_count("draw"), 2);
+    }
+
+    #[test]
+    fn dcs_is_discarded() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        parser.feed(format!("{}P$qm{}\\ab{}1$r0m{}", ESC, ESC, DCS, BEL));
+        let counter = counter.lock().unwrap();
+        assert_eq!(counter.get_count("dcs_dispatch"), 2);
+        assert_eq!(
+            counter.get_last_string("dcs_dispatch"),
+            Some(&"1$r0m".to_string())
+        );
+        assert_eq!(counter.get_count("draw"), 2);
+
+        let screen = Arc::new(Mutex::new(Screen::new(5, 1)));
+        let mut parser = Parser::new(screen.clone());
+        parser.feed(format!("{}Ptmux;x{}\\ab", ESC, ESC));
+        assert_eq!(screen.lock().unwrap().display(), vec!["ab   ".to_string()]);
+    }
+
+    #[test]
+    fn apc_and_pm_are_discarded() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        parser.feed(format!(
+            "a{}_Gf=100;AAAA{}\\b{}^secret{}c",
+            ESC, ESC, ESC, BEL
+        ));
+        let counter = counter.lock().unwrap();
+        assert_eq!(counter.get_count("apc_dispatch"), 1);
+        assert_eq!(
+            counter.get_last_string("apc_dispatch"),
+            Some(&"Gf=100;AAAA".to_string())
+        );
+        assert_eq!(counter.get_count("draw"), 3);
+
+        let screen = Arc::new(Mutex::new(Screen::new(5, 1)));
+        let mut parser = Parser::new(screen.clone());
+        parser.feed(format!("a{}Gi=1{}b{}1;2{}c", APC, ST, PM, ST));
+        assert_eq!(screen.lock().unwrap().display(), vec!["abc  ".to_string()]);
+    }
+
+    #[test]
+    fn osc_without_parameters() {
+        let screen = Arc::new(Mutex::new(Screen::new(5, 1)));
+        let mut parser = Parser::new(screen.clone());
+
+        parser.feed(format!("{}]{}{}]2{}ab", ESC, BEL, ESC, BEL));
+        let screen = screen.lock().unwrap();
+        assert_eq!(screen.title, "");
+        assert_eq!(screen.display(), vec!["ab   ".to_string()]);
+    }
+
     #[test]
     fn define_charset() {
         // Should be a noop. All input is UTF8.
@@ -460,6 +1359,63 @@ mod test {
         assert_eq!(screen.lock().unwrap().display()[0], "   ".to_string());
     }
 
+    #[test]
+    fn define_charset_non_utf8() {
+        let screen = Arc::new(Mutex::new(Screen::new(3, 3)));
+        let mut parser = Parser::new(screen.clone());
+        parser.set_use_utf8(false);
+
+        // Line drawing as G1, then shift out to it.
+        parser.feed(format!("{})0{}lqk{}x", ESC, SO, SI));
+        assert_eq!(screen.lock().unwrap().display()[0], "┌─┐".to_string());
+        assert_eq!(screen.lock().unwrap().display()[1], "x  ".to_string());
+
+        // And as G0 until switched back to ASCII.
+        parser.feed(format!("\r\n{}(0mq{}(Bq", ESC, ESC));
+        assert_eq!(screen.lock().unwrap().display()[2], "└─q".to_string());
+    }
+
+    #[test]
+    fn line_drawing_utf8() {
+        let screen = Arc::new(Mutex::new(Screen::new(4, 3)));
+        let mut parser = Parser::new(screen.clone());
+
+        parser.feed(format!("{}(0lqqk\r\nx  x\r\nmqqj{}(B", ESC, ESC));
+        assert_eq!(
+            screen.lock().unwrap().display(),
+            vec!["┌──┐".to_string(), "│  │".to_string(), "└──┘".to_string()]
+        );
+
+        // Back to ASCII, with UTF-8 text untouched by the designation.
+        parser.feed(format!("\x1b[Hq\u{e9}{}(0\u{e9}", ESC));
+        assert_eq!(
+            screen.lock().unwrap().display()[0],
+            "q\u{e9}\u{e9}┐".to_string()
+        );
+    }
+
+    #[test]
+    fn select_other_charset() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        // In UTF-8 mode only line drawing and ASCII may be designated.
+        parser.feed(format!("{}(U", ESC));
+        assert_eq!(counter.lock().unwrap().get_count("define_charset"), 0);
+
+        // ESC % @ leaves UTF-8 mode.
+        parser.feed(format!("{}%@{}(U{}", ESC, ESC, SO));
+        assert_eq!(counter.lock().unwrap().get_count("define_charset"), 1);
+        assert_eq!(counter.lock().unwrap().get_count("shift_out"), 1);
+
+        // ESC % G and ESC % 8 enter it again.
+        for code in ["G", "8"] {
+            parser.feed(format!("{}%@{}%{}{}(U{}", ESC, ESC, code, ESC, SO));
+            assert_eq!(counter.lock().unwrap().get_count("define_charset"), 1);
+            assert_eq!(counter.lock().unwrap().get_count("shift_out"), 1);
+        }
+    }
+
     #[test]
     fn test_non_utf8_shifts() {
         let counter = Arc::new(Mutex::new(Counter::new()));
@@ -477,6 +1433,282 @@ mod test {
         assert_eq!(counter.lock().unwrap().get_count("shift_out"), 1);
     }
 
+    #[test]
+    fn warning_bell_volume() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        // CSI 4 SP t is DECSWBV, not a window manipulation request.
+        parser.feed(format!("{}4 t", CSI));
+
+        let counter_lock = counter.lock().unwrap();
+        assert_eq!(counter_lock.get_count("set_warning_bell_volume"), 1);
+        assert_eq!(
+            counter_lock.get_last_params("set_warning_bell_volume"),
+            Some(&vec![4])
+        );
+        for (name, count) in counter_lock.counts.iter() {
+            if name != &"set_warning_bell_volume" {
+                assert_eq!(*count, 0, "Unexpected handler {} was called", name);
+            }
+        }
+    }
+
+    #[test]
+    fn report_mode() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        parser.feed(format!("{}?25$p", CSI));
+        assert_eq!(counter.lock().unwrap().get_count("report_mode"), 1);
+        assert_eq!(
+            counter.lock().unwrap().get_last_params("report_mode"),
+            Some(&vec![25])
+        );
+        assert_eq!(counter.lock().unwrap().get_last_private(), Some(true));
+
+        parser.feed(format!("{}4$p", CSI));
+        assert_eq!(counter.lock().unwrap().get_count("report_mode"), 2);
+        assert_eq!(
+            counter.lock().unwrap().get_last_params("report_mode"),
+            Some(&vec![4])
+        );
+        assert_eq!(counter.lock().unwrap().get_last_private(), Some(false));
+    }
+
+    #[test]
+    fn resync_after_malformed_sequences() {
+        for garbage in [
+            "\x1b#",
+            "\x1b%",
+            "\x1b(",
+            "\x1b",
+            "\x1b[12;",
+            "\x1b[?1\u{9b}",
+            "\x1b[1\u{e9}",
+            "\x1b[4:3m",
+            "\x1b[4:3",
+            "\x1b[1?h",
+            "\x1b[1;>c",
+        ] {
+            let screen = Arc::new(Mutex::new(Screen::new(4, 2)));
+            let mut parser = Parser::new(screen.clone());
+            parser.set_strict(true);
+            parser.feed("ab\r\ncd".to_string());
+            parser.feed(format!("{}\x1b[2Jx", garbage));
+            assert!(parser.in_ground_state(), "{:?}", garbage);
+            assert_eq!(
+                parser.take_errors().len(),
+                1 + garbage.contains('\u{9b}') as usize,
+                "{:?}",
+                garbage
+            );
+            assert_eq!(
+                screen.lock().unwrap().display(),
+                vec!["    ".to_string(), "  x ".to_string()],
+                "{:?}",
+                garbage
+            );
+        }
+
+        // Colon separated parameters aren't drawn as text.
+        let screen = Arc::new(Mutex::new(Screen::new(4, 1)));
+        let mut parser = Parser::new(screen.clone());
+        parser.feed("a\x1b[4:3mb\x1b[38:2:1:2:3mc".to_string());
+        assert_eq!(screen.lock().unwrap().display(), vec!["abc ".to_string()]);
+        // They are reported as malformed, not as an interruption.
+        parser.set_strict(true);
+        parser.feed("\x1b[4:3m".to_string());
+        assert_eq!(
+            parser.take_errors(),
+            vec![ParseError::Malformed(":".to_string())]
+        );
+
+        // Padding inside a sequence doesn't break it up.
+        let screen = Arc::new(Mutex::new(Screen::new(4, 2)));
+        let mut parser = Parser::new(screen.clone());
+        parser.feed("ab\x1b[2\x00\x7fJ".to_string());
+        assert_eq!(
+            screen.lock().unwrap().display(),
+            vec!["    ".to_string(), "    ".to_string()]
+        );
+    }
+
+    #[test]
+    fn cancel_sequences() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        // CAN aborts a partial CSI, the following bytes parse fresh.
+        parser.feed(format!("{}12{}3A{}2B", CSI, CAN, CSI));
+        {
+            let counter = counter.lock().unwrap();
+            assert_eq!(counter.get_count("cursor_up"), 0);
+            assert_eq!(counter.get_count("cursor_down"), 1);
+            assert_eq!(counter.get_last_params("cursor_down"), Some(&vec![2]));
+            assert_eq!(counter.get_count("draw"), 2);
+            assert_eq!(counter.get_last_string("draw"), Some(&"A".to_string()));
+        }
+
+        // SUB also draws a replacement character, in any state.
+        let screen = Arc::new(Mutex::new(Screen::new(6, 1)));
+        let mut parser = Parser::new(screen.clone());
+        parser.feed(format!(
+            "{}1{}a{}{}b{}]2;t{}c",
+            CSI, SUB, ESC, SUB, ESC, SUB
+        ));
+        let screen = screen.lock().unwrap();
+        assert_eq!(
+            screen.display(),
+            vec!["\u{FFFD}a\u{FFFD}b\u{FFFD}c".to_string()]
+        );
+        assert_eq!(screen.title, "");
+    }
+
+    #[test]
+    fn strict_mode() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        // Nothing is recorded by default.
+        parser.feed(format!("{}5y{}48;5;100500m", CSI, CSI));
+        assert!(parser.take_errors().is_empty());
+
+        parser.set_strict(true);
+        parser.feed(format!("{}5y{}48;5;100500m{}1;31m", CSI, CSI, CSI));
+        parser.feed(format!("{}]2;title{}[A{}12", ESC, ESC, CSI));
+        parser.feed(CAN.to_string());
+        assert_eq!(
+            parser.take_errors(),
+            vec![
+                ParseError::UnknownCsi("y".to_string()),
+                ParseError::InvalidSgr(vec![48, 5, 9999]),
+                ParseError::Truncated(format!("{}[", ESC)),
+                ParseError::Truncated(CAN.to_string()),
+            ]
+        );
+        assert!(parser.take_errors().is_empty());
+
+        // Sequences are still handled as in non-strict mode.
+        assert_eq!(
+            counter
+                .lock()
+                .unwrap()
+                .get_count("select_graphic_rendition"),
+            3
+        );
+        assert_eq!(counter.lock().unwrap().get_count("cursor_up"), 1);
+    }
+
+    #[test]
+    fn empty_sgr_parameters() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        for (params, expected) in [
+            ("", vec![0]),
+            (";31", vec![0, 31]),
+            ("1;", vec![1, 0]),
+            (";", vec![0, 0]),
+            ("1;;4", vec![1, 0, 4]),
+        ] {
+            parser.feed(format!("{}{}m", CSI, params));
+            assert_eq!(
+                counter
+                    .lock()
+                    .unwrap()
+                    .get_last_params("select_graphic_rendition"),
+                Some(&expected),
+                "{:?}",
+                params
+            );
+        }
+    }
+
+    #[test]
+    fn long_parameters() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        for (params, expected) in [
+            ("007", vec![7]),
+            ("00031", vec![31]),
+            ("0000000031;0", vec![31, 0]),
+            (&"9".repeat(50), vec![9999]),
+            (&"0".repeat(50), vec![0]),
+        ] {
+            parser.feed(format!("{}{}m", CSI, params));
+            assert_eq!(
+                counter
+                    .lock()
+                    .unwrap()
+                    .get_last_params("select_graphic_rendition"),
+                Some(&expected),
+                "{:?}",
+                params
+            );
+        }
+    }
+
+    #[test]
+    fn parameter_limit() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+
+        parser.feed(format!("{}{}m", CSI, "1;".repeat(10000)));
+        assert_eq!(
+            counter
+                .lock()
+                .unwrap()
+                .get_last_params("select_graphic_rendition")
+                .map(Vec::len),
+            Some(32)
+        );
+
+        parser.set_max_params(2);
+        parser.feed(format!("{}3;4;5{}", CSI, HVP));
+        assert_eq!(
+            counter.lock().unwrap().get_last_params("cursor_position"),
+            Some(&vec![3, 4])
+        );
+
+        parser.feed(format!("{}{}A", CSI, "9".repeat(10000)));
+        assert_eq!(
+            counter.lock().unwrap().get_last_params("cursor_up"),
+            Some(&vec![9999])
+        );
+    }
+
+    #[test]
+    fn string_length_limit() {
+        let screen = Arc::new(Mutex::new(Screen::new(5, 1)));
+        let mut parser = Parser::new(screen.clone());
+        parser.set_strict(true);
+        parser.set_max_string_length(8);
+
+        parser.feed(format!("{}]2;123456{}", ESC, BEL));
+        assert_eq!(screen.lock().unwrap().title, "123456");
+
+        // The string is dropped, the rest of it isn't drawn.
+        parser.feed(format!("{}]2;1234567{}", ESC, BEL));
+        assert_eq!(screen.lock().unwrap().title, "123456");
+        assert_eq!(screen.lock().unwrap().display(), vec!["     ".to_string()]);
+        assert_eq!(parser.take_errors(), vec![ParseError::TooLong]);
+
+        // Nor is the rest of a string ended by `ST`, text after it is.
+        parser.feed(format!("{}]2;123456789{}\\ab", ESC, ESC));
+        assert_eq!(screen.lock().unwrap().title, "123456");
+        assert_eq!(screen.lock().unwrap().display(), vec!["ab   ".to_string()]);
+        assert_eq!(parser.take_errors(), vec![ParseError::TooLong]);
+
+        // An unterminated string doesn't grow without bound.
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+        parser.feed(format!("{}P{}", ESC, "x".repeat(10000)));
+        assert_eq!(counter.lock().unwrap().get_count("dcs_dispatch"), 0);
+        assert_eq!(counter.lock().unwrap().get_count("draw"), 0);
+    }
+
     #[test]
     fn test_dollar_skip() {
         let counter = Arc::new(Mutex::new(Counter::new()));
@@ -493,5 +1725,288 @@ mod test {
 
         // Check that draw still wasn't called
         assert_eq!(counter.lock().unwrap().get_count("draw"), 0);
+
+        // Only DECRQM is dispatched, with the first parameter as the mode.
+        assert_eq!(counter.lock().unwrap().get_count("report_mode"), 1);
+        assert_eq!(
+            counter.lock().unwrap().get_last_params("report_mode"),
+            Some(&vec![12])
+        );
+    }
+
+    #[test]
+    fn csi_private_markers() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+        parser.set_strict(true);
+
+        parser.feed(format!("{}>c{}=1c", CSI, CSI));
+        {
+            let counter = counter.lock().unwrap();
+            assert_eq!(counter.get_count("report_device_attributes"), 0);
+            assert_eq!(counter.get_count("report_secondary_device_attributes"), 1);
+            assert_eq!(
+                counter.get_last_params("report_secondary_device_attributes"),
+                Some(&vec![0])
+            );
+            assert_eq!(counter.get_count("report_tertiary_device_attributes"), 1);
+            assert_eq!(
+                counter.get_last_params("report_tertiary_device_attributes"),
+                Some(&vec![1])
+            );
+        }
+
+        // modifyOtherKeys isn't SGR 4;1, XTVERSION isn't handled.
+        parser.feed(format!("{}>4;1m{}>q", CSI, CSI));
+        {
+            let counter = counter.lock().unwrap();
+            assert_eq!(counter.get_count("select_graphic_rendition"), 0);
+            assert_eq!(counter.get_count("set_modify_other_keys"), 1);
+            assert_eq!(
+                counter.get_last_params("set_modify_other_keys"),
+                Some(&vec![1])
+            );
+        }
+        assert_eq!(
+            parser.take_errors(),
+            vec![ParseError::UnknownCsi(">q".to_string())]
+        );
+
+        // The kitty keyboard protocol.
+        parser.feed(format!("{}>5u{}<u{}?u", CSI, CSI, CSI));
+        {
+            let counter = counter.lock().unwrap();
+            assert_eq!(
+                counter.get_last_params("push_keyboard_flags"),
+                Some(&vec![5])
+            );
+            assert_eq!(
+                counter.get_last_params("pop_keyboard_flags"),
+                Some(&vec![0])
+            );
+            assert_eq!(counter.get_count("report_keyboard_flags"), 1);
+        }
+        assert!(parser.take_errors().is_empty());
+
+        parser.feed(format!("{}c", CSI));
+        assert_eq!(
+            counter
+                .lock()
+                .unwrap()
+                .get_count("report_device_attributes"),
+            1
+        );
+
+        // Only a marker before the first parameter, the rest is dropped.
+        parser.feed(format!("{}25?l{}1;?2J{}??5h", CSI, CSI, CSI));
+        {
+            let counter = counter.lock().unwrap();
+            assert_eq!(counter.get_count("reset_mode"), 0);
+            assert_eq!(counter.get_count("erase_in_display"), 0);
+            assert_eq!(counter.get_count("set_mode"), 0);
+        }
+        assert_eq!(
+            parser.take_errors(),
+            vec![
+                ParseError::Malformed("?".to_string()),
+                ParseError::Malformed("?".to_string()),
+                ParseError::Malformed("?".to_string()),
+            ]
+        );
+        assert!(parser.in_ground_state());
+    }
+
+    #[test]
+    fn csi_intermediates() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+        parser.set_strict(true);
+
+        // DECSTR, DECSCA, DECIC and DECSACE: the final isn't drawn and
+        // isn't mistaken for the sequence without intermediates.
+        parser.feed(format!("{}!p{}1\"q{}2'}}{}2*x", CSI, CSI, CSI, CSI));
+        {
+            let counter = counter.lock().unwrap();
+            for (name, count) in counter.counts.iter() {
+                assert_eq!(*count, 0, "Unexpected handler {} was called", name);
+            }
+        }
+        assert_eq!(
+            parser.take_errors(),
+            vec![
+                ParseError::UnknownCsi("!p".to_string()),
+                ParseError::UnknownCsi("\"q".to_string()),
+                ParseError::UnknownCsi("'}".to_string()),
+                ParseError::UnknownCsi("*x".to_string()),
+            ]
+        );
+
+        assert_eq!(counter.lock().unwrap().get_last_intermediates(), Some("*"));
+
+        // Parameters before a `$` are kept.
+        parser.feed(format!("{}?1;2$p", CSI));
+        assert_eq!(counter.lock().unwrap().get_count("report_mode"), 1);
+        assert_eq!(
+            counter.lock().unwrap().get_last_params("report_mode"),
+            Some(&vec![1])
+        );
+        assert_eq!(counter.lock().unwrap().get_last_intermediates(), Some("$"));
+        parser.feed(format!("{}2A", CSI));
+        assert_eq!(counter.lock().unwrap().get_last_intermediates(), Some(""));
+    }
+
+    #[test]
+    fn stream_parser_borrows_listener() {
+        let mut counter = Counter::new();
+        let mut parser = StreamParser::new();
+
+        // A sequence split across feeds is completed by the next one.
+        parser.feed(&mut counter, &format!("{}[5", ESC));
+        assert_eq!(counter.get_count("cursor_down"), 0);
+        parser.feed(&mut counter, "B");
+        assert_eq!(counter.get_count("cursor_down"), 1);
+        assert_eq!(counter.last_params["cursor_down"], vec![5]);
+
+        // The listener is usable between feeds.
+        counter.counts.clear();
+        parser.feed(&mut counter, &format!("{}]2;title{}", ESC, BEL));
+        assert_eq!(counter.get_count("set_title"), 1);
+
+        let mut screen = Screen::new(10, 2);
+        parser.feed(&mut screen, &format!("ab{}[2;3Hc", ESC));
+        assert_eq!(screen.display(), vec!["ab        ", "  c       "]);
+    }
+
+    #[test]
+    fn feed_bytes() {
+        let screen = Arc::new(Mutex::new(Screen::new(10, 1)));
+        let mut parser = Parser::new(screen.clone());
+
+        // a) UTF-8, with a character split between feeds
+        parser.feed_bytes(b"a\xc3");
+        parser.feed_bytes(b"\xbc\xe3\x82\xb3");
+        assert_eq!(screen.lock().unwrap().display(), vec!["aüコ      "]);
+
+        // b) invalid sequences
+        parser.feed_bytes(b"\xff\xc3b");
+        assert_eq!(
+            screen.lock().unwrap().display(),
+            vec!["aüコ\u{FFFD}\u{FFFD}b   "]
+        );
+
+        // c) Latin-1 once UTF-8 is turned off
+        parser.feed_bytes(b"\x1b%@\xe9\xfc");
+        assert_eq!(
+            screen.lock().unwrap().display(),
+            vec!["aüコ\u{FFFD}\u{FFFD}béü "]
+        );
+    }
+
+    #[test]
+    fn feed_until() {
+        let screen = Arc::new(Mutex::new(Screen::new(10, 1)));
+        let mut parser = Parser::new(screen.clone());
+        assert_eq!(parser.feed("abc".to_string()), 3);
+        assert_eq!(parser.feed_bytes("ü".as_bytes()), 2);
+
+        let data = "de\x1b[?2026hfg\x1b[?2026lhi";
+        let mut seen = Vec::new();
+        let consumed = parser.feed_until(data, |seq| {
+            seen.push(seq.to_string());
+            seq == "\x1b[?2026l"
+        });
+        assert_eq!(&data[consumed..], "hi");
+        assert_eq!(seen, vec!["d", "e", "\x1b[?2026h", "f", "g", "\x1b[?2026l"]);
+        assert_eq!(screen.lock().unwrap().display(), vec!["abcüdefg  "]);
+
+        // Without a match everything is consumed.
+        assert_eq!(parser.feed_until("hi", |_| false), 2);
+    }
+
+    #[test]
+    fn feed_bytes_with_encoding() {
+        let screen = Arc::new(Mutex::new(Screen::new(12, 1)));
+        let mut parser = Parser::new_with_encoding(screen.clone(), encoding_rs::SHIFT_JIS);
+
+        // "コンニチハ", split in the middle of a character.
+        parser.feed_bytes(b"\x83R\x83\x93\x83");
+        parser.feed_bytes(b"j\x83`\x83n");
+        assert_eq!(screen.lock().unwrap().display(), vec!["コンニチハ  "]);
+
+        // Switching to UTF-8 and back at runtime.
+        parser.feed_bytes(b"\r\x1b%G\xe3\x83\x8f\x1b%@\x83n");
+        assert_eq!(screen.lock().unwrap().display(), vec!["ハハニチハ  "]);
+
+        // GBK
+        let screen = Arc::new(Mutex::new(Screen::new(4, 1)));
+        let mut parser = Parser::new(screen.clone());
+        parser.set_encoding(encoding_rs::GBK);
+        parser.feed_bytes(b"\x9dh\xd7\xd6");
+        assert_eq!(screen.lock().unwrap().display(), vec!["漢字"]);
+    }
+
+    #[test]
+    fn feed_complete() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter.clone());
+        assert!(parser.feed_complete("\x1b[2J"));
+        assert_eq!(counter.lock().unwrap().get_count("erase_in_display"), 1);
+
+        assert!(!parser.feed_complete("\x1b[2"));
+        assert_eq!(counter.lock().unwrap().get_count("erase_in_display"), 1);
+
+        // The rest of the sequence completes it.
+        assert!(parser.feed_complete("J"));
+        assert_eq!(counter.lock().unwrap().get_count("erase_in_display"), 2);
+    }
+
+    #[test]
+    fn in_ground_state() {
+        let counter = Arc::new(Mutex::new(Counter::new()));
+        let mut parser = Parser::new(counter);
+        assert!(parser.in_ground_state());
+
+        parser.feed("\x1b[".to_owned());
+        assert!(!parser.in_ground_state());
+        parser.feed("1m".to_owned());
+        assert!(parser.in_ground_state());
+
+        parser.feed("\x1b]0;title".to_owned());
+        assert!(!parser.in_ground_state());
+        parser.feed("\x07".to_owned());
+        assert!(parser.in_ground_state());
+    }
+
+    #[test]
+    fn set_title_split_across_feeds() {
+        let title = "\x1b]2;split title\x1b\\";
+        // Every way of cutting the sequence in two or three pieces,
+        // through the introducer, the payload and the terminator.
+        for first in 1..title.len() {
+            for second in first..title.len() {
+                let screen = Arc::new(Mutex::new(Screen::new(20, 1)));
+                let mut parser = Parser::new(screen.clone());
+                for chunk in [&title[..first], &title[first..second], &title[second..]] {
+                    parser.feed(chunk.to_owned());
+                }
+                let screen = screen.lock().unwrap();
+                assert_eq!(
+                    screen.title, "split title",
+                    "split at {} and {}",
+                    first, second
+                );
+                assert_eq!(screen.display(), vec![" ".repeat(20)]);
+            }
+        }
+
+        // Terminated by BEL, with a character cut between its bytes.
+        let mut screen = Screen::new(20, 1);
+        let title = "\x1b]0;caf\u{e9}\x07".as_bytes();
+        let cut = title.len() - 2;
+        screen.feed_bytes(&title[..cut]);
+        assert_eq!(screen.title, "");
+        screen.feed_bytes(&title[cut..]);
+        assert_eq!(screen.title, "caf\u{e9}");
+        assert_eq!(screen.icon_name, "caf\u{e9}");
     }
 }
@@ -12,13 +12,25 @@ use crate::control::{
     CUU,
     DA,
     DCH,
+    DECBI,
+    DECCARA,
+    DECFI,
+    DECID,
+    DECKPAM,
+    DECKPNM,
+    DECRARA,
     DECRC,
+    DECRQM,
     DECSC,
+    DECSWBV,
     DL,
+    DSR,
     ECH,
     ED,
     EL,
+    ENQ,
     FF,
+    HPB,
     HPR,
     HT,
     HTS,
@@ -26,19 +38,25 @@ use crate::control::{
     ICH,
     IL,
     IND,
+    KITTY_KEYBOARD,
     LF,
+    MC,
     NEL,
     RI,
     RIS,
     RM,
+    SD,
     SGR,
     SI,
     SM,
     SO,
+    SU,
     TBC,
     VPA,
+    VPB,
     VPR,
     VT,
+    XTWINOPS,
 };
 
 pub trait ParserListener {
@@ -54,12 +72,37 @@ pub trait ParserListener {
     fn shift_out(&mut self);
     fn shift_in(&mut self);
 
+    /// NEL (`ESC E`): a carriage return followed by a line feed.
+    fn next_line(&mut self) {
+        self.cariage_return();
+        self.linefeed();
+    }
+
+    /// DECBI (`ESC 6`): move the cursor one column left, scrolling the
+    /// line right at the left margin. Ignored by default.
+    fn back_index(&mut self) {}
+
+    /// DECFI (`ESC 9`): move the cursor one column right, scrolling the
+    /// line left at the right margin. Ignored by default.
+    fn forward_index(&mut self) {}
+
+    /// DECKPAM (`ESC =`): the keypad sends application sequences.
+    /// Ignored by default.
+    fn keypad_application_mode(&mut self) {}
+
+    /// DECKPNM (`ESC >`): the keypad sends numeric characters. Ignored
+    /// by default.
+    fn keypad_numeric_mode(&mut self) {}
+
     // basic escape code actions
     fn bell(&mut self);
     fn backspace(&mut self);
     fn tab(&mut self);
     fn cariage_return(&mut self);
 
+    /// ENQ: send the answerback message. Ignored by default.
+    fn enquiry(&mut self) {}
+
     fn draw(&mut self, input: &str);
 
     //csi commands
@@ -87,6 +130,97 @@ pub trait ParserListener {
     fn set_title(&mut self, title: &str);
     fn set_icon_name(&mut self, icon_name: &str);
 
+    /// SU (`CSI Ps S`): scroll the scrolling region up by `count` lines.
+    /// Ignored by default.
+    fn scroll_up(&mut self, _count: Option<u32>) {}
+
+    /// SD (`CSI Ps T`): scroll the scrolling region down by `count`
+    /// lines. Ignored by default.
+    fn scroll_down(&mut self, _count: Option<u32>) {}
+
+    /// DSR (`CSI Ps n`): report the terminal status or, for 6, the
+    /// cursor position. Ignored by default.
+    fn report_device_status(&mut self, _mode: Option<u32>, _private: Option<bool>) {}
+
+    /// DECRQM (`CSI Ps $ p`): report whether `mode` is set. Ignored by
+    /// default.
+    fn report_mode(&mut self, _mode: u32, _is_private: bool) {}
+
+    /// OSC 8 hyperlink: text drawn from now on links to `uri`, none if
+    /// it is empty. Ignored by default.
+    fn set_hyperlink(&mut self, _params: &str, _uri: &str) {}
+
+    /// DECSWBV (`CSI Ps SP t`): set the warning bell volume. Ignored by
+    /// default.
+    fn set_warning_bell_volume(&mut self, _volume: Option<u32>) {}
+
+    /// Receives the payload of a device control string (`DCS ... ST`),
+    /// ignored by default.
+    fn dcs_dispatch(&mut self, _data: &str) {}
+
+    /// Receives the payload of an application program command
+    /// (`APC ... ST`), ignored by default.
+    fn apc_dispatch(&mut self, _data: &str) {}
+
+    /// Media copy (`CSI i`): print the screen or the cursor line, or
+    /// switch the printer controller (4, 5) or, if `private`, auto print
+    /// (4, 5) mode on and off. Ignored by default.
+    fn media_copy(&mut self, _mode: Option<u32>, _private: Option<bool>) {}
+
+    /// Secondary device attributes request (`CSI > c`), ignored by
+    /// default like on a VT102.
+    fn report_secondary_device_attributes(&mut self, _mode: Option<u32>) {}
+
+    /// Tertiary device attributes request (`CSI = c`), ignored by
+    /// default.
+    fn report_tertiary_device_attributes(&mut self, _mode: Option<u32>) {}
+
+    /// Set xterm's modifyOtherKeys level (`CSI > 4 ; Ps m`), 0 turns it
+    /// off. Ignored by default.
+    fn set_modify_other_keys(&mut self, _level: u32) {}
+
+    /// xterm's window manipulation (`CSI Ps ; Ps ; Ps t`), the first
+    /// parameter picks the operation. Ignored by default.
+    fn window_op(&mut self, _params: &[u32]) {}
+
+    /// DECCARA (`CSI Pt ; Pl ; Pb ; Pr ; Ps... $ r`): apply the SGR
+    /// attributes `Ps` to the rectangle with the given top, left, bottom
+    /// and right edges. Ignored by default.
+    fn change_attributes_in_rectangle(&mut self, _params: &[u32]) {}
+
+    /// DECRARA (`CSI Pt ; Pl ; Pb ; Pr ; Ps... $ t`): toggle the SGR
+    /// attributes `Ps` in the rectangle. Ignored by default.
+    fn reverse_attributes_in_rectangle(&mut self, _params: &[u32]) {}
+
+    /// Kitty keyboard protocol (`CSI > flags u`): push `flags`, the
+    /// keyboard enhancements the application wants, onto the stack.
+    /// Ignored by default.
+    fn push_keyboard_flags(&mut self, _flags: u32) {}
+
+    /// Kitty keyboard protocol (`CSI < Ps u`): pop `count` entries, one
+    /// if missing, off the stack. Ignored by default.
+    fn pop_keyboard_flags(&mut self, _count: Option<u32>) {}
+
+    /// Kitty keyboard protocol (`CSI ? u`): report the current flags.
+    /// Ignored by default.
+    fn report_keyboard_flags(&mut self) {}
+
+    /// Whether printer controller mode (`CSI 5 i`) is on, in which the
+    /// parser passes everything up to `CSI 4 i` to `draw` as it is,
+    /// sequences included. `false` by default.
+    fn printer_controller_mode(&self) -> bool {
+        false
+    }
+
+    /// xterm's dynamic colors (`OSC Ps ; Pt ST`): set the default
+    /// foreground (10), background (11) or cursor (12) color to `value`,
+    /// or report it if `value` is `?`. Ignored by default.
+    fn set_dynamic_color(&mut self, _code: u32, _value: &str) {}
+
+    /// Restore the dynamic color `code`, 10, 11 or 12, to its default
+    /// (`OSC 110`, `OSC 111` or `OSC 112`). Ignored by default.
+    fn reset_dynamic_color(&mut self, _code: u32) {}
+
     fn escape_dispatch(&mut self, escape_command: &str) {
         match escape_command {
             ec if ec == RIS => {
@@ -96,7 +230,7 @@ pub trait ParserListener {
                 self.index();
             }
             ec if ec == NEL => {
-                self.linefeed();
+                self.next_line();
             }
             ec if ec == RI => {
                 self.reverse_index();
@@ -110,9 +244,23 @@ pub trait ParserListener {
             ec if ec == DECRC => {
                 self.restore_cursor();
             }
-            _ => {
-                println!("un expected escape code")
+            ec if ec == DECBI => {
+                self.back_index();
             }
+            ec if ec == DECFI => {
+                self.forward_index();
+            }
+            ec if ec == DECKPAM => {
+                self.keypad_application_mode();
+            }
+            ec if ec == DECKPNM => {
+                self.keypad_numeric_mode();
+            }
+            // Obsolete form of primary DA.
+            ec if ec == DECID => {
+                self.report_device_attributes(Some(0), Some(false));
+            }
+            _ => {}
         }
     }
 
@@ -139,15 +287,87 @@ pub trait ParserListener {
             ec if ec == SI => {
                 self.shift_in();
             }
-            _ => {
-                println!("un expected escape code")
+            ec if ec == ENQ => {
+                self.enquiry();
             }
+            _ => {}
         }
     }
 
-    fn csi_dispatch(&mut self, csi_command: &str, params: &[u32], is_private: bool) {
-        dbg!("dispatching CSI");
-        dbg!(csi_command);
+    /// Receives a CSI sequence: its final, parameters, `?`, `>`, `=` or
+    /// `<` prefix and intermediate bytes, e.g. `$` for DECRQM. Override
+    /// to see every sequence, calling `route_csi` to still have it
+    /// handled.
+    fn csi_dispatch(
+        &mut self,
+        csi_command: &str,
+        params: &[u32],
+        private_marker: Option<char>,
+        intermediates: &str,
+    ) {
+        self.route_csi(csi_command, params, private_marker, intermediates);
+    }
+
+    /// Calls the handler for a CSI sequence, the default `csi_dispatch`.
+    fn route_csi(
+        &mut self,
+        csi_command: &str,
+        params: &[u32],
+        private_marker: Option<char>,
+        intermediates: &str,
+    ) {
+        match private_marker {
+            Some('>' | '<' | '?') if csi_command == KITTY_KEYBOARD && intermediates.is_empty() => {
+                return match private_marker {
+                    Some('>') => self.push_keyboard_flags(params.first().cloned().unwrap_or(0)),
+                    Some('<') => self.pop_keyboard_flags(params.first().cloned()),
+                    _ => self.report_keyboard_flags(),
+                };
+            }
+            None | Some('?') => {}
+            Some('>') if csi_command == DA && intermediates.is_empty() => {
+                return self.report_secondary_device_attributes(params.first().cloned());
+            }
+            // xterm's modifyOtherKeys: `CSI > 4 ; Ps m` sets the level,
+            // `CSI > 4 n` turns it off.
+            Some('>')
+                if (csi_command == SGR || csi_command == DSR)
+                    && intermediates.is_empty()
+                    && params.first() == Some(&4) =>
+            {
+                let level = if csi_command == SGR {
+                    params.get(1).cloned()
+                } else {
+                    None
+                };
+                return self.set_modify_other_keys(level.unwrap_or(0));
+            }
+            Some('=') if csi_command == DA && intermediates.is_empty() => {
+                return self.report_tertiary_device_attributes(params.first().cloned());
+            }
+            // Other prefixed sequences, e.g. xterm's `CSI > Ps ; Ps m`,
+            // are skipped rather than handled as the unprefixed one.
+            _ => return,
+        }
+        let is_private = private_marker == Some('?');
+        match intermediates {
+            "" => {}
+            " " if csi_command == DECSWBV => {
+                return self.set_warning_bell_volume(params.first().cloned());
+            }
+            "$" if csi_command == DECRQM => {
+                return self.report_mode(params.first().cloned().unwrap_or_default(), is_private);
+            }
+            "$" if csi_command == DECCARA && !is_private => {
+                return self.change_attributes_in_rectangle(params);
+            }
+            "$" if csi_command == DECRARA && !is_private => {
+                return self.reverse_attributes_in_rectangle(params);
+            }
+            // Sequences with other intermediates are skipped, they would
+            // be misrouted by their final alone.
+            _ => return,
+        }
         match csi_command {
             ec if ec == ICH => self.insert_characters(if !params.is_empty() {
                 Some(params[0])
@@ -227,21 +447,28 @@ pub trait ParserListener {
                 None
             }),
             ec if ec == DCH => self.delete_characters(params.iter().cloned().next()),
+            ec if ec == SU => self.scroll_up(params.iter().cloned().next()),
+            ec if ec == SD => self.scroll_down(params.iter().cloned().next()),
             ec if ec == ECH => self.erase_characters(params.iter().cloned().next()),
             ec if ec == HPR => self.cursor_forward(params.iter().cloned().next()),
+            ec if ec == HPB => self.cursor_back(params.iter().cloned().next()),
             ec if ec == DA => self.report_device_attributes(params.iter().cloned().next(), None),
             ec if ec == VPA => self.cursor_to_line(params.iter().cloned().next()),
             ec if ec == VPR => self.cursor_down(params.iter().cloned().next()),
+            ec if ec == VPB => self.cursor_up(params.iter().cloned().next()),
             ec if ec == HVP => {
                 self.cursor_position(params.iter().cloned().nth(0), params.iter().cloned().nth(1))
             }
             ec if ec == TBC => self.clear_tab_stop(params.iter().cloned().next()),
             ec if ec == SM => self.set_mode(params, is_private),
+            ec if ec == MC => self.media_copy(params.iter().cloned().next(), Some(is_private)),
             ec if ec == RM => self.reset_mode(params, is_private),
             ec if ec == SGR => self.select_graphic_rendition(params),
-            _ => {
-                println!("unexpected csi escape code");
+            ec if ec == DSR => {
+                self.report_device_status(params.iter().cloned().next(), Some(is_private))
             }
+            ec if ec == XTWINOPS && !is_private => self.window_op(params),
+            _ => {}
         }
     }
 }
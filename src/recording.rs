@@ -0,0 +1,541 @@
+//! A listener recording the commands it receives, for inspecting what
+//! an application sent or keeping it as a test fixture.
+
+use crate::observer::EventKind;
+use crate::parser_listener::ParserListener;
+use crate::prelude::*;
+
+/// A command received by a `ParserListener`, the owned counterpart of
+/// `EventKind`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    AlignmentDisplay,
+    DefineCharset { code: String, mode: String },
+    Reset,
+    Index,
+    Linefeed,
+    NextLine,
+    ReverseIndex,
+    BackIndex,
+    ForwardIndex,
+    SetTabStop,
+    SaveCursor,
+    RestoreCursor,
+    ShiftOut,
+    ShiftIn,
+    KeypadApplicationMode,
+    KeypadNumericMode,
+    Bell,
+    Enquiry,
+    Backspace,
+    Tab,
+    CariageReturn,
+    Draw(String),
+    InsertCharacters(Option<u32>),
+    CursorUp(Option<u32>),
+    CursorDown(Option<u32>),
+    CursorForward(Option<u32>),
+    CursorBack(Option<u32>),
+    CursorDown1(Option<u32>),
+    CursorUp1(Option<u32>),
+    CursorToColumn(Option<u32>),
+    CursorPosition { line: Option<u32>, character: Option<u32> },
+    EraseInDisplay { how: Option<u32>, private: Option<bool> },
+    EraseInLine { how: Option<u32>, private: Option<bool> },
+    InsertLines(Option<u32>),
+    DeleteLines(Option<u32>),
+    ScrollUp(Option<u32>),
+    ScrollDown(Option<u32>),
+    DeleteCharacters(Option<u32>),
+    EraseCharacters(Option<u32>),
+    ReportDeviceAttributes { mode: Option<u32>, private: Option<bool> },
+    ReportDeviceStatus { mode: Option<u32>, private: Option<bool> },
+    CursorToLine(Option<u32>),
+    ClearTabStop(Option<u32>),
+    SetMode { modes: Vec<u32>, is_private: bool },
+    ReportMode { mode: u32, is_private: bool },
+    ResetMode { modes: Vec<u32>, is_private: bool },
+    SelectGraphicRendition(Vec<u32>),
+    SetTitle(String),
+    SetIconName(String),
+    SetHyperlink { params: String, uri: String },
+    SetWarningBellVolume(Option<u32>),
+    DcsDispatch(String),
+    ApcDispatch(String),
+    MediaCopy { mode: Option<u32>, private: Option<bool> },
+    ReportSecondaryDeviceAttributes(Option<u32>),
+    ReportTertiaryDeviceAttributes(Option<u32>),
+    SetModifyOtherKeys(u32),
+    WindowOp(Vec<u32>),
+    ChangeAttributesInRectangle(Vec<u32>),
+    ReverseAttributesInRectangle(Vec<u32>),
+    PushKeyboardFlags(u32),
+    PopKeyboardFlags(Option<u32>),
+    ReportKeyboardFlags,
+    SetDynamicColor { code: u32, value: String },
+    ResetDynamicColor(u32),
+}
+
+impl From<&EventKind<'_>> for Command {
+    fn from(event: &EventKind<'_>) -> Self {
+        match event {
+            EventKind::AlignmentDisplay => Command::AlignmentDisplay,
+            EventKind::DefineCharset { code, mode } => {
+                Command::DefineCharset { code: code.to_string(), mode: mode.to_string() }
+            }
+            EventKind::Reset => Command::Reset,
+            EventKind::Index => Command::Index,
+            EventKind::Linefeed => Command::Linefeed,
+            EventKind::NextLine => Command::NextLine,
+            EventKind::ReverseIndex => Command::ReverseIndex,
+            EventKind::BackIndex => Command::BackIndex,
+            EventKind::ForwardIndex => Command::ForwardIndex,
+            EventKind::SetTabStop => Command::SetTabStop,
+            EventKind::SaveCursor => Command::SaveCursor,
+            EventKind::RestoreCursor => Command::RestoreCursor,
+            EventKind::ShiftOut => Command::ShiftOut,
+            EventKind::ShiftIn => Command::ShiftIn,
+            EventKind::KeypadApplicationMode => Command::KeypadApplicationMode,
+            EventKind::KeypadNumericMode => Command::KeypadNumericMode,
+            EventKind::Bell => Command::Bell,
+            EventKind::Enquiry => Command::Enquiry,
+            EventKind::Backspace => Command::Backspace,
+            EventKind::Tab => Command::Tab,
+            EventKind::CariageReturn => Command::CariageReturn,
+            EventKind::Draw(input) => Command::Draw(input.to_string()),
+            EventKind::InsertCharacters(count) => Command::InsertCharacters(*count),
+            EventKind::CursorUp(count) => Command::CursorUp(*count),
+            EventKind::CursorDown(count) => Command::CursorDown(*count),
+            EventKind::CursorForward(count) => Command::CursorForward(*count),
+            EventKind::CursorBack(count) => Command::CursorBack(*count),
+            EventKind::CursorDown1(count) => Command::CursorDown1(*count),
+            EventKind::CursorUp1(count) => Command::CursorUp1(*count),
+            EventKind::CursorToColumn(character) => Command::CursorToColumn(*character),
+            EventKind::CursorPosition { line, character } => {
+                Command::CursorPosition { line: *line, character: *character }
+            }
+            EventKind::EraseInDisplay { how, private } => {
+                Command::EraseInDisplay { how: *how, private: *private }
+            }
+            EventKind::EraseInLine { how, private } => {
+                Command::EraseInLine { how: *how, private: *private }
+            }
+            EventKind::InsertLines(count) => Command::InsertLines(*count),
+            EventKind::DeleteLines(count) => Command::DeleteLines(*count),
+            EventKind::ScrollUp(count) => Command::ScrollUp(*count),
+            EventKind::ScrollDown(count) => Command::ScrollDown(*count),
+            EventKind::DeleteCharacters(count) => Command::DeleteCharacters(*count),
+            EventKind::EraseCharacters(count) => Command::EraseCharacters(*count),
+            EventKind::ReportDeviceAttributes { mode, private } => {
+                Command::ReportDeviceAttributes { mode: *mode, private: *private }
+            }
+            EventKind::ReportDeviceStatus { mode, private } => {
+                Command::ReportDeviceStatus { mode: *mode, private: *private }
+            }
+            EventKind::CursorToLine(line) => Command::CursorToLine(*line),
+            EventKind::ClearTabStop(how) => Command::ClearTabStop(*how),
+            EventKind::SetMode { modes, is_private } => {
+                Command::SetMode { modes: modes.to_vec(), is_private: *is_private }
+            }
+            EventKind::ReportMode { mode, is_private } => {
+                Command::ReportMode { mode: *mode, is_private: *is_private }
+            }
+            EventKind::ResetMode { modes, is_private } => {
+                Command::ResetMode { modes: modes.to_vec(), is_private: *is_private }
+            }
+            EventKind::SelectGraphicRendition(modes) => {
+                Command::SelectGraphicRendition(modes.to_vec())
+            }
+            EventKind::SetTitle(title) => Command::SetTitle(title.to_string()),
+            EventKind::SetIconName(icon_name) => Command::SetIconName(icon_name.to_string()),
+            EventKind::SetHyperlink { params, uri } => {
+                Command::SetHyperlink { params: params.to_string(), uri: uri.to_string() }
+            }
+            EventKind::SetWarningBellVolume(volume) => Command::SetWarningBellVolume(*volume),
+            EventKind::DcsDispatch(data) => Command::DcsDispatch(data.to_string()),
+            EventKind::ApcDispatch(data) => Command::ApcDispatch(data.to_string()),
+            EventKind::MediaCopy { mode, private } => {
+                Command::MediaCopy { mode: *mode, private: *private }
+            }
+            EventKind::ReportSecondaryDeviceAttributes(mode) => {
+                Command::ReportSecondaryDeviceAttributes(*mode)
+            }
+            EventKind::ReportTertiaryDeviceAttributes(mode) => {
+                Command::ReportTertiaryDeviceAttributes(*mode)
+            }
+            EventKind::SetModifyOtherKeys(level) => Command::SetModifyOtherKeys(*level),
+            EventKind::WindowOp(params) => Command::WindowOp(params.to_vec()),
+            EventKind::ChangeAttributesInRectangle(params) => {
+                Command::ChangeAttributesInRectangle(params.to_vec())
+            }
+            EventKind::ReverseAttributesInRectangle(params) => {
+                Command::ReverseAttributesInRectangle(params.to_vec())
+            }
+            EventKind::PushKeyboardFlags(flags) => Command::PushKeyboardFlags(*flags),
+            EventKind::PopKeyboardFlags(count) => Command::PopKeyboardFlags(*count),
+            EventKind::ReportKeyboardFlags => Command::ReportKeyboardFlags,
+            EventKind::SetDynamicColor { code, value } => {
+                Command::SetDynamicColor { code: *code, value: value.to_string() }
+            }
+            EventKind::ResetDynamicColor(code) => Command::ResetDynamicColor(*code),
+        }
+    }
+}
+
+/// A listener which only records the commands it receives, in order.
+///
+/// ```
+/// use memterm::parser::StreamParser;
+/// use memterm::recording::{Command, RecordingListener};
+///
+/// let mut recording = RecordingListener::new();
+/// StreamParser::new().feed(&mut recording, "\x1b[2J");
+/// assert_eq!(
+///     recording.commands(),
+///     [Command::EraseInDisplay { how: Some(2), private: None }]
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RecordingListener {
+    commands: Vec<Command>,
+}
+
+impl RecordingListener {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The commands received so far.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Removes and returns the commands received so far.
+    pub fn take_commands(&mut self) -> Vec<Command> {
+        core::mem::take(&mut self.commands)
+    }
+}
+
+impl ParserListener for RecordingListener {
+    fn alignment_display(&mut self) {
+        self.commands.push(Command::AlignmentDisplay);
+    }
+
+    fn define_charset(&mut self, code: &str, mode: &str) {
+        self.commands
+            .push(Command::DefineCharset { code: code.to_string(), mode: mode.to_string() });
+    }
+
+    fn reset(&mut self) {
+        self.commands.push(Command::Reset);
+    }
+
+    fn index(&mut self) {
+        self.commands.push(Command::Index);
+    }
+
+    fn linefeed(&mut self) {
+        self.commands.push(Command::Linefeed);
+    }
+
+    fn next_line(&mut self) {
+        self.commands.push(Command::NextLine);
+    }
+
+    fn reverse_index(&mut self) {
+        self.commands.push(Command::ReverseIndex);
+    }
+
+    fn back_index(&mut self) {
+        self.commands.push(Command::BackIndex);
+    }
+
+    fn forward_index(&mut self) {
+        self.commands.push(Command::ForwardIndex);
+    }
+
+    fn set_tab_stop(&mut self) {
+        self.commands.push(Command::SetTabStop);
+    }
+
+    fn save_cursor(&mut self) {
+        self.commands.push(Command::SaveCursor);
+    }
+
+    fn restore_cursor(&mut self) {
+        self.commands.push(Command::RestoreCursor);
+    }
+
+    fn shift_out(&mut self) {
+        self.commands.push(Command::ShiftOut);
+    }
+
+    fn shift_in(&mut self) {
+        self.commands.push(Command::ShiftIn);
+    }
+
+    fn keypad_application_mode(&mut self) {
+        self.commands.push(Command::KeypadApplicationMode);
+    }
+
+    fn keypad_numeric_mode(&mut self) {
+        self.commands.push(Command::KeypadNumericMode);
+    }
+
+    fn bell(&mut self) {
+        self.commands.push(Command::Bell);
+    }
+
+    fn enquiry(&mut self) {
+        self.commands.push(Command::Enquiry);
+    }
+
+    fn backspace(&mut self) {
+        self.commands.push(Command::Backspace);
+    }
+
+    fn tab(&mut self) {
+        self.commands.push(Command::Tab);
+    }
+
+    fn cariage_return(&mut self) {
+        self.commands.push(Command::CariageReturn);
+    }
+
+    fn draw(&mut self, input: &str) {
+        self.commands.push(Command::Draw(input.to_string()));
+    }
+
+    fn insert_characters(&mut self, count: Option<u32>) {
+        self.commands.push(Command::InsertCharacters(count));
+    }
+
+    fn cursor_up(&mut self, count: Option<u32>) {
+        self.commands.push(Command::CursorUp(count));
+    }
+
+    fn cursor_down(&mut self, count: Option<u32>) {
+        self.commands.push(Command::CursorDown(count));
+    }
+
+    fn cursor_forward(&mut self, count: Option<u32>) {
+        self.commands.push(Command::CursorForward(count));
+    }
+
+    fn cursor_back(&mut self, count: Option<u32>) {
+        self.commands.push(Command::CursorBack(count));
+    }
+
+    fn cursor_down1(&mut self, count: Option<u32>) {
+        self.commands.push(Command::CursorDown1(count));
+    }
+
+    fn cursor_up1(&mut self, count: Option<u32>) {
+        self.commands.push(Command::CursorUp1(count));
+    }
+
+    fn cursor_to_column(&mut self, character: Option<u32>) {
+        self.commands.push(Command::CursorToColumn(character));
+    }
+
+    fn cursor_position(&mut self, line: Option<u32>, character: Option<u32>) {
+        self.commands
+            .push(Command::CursorPosition { line, character });
+    }
+
+    fn erase_in_display(&mut self, how: Option<u32>, private: Option<bool>) {
+        self.commands.push(Command::EraseInDisplay { how, private });
+    }
+
+    fn erase_in_line(&mut self, how: Option<u32>, private: Option<bool>) {
+        self.commands.push(Command::EraseInLine { how, private });
+    }
+
+    fn insert_lines(&mut self, count: Option<u32>) {
+        self.commands.push(Command::InsertLines(count));
+    }
+
+    fn delete_lines(&mut self, count: Option<u32>) {
+        self.commands.push(Command::DeleteLines(count));
+    }
+
+    fn scroll_up(&mut self, count: Option<u32>) {
+        self.commands.push(Command::ScrollUp(count));
+    }
+
+    fn scroll_down(&mut self, count: Option<u32>) {
+        self.commands.push(Command::ScrollDown(count));
+    }
+
+    fn delete_characters(&mut self, count: Option<u32>) {
+        self.commands.push(Command::DeleteCharacters(count));
+    }
+
+    fn erase_characters(&mut self, count: Option<u32>) {
+        self.commands.push(Command::EraseCharacters(count));
+    }
+
+    fn report_device_attributes(&mut self, mode: Option<u32>, private: Option<bool>) {
+        self.commands
+            .push(Command::ReportDeviceAttributes { mode, private });
+    }
+
+    fn report_device_status(&mut self, mode: Option<u32>, private: Option<bool>) {
+        self.commands
+            .push(Command::ReportDeviceStatus { mode, private });
+    }
+
+    fn cursor_to_line(&mut self, line: Option<u32>) {
+        self.commands.push(Command::CursorToLine(line));
+    }
+
+    fn clear_tab_stop(&mut self, how: Option<u32>) {
+        self.commands.push(Command::ClearTabStop(how));
+    }
+
+    fn set_mode(&mut self, modes: &[u32], is_private: bool) {
+        self.commands
+            .push(Command::SetMode { modes: modes.to_vec(), is_private });
+    }
+
+    fn report_mode(&mut self, mode: u32, is_private: bool) {
+        self.commands.push(Command::ReportMode { mode, is_private });
+    }
+
+    fn reset_mode(&mut self, modes: &[u32], is_private: bool) {
+        self.commands
+            .push(Command::ResetMode { modes: modes.to_vec(), is_private });
+    }
+
+    fn select_graphic_rendition(&mut self, modes: &[u32]) {
+        self.commands
+            .push(Command::SelectGraphicRendition(modes.to_vec()));
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.commands.push(Command::SetTitle(title.to_string()));
+    }
+
+    fn set_icon_name(&mut self, icon_name: &str) {
+        self.commands
+            .push(Command::SetIconName(icon_name.to_string()));
+    }
+
+    fn set_hyperlink(&mut self, params: &str, uri: &str) {
+        self.commands
+            .push(Command::SetHyperlink { params: params.to_string(), uri: uri.to_string() });
+    }
+
+    fn set_warning_bell_volume(&mut self, volume: Option<u32>) {
+        self.commands.push(Command::SetWarningBellVolume(volume));
+    }
+
+    fn dcs_dispatch(&mut self, data: &str) {
+        self.commands.push(Command::DcsDispatch(data.to_string()));
+    }
+
+    fn apc_dispatch(&mut self, data: &str) {
+        self.commands.push(Command::ApcDispatch(data.to_string()));
+    }
+
+    fn media_copy(&mut self, mode: Option<u32>, private: Option<bool>) {
+        self.commands.push(Command::MediaCopy { mode, private });
+    }
+
+    fn report_secondary_device_attributes(&mut self, mode: Option<u32>) {
+        self.commands
+            .push(Command::ReportSecondaryDeviceAttributes(mode));
+    }
+
+    fn report_tertiary_device_attributes(&mut self, mode: Option<u32>) {
+        self.commands
+            .push(Command::ReportTertiaryDeviceAttributes(mode));
+    }
+
+    fn set_modify_other_keys(&mut self, level: u32) {
+        self.commands.push(Command::SetModifyOtherKeys(level));
+    }
+
+    fn window_op(&mut self, params: &[u32]) {
+        self.commands.push(Command::WindowOp(params.to_vec()));
+    }
+
+    fn change_attributes_in_rectangle(&mut self, params: &[u32]) {
+        self.commands
+            .push(Command::ChangeAttributesInRectangle(params.to_vec()));
+    }
+
+    fn reverse_attributes_in_rectangle(&mut self, params: &[u32]) {
+        self.commands
+            .push(Command::ReverseAttributesInRectangle(params.to_vec()));
+    }
+
+    fn push_keyboard_flags(&mut self, flags: u32) {
+        self.commands.push(Command::PushKeyboardFlags(flags));
+    }
+
+    fn pop_keyboard_flags(&mut self, count: Option<u32>) {
+        self.commands.push(Command::PopKeyboardFlags(count));
+    }
+
+    fn report_keyboard_flags(&mut self) {
+        self.commands.push(Command::ReportKeyboardFlags);
+    }
+
+    fn set_dynamic_color(&mut self, code: u32, value: &str) {
+        self.commands
+            .push(Command::SetDynamicColor { code, value: value.to_string() });
+    }
+
+    fn reset_dynamic_color(&mut self, code: u32) {
+        self.commands.push(Command::ResetDynamicColor(code));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Command, RecordingListener};
+    use crate::observer::ObserverListener;
+    use crate::parser::StreamParser;
+    use crate::screen::Screen;
+
+    #[test]
+    fn erase_in_display() {
+        let mut recording = RecordingListener::new();
+        let mut parser = StreamParser::new();
+        parser.feed(&mut recording, "\x1b[2J");
+        assert_eq!(
+            recording.commands(),
+            [Command::EraseInDisplay { how: Some(2), private: None }]
+        );
+
+        parser.feed(&mut recording, "a\x1b[1;31m\x1b]0;title\x07\x1b[?1049h");
+        assert_eq!(
+            recording.take_commands(),
+            vec![
+                Command::EraseInDisplay { how: Some(2), private: None },
+                Command::Draw("a".to_string()),
+                Command::SelectGraphicRendition(vec![1, 31]),
+                Command::SetIconName("title".to_string()),
+                Command::SetTitle("title".to_string()),
+                Command::SetMode { modes: vec![1049], is_private: true },
+            ]
+        );
+        assert!(recording.commands().is_empty());
+    }
+
+    #[test]
+    fn record_observed_events() {
+        let mut recording = RecordingListener::new();
+        StreamParser::new().feed(&mut recording, "x\x1b[2;3H\x1b[K");
+
+        // The same commands, converted from the events of an observer.
+        let mut commands = Vec::new();
+        let mut observer = ObserverListener::new(Screen::new(4, 2), |event| {
+            commands.push(Command::from(event))
+        });
+        StreamParser::new().feed(&mut observer, "x\x1b[2;3H\x1b[K");
+        assert_eq!(observer.into_inner().display(), vec!["x   ", "    "]);
+        assert_eq!(commands, recording.commands());
+    }
+}
@@ -0,0 +1,82 @@
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+
+/// Receives the replies a screen sends back to the host process, e.g.
+/// device attribute and cursor position reports.
+pub trait Reporter: Send {
+    fn report(&mut self, data: &str);
+
+    /// Like `report`, but for sinks which can fail, e.g. a closed pipe.
+    /// The error surfaces from `Screen::try_feed`.
+    #[cfg(feature = "std")]
+    fn try_report(&mut self, data: &str) -> io::Result<()> {
+        self.report(data);
+        Ok(())
+    }
+}
+
+/// A reporter which queues replies until the host takes them.
+///
+/// Clones share the same queue, so one handle can be given to the screen
+/// while another is kept to read the replies back.
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+pub struct VecReporter {
+    replies: Arc<Mutex<Vec<String>>>,
+}
+
+#[cfg(feature = "std")]
+impl VecReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns all queued replies, oldest first.
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.replies.lock().unwrap())
+    }
+
+    /// Returns `true` if there are no replies waiting to be read.
+    pub fn is_empty(&self) -> bool {
+        self.replies.lock().unwrap().is_empty()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Reporter for VecReporter {
+    fn report(&mut self, data: &str) {
+        self.replies.lock().unwrap().push(data.to_owned());
+    }
+}
+
+/// A reporter which writes replies to `W`, e.g. the pty of the host.
+#[cfg(feature = "std")]
+pub struct WriteReporter<W> {
+    writer: W,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write + Send> WriteReporter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write + Send> Reporter for WriteReporter<W> {
+    /// Write errors are ignored, use `Screen::try_feed` to see them.
+    fn report(&mut self, data: &str) {
+        let _ = self.try_report(data);
+    }
+
+    fn try_report(&mut self, data: &str) -> io::Result<()> {
+        self.writer.write_all(data.as_bytes())?;
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,476 @@
+//! A compact binary form of a screen, for storing sessions.
+//!
+//! Cells are run-length encoded: each row is a list of runs of cells
+//! sharing the same attributes, which are stored once in a table at the
+//! start. A run of blanks takes a few bytes however long it is.
+//!
+//! The layout, numbers being LEB128 varints and strings a varint byte
+//! length followed by UTF-8:
+//!
+//! ```text
+//! "MTRL" version
+//! columns lines
+//! attribute count, then per attribute: fg bg flags [hyperlink]
+//! cursor x, cursor y, cursor flags, cursor attribute, cursor data
+//! pending wrap
+//! mode count, modes
+//! 0 | 1 top bottom             margins
+//! title icon_name
+//! lines rows
+//! scrollback row count, rows
+//! ```
+//!
+//! where a row is its wrapped flag and run count followed by the runs,
+//! each a cell count and attribute index, then 0 if every cell is a
+//! blank or 1 and the data of each cell.
+
+use alloc::sync::Arc;
+use core::fmt;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::screen::{color, CharOpts, Margins, Screen};
+
+const MAGIC: &[u8] = b"MTRL";
+const VERSION: u8 = 1;
+
+/// The most cells `from_rle` reads for the screen, and again for the
+/// scrollback. A run of blanks takes a few bytes however long it is, so
+/// the input size alone doesn't bound what a corrupt one can allocate.
+const MAX_CELLS: u64 = 1 << 22;
+
+/// Why `Screen::from_rle` couldn't read its input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RleError {
+    /// The input doesn't start with the format's magic bytes.
+    BadMagic,
+    /// The input was written by a newer, unknown version of the format.
+    UnsupportedVersion(u8),
+    /// The input ends in the middle of the screen.
+    Truncated,
+    /// A value is out of range, e.g. a row wider than the screen.
+    Invalid,
+}
+
+impl fmt::Display for RleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RleError::BadMagic => write!(f, "not a screen snapshot"),
+            RleError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            RleError::Truncated => write!(f, "snapshot is truncated"),
+            RleError::Invalid => write!(f, "snapshot is invalid"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RleError {}
+
+/// Collects the attribute table while the screen is written.
+#[derive(Default)]
+struct Attrs {
+    indices: HashMap<CharOpts, usize>,
+    table: Vec<CharOpts>,
+}
+
+impl Attrs {
+    fn index(&mut self, cell: &CharOpts) -> usize {
+        let key = CharOpts { data: String::new(), ..cell.clone() };
+        if let Some(&index) = self.indices.get(&key) {
+            return index;
+        }
+        self.table.push(key.clone());
+        self.indices.insert(key, self.table.len() - 1);
+        self.table.len() - 1
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn put_row(out: &mut Vec<u8>, attrs: &mut Attrs, row: &[CharOpts], wrapped: bool) {
+    out.push(wrapped as u8);
+    let mut runs: Vec<(usize, &[CharOpts])> = Vec::new();
+    let mut start = 0;
+    while start < row.len() {
+        let first = &row[start];
+        let len = row[start..]
+            .iter()
+            .take_while(|cell| cell.same_style(first) && cell.hyperlink == first.hyperlink)
+            .count();
+        let index = attrs.index(first);
+        runs.push((index, &row[start..start + len]));
+        start += len;
+    }
+
+    put_varint(out, runs.len() as u64);
+    for (index, cells) in runs {
+        put_varint(out, cells.len() as u64);
+        put_varint(out, index as u64);
+        if cells.iter().all(|cell| cell.data == " ") {
+            out.push(0);
+        } else {
+            out.push(1);
+            for cell in cells {
+                put_str(out, &cell.data);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, RleError> {
+        let (&byte, rest) = self.data.split_first().ok_or(RleError::Truncated)?;
+        self.data = rest;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], RleError> {
+        if self.data.len() < len {
+            return Err(RleError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, RleError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(RleError::Invalid)
+    }
+
+    fn u32(&mut self) -> Result<u32, RleError> {
+        u32::try_from(self.varint()?).map_err(|_| RleError::Invalid)
+    }
+
+    fn usize(&mut self) -> Result<usize, RleError> {
+        usize::try_from(self.varint()?).map_err(|_| RleError::Invalid)
+    }
+
+    fn bool(&mut self) -> Result<bool, RleError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(RleError::Invalid),
+        }
+    }
+
+    fn str(&mut self) -> Result<&'a str, RleError> {
+        let len = self.usize()?;
+        core::str::from_utf8(self.bytes(len)?).map_err(|_| RleError::Invalid)
+    }
+
+    /// Reads a row of at most `max_width` cells, returns it with its
+    /// wrapped flag.
+    fn row(
+        &mut self,
+        attrs: &[CharOpts],
+        max_width: usize,
+    ) -> Result<(Vec<CharOpts>, bool), RleError> {
+        let wrapped = self.bool()?;
+        let runs = self.usize()?;
+        let mut row = Vec::new();
+        for _ in 0..runs {
+            let len = self.usize()?;
+            let attr = attrs.get(self.usize()?).ok_or(RleError::Invalid)?;
+            if len > max_width - row.len() {
+                return Err(RleError::Invalid);
+            }
+            if self.bool()? {
+                for _ in 0..len {
+                    let data = self.str()?;
+                    row.push(CharOpts { data: data.to_owned(), ..attr.clone() });
+                }
+            } else {
+                row.extend((0..len).map(|_| CharOpts { data: " ".to_owned(), ..attr.clone() }));
+            }
+        }
+        Ok((row, wrapped))
+    }
+}
+
+impl Screen {
+    /// Returns the screen, its scrollback, the cursor, modes, margins
+    /// and titles in a compact binary form, see the `rle` module. Read
+    /// it back with `from_rle`.
+    pub fn to_rle(&self) -> Vec<u8> {
+        let mut attrs = Attrs::default();
+        let mut body = Vec::new();
+
+        let cursor_attr = attrs.index(&self.cursor.attr);
+        put_varint(&mut body, self.cursor.x.into());
+        put_varint(&mut body, self.cursor.y.into());
+        body.push(self.cursor.hidden as u8 | (self.cursor.blink as u8) << 1);
+        put_varint(&mut body, cursor_attr as u64);
+        put_str(&mut body, &self.cursor.attr.data);
+        body.push(self.pending_wrap as u8);
+
+        let mut modes = self.mode.iter().copied().collect::<Vec<_>>();
+        modes.sort_unstable();
+        put_varint(&mut body, modes.len() as u64);
+        for mode in modes {
+            put_varint(&mut body, mode.into());
+        }
+        match self.margins {
+            Some(Margins { top, bottom }) => {
+                body.push(1);
+                put_varint(&mut body, top.into());
+                put_varint(&mut body, bottom.into());
+            }
+            None => body.push(0),
+        }
+        put_str(&mut body, &self.title);
+        put_str(&mut body, &self.icon_name);
+
+        for (y, row) in self.buffer.iter().enumerate() {
+            put_row(&mut body, &mut attrs, row, self.is_wrapped(y as u32));
+        }
+        put_varint(&mut body, self.scrollback.len() as u64);
+        for row in &self.scrollback {
+            put_row(&mut body, &mut attrs, row, false);
+        }
+
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        put_varint(&mut out, self.columns.into());
+        put_varint(&mut out, self.lines.into());
+        put_varint(&mut out, attrs.table.len() as u64);
+        for attr in &attrs.table {
+            put_str(&mut out, &attr.fg);
+            put_str(&mut out, &attr.bg);
+            let flags = [
+                attr.bold,
+                attr.italics,
+                attr.underscore,
+                attr.strikethrough,
+                attr.reverse,
+                attr.blink,
+                attr.hyperlink.is_some(),
+                attr.conceal,
+            ];
+            out.push(
+                flags
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (bit, &set)| byte | (set as u8) << bit),
+            );
+            if let Some(hyperlink) = &attr.hyperlink {
+                put_str(&mut out, hyperlink);
+            }
+        }
+        out.extend_from_slice(&body);
+        out
+    }
+
+    /// Reads a screen written by `to_rle`. Settings which aren't part of
+    /// the format, such as callbacks and the reporter, have their
+    /// defaults.
+    pub fn from_rle(data: &[u8]) -> Result<Screen, RleError> {
+        let mut reader = Reader { data };
+        if reader.bytes(MAGIC.len()).map_err(|_| RleError::BadMagic)? != MAGIC {
+            return Err(RleError::BadMagic);
+        }
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(RleError::UnsupportedVersion(version));
+        }
+        let columns = reader.u32()?;
+        let lines = reader.u32()?;
+        if columns == 0 || lines == 0 || u64::from(columns) * u64::from(lines) > MAX_CELLS {
+            return Err(RleError::Invalid);
+        }
+
+        let mut attrs = Vec::new();
+        for _ in 0..reader.usize()? {
+            let fg = color(reader.str()?);
+            let bg = color(reader.str()?);
+            let flags = reader.byte()?;
+            let flag = |bit: u8| flags & 1 << bit != 0;
+            let hyperlink = if flag(6) {
+                Some(Arc::from(reader.str()?))
+            } else {
+                None
+            };
+            attrs.push(CharOpts {
+                data: String::new(),
+                fg,
+                bg,
+                bold: flag(0),
+                italics: flag(1),
+                underscore: flag(2),
+                strikethrough: flag(3),
+                reverse: flag(4),
+                blink: flag(5),
+                conceal: flag(7),
+                hyperlink,
+            });
+        }
+
+        let mut screen = Screen::new(columns, lines);
+        screen.cursor.x = reader.u32()?;
+        screen.cursor.y = reader.u32()?;
+        let flags = reader.byte()?;
+        screen.cursor.hidden = flags & 1 != 0;
+        screen.cursor.blink = flags & 2 != 0;
+        let attr = attrs.get(reader.usize()?).ok_or(RleError::Invalid)?;
+        screen.cursor.attr = CharOpts { data: reader.str()?.to_owned(), ..attr.clone() };
+        screen.pending_wrap = reader.bool()?;
+        if screen.cursor.x >= columns || screen.cursor.y >= lines {
+            return Err(RleError::Invalid);
+        }
+
+        screen.mode.clear();
+        for _ in 0..reader.usize()? {
+            screen.mode.insert(reader.u32()?);
+        }
+        screen.margins = if reader.bool()? {
+            let (top, bottom) = (reader.u32()?, reader.u32()?);
+            if top > bottom || bottom >= lines {
+                return Err(RleError::Invalid);
+            }
+            Some(Margins { top, bottom })
+        } else {
+            None
+        };
+        screen.title = reader.str()?.to_owned();
+        screen.icon_name = reader.str()?.to_owned();
+
+        for y in 0..lines as usize {
+            let (row, wrapped) = reader.row(&attrs, columns as usize)?;
+            if row.len() != columns as usize {
+                return Err(RleError::Invalid);
+            }
+            screen.buffer[y] = row;
+            screen.wrapped[y] = wrapped;
+        }
+        // Every row takes at least two bytes.
+        let rows = reader.usize()?;
+        if rows > reader.data.len() / 2 {
+            return Err(RleError::Invalid);
+        }
+        let mut cells = MAX_CELLS as usize;
+        for _ in 0..rows {
+            // Scrollback rows keep the width they had when they scrolled
+            // off, which may differ from the current one.
+            let (row, _) = reader.row(&attrs, cells)?;
+            cells -= row.len();
+            screen.scrollback.push_back(row);
+        }
+        screen.scrollback_limit = screen.scrollback_limit.max(screen.scrollback.len());
+        screen.dirty.extend(0..lines);
+        Ok(screen)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{put_varint, RleError};
+    use crate::screen::Screen;
+
+    fn styled_screen() -> Screen {
+        let mut screen = Screen::new(12, 4);
+        screen.scrollback_limit = 10;
+        screen.feed("\x1b]2;title\x07\x1b[1;31mred\x1b[0m \x1b[4;44m\u{754c}e\u{301}\x1b[0m\r\n");
+        screen.feed("\x1b]8;;https://example.com\x07link\x1b]8;;\x07 plain text\r\n");
+        screen.feed("scrolled\r\n\r\nwrapping over the edge\x1b[?25l\x1b[7m");
+        screen
+    }
+
+    #[test]
+    fn round_trip() {
+        let screen = styled_screen();
+        let restored = Screen::from_rle(&screen.to_rle()).unwrap();
+
+        assert!(restored == screen);
+        assert_eq!(restored.display(), screen.display());
+        assert_eq!(restored.cursor, screen.cursor);
+        assert_eq!(restored.pending_wrap, screen.pending_wrap);
+        assert_eq!(restored.title, "title");
+        assert_eq!(restored.wrapped, screen.wrapped);
+        assert_eq!(restored.scrollback, screen.scrollback);
+        assert_eq!(restored.hyperlinks(), screen.hyperlinks());
+    }
+
+    #[test]
+    fn runs_are_compact() {
+        let blank = Screen::new(200, 100).to_rle();
+        // A header, one attribute and about six bytes per row for its
+        // single run, instead of 20000 cells.
+        assert!(blank.len() < 700, "{} bytes", blank.len());
+        assert!(Screen::from_rle(&blank).unwrap() == Screen::new(200, 100));
+    }
+
+    #[test]
+    fn invalid_input() {
+        let data = styled_screen().to_rle();
+        assert_eq!(Screen::from_rle(b"").err(), Some(RleError::BadMagic));
+        assert_eq!(Screen::from_rle(b"JSON{}").err(), Some(RleError::BadMagic));
+
+        let mut newer = data.clone();
+        newer[4] = 2;
+        assert_eq!(
+            Screen::from_rle(&newer).err(),
+            Some(RleError::UnsupportedVersion(2))
+        );
+
+        for len in 5..data.len() {
+            assert!(Screen::from_rle(&data[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn corrupt_sizes() {
+        // A huge screen, rejected before anything is allocated.
+        let mut header = b"MTRL\x01".to_vec();
+        put_varint(&mut header, u32::MAX.into());
+        put_varint(&mut header, 2);
+        assert_eq!(Screen::from_rle(&header).err(), Some(RleError::Invalid));
+
+        // The scrollback row count is the last byte of a screen without
+        // scrollback.
+        let data = Screen::new(4, 2).to_rle();
+        assert_eq!(data.last(), Some(&0));
+        let with_scrollback = |scrollback: &[u8]| {
+            let mut data = data[..data.len() - 1].to_vec();
+            data.extend_from_slice(scrollback);
+            Screen::from_rle(&data)
+        };
+        assert!(with_scrollback(&[1, 0, 1, 4, 0, 0]).is_ok());
+
+        // A huge count of rows, or a row with a huge run of blanks.
+        let mut rows = Vec::new();
+        put_varint(&mut rows, u64::MAX >> 1);
+        assert_eq!(with_scrollback(&rows).err(), Some(RleError::Invalid));
+        let mut run = vec![1, 0, 1];
+        put_varint(&mut run, u32::MAX.into());
+        run.extend_from_slice(&[0, 0]);
+        assert_eq!(with_scrollback(&run).err(), Some(RleError::Invalid));
+    }
+}
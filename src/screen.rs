@@ -1,27 +1,64 @@
-use std::collections::{HashMap, HashSet};
-use std::fmt::Display;
+use alloc::sync::Arc;
+use core::fmt::Display;
 
-use lazy_static::lazy_static;
 use unicode_normalization::char::is_combining_mark;
-use unicode_normalization::{char, UnicodeNormalization};
+use unicode_normalization::{char, is_nfc, UnicodeNormalization};
+use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthChar;
 
 use crate::charset::{LAT1_MAP, MAPS, VT100_MAP};
+use crate::collections::{HashMap, HashSet, VecDeque};
+#[cfg(feature = "std")]
+use crate::error::TermError;
 use crate::graphics::{BG_256, BG_AIXTERM, BG_ANSI, FG_256, FG_AIXTERM, FG_ANSI, FG_BG_256, TEXT};
-use crate::modes::{DECAWM, DECCOLM, DECOM, DECSCNM, DECTCEM, IRM, LNM};
+use crate::input::{self, Key, Modifiers};
+use crate::lazy::Lazy;
+use crate::modes::{
+    ALT_SCREEN,
+    ALT_SCREEN_SAVE_CURSOR,
+    CURSOR_BLINK,
+    DECAWM,
+    DECCKM,
+    DECCOLM,
+    DECOM,
+    DECSCNM,
+    DECTCEM,
+    IRM,
+    LNM,
+    REVERSE_WRAP,
+    SAVE_CURSOR,
+    SYNCHRONIZED_OUTPUT,
+};
+use crate::parser::{ReplacementPolicy, StreamParser};
 use crate::parser_listener::ParserListener;
+use crate::prelude::*;
+use crate::reporter::Reporter;
+#[cfg(feature = "std")]
+use crate::reporter::VecReporter;
 
-#[derive(Clone, PartialEq, Debug)]
+/// The colors `default_fg_color` and `default_bg_color` start out
+/// with, in xterm's `rgb:` notation. The cursor takes the foreground.
+const DEFAULT_FG_COLOR: &str = "rgb:ffff/ffff/ffff";
+const DEFAULT_BG_COLOR: &str = "rgb:0000/0000/0000";
+
+/// How many entries `keyboard_flags` keeps.
+const KEYBOARD_FLAGS_DEPTH: usize = 16;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct CharOpts {
     pub data: String,
-    pub fg: String,
-    pub bg: String,
+    /// Colors and hyperlinks are shared between cells, see `color`.
+    pub fg: Arc<str>,
+    pub bg: Arc<str>,
     pub bold: bool,
     pub italics: bool,
     pub underscore: bool,
     pub strikethrough: bool,
     pub reverse: bool,
     pub blink: bool,
+    /// Hidden text, set by SGR 8; see `DisplayOptions::hide_concealed`.
+    pub conceal: bool,
+    pub hyperlink: Option<Arc<str>>,
 }
 
 impl CharOpts {
@@ -36,21 +73,116 @@ impl CharOpts {
             strikethrough: self.strikethrough,
             reverse: self.reverse,
             blink: self.blink,
+            conceal: self.conceal,
+            hyperlink: self.hyperlink.clone(),
+        }
+    }
+
+    /// Overwrite this cell with `attr` and a single character of data,
+    /// reusing the existing string allocations where possible.
+    fn assign(&mut self, attr: &CharOpts, data: char) {
+        self.data.clear();
+        self.data.push(data);
+        self.fg.clone_from(&attr.fg);
+        self.bg.clone_from(&attr.bg);
+        self.bold = attr.bold;
+        self.italics = attr.italics;
+        self.underscore = attr.underscore;
+        self.strikethrough = attr.strikethrough;
+        self.reverse = attr.reverse;
+        self.blink = attr.blink;
+        self.conceal = attr.conceal;
+        self.hyperlink.clone_from(&attr.hyperlink);
+    }
+
+    /// Returns `true` if both cells are drawn with the same attributes,
+    /// regardless of their data.
+    pub(crate) fn same_style(&self, other: &CharOpts) -> bool {
+        self.fg == other.fg
+            && self.bg == other.bg
+            && self.bold == other.bold
+            && self.italics == other.italics
+            && self.underscore == other.underscore
+            && self.strikethrough == other.strikethrough
+            && self.reverse == other.reverse
+            && self.blink == other.blink
+            && self.conceal == other.conceal
+    }
+
+    /// Returns the SGR parameters selecting these attributes, starting
+    /// with a reset.
+    fn sgr_params(&self) -> Vec<String> {
+        let mut params = vec!["0".to_string()];
+        let flags = [
+            ("bold", self.bold),
+            ("italics", self.italics),
+            ("underscore", self.underscore),
+            ("blink", self.blink),
+            ("reverse", self.reverse),
+            ("strikethrough", self.strikethrough),
+            ("conceal", self.conceal),
+        ];
+        for (name, set) in flags {
+            if let Some(code) = code_for(&TEXT, &format!("+{}", name)).filter(|_| set) {
+                params.push(code.to_string());
+            }
+        }
+        params.extend(color_param(&self.fg, &FG_ANSI, &FG_AIXTERM, FG_256));
+        params.extend(color_param(&self.bg, &BG_ANSI, &BG_AIXTERM, BG_256));
+        params
+    }
+
+    /// Returns the inline CSS for these attributes; empty if they are
+    /// all defaults. Reverse video swaps the colors, a default color
+    /// stays unset.
+    fn css_style(&self) -> String {
+        let (fg, bg) = if self.reverse {
+            (&self.bg, &self.fg)
+        } else {
+            (&self.fg, &self.bg)
+        };
+
+        let mut style = Vec::new();
+        if let Some(color) = css_color(fg) {
+            style.push(format!("color:{}", color));
+        }
+        if let Some(color) = css_color(bg) {
+            style.push(format!("background-color:{}", color));
+        }
+        if self.bold {
+            style.push("font-weight:bold".to_string());
+        }
+        if self.italics {
+            style.push("font-style:italic".to_string());
         }
+        let decorations = [
+            ("underline", self.underscore),
+            ("line-through", self.strikethrough),
+            ("blink", self.blink),
+        ]
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+        if !decorations.is_empty() {
+            style.push(format!("text-decoration:{}", decorations.join(" ")));
+        }
+        style.join(";")
     }
 
     fn update_from_map(&mut self, map: HashMap<String, String>) {
         for (key, value) in map {
             match key.as_str() {
                 "data" => self.data = value,
-                "fg" => self.fg = value,
-                "bg" => self.bg = value,
+                "fg" => self.fg = color(&value),
+                "bg" => self.bg = color(&value),
                 "bold" => self.bold = value.parse().unwrap_or(false),
                 "italics" => self.italics = value.parse().unwrap_or(false),
                 "underscore" => self.underscore = value.parse().unwrap_or(false),
                 "strikethrough" => self.strikethrough = value.parse().unwrap_or(false),
                 "reverse" => self.reverse = value.parse().unwrap_or(false),
                 "blink" => self.blink = value.parse().unwrap_or(false),
+                "conceal" => self.conceal = value.parse().unwrap_or(false),
                 _ => {}
             }
         }
@@ -59,40 +191,194 @@ impl CharOpts {
     fn to_map(&self) -> HashMap<String, String> {
         let mut map = HashMap::new();
         map.insert("data".to_string(), self.data.clone());
-        map.insert("fg".to_string(), self.fg.clone());
-        map.insert("bg".to_string(), self.bg.clone());
+        map.insert("fg".to_string(), self.fg.to_string());
+        map.insert("bg".to_string(), self.bg.to_string());
         map.insert("bold".to_string(), self.bold.to_string());
         map.insert("italics".to_string(), self.italics.to_string());
         map.insert("underscore".to_string(), self.underscore.to_string());
         map.insert("strikethrough".to_string(), self.strikethrough.to_string());
         map.insert("reverse".to_string(), self.reverse.to_string());
         map.insert("blink".to_string(), self.blink.to_string());
+        map.insert("conceal".to_string(), self.conceal.to_string());
         map
     }
 }
 
+/// Returns the number of columns a character takes on screen.
+pub type WidthFn = dyn Fn(char) -> u8 + Send;
+
+/// Called with the new value when the host changes a string setting,
+/// e.g. the title.
+pub type StringCallback = dyn FnMut(&str) + Send;
+
+/// Called with the new columns and lines when the screen is resized.
+pub type ResizeCallback = dyn FnMut(u32, u32) + Send;
+
+/// Called with the new state when the host turns a setting on or off.
+pub type FlagCallback = dyn FnMut(bool) + Send;
+
+/// Called with the bell style when the host rings the bell.
+pub type BellCallback = dyn FnMut(BellStyle) + Send;
+
+/// Returns the number of columns the grapheme cluster takes: the width of
+/// its base character, or two for emoji presentation sequences and flags.
+/// Character widths come from `width_fn`, falling back to `unicode-width`.
+fn grapheme_width(cluster: &str, width_fn: Option<&WidthFn>) -> usize {
+    let mut chars = cluster.chars();
+    let Some(base) = chars.next() else {
+        return 0;
+    };
+    let width = match width_fn {
+        Some(width_fn) => width_fn(base) as usize,
+        None => base.width().unwrap_or(0),
+    };
+    let is_regional_indicator = ('\u{1F1E6}'..='\u{1F1FF}').contains(&base);
+    if chars.next().is_some() && (is_regional_indicator || cluster.contains('\u{FE0F}')) {
+        2
+    } else {
+        width
+    }
+}
+
+/// The named and 256 color palette colors, shared by all cells using
+/// them.
+static COLORS: Lazy<HashMap<String, Arc<str>>> = Lazy::new(|| {
+    let names = FG_ANSI
+        .values()
+        .chain(BG_ANSI.values())
+        .chain(FG_AIXTERM.values())
+        .chain(BG_AIXTERM.values())
+        .chain(FG_BG_256.iter())
+        .map(String::as_str)
+        .chain(["default"]);
+    names
+        .map(|name| (name.to_owned(), Arc::from(name)))
+        .collect()
+});
+
+/// Returns the shared string for `name`, so that cells of the same
+/// color don't each hold a copy. Other colors, i.e. 24-bit ones, are
+/// allocated once per SGR sequence and shared by the cells drawn with it.
+pub(crate) fn color(name: &str) -> Arc<str> {
+    COLORS.get(name).cloned().unwrap_or_else(|| Arc::from(name))
+}
+
+/// Looks up the SGR code which maps to `name` in one of the `graphics`
+/// tables.
+fn code_for(map: &HashMap<u32, String>, name: &str) -> Option<u32> {
+    map.iter()
+        .find(|(_, value)| *value == name)
+        .map(|(code, _)| *code)
+}
+
+/// Returns the SGR parameter selecting `color`, either a named color or
+/// a `rrggbb` hex string, or `None` for the default color.
+fn color_param(
+    color: &str,
+    ansi: &HashMap<u32, String>,
+    aixterm: &HashMap<u32, String>,
+    extended: u32,
+) -> Option<String> {
+    if color == "default" {
+        return None;
+    }
+    if let Some(code) = code_for(ansi, color).or_else(|| code_for(aixterm, color)) {
+        return Some(code.to_string());
+    }
+    let rgb = u32::from_str_radix(color, 16)
+        .ok()
+        .filter(|_| color.len() == 6)?;
+    Some(format!(
+        "{};2;{};{};{}",
+        extended,
+        rgb >> 16,
+        (rgb >> 8) & 0xff,
+        rgb & 0xff
+    ))
+}
+
+/// Returns `color` in xterm's `rgb:rrrr/gggg/bbbb` form, given as
+/// `rgb:r/g/b` with one to four hex digits per channel, scaled to 16
+/// bits, or as `#rgb`, `#rrggbb`, `#rrrgggbbb` or `#rrrrggggbbbb`, whose
+/// digits are the high bits.
+fn parse_x_color(color: &str) -> Option<String> {
+    let hex = |digits: &str| {
+        if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        u32::from_str_radix(digits, 16).ok()
+    };
+    let channels = if let Some(rgb) = color.strip_prefix("rgb:") {
+        let channels = rgb
+            .split('/')
+            .map(|digits| {
+                let value = hex(digits)?;
+                let max = (1 << (4 * digits.len())) - 1;
+                Some((value * 0xffff + max / 2) / max)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        (channels.len() == 3).then_some(channels)?
+    } else {
+        let digits = color.strip_prefix('#')?;
+        let width = digits.len() / 3;
+        if digits.len() % 3 != 0 || !digits.is_ascii() {
+            return None;
+        }
+        (0..3)
+            .map(|i| Some(hex(&digits[i * width..(i + 1) * width])? << (16 - 4 * width)))
+            .collect::<Option<Vec<_>>>()?
+    };
+    Some(format!(
+        "rgb:{:04x}/{:04x}/{:04x}",
+        channels[0], channels[1], channels[2]
+    ))
+}
+
+/// Returns the CSS color for `color`: named colors use the xterm
+/// palette, hex strings pass through and the default color is `None`.
+fn css_color(color: &str) -> Option<String> {
+    if color == "default" {
+        return None;
+    }
+    let index = code_for(&FG_ANSI, color)
+        .map(|code| code - 30)
+        .or_else(|| code_for(&FG_AIXTERM, color).map(|code| code - 90 + 8));
+    match index {
+        Some(index) => Some(format!("#{}", FG_BG_256[index as usize])),
+        None if color.len() == 6 && color.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Some(format!("#{}", color))
+        }
+        None => None,
+    }
+}
+
 impl Default for CharOpts {
     fn default() -> Self {
         Self {
             data: " ".to_owned(),
-            fg: "default".to_owned(),
-            bg: "default".to_owned(),
+            fg: color("default"),
+            bg: color("default"),
             bold: false,
             italics: false,
             underscore: false,
             strikethrough: false,
             reverse: false,
             blink: false,
+            conceal: false,
+            hyperlink: None,
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Cursor {
     pub x: u32,
     pub y: u32,
     pub attr: CharOpts,
     pub hidden: bool,
+    /// Whether the cursor blinks, set by `CSI ? 12 h`. Not to be
+    /// confused with `attr.blink`, which makes text blink.
+    pub blink: bool,
 }
 
 /// A container for screen's scroll margins
@@ -103,6 +389,7 @@ pub struct Margins {
 }
 
 /// A container for savepoint, created on :data:`~pyte.escape.DECSC`.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Savepoint {
     pub cursor: Cursor,
     pub g0_charset: [char; 256],
@@ -112,28 +399,110 @@ pub struct Savepoint {
     pub wrap: bool,
 }
 
-lazy_static! {
-    static ref _DEFAULT_MODE: HashSet<u32> = {
-        let mut m = HashSet::new();
-        m.insert(DECAWM);
-        m.insert(DECTCEM);
-        m
-    };
+/// A copy of the screen state taken by `Screen::snapshot`, put back by
+/// `Screen::restore`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreenSnapshot {
+    columns: u32,
+    lines: u32,
+    buffer: Vec<Vec<CharOpts>>,
+    wrapped: Vec<bool>,
+    primary_screen: Option<(Vec<Vec<CharOpts>>, Vec<bool>)>,
+    cursor: Cursor,
+    pending_wrap: bool,
+    savepoint: Option<Savepoint>,
+    mode: HashSet<u32>,
+    margins: Option<Margins>,
+    tabstops: HashSet<u32>,
+    charset: Charset,
+    g0_charset: [char; 256],
+    g1_charset: [char; 256],
+    title: String,
+    icon_name: String,
+    saved_columns: Option<u32>,
+    keypad_application: bool,
 }
 
+static _DEFAULT_MODE: Lazy<HashSet<u32>> = Lazy::new(|| {
+    let mut m = HashSet::new();
+    m.insert(DECAWM);
+    m.insert(DECTCEM);
+    m
+});
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Charset {
     G0,
     G1,
 }
 
+/// How a frontend should ring the bell, see `Screen::set_bell_style`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BellStyle {
+    /// Beep, the default.
+    #[default]
+    Audible,
+    /// Flash the screen instead.
+    Visual,
+}
+
 pub struct Screen {
-    pub savepoints: Vec<Savepoint>,
+    /// The cursor state saved by DECSC, there is only one.
+    pub savepoint: Option<Savepoint>,
     pub columns: u32,
     pub lines: u32,
     pub dirty: HashSet<u32>,
+    /// Rows scrolled off the top of the screen, oldest first, at most
+    /// `scrollback_limit` of them.
+    pub scrollback: VecDeque<Vec<CharOpts>>,
+    /// How many rows `scrollback` keeps, 0 to keep none. Each row holds
+    /// `columns` cells, so the default of 1000 costs a thousand screen
+    /// rows of memory once the history fills up.
+    pub scrollback_limit: usize,
+    /// Whether switching column mode (DECCOLM) clears the screen, as on
+    /// a VT100. Some emulators keep the content, set to `false` to do
+    /// the same. `true` by default.
+    pub decolm_clears: bool,
+    /// Background color erase: whether erasing, scrolling, inserting and
+    /// deleting fill the new blanks with the cursor attributes, as
+    /// terminals with the `bce` terminfo capability do, or with the
+    /// defaults. `true` by default, like xterm.
+    pub bce: bool,
+    /// Whether drawn text, and a cell a combining mark is added to, is
+    /// stored NFC normalized, e.g. `e\u{301}` as `\u{e9}`. `true` by
+    /// default, set to `false` to keep the code points as received.
+    pub normalize: bool,
+    /// Whether `display` keeps every row exactly `columns` wide. A wide
+    /// character without room for its trailing half, e.g. drawn in the
+    /// last column with auto wrap off, is then shown as a space, and the
+    /// cell after a wide character is only skipped if it is the trailing
+    /// half. `false` by default, as in pyte.
+    pub display_fixed_width: bool,
+    /// The width and height of a cell in pixels, used to report the
+    /// size of the text area in pixels (`CSI 14 t`). `(8, 16)` by
+    /// default.
+    pub cell_size: (u32, u32),
+    /// The default foreground, background and cursor colors as last set
+    /// by the application with `OSC 10`, `OSC 11` and `OSC 12`, always in
+    /// `rgb:rrrr/gggg/bbbb` form, e.g. `#ff0000` is kept as
+    /// `rgb:ffff/0000/0000`. Values which don't parse are ignored and
+    /// `OSC 110` to `OSC 112` restore the defaults. A renderer draws
+    /// `"default"` colors with these, and queries are answered with them.
+    /// White on black by default.
+    pub default_fg_color: String,
+    pub default_bg_color: String,
+    pub cursor_color: String,
+    /// How many rows `display_view` is scrolled back into `scrollback`.
+    pub view_offset: u32,
+    /// The first and last changed column of each row changed since the
+    /// last `take_dirty_ranges` or `take_dirty_cells`.
+    pub dirty_ranges: HashMap<u32, (u32, u32)>,
     pub margins: Option<Margins>,
-    pub buffer: HashMap<u32, HashMap<u32, CharOpts>>,
+    pub buffer: Vec<Vec<CharOpts>>,
+    /// The rows and `wrapped` flags of the primary screen while the
+    /// alternate screen (`CSI ? 1047 h` or `CSI ? 1049 h`) is shown in
+    /// `buffer`.
+    pub primary_screen: Option<(Vec<Vec<CharOpts>>, Vec<bool>)>,
     pub mode: HashSet<u32>,
     pub title: String,
     pub icon_name: String,
@@ -143,79 +512,387 @@ pub struct Screen {
     pub tabstops: HashSet<u32>,
     pub cursor: Cursor,
     pub saved_columns: Option<u32>,
+    /// Set after a character is written into the last column with
+    /// `DECAWM` on: the cursor stays on that column and the line wraps
+    /// when the next character is drawn. Cursor movement, erasing and
+    /// editing operations cancel it.
+    pub pending_wrap: bool,
+    /// `wrapped[y]` is set when row `y` continues the row above it
+    /// because the text was auto wrapped, see `is_wrapped`.
+    pub wrapped: Vec<bool>,
+    /// Where replies to the host (device attributes, status reports)
+    /// are sent. Replies are dropped when unset.
+    pub reporter: Option<Box<dyn Reporter>>,
+    /// Called with the new title when the host sets it (OSC 0 or 2).
+    pub on_title_change: Option<Box<StringCallback>>,
+    /// Called with the new icon name when the host sets it (OSC 0 or 1).
+    pub on_icon_name_change: Option<Box<StringCallback>>,
+    /// Called with the new size after `resize`, `resize_reflow` or
+    /// DECCOLM change it, e.g. to resize the pty.
+    pub on_resize: Option<Box<ResizeCallback>>,
+    /// Called with `true` when synchronized output (`CSI ? 2026 h`)
+    /// begins a frame and `false` when it ends.
+    pub on_sync_change: Option<Box<FlagCallback>>,
+    /// Called with `bell_style` when the host rings the bell (`BEL`).
+    pub on_bell: Option<Box<BellCallback>>,
+    /// Whether the bell should be heard or seen, kept by `reset`.
+    pub bell_style: BellStyle,
+    /// The first reply `reporter` failed to write, for `try_feed`.
+    #[cfg(feature = "std")]
+    output_error: Option<std::io::Error>,
+    /// Set by DECKPAM when the keypad sends application sequences,
+    /// cleared by DECKPNM.
+    pub keypad_application: bool,
+    /// Sent to the host in reply to `ENQ`, empty by default.
+    pub answerback: String,
+    /// xterm's modifyOtherKeys level set by `CSI > 4 ; Ps m`, for input
+    /// encoders deciding how to report modified keys. 0, off, by default.
+    pub modify_other_keys: u32,
+    /// The kitty keyboard protocol flags pushed by `CSI > flags u`, the
+    /// current ones last, for input encoders like `modify_other_keys`.
+    /// Empty, no enhancements, by default.
+    pub keyboard_flags: Vec<u32>,
+    /// Set by `CSI 5 i`: everything the parser reads, controls and
+    /// sequences included, goes to `printer_output` instead of the screen
+    /// until `CSI 4 i`.
+    pub printer_controller: bool,
+    /// Set by `CSI ? 5 i`: the cursor line is printed before each
+    /// linefeed, cleared by `CSI ? 4 i`.
+    pub auto_print: bool,
+    /// Text sent to the printer by media copy, see `take_printer_output`.
+    pub printer_output: String,
+    /// Overrides the width `unicode-width` gives characters, see
+    /// `set_width_fn`.
+    pub width_fn: Option<Box<WidthFn>>,
+    /// What control characters and zero width characters other than
+    /// combining marks reaching `draw` are drawn as, nothing by default,
+    /// see `set_replacement_policy`.
+    pub replacement_policy: ReplacementPolicy,
+    /// Parser state kept between `feed` calls, so sequences may be split
+    /// across them.
+    pub parser: StreamParser,
+    /// The state `reset` returns to, see `ScreenBuilder`.
+    defaults: Defaults,
 }
 
-impl Display for Screen {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("Screen ({}, {})", self.columns, self.lines))
+/// The initial state of a screen, set by `ScreenBuilder`.
+#[derive(Clone, Debug)]
+struct Defaults {
+    mode: HashSet<u32>,
+    fg: Arc<str>,
+    bg: Arc<str>,
+    tab_width: u32,
+    g0_charset: [char; 256],
+    g1_charset: [char; 256],
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Self {
+            mode: _DEFAULT_MODE.clone(),
+            fg: color("default"),
+            bg: color("default"),
+            // From ``man terminfo`` -- "... hardware tabs are initially
+            // set every `n` spaces when the terminal is powered up. Since
+            // we aim to support VT102 / VT220 and linux -- we use n = 8.
+            tab_width: 8,
+            g0_charset: LAT1_MAP,
+            g1_charset: VT100_MAP,
+        }
     }
 }
 
-impl Screen {
+/// Configures the initial state of a `Screen`, which `reset` also
+/// returns to.
+///
+/// ```
+/// use memterm::screen::ScreenBuilder;
+///
+/// let screen = ScreenBuilder::new(80, 24)
+///     .tab_width(4)
+///     .default_fg("green")
+///     .build();
+/// assert_eq!(&*screen.cursor.attr.fg, "green");
+/// ```
+#[derive(Clone, Debug)]
+pub struct ScreenBuilder {
+    columns: u32,
+    lines: u32,
+    defaults: Defaults,
+}
+
+impl ScreenBuilder {
+    /// A builder for a `columns` by `lines` screen with the defaults of
+    /// `Screen::new`.
     pub fn new(columns: u32, lines: u32) -> Self {
+        Self { columns, lines, defaults: Defaults::default() }
+    }
+
+    /// Replace the initially set modes, `DECAWM` and `DECTCEM` by
+    /// default. Modes are only recorded, e.g. `DECCOLM` doesn't resize.
+    pub fn modes(mut self, modes: &[u32]) -> Self {
+        self.defaults.mode = modes.iter().copied().collect();
+        self
+    }
+
+    /// Whether auto wrap (`DECAWM`) is initially on.
+    pub fn autowrap(mut self, autowrap: bool) -> Self {
+        if autowrap {
+            self.defaults.mode.insert(DECAWM);
+        } else {
+            self.defaults.mode.remove(&DECAWM);
+        }
+        self
+    }
+
+    /// The color of blank cells and of text after `SGR 0` or `SGR 39`, a
+    /// color name or hex `"rrggbb"` value.
+    pub fn default_fg(mut self, fg: &str) -> Self {
+        self.defaults.fg = color(fg);
+        self
+    }
+
+    /// The background counterpart of `default_fg`.
+    pub fn default_bg(mut self, bg: &str) -> Self {
+        self.defaults.bg = color(bg);
+        self
+    }
+
+    /// Put tab stops every `tab_width` columns, none if it is 0.
+    pub fn tab_width(mut self, tab_width: u32) -> Self {
+        self.defaults.tab_width = tab_width;
+        self
+    }
+
+    /// The ``G0`` charset, ``LAT1_MAP`` by default.
+    pub fn g0_charset(mut self, charset: [char; 256]) -> Self {
+        self.defaults.g0_charset = charset;
+        self
+    }
+
+    /// The ``G1`` charset, ``VT100_MAP`` by default.
+    pub fn g1_charset(mut self, charset: [char; 256]) -> Self {
+        self.defaults.g1_charset = charset;
+        self
+    }
+
+    pub fn build(self) -> Screen {
+        let defaults = self.defaults;
         let mut screen = Screen {
-            savepoints: Vec::new(),
-            columns,
-            lines,
-            buffer: HashMap::new(),
+            savepoint: None,
+            columns: self.columns,
+            lines: self.lines,
+            buffer: Vec::new(),
+            primary_screen: None,
             dirty: HashSet::new(),
-            mode: _DEFAULT_MODE.clone(),
+            dirty_ranges: HashMap::new(),
+            scrollback: VecDeque::new(),
+            scrollback_limit: 1000,
+            decolm_clears: true,
+            bce: true,
+            normalize: true,
+            display_fixed_width: false,
+            cell_size: (8, 16),
+            default_fg_color: DEFAULT_FG_COLOR.to_owned(),
+            default_bg_color: DEFAULT_BG_COLOR.to_owned(),
+            cursor_color: DEFAULT_FG_COLOR.to_owned(),
+            view_offset: 0,
+            mode: defaults.mode.clone(),
             margins: None,
             title: String::new(),
             icon_name: String::new(),
             charset: Charset::G0,
-            g0_charset: LAT1_MAP.clone(),
-            g1_charset: VT100_MAP.clone(),
+            g0_charset: defaults.g0_charset,
+            g1_charset: defaults.g1_charset,
             tabstops: HashSet::new(),
             cursor: Cursor {
                 x: 0,
                 y: 0,
                 attr: CharOpts::default(),
                 hidden: false,
+                blink: false,
             },
             saved_columns: None,
+            pending_wrap: false,
+            wrapped: Vec::new(),
+            reporter: None,
+            #[cfg(feature = "std")]
+            output_error: None,
+            on_title_change: None,
+            on_resize: None,
+            on_sync_change: None,
+            on_bell: None,
+            bell_style: BellStyle::default(),
+            on_icon_name_change: None,
+            keypad_application: false,
+            answerback: String::new(),
+            modify_other_keys: 0,
+            keyboard_flags: Vec::new(),
+            printer_controller: false,
+            auto_print: false,
+            printer_output: String::new(),
+            width_fn: None,
+            replacement_policy: ReplacementPolicy::Skip,
+            parser: StreamParser::new(),
+            defaults,
         };
 
+        // Sets up the buffer, tab stops and cursor from the defaults.
         screen.reset();
         screen
     }
+}
+
+/// How `Screen::display_with` renders cells. The defaults match
+/// `Screen::display`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DisplayOptions {
+    /// Render concealed (SGR 8) text as spaces, as a terminal shows it.
+    pub hide_concealed: bool,
+    /// Render blinking text as spaces, as in the "off" phase of the
+    /// blink.
+    pub blink_off: bool,
+    /// Render the trailing half of a wide character as this, rather than
+    /// leaving it out, so every cell takes one character of the line.
+    pub wide_continuation: Option<char>,
+}
+
+/// A rectangle of cells: the 0-based rows and columns of its edges, all
+/// inclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub top: u32,
+    pub left: u32,
+    pub bottom: u32,
+    pub right: u32,
+}
+
+/// A cell which differs between two screens, see `Screen::diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellChange {
+    pub x: u32,
+    pub y: u32,
+    pub old: CharOpts,
+    pub new: CharOpts,
+}
+
+/// Screens are equal when they show the same cells, with the cursor in
+/// the same place and the same modes set.
+impl PartialEq for Screen {
+    fn eq(&self, other: &Self) -> bool {
+        self.columns == other.columns
+            && self.lines == other.lines
+            && self.cursor == other.cursor
+            && self.mode == other.mode
+            && self.diff(other).is_empty()
+    }
+}
+
+impl Display for Screen {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!("Screen ({}, {})", self.columns, self.lines))
+    }
+}
+
+impl Screen {
+    pub fn new(columns: u32, lines: u32) -> Self {
+        ScreenBuilder::new(columns, lines).build()
+    }
 
     ///A list of screen lines as unicode strings.
-    pub fn display(&mut self) -> Vec<String> {
-        let default_char = self.default_char();
-        let render = |line: &mut HashMap<u32, CharOpts>| -> String {
-            let mut result = String::new();
-            let mut is_wide_char = false;
-            for x in 0..self.columns {
-                if is_wide_char {
-                    is_wide_char = false;
-                    continue;
-                }
-                let char = line.entry(x).or_insert(default_char.clone()).data.clone();
-                is_wide_char = char
-                    .chars()
-                    .next()
-                    .expect("can not read char")
-                    .width()
-                    .is_some_and(|s| s == 2);
-                result.push_str(&char);
-            }
+    pub fn display(&self) -> Vec<String> {
+        self.display_with(DisplayOptions::default())
+    }
 
-            return result;
-        };
+    /// Like `display`, with concealed and blinking text and wide
+    /// characters rendered as `options` asks.
+    pub fn display_with(&self, options: DisplayOptions) -> Vec<String> {
+        self.buffer
+            .iter()
+            .map(|line| self.display_line_with(line, &options))
+            .collect()
+    }
+
+    /// Like `display`, but scrolled back `view_offset` rows: the top rows
+    /// come from `scrollback`, padded or cut to the screen width.
+    pub fn display_view(&self) -> Vec<String> {
+        let offset = usize::min(self.view_offset as usize, self.scrollback.len());
+        let blank = self.default_char();
+        let history = self
+            .scrollback
+            .range(self.scrollback.len() - offset..)
+            .take(self.lines as usize)
+            .map(|line| {
+                let mut line = line.clone();
+                line.resize(self.columns as usize, blank.clone());
+                self.display_line(&line)
+            });
+        let live = self.buffer[..self.buffer.len().saturating_sub(offset)]
+            .iter()
+            .map(|line| self.display_line(line));
+        history.chain(live).collect()
+    }
 
-        let mut result = Vec::new();
-        for y in 0..self.lines {
-            let line_render = render(
-                &mut self
-                    .buffer
-                    .entry(y)
-                    .or_insert(HashMap::<u32, CharOpts>::new()),
-            );
-            result.push(line_render);
+    /// Scroll the view `count` rows back into `scrollback`, see
+    /// `display_view`.
+    pub fn scroll_view_up(&mut self, count: u32) {
+        self.view_offset = u32::min(self.view_offset + count, self.scrollback.len() as u32);
+    }
+
+    /// Scroll the view `count` rows towards the live screen.
+    pub fn scroll_view_down(&mut self, count: u32) {
+        self.view_offset = self.view_offset.saturating_sub(count);
+    }
+
+    /// Append a row scrolled off the top to `scrollback`, dropping the
+    /// oldest ones beyond `scrollback_limit`, which may have been lowered
+    /// since. A scrolled back view moves along so it keeps showing the
+    /// same rows.
+    fn push_scrollback(&mut self, row: Vec<CharOpts>) {
+        while self.scrollback.len() >= self.scrollback_limit {
+            if self.scrollback.pop_front().is_none() {
+                return;
+            }
+        }
+        self.scrollback.push_back(row);
+        if self.view_offset > 0 {
+            self.view_offset = u32::min(self.view_offset + 1, self.scrollback.len() as u32);
         }
+    }
+
+    /// Renders `line` one column per cell, a wide character standing for
+    /// its trailing half too. See `display_fixed_width` for the ones
+    /// without room for it.
+    fn display_line(&self, line: &[CharOpts]) -> String {
+        self.display_line_with(line, &DisplayOptions::default())
+    }
 
-        return result;
+    fn display_line_with(&self, line: &[CharOpts], options: &DisplayOptions) -> String {
+        let mut result = String::with_capacity(line.len());
+        let mut cells = line.iter().peekable();
+        while let Some(cell) = cells.next() {
+            let hidden = !cell.data.is_empty()
+                && (options.hide_concealed && cell.conceal || options.blink_off && cell.blink);
+            let data = if hidden { " " } else { &cell.data };
+            if grapheme_width(&cell.data, self.width_fn.as_deref()) != 2 {
+                result.push_str(data);
+                continue;
+            }
+            if !self.display_fixed_width {
+                cells.next();
+            } else if cells.next_if(|next| next.data.is_empty()).is_none() {
+                result.push(' ');
+                continue;
+            }
+            result.push_str(data);
+            match options.wide_continuation {
+                Some(continuation) => result.push(continuation),
+                // A space, as the hidden character took two columns.
+                None if hidden => result.push(' '),
+                None => {}
+            }
+        }
+        result
     }
 
     /// Resize the screen to the given size.
@@ -228,6 +905,9 @@ impl Screen {
     /// be added at the right, and if it has more columns will be
     /// clipped at the right.
     ///
+    /// The cursor keeps its position, moved onto the last line or column
+    /// if it is past the new ones. The margins are dropped.
+    ///
     /// # Arguments
     ///
     /// * `lines` - number of lines in the new screen.
@@ -245,25 +925,161 @@ impl Screen {
             return; // No changes.
         }
 
+        let old_columns = self.columns;
         self.dirty.extend(0..lines);
+        self.pending_wrap = false;
 
         if lines < self.lines {
-            self.save_cursor();
+            let cursor = self.cursor.clone();
             self.cursor_position(Some(0), Some(0));
             self.delete_lines(Some(self.lines - lines)); // Drop from the top.
-            self.restore_cursor();
+            self.cursor = cursor;
+        }
+
+        let default_char = self.default_char();
+        for line in self.buffer.iter_mut() {
+            line.resize(columns as usize, default_char.clone());
+        }
+        self.buffer
+            .resize(lines as usize, vec![default_char; columns as usize]);
+        self.wrapped.resize(lines as usize, false);
+
+        (self.lines, self.columns) = (lines, columns);
+        self.mark_dirty_rows(0..lines);
+        // Unlike DECSTBM, dropping the margins here leaves the cursor,
+        // only clamped to the new size.
+        self.margins = None;
+        self.ensure_hbounds();
+        self.ensure_vbounds(None);
+        self.resized(old_columns);
+    }
+
+    /// Resize the screen to `columns` by `lines`, in the order of
+    /// `Screen::new`. See `resize`.
+    pub fn set_size(&mut self, columns: u32, lines: u32) {
+        self.resize(Some(lines), Some(columns));
+    }
+
+    /// Change the number of columns only, see `resize`.
+    pub fn resize_cols(&mut self, columns: u32) {
+        self.resize(None, Some(columns));
+    }
+
+    /// Change the number of lines only, see `resize`.
+    pub fn resize_rows(&mut self, lines: u32) {
+        self.resize(Some(lines), None);
+    }
+
+    /// Called once the size changed from `old_columns`: drops tab stops
+    /// past the new width, puts default ones in added columns and calls
+    /// `on_resize`.
+    fn resized(&mut self, old_columns: u32) {
+        let columns = self.columns;
+        self.tabstops.retain(|&x| x < columns);
+        let tab_width = self.defaults.tab_width;
+        if tab_width > 0 && columns > old_columns {
+            let first = old_columns.div_ceil(tab_width).max(1) * tab_width;
+            self.tabstops
+                .extend((first..columns).step_by(tab_width as usize));
+        }
+
+        if let Some(callback) = self.on_resize.as_mut() {
+            callback(self.columns, self.lines);
+        }
+    }
+
+    /// Resize the screen, re-wrapping text to the new width.
+    ///
+    /// Unlike `resize`, rows joined by auto wrap (see `is_wrapped`) are
+    /// treated as one logical line and laid out again at the new width,
+    /// keeping the attributes of every cell. The cursor stays on the
+    /// character it was on. If the result has more rows than `lines`,
+    /// blank rows below the cursor are dropped first, then rows at the
+    /// top.
+    pub fn resize_reflow(&mut self, lines: u32, columns: u32) {
+        if lines == self.lines && columns == self.columns {
+            return; // No changes.
         }
 
-        if columns < self.columns {
-            for line in self.buffer.values_mut() {
-                for x in columns..self.columns {
-                    line.remove(&x);
+        let old_columns = self.columns;
+        let blank = self.default_char();
+        let width = columns as usize;
+        let mut rows: Vec<Vec<CharOpts>> = Vec::new();
+        let mut wrapped: Vec<bool> = Vec::new();
+        let mut cursor = (0, 0);
+
+        let mut y = 0;
+        while y < self.buffer.len() {
+            // Collect the logical line starting at row `y`.
+            let start = y;
+            let mut cells: Vec<CharOpts> = self.buffer[y].clone();
+            y += 1;
+            while y < self.buffer.len() && self.is_wrapped(y as u32) {
+                cells.extend(self.buffer[y].iter().cloned());
+                y += 1;
+            }
+
+            let cursor_cell = (start..y).contains(&(self.cursor.y as usize)).then(|| {
+                (self.cursor.y as usize - start) * self.columns as usize + self.cursor.x as usize
+            });
+            let keep = cursor_cell.map_or(0, |cell| cell + 1);
+            while cells.len() > keep && cells.last() == Some(&blank) {
+                cells.pop();
+            }
+
+            // Lay it out again, never splitting a wide character.
+            let mut row: Vec<CharOpts> = Vec::with_capacity(width);
+            wrapped.push(false);
+            for (i, cell) in cells.into_iter().enumerate() {
+                let is_wide = grapheme_width(&cell.data, self.width_fn.as_deref()) == 2;
+                if row.len() == width || (is_wide && row.len() + 2 > width && !row.is_empty()) {
+                    row.resize(width, blank.clone());
+                    rows.push(core::mem::replace(&mut row, Vec::with_capacity(width)));
+                    wrapped.push(true);
                 }
+                if cursor_cell == Some(i) {
+                    cursor = (rows.len(), row.len());
+                }
+                row.push(cell);
             }
+            row.resize(width, blank.clone());
+            rows.push(row);
+        }
+
+        // Pending wrap only survives if the character is still in the
+        // last column, otherwise the cursor moves right past it.
+        if self.pending_wrap && cursor.1 + 1 < width {
+            cursor.1 += 1;
+            self.pending_wrap = false;
+        }
+
+        while rows.len() > lines as usize
+            && rows.len() - 1 > cursor.0
+            && rows.last() == Some(&vec![blank.clone(); width])
+        {
+            rows.pop();
+            wrapped.pop();
         }
+        let excess = rows.len().saturating_sub(lines as usize);
+        rows.drain(..excess);
+        wrapped.drain(..excess);
+        if let Some(first) = wrapped.first_mut() {
+            *first = false;
+        }
+        rows.resize(lines as usize, vec![blank; width]);
+        wrapped.resize(lines as usize, false);
 
+        self.buffer = rows;
+        self.wrapped = wrapped;
         (self.lines, self.columns) = (lines, columns);
-        self.set_margins(None, None);
+        self.dirty.extend(0..lines);
+        self.mark_dirty_rows(0..lines);
+        self.cursor.y = cursor.0.saturating_sub(excess) as u32;
+        self.cursor.x = cursor.1 as u32;
+        self.ensure_hbounds();
+        self.ensure_vbounds(None);
+        self.margins = None;
+        self.resized(old_columns);
     }
 
     // Select top and bottom margins for the scrolling region.
@@ -274,6 +1090,7 @@ impl Screen {
         // XXX 0 corresponds to the CSI with no parameters.
         if top.or(Some(0)).expect("unexpected top value") == 0 && bottom.is_none() {
             self.margins = None;
+            self.cursor_position(None, None);
             return;
         }
 
@@ -285,28 +1102,14 @@ impl Screen {
         // Arguments are 1-based, while :attr:`margins` are zero
         // based -- so we have to decrement them by one. We also
         // make sure that both of them is bounded by [0, lines - 1].
-        let top = if top.is_none() {
-            margins_inner.top as i32
-        } else {
-            i32::max(
-                0,
-                i32::min(
-                    top.expect("unexpected top value") as i32 - 1,
-                    self.lines as i32 - 1,
-                ),
-            )
+        let top = match top {
+            None => margins_inner.top as i32,
+            Some(top) => i32::max(0, i32::min(top as i32 - 1, self.lines as i32 - 1)),
         };
 
-        let bottom = if bottom.is_none() {
-            margins_inner.bottom as i32
-        } else {
-            i32::max(
-                0,
-                i32::min(
-                    bottom.expect("unexpected bottom value") as i32 - 1,
-                    self.lines as i32 - 1,
-                ),
-            )
+        let bottom = match bottom {
+            None => margins_inner.bottom as i32,
+            Some(bottom) => i32::max(0, i32::min(bottom as i32 - 1, self.lines as i32 - 1)),
         };
 
         // Even though VT102 and VT220 require DECSTBM to ignore
@@ -316,3447 +1119,7329 @@ impl Screen {
             self.margins = Some(Margins { top: top as u32, bottom: bottom as u32 });
             // The cursor moves to the home position when the top and
             // bottom margins of the scrolling region (DECSTBM) changes.
+            // The margins are set first so that under DECOM home is the
+            // top of the new region.
             self.cursor_position(None, None);
         }
     }
 
     // Ensure the cursor is within horizontal screen bounds."""
     pub fn ensure_hbounds(&mut self) {
-        self.cursor.x = u32::min(u32::max(0, self.cursor.x), self.columns - 1)
+        self.cursor.x = u32::min(self.cursor.x, self.columns.saturating_sub(1))
     }
 
     // Ensure the cursor is within vertical screen bounds.
     pub fn ensure_vbounds(&mut self, use_margins: Option<bool>) {
-        let (top, bottom) = if (use_margins.unwrap_or(false) || self.mode.contains(&DECOM))
-            && self.margins.is_some()
-        {
-            let Margins { top, bottom } = self.margins.unwrap();
-            (top, bottom)
-        } else {
-            (0, self.lines - 1)
+        let (top, bottom) = match self.margins {
+            Some(Margins { top, bottom })
+                if use_margins.unwrap_or(false) || self.mode.contains(&DECOM) =>
+            {
+                (top, bottom)
+            }
+            _ => (0, self.lines.saturating_sub(1)),
         };
 
         self.cursor.y = u32::min(u32::max(top, self.cursor.y), bottom)
     }
 
-    /// Write to the process input.
-    pub fn write_process_input(&self, _input: &str) {
-        // Implementation for writing to the process input.
+    /// Returns `true` if `mode` is set. Private modes (`CSI ? ...`) are
+    /// looked up with `private` set, e.g. `is_mode_set(7, true)` for
+    /// auto wrap.
+    pub fn is_mode_set(&self, mode: u32, private: bool) -> bool {
+        let mode = if private { mode << 5 } else { mode };
+        self.mode.contains(&mode)
     }
 
-    /// Returns an empty character with default foreground and background colors.
-    pub fn default_char(&self) -> CharOpts {
-        CharOpts {
-            data: " ".to_owned(),
-            fg: "default".to_owned(),
-            bg: "default".to_owned(),
-            reverse: self.mode.contains(&DECSCNM),
-            ..CharOpts::default()
+    /// The modes which are set, encoded as the constants in
+    /// `crate::modes`.
+    pub fn modes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.mode.iter().copied()
+    }
+
+    /// Returns `true` if the cursor keys send application sequences
+    /// (DECCKM).
+    pub fn cursor_keys_application(&self) -> bool {
+        self.mode.contains(&DECCKM)
+    }
+
+    /// Returns the 0-based top and bottom lines of the scrolling region,
+    /// the whole screen if no margins are set.
+    pub fn scroll_region(&self) -> (u32, u32) {
+        match self.margins {
+            Some(Margins { top, bottom }) => (top, bottom),
+            None => (0, self.lines.saturating_sub(1)),
         }
     }
-}
 
-impl ParserListener for Screen {
-    /// Fills screen with uppercase E's for screen focus and alignment.
-    fn alignment_display(&mut self) {
-        self.dirty.extend(0..self.lines);
-        for y in 0..self.lines {
-            let line = self.buffer.entry(y).or_insert_with(HashMap::new);
-            for x in 0..self.columns {
-                // TODO check this default, should be default_char on screen
-                let char_opts = line.entry(x).or_insert_with(CharOpts::default);
-                char_opts.data = "E".to_string();
-            }
+    /// Set the distance between the tab stops `reset` puts in. With
+    /// `reseed` the current tab stops are replaced right away, otherwise
+    /// they are kept until the next reset.
+    pub fn set_tab_width(&mut self, tab_width: u32, reseed: bool) {
+        self.defaults.tab_width = tab_width;
+        if reseed {
+            self.reset_tab_stops();
         }
     }
 
-    /// Define ``G0`` or ``G1`` charset.
-    ///
-    /// # Arguments
-    /// * `code` - character set code, should be a character
-    ///  from ``"B0UK"``, otherwise ignored.
-    ///
-    /// * `mode` - if ``"("`` ``G0`` charset is defined, if
-    ///  ``")"`` we operate on ``G1``.
-    ///
-    /// **Warning:** User-defined charsets are currently not supported.
-    fn define_charset(&mut self, code: &str, mode: &str) {
-        if MAPS.keys().any(|&a| a == code) {
-            if mode == "(" {
-                self.g0_charset = MAPS
-                    .get(code)
-                    .expect(&format!("unexpected character map key {}", code))
-                    .clone();
-            } else if mode == ")" {
-                self.g1_charset = MAPS
-                    .get(code)
-                    .expect(&format!("unexpected character map key {}", code))
-                    .clone();
-            }
+    /// Put tab stops every `tab_width` columns, none if it is 0.
+    fn reset_tab_stops(&mut self) {
+        self.tabstops.clear();
+        let tab_width = self.defaults.tab_width;
+        if tab_width > 0 {
+            self.tabstops
+                .extend((tab_width..self.columns).step_by(tab_width as usize));
         }
     }
 
-    /// Reset the terminal to its initial state.
-    ///
-    /// - Scrolling margins are reset to screen boundaries.
-    /// - Cursor is moved to home location `(0, 0)` and its attributes are set to defaults.
-    /// - Screen is cleared, each character is reset to default char.
-    /// - Tabstops are reset to "every eight columns".
-    /// - All lines are marked as dirty.
-    ///
-    /// **Warning**
-    /// Neither VT220 nor VT102 manuals mention that terminal modes and tabstops should be reset as well.
-    /// Thanks to `xterm` -- we now know that.
-    fn reset(&mut self) {
-        self.dirty.clear();
-        self.dirty.extend(0..self.lines);
-        self.buffer.clear();
-        self.margins = None;
+    /// Returns the columns with a tab stop, in ascending order.
+    pub fn tab_stops(&self) -> Vec<u32> {
+        let mut tab_stops: Vec<u32> = self.tabstops.iter().copied().collect();
+        tab_stops.sort_unstable();
+        tab_stops
+    }
 
-        self.mode = _DEFAULT_MODE.clone();
+    pub fn has_tab_stop(&self, x: u32) -> bool {
+        self.tabstops.contains(&x)
+    }
 
-        self.title = "".to_owned();
-        self.icon_name = "".to_owned();
+    /// Replace the tab stops with `columns`, leaving out the ones past
+    /// the right edge of the screen.
+    pub fn set_tab_stops(&mut self, columns: &[u32]) {
+        self.tabstops.clear();
+        self.tabstops
+            .extend(columns.iter().copied().filter(|&x| x < self.columns));
+    }
 
-        self.charset = Charset::G0;
-        self.g0_charset = LAT1_MAP.clone();
-        self.g1_charset = VT100_MAP.clone();
+    /// Move to the previous tab stop, or the beginning of the line if
+    /// there aren't any left.
+    pub fn back_tab(&mut self) {
+        self.pending_wrap = false;
+        let x = self.cursor.x;
+        self.cursor.x = self
+            .tabstops
+            .iter()
+            .copied()
+            .filter(|&stop| stop < x)
+            .max()
+            .unwrap_or(0);
+    }
+
+    /// Returns the sequence to send to the host when `key` is pressed,
+    /// see `input::encode`.
+    pub fn encode_key(&self, key: Key) -> String {
+        String::from_utf8_lossy(&input::encode(key, Modifiers::NONE, self)).into_owned()
+    }
+
+    /// Returns the cells which differ between this screen and `other`,
+    /// row by row. Cells outside of one of the screens compare as that
+    /// screen's default character.
+    pub fn diff(&self, other: &Screen) -> Vec<CellChange> {
+        let (old_default, new_default) = (self.default_char(), other.default_char());
+        let mut changes = Vec::new();
+        for y in 0..u32::max(self.lines, other.lines) {
+            for x in 0..u32::max(self.columns, other.columns) {
+                let old = self.visible_cell(x, y).unwrap_or(&old_default);
+                let new = other.visible_cell(x, y).unwrap_or(&new_default);
+                if old != new {
+                    changes.push(CellChange { x, y, old: old.clone(), new: new.clone() });
+                }
+            }
+        }
+        changes
+    }
 
-        // From ``man terminfo`` -- "... hardware tabs are initially
-        // set every `n` spaces when the terminal is powered up. Since
-        // we aim to support VT102 / VT220 and linux -- we use n = 8.
-        self.tabstops.clear();
-        self.tabstops.extend((8..self.columns).step_by(8));
+    /// Returns the cells of the screen in row-major order with their
+    /// `(x, y)` position. The trailing half of a wide character is
+    /// skipped, the leading one covers both columns.
+    pub fn cells(&self) -> impl Iterator<Item = (u32, u32, &CharOpts)> + '_ {
+        (0..self.lines).flat_map(move |y| {
+            (0..self.columns)
+                .filter(move |&x| !self.is_continuation(x, y))
+                .filter_map(move |x| Some((x, y, self.visible_cell(x, y)?)))
+        })
+    }
 
-        self.cursor = Cursor {
-            x: 0,
-            y: 0,
-            hidden: false,
-            attr: self.default_char(),
+    /// Returns the cell shown at `column` of row `y` with the column it
+    /// starts at, e.g. for a mouse click. On the trailing half of a wide
+    /// character that is its leading cell, one column to the left.
+    /// Combining marks are part of the cell of their base character.
+    pub fn char_at_column(&self, y: u32, column: u32) -> Option<(u32, CharOpts)> {
+        let x = if self.is_continuation(column, y) {
+            column - 1
+        } else {
+            column
         };
-        self.cursor_position(None, None);
-
-        self.saved_columns = None
+        Some((x, self.visible_cell(x, y)?.clone()))
     }
 
-    /// Move the cursor down one line in the same column. If the
-    /// cursor is at the last line, create a new line at the bottom.
-    fn index(&mut self) {
-        let Margins { top, bottom } = self
-            .margins
-            .or(Some(Margins { top: 0, bottom: self.lines - 1 }))
-            .expect("unexpected margin found");
+    /// Returns the cell at column `x` of row `y`, or `None` outside the
+    /// screen.
+    pub fn get_cell(&self, x: u32, y: u32) -> Option<&CharOpts> {
+        self.visible_cell(x, y)
+    }
 
-        if self.cursor.y == bottom {
-            // Mark all lines as dirty
-            self.dirty.extend(0..self.lines);
-            let mut new_buffer: HashMap<u32, HashMap<u32, CharOpts>> = HashMap::new();
-            // Copy lines before top margin unchanged
-            for y in 0..top {
-                if let Some(line) = self.buffer.get(&y) {
-                    new_buffer.insert(y, line.clone());
-                }
-            }
-            // Move lines up (decrement keys)
-            for y in top..bottom {
-                if let Some(line) = self.buffer.get(&(y + 1)) {
-                    new_buffer.insert(y, line.clone());
-                }
-            }
-
-            // Insert empty line at bottom
-            new_buffer.insert(bottom, HashMap::new());
-
-            // Copy lines after bottom margin unchanged
-            for y in (bottom + 1)..self.lines {
-                if let Some(line) = self.buffer.get(&y) {
-                    new_buffer.insert(y, line.clone());
-                }
+    /// Writes `cells` to row `y` as they are, each at its column, e.g. to
+    /// seed a fixture or restore saved contents without going through
+    /// the parser. Cells outside the screen are skipped. A wide character
+    /// needs an empty cell after it for its trailing half.
+    ///
+    /// ```
+    /// use memterm::screen::{CharOpts, Screen};
+    ///
+    /// let mut screen = Screen::new(10, 2);
+    /// let bold = CharOpts {
+    ///     data: "x".to_string(),
+    ///     bold: true,
+    ///     ..screen.default_char()
+    /// };
+    /// screen.set_cells(1, vec![(2, bold.clone())]);
+    /// assert_eq!(screen.get_cell(2, 1), Some(&bold));
+    /// assert_eq!(screen.display(), vec!["          ", "  x       "]);
+    /// assert!(screen.dirty.contains(&1));
+    /// ```
+    pub fn set_cells(&mut self, y: u32, cells: Vec<(u32, CharOpts)>) {
+        if y >= self.lines {
+            return;
+        }
+        for (x, cell) in cells {
+            if let Some(target) = self.buffer[y as usize].get_mut(x as usize) {
+                *target = cell;
+                self.dirty.insert(y);
+                self.mark_dirty_cells(y, x..x + 1);
             }
-
-            // Replace old buffer with new one
-            self.buffer = new_buffer;
-        } else {
-            self.cursor_down(None);
         }
     }
 
-    // Perform an index and, if LNM is set, a  carriage return.
-    fn linefeed(&mut self) {
-        self.index();
-        if self.mode.contains(&LNM) {
-            self.cariage_return();
+    fn visible_cell(&self, x: u32, y: u32) -> Option<&CharOpts> {
+        if x >= self.columns || y >= self.lines {
+            return None;
+        }
+        self.buffer.get(y as usize)?.get(x as usize)
+    }
+
+    /// Returns a copy of the screen contents, cursor, modes and the other
+    /// state sequences can change, to be put back with `restore`.
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        ScreenSnapshot {
+            columns: self.columns,
+            lines: self.lines,
+            buffer: self.buffer.clone(),
+            wrapped: self.wrapped.clone(),
+            primary_screen: self.primary_screen.clone(),
+            cursor: self.cursor.clone(),
+            pending_wrap: self.pending_wrap,
+            savepoint: self.savepoint.clone(),
+            mode: self.mode.clone(),
+            margins: self.margins,
+            tabstops: self.tabstops.clone(),
+            charset: self.charset,
+            g0_charset: self.g0_charset,
+            g1_charset: self.g1_charset,
+            title: self.title.clone(),
+            icon_name: self.icon_name.clone(),
+            saved_columns: self.saved_columns,
+            keypad_application: self.keypad_application,
         }
     }
 
-    // Move the cursor up one line in the same column. If the cursor
-    // at the first line, create a new line at the top.
-    fn reverse_index(&mut self) {
-        let (top, bottom) = match &self.margins {
-            Some(margins) => (margins.top, margins.bottom),
-            None => (0, self.lines - 1),
-        };
+    /// Put back the state saved by `snapshot`. All lines are marked
+    /// dirty.
+    pub fn restore(&mut self, snapshot: ScreenSnapshot) {
+        self.columns = snapshot.columns;
+        self.lines = snapshot.lines;
+        self.buffer = snapshot.buffer;
+        self.wrapped = snapshot.wrapped;
+        self.primary_screen = snapshot.primary_screen;
+        self.cursor = snapshot.cursor;
+        self.pending_wrap = snapshot.pending_wrap;
+        self.savepoint = snapshot.savepoint;
+        self.mode = snapshot.mode;
+        self.margins = snapshot.margins;
+        self.tabstops = snapshot.tabstops;
+        self.charset = snapshot.charset;
+        self.g0_charset = snapshot.g0_charset;
+        self.g1_charset = snapshot.g1_charset;
+        self.title = snapshot.title;
+        self.icon_name = snapshot.icon_name;
+        self.saved_columns = snapshot.saved_columns;
+        self.keypad_application = snapshot.keypad_application;
 
-        if self.cursor.y == top {
-            // Mark all lines as dirty
-            self.dirty.extend(0..self.lines);
-            let mut new_buffer: HashMap<u32, HashMap<u32, CharOpts>> = HashMap::new();
+        self.dirty.clear();
+        self.dirty.extend(0..self.lines);
+        self.mark_dirty_rows(0..self.lines);
+    }
+
+    /// Draw `data` as `draw` does, wrapping and applying insert mode and
+    /// charsets, but with `attr` in place of the cursor attributes.
+    /// `cursor.attr` is left unchanged.
+    pub fn draw_with_attr(&mut self, data: &str, attr: &CharOpts) {
+        let saved = core::mem::replace(&mut self.cursor.attr, attr.clone());
+        self.draw(data);
+        self.cursor.attr = saved;
+    }
+
+    /// Send replies to the host process through `reporter`.
+    pub fn set_reporter(&mut self, reporter: impl Reporter + 'static) {
+        self.reporter = Some(Box::new(reporter));
+    }
+
+    /// Parse `data` and apply it to the screen. Returns the number of
+    /// bytes consumed, all of `data`.
+    pub fn feed(&mut self, data: &str) -> usize {
+        let mut parser = core::mem::take(&mut self.parser);
+        let consumed = parser.feed(self, data);
+        self.parser = parser;
+        consumed
+    }
+
+    /// Like `feed`, but fails if a reply couldn't be written to the host
+    /// or, in strict mode, if the input had errors. The whole of `data`
+    /// is applied to the screen either way. Only errors in `data` count,
+    /// ones left from earlier `feed` calls are dropped.
+    #[cfg(feature = "std")]
+    pub fn try_feed(&mut self, data: &str) -> Result<(), TermError> {
+        self.output_error = None;
+        self.parser.take_errors();
+        self.feed(data);
+        let errors = self.parser.take_errors();
+        if let Some(error) = self.output_error.take() {
+            return Err(TermError::Output(error));
+        }
+        if !errors.is_empty() {
+            return Err(TermError::Parse(errors));
+        }
+        Ok(())
+    }
 
-            // Copy lines before top margin unchanged
-            for y in 0..top {
-                if let Some(line) = self.buffer.get(&y) {
-                    new_buffer.insert(y, line.clone());
-                }
-            }
+    /// Like `feed`, but returns the replies `data` made the screen send,
+    /// e.g. to device attribute and status requests, instead of passing
+    /// them to `reporter`.
+    ///
+    /// ```
+    /// use memterm::screen::Screen;
+    ///
+    /// let mut screen = Screen::new(10, 2);
+    /// assert_eq!(screen.feed_with_output("ab\x1b[6n"), "\x1b[1;3R");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn feed_with_output(&mut self, data: &str) -> String {
+        let output = VecReporter::new();
+        let reporter = self.reporter.replace(Box::new(output.clone()));
+        self.feed(data);
+        self.reporter = reporter;
+        output.take().concat()
+    }
 
-            // Move lines within margins down
-            for y in (top..=bottom).rev() {
-                if let Some(line) = self.buffer.get(&y) {
-                    new_buffer.insert(y + 1, line.clone());
-                }
-            }
+    /// Like `feed`, but stops after the first sequence matching
+    /// `predicate`, see `StreamParser::feed_until`.
+    pub fn feed_until(&mut self, data: &str, predicate: impl FnMut(&str) -> bool) -> usize {
+        let mut parser = core::mem::take(&mut self.parser);
+        let consumed = parser.feed_until(self, data, predicate);
+        self.parser = parser;
+        consumed
+    }
 
-            // Insert empty line at top margin
-            new_buffer.insert(top, HashMap::new());
+    /// Like `feed`, but takes undecoded input, see
+    /// `StreamParser::feed_bytes`.
+    pub fn feed_bytes(&mut self, data: &[u8]) -> usize {
+        let mut parser = core::mem::take(&mut self.parser);
+        let consumed = parser.feed_bytes(self, data);
+        self.parser = parser;
+        consumed
+    }
 
-            // Copy lines after bottom margin unchanged
-            for y in (bottom + 1)..self.lines {
-                if let Some(line) = self.buffer.get(&y) {
-                    new_buffer.insert(y, line.clone());
-                }
-            }
+    /// Call `callback` whenever the host sets the title, e.g. to update
+    /// the window title.
+    pub fn set_on_title_change(&mut self, callback: impl FnMut(&str) + Send + 'static) {
+        self.on_title_change = Some(Box::new(callback));
+    }
 
-            self.buffer = new_buffer;
-        } else {
-            self.cursor_up(None);
-        }
+    /// Call `callback` with the new columns and lines whenever the screen
+    /// size changes.
+    pub fn set_on_resize(&mut self, callback: impl FnMut(u32, u32) + Send + 'static) {
+        self.on_resize = Some(Box::new(callback));
     }
 
-    // Set a horizontal tab stop at cursor position.
-    fn set_tab_stop(&mut self) {
-        self.tabstops.insert(self.cursor.x);
+    /// Call `callback` when synchronized output is turned on or off, e.g.
+    /// to begin and end a frame.
+    pub fn set_on_sync_change(&mut self, callback: impl FnMut(bool) + Send + 'static) {
+        self.on_sync_change = Some(Box::new(callback));
     }
 
-    //  Push the current cursor position onto the stack.
-    fn save_cursor(&mut self) {
-        self.savepoints.push(Savepoint {
-            cursor: self.cursor.clone(),
-            g0_charset: self.g0_charset.clone(),
-            g1_charset: self.g1_charset.clone(),
-            charset: self.charset,
-            origin: self.mode.contains(&DECOM),
-            wrap: self.mode.contains(&DECAWM),
-        })
+    /// Call `callback` with the bell style whenever the host rings the
+    /// bell.
+    pub fn set_on_bell(&mut self, callback: impl FnMut(BellStyle) + Send + 'static) {
+        self.on_bell = Some(Box::new(callback));
     }
 
-    /// Set the current cursor position to whatever cursor is on top
-    /// of the stack.
-    fn restore_cursor(&mut self) {
-        if self.savepoints.len() > 0 {
-            let savepoint = self
-                .savepoints
-                .pop()
-                .expect("can not retrieve last savepoint");
-
-            self.g0_charset = savepoint.g0_charset.clone();
-            self.g1_charset = savepoint.g1_charset.clone();
-            self.charset = savepoint.charset;
+    /// Choose whether the bell is audible, the default, or visual. It is
+    /// passed to `on_bell`, ringing it is left to the frontend.
+    pub fn set_bell_style(&mut self, style: BellStyle) {
+        self.bell_style = style;
+    }
 
-            if savepoint.origin {
-                self.set_mode(&[DECOM], false)
-            }
-            if savepoint.wrap {
-                self.set_mode(&[DECAWM], false)
-            }
+    /// Returns `true` while the host is drawing a frame with synchronized
+    /// output (mode 2026) on, updates should be held back until it ends.
+    pub fn synchronized_output(&self) -> bool {
+        self.mode.contains(&SYNCHRONIZED_OUTPUT)
+    }
 
-            self.cursor = savepoint.cursor;
-            self.ensure_hbounds();
-            self.ensure_vbounds(Some(true));
-        } else {
-            // If nothing was saved, the cursor moves to home position;
-            // origin mode is reset. :todo: DECAWM?
-            self.reset_mode(&[DECOM], false);
-            self.cursor_position(None, None);
+    /// Calls `on_sync_change` if synchronized output isn't `was_on`
+    /// anymore.
+    fn sync_changed(&mut self, was_on: bool) {
+        let on = self.synchronized_output();
+        if on != was_on {
+            if let Some(callback) = self.on_sync_change.as_mut() {
+                callback(on);
+            }
         }
     }
 
-    /// Select ``G1`` character set.
-    fn shift_out(&mut self) {
-        self.charset = Charset::G1;
+    /// Call `callback` whenever the host sets the icon name.
+    pub fn set_on_icon_name_change(&mut self, callback: impl FnMut(&str) + Send + 'static) {
+        self.on_icon_name_change = Some(Box::new(callback));
     }
 
-    /// Select ``G0`` character set.
-    fn shift_in(&mut self) {
-        self.charset = Charset::G0;
+    /// Use `width_fn` to tell how many columns a character takes, e.g. to
+    /// match a terminal which draws ambiguous width characters wide.
+    pub fn set_width_fn(&mut self, width_fn: impl Fn(char) -> u8 + Send + 'static) {
+        self.width_fn = Some(Box::new(width_fn));
     }
 
-    /// Bell stub -- the actual implementation should probably be by the end-user.
-    fn bell(&mut self) {}
+    /// Choose what undecodable input, control characters without an
+    /// action and zero width characters other than combining marks, e.g.
+    /// U+200B, are drawn as: U+FFFD, nothing or another character. By
+    /// default undecodable input is drawn as U+FFFD and the others are
+    /// dropped. A replacement which is itself zero width or a control
+    /// character is dropped as well.
+    pub fn set_replacement_policy(&mut self, policy: ReplacementPolicy) {
+        self.replacement_policy = policy;
+        self.parser.set_replacement_policy(policy);
+    }
 
-    /// Move cursor to the left one or keep it in its position if
-    /// it's at the beginning of the line already.
-    fn backspace(&mut self) {
-        self.cursor_back(None);
+    /// Whether the cursor should be drawn, see DECTCEM.
+    pub fn cursor_visible(&self) -> bool {
+        !self.cursor.hidden
     }
 
-    /// Move to the next tab space, or the end of the screen if there
-    /// aren't anymore left.
-    fn tab(&mut self) {
-        // Convert HashSet to a Vec
-        let mut vec: Vec<_> = self.tabstops.iter().collect();
-        // Sort the Vec
-        vec.sort();
+    /// Returns the 0-based column and line of the cursor.
+    pub fn cursor_xy(&self) -> (u32, u32) {
+        (self.cursor.x, self.cursor.y)
+    }
+
+    /// Returns the 0-based column of the cursor.
+    pub fn cursor_col(&self) -> u32 {
+        self.cursor.x
+    }
+
+    /// Returns the 0-based line of the cursor.
+    pub fn cursor_row(&self) -> u32 {
+        self.cursor.y
+    }
 
-        let mut column: u32 = 0;
-        for &stop in vec.iter() {
-            if self.cursor.x < *stop {
-                column = *stop;
-                break;
+    /// Returns the 1-based `(line, column)` of the cursor as a cursor
+    /// position report (CPR) gives them, lines counted from the top
+    /// margin in origin mode.
+    pub fn cursor_report_position(&self) -> (u32, u32) {
+        let mut y = self.cursor.y + 1;
+        // "Origin mode (DECOM) selects line numbering."
+        if self.mode.contains(&DECOM) {
+            if let Some(margins) = self.margins {
+                y -= margins.top;
             }
         }
+        (y, self.cursor.x + 1)
+    }
 
-        if column == 0 {
-            column = self.columns - 1;
-        }
+    /// The attributes characters are drawn with.
+    pub fn current_attr(&self) -> &CharOpts {
+        &self.cursor.attr
+    }
 
-        self.cursor.x = column;
+    /// Set the attributes characters are drawn with, the `data` of `attr`
+    /// is ignored.
+    pub fn set_attr(&mut self, attr: CharOpts) {
+        self.cursor.attr = attr;
     }
 
-    /// Move the cursor to the beginning of the current line.
-    fn cariage_return(&mut self) {
-        self.cursor.x = 0;
+    /// Set the foreground color, a name such as `"red"` or a hex
+    /// `"rrggbb"` value as `select_graphic_rendition` stores them.
+    pub fn set_fg(&mut self, color: &str) {
+        self.cursor.attr.fg = self::color(color);
     }
 
-    /// Display decoded characters at the current cursor position and
-    /// advances the cursor if `DECAWM` is set.
-    ///
-    /// # Parameters
-    /// - `data`: Text to display.
-    ///
-    /// # Version
-    /// - Changed in version 0.5.0: Character width is taken into account.
-    ///   Specifically, zero-width and unprintable characters do not affect
-    ///   screen state. Full-width characters are rendered into two consecutive
-    ///   character containers.
-    fn draw(&mut self, data: &str) {
-        dbg!("draw");
-        let data = data
-            .chars()
-            .map(|c| {
-                if self.charset == Charset::G1 {
-                    if c as usize > 255 {
-                        c
-                    } else {
-                        self.g1_charset[c as usize]
-                    }
-                } else {
-                    if c as usize > 255 {
-                        c
-                    } else {
-                        self.g0_charset[c as usize]
-                    }
-                }
-            })
-            .collect::<String>();
+    /// Set the background color, see `set_fg`.
+    pub fn set_bg(&mut self, color: &str) {
+        self.cursor.attr.bg = self::color(color);
+    }
 
-        for char in data.chars() {
-            let char_width = char.width().unwrap_or(0);
+    pub fn set_bold(&mut self, bold: bool) {
+        self.cursor.attr.bold = bold;
+    }
 
-            // If this was the last column in a line and auto wrap mode is
-            // enabled, move the cursor to the beginning of the next line,
-            // otherwise replace characters already displayed with newly
-            // entered.
-            if self.cursor.x == self.columns {
-                if self.mode.contains(&DECAWM) {
-                    self.dirty.insert(self.cursor.y);
-                    self.cariage_return();
-                    self.linefeed();
-                } else if char_width > 0 {
-                    self.cursor.x = self.cursor.x.saturating_sub(char_width as u32);
-                }
-            }
+    /// Reset the attributes characters are drawn with to the defaults,
+    /// same as `CSI 0 m`.
+    pub fn reset_attr(&mut self) {
+        self.select_graphic_rendition(&[0]);
+    }
 
-            // If Insert mode is set, new characters move old characters to
-            // the right, otherwise terminal is in Replace mode and new
-            // characters replace old characters at cursor position.
-            if self.mode.contains(&IRM) && char_width > 0 {
-                self.insert_characters(Some(char_width as u32));
-            }
+    /// Decodes the SGR parameters `attrs` into the `CharOpts` fields
+    /// they change, as `to_map` names them.
+    fn sgr_changes(&self, attrs: &[u32]) -> HashMap<String, String> {
+        let mut replace = HashMap::new();
+        let mut attrs_list = attrs.to_vec();
+        attrs_list.reverse();
 
-            let line = self
-                .buffer
-                .entry(self.cursor.y)
-                .or_insert_with(HashMap::new);
-            if char_width == 1 {
-                line.insert(
-                    self.cursor.x,
-                    self.cursor.attr.clone_with_data(char.to_string()),
-                );
-            } else if char_width == 2 {
-                line.insert(
-                    self.cursor.x,
-                    self.cursor.attr.clone_with_data(char.to_string()),
-                );
-                if self.cursor.x + 1 < self.columns {
-                    line.insert(
-                        self.cursor.x + 1,
-                        self.cursor.attr.clone_with_data("".to_string()),
+        while let Some(attr) = attrs_list.pop() {
+            match attr {
+                0 => {
+                    // Reset all attributes.
+                    replace.extend(self.default_char().to_map());
+                }
+                attr if FG_ANSI.contains_key(&attr) => {
+                    replace.insert("fg".to_string(), FG_ANSI[&attr].clone());
+                }
+                attr if BG_ANSI.contains_key(&attr) => {
+                    replace.insert("bg".to_string(), BG_ANSI[&attr].clone());
+                }
+                attr if TEXT.contains_key(&attr) => {
+                    let attr_str = &TEXT[&attr];
+                    replace.insert(
+                        attr_str[1..].to_string(),
+                        attr_str.starts_with('+').to_string(),
                     );
                 }
-            } else if char_width == 0 && is_combining_mark(char) {
-                if self.cursor.x > 0 {
-                    if let Some(last) = line.get_mut(&(self.cursor.x - 1)) {
-                        last.data = last.data.nfc().collect::<String>() + &char.to_string();
-                    }
-                } else if self.cursor.y > 0 {
-                    if let Some(last) = self
-                        .buffer
-                        .get_mut(&(self.cursor.y - 1))
-                        .and_then(|l| l.get_mut(&(self.columns - 1)))
-                    {
-                        last.data = last.data.nfc().collect::<String>() + &char.to_string();
+                attr if FG_AIXTERM.contains_key(&attr) => {
+                    replace.insert("fg".to_string(), FG_AIXTERM[&attr].clone());
+                }
+                attr if BG_AIXTERM.contains_key(&attr) => {
+                    replace.insert("bg".to_string(), BG_AIXTERM[&attr].clone());
+                }
+                attr if attr == FG_256 || attr == BG_256 => {
+                    let key = if attr == FG_256 { "fg" } else { "bg" };
+                    // Without a `5` or `2` after it the parameter is
+                    // ignored, and the ones following it apply as usual.
+                    match attrs_list.last() {
+                        Some(5) => {
+                            attrs_list.pop();
+                            if let Some(m) = attrs_list.pop() {
+                                if m < 16 {
+                                    replace.insert(key.to_string(), FG_BG_256[m as usize].clone());
+                                }
+                            }
+                        }
+                        Some(2) => {
+                            attrs_list.pop();
+                            if let (Some(r), Some(g), Some(b)) =
+                                (attrs_list.pop(), attrs_list.pop(), attrs_list.pop())
+                            {
+                                replace.insert(
+                                    key.to_string(),
+                                    format!("{:02x}{:02x}{:02x}", r, g, b),
+                                );
+                            }
+                        }
+                        _ => {}
                     }
                 }
-            } else {
-                break; // Unprintable character or doesn't advance the cursor.
+                _ => {}
             }
+        }
 
-            // .. note:: We can't use `cursor_forward()`, because that
-            //           way, we'll never know when to linefeed.
-            if char_width > 0 {
-                self.cursor.x = std::cmp::min(self.cursor.x + char_width as u32, self.columns);
+        // SGR 39 and 49 select the configured default colors.
+        for key in ["fg", "bg"] {
+            if replace.get(key).is_some_and(|value| value == "default") {
+                let value = if key == "fg" {
+                    &self.defaults.fg
+                } else {
+                    &self.defaults.bg
+                };
+                replace.insert(key.to_string(), value.to_string());
             }
         }
-
-        self.dirty.insert(self.cursor.y);
+        replace
     }
 
-    /// Insert the indicated # of blank characters at the cursor
-    /// position. The cursor does not move and remains at the beginning
-    /// of the inserted blank characters. Data on the line is shifted
-    /// forward.
-    ///
-    /// # Arguments
-    ///
-    /// * `count` - number of characters to insert.
-    fn insert_characters(&mut self, count: Option<u32>) {
-        self.dirty.insert(self.cursor.y);
-
-        let count = count.unwrap_or(1);
-        let default = self.default_char();
+    /// Apply the SGR attributes `attrs` to the cells in `rect`, clamped
+    /// to the screen, keeping their text (DECCARA). No attributes is the
+    /// same as `0`, turning them all off.
+    pub fn change_rectangle_attrs(&mut self, rect: Rect, attrs: &[u32]) {
+        let mut changes = self.sgr_changes(if attrs.is_empty() { &[0] } else { attrs });
+        changes.remove("data");
+        self.update_rectangle(rect, |cell| cell.update_from_map(changes.clone()));
+    }
 
-        let line = self
-            .buffer
-            .get_mut(&self.cursor.y)
-            .expect("can not retrieve line");
-        for x in (self.cursor.x..self.columns + 1).rev() {
-            if x + count <= self.columns {
-                let x_val = line.get(&x);
-                match x_val {
-                    Some(val) => {
-                        line.insert(x + count, val.clone());
-                    }
-                    None => {
-                        line.insert(x + count, default.clone());
-                    }
+    /// Toggle bold (`1`), underscore (`4`), blink (`5`) and reverse (`7`)
+    /// in the cells in `rect`, clamped to the screen (DECRARA). `0`, or
+    /// no attributes, toggles all four, others are ignored.
+    pub fn reverse_rectangle_attrs(&mut self, rect: Rect, attrs: &[u32]) {
+        let (mut bold, mut underscore, mut blink, mut reverse) = (false, false, false, false);
+        for &attr in if attrs.is_empty() { &[0] } else { attrs } {
+            match attr {
+                0 => {
+                    bold = !bold;
+                    underscore = !underscore;
+                    blink = !blink;
+                    reverse = !reverse;
                 }
+                1 => bold = !bold,
+                4 => underscore = !underscore,
+                5 => blink = !blink,
+                7 => reverse = !reverse,
+                _ => {}
             }
-            line.insert(x, default.clone());
         }
+        self.update_rectangle(rect, |cell| {
+            cell.bold ^= bold;
+            cell.underscore ^= underscore;
+            cell.blink ^= blink;
+            cell.reverse ^= reverse;
+        });
     }
 
-    fn cursor_up(&mut self, count: Option<u32>) {
-        let top = match &self.margins {
-            Some(margins) => margins.top,
-            None => 0,
-        };
-        let count = count.unwrap_or(1);
-        self.cursor.y = self.cursor.y.saturating_sub(count).max(top);
+    /// Calls `update` with each cell in `rect`, clamped to the screen.
+    fn update_rectangle(&mut self, rect: Rect, mut update: impl FnMut(&mut CharOpts)) {
+        let bottom = rect.bottom.min(self.lines.saturating_sub(1));
+        let right = rect.right.min(self.columns.saturating_sub(1));
+        if rect.top > bottom || rect.left > right {
+            return;
+        }
+        for y in rect.top..=bottom {
+            if let Some(line) = self.buffer.get_mut(y as usize) {
+                let cells = line.iter_mut().take(right as usize + 1);
+                cells.skip(rect.left as usize).for_each(&mut update);
+            }
+            self.dirty.insert(y);
+            self.mark_dirty_cells(y, rect.left..right + 1);
+        }
     }
 
-    fn cursor_down(&mut self, count: Option<u32>) {
-        let bottom = match &self.margins {
-            Some(margins) => margins.bottom,
-            None => self.lines - 1,
+    /// Splits DECCARA and DECRARA parameters into the rectangle, whose
+    /// edges are 1-based and default to the screen edges when 0 or
+    /// missing, and the attributes.
+    fn rectangle_params<'a>(&self, params: &'a [u32]) -> (Rect, &'a [u32]) {
+        let edge = |i: usize, default: u32| {
+            params
+                .get(i)
+                .filter(|&&edge| edge > 0)
+                .map_or(default, |&edge| edge - 1)
         };
-        let count = count.unwrap_or(1);
-        self.cursor.y = (self.cursor.y + count).min(bottom);
+        let rect = Rect {
+            top: edge(0, 0),
+            left: edge(1, 0),
+            bottom: edge(2, self.lines.saturating_sub(1)),
+            right: edge(3, self.columns.saturating_sub(1)),
+        };
+        (rect, params.get(4..).unwrap_or_default())
     }
 
-    fn cursor_down1(&mut self, count: Option<u32>) {
-        self.cursor_down(count);
-        self.cariage_return();
+    /// Clear the whole screen, keeping the cursor position and
+    /// attributes, same as `CSI 2 J`.
+    pub fn clear(&mut self) {
+        self.erase_in_display(Some(2), None);
     }
 
-    /// Move cursor right the indicated number of columns. Cursor stops
-    /// at the right margin.
-    ///
-    /// # Parameters
-    /// - `count`: Number of columns to skip.
-    fn cursor_forward(&mut self, count: Option<u32>) {
-        self.cursor.x += count.unwrap_or(1);
-        self.ensure_hbounds();
+    /// Clear the line the cursor is on, same as `CSI 2 K`.
+    pub fn clear_line(&mut self) {
+        self.erase_in_line(Some(2), None);
     }
 
-    /// Move cursor left the indicated # of columns. Cursor stops
-    /// at left margin.
-    ///
-    /// # Arguements
-    ///
-    /// * `count` - number of columns to skip
-    fn cursor_back(&mut self, count: Option<u32>) {
-        // Handle the case when we've just drawn in the last column
-        // and would wrap the line on the next :meth:`draw()` call.
-        if self.cursor.x == self.columns {
-            self.cursor.x -= 1
+    /// Set the message sent to the host in reply to `ENQ`.
+    pub fn set_answerback(&mut self, answerback: &str) {
+        self.answerback = answerback.to_owned();
+    }
+
+    /// Write to the process input.
+    pub fn write_process_input(&mut self, input: &str) {
+        if let Some(reporter) = self.reporter.as_mut() {
+            #[cfg(feature = "std")]
+            if let Err(error) = reporter.try_report(input) {
+                self.output_error.get_or_insert(error);
+            }
+            #[cfg(not(feature = "std"))]
+            reporter.report(input);
         }
-        if self.cursor.x >= count.unwrap_or(1) {
-            self.cursor.x -= count.unwrap_or(1);
-        } else {
-            self.cursor.x = 0;
+    }
+
+    /// Removes and returns the text printed so far, see `media_copy`.
+    pub fn take_printer_output(&mut self) -> String {
+        core::mem::take(&mut self.printer_output)
+    }
+
+    /// Print row `y` with trailing blanks trimmed.
+    fn print_line(&mut self, y: u32) {
+        if let Some(line) = self.buffer.get(y as usize) {
+            let line = self.display_line(line);
+            self.printer_output.push_str(line.trim_end());
+            self.printer_output.push('\n');
         }
-        self.ensure_hbounds();
     }
 
-    fn cursor_up1(&mut self, count: Option<u32>) {
-        self.cursor_up(count);
-        self.cariage_return();
+    /// The screen contents as plain text, one line per row with
+    /// trailing blanks trimmed.
+    pub fn text(&self) -> String {
+        self.text_with(true)
     }
 
-    fn cursor_to_column(&mut self, character: Option<u32>) {
-        self.cursor.x = character.unwrap_or(1) - 1;
-        self.ensure_hbounds();
+    /// The screen contents as plain text, one line per row. When `trim`
+    /// is `false` every line keeps its trailing blanks, as in `display`.
+    pub fn text_with(&self, trim: bool) -> String {
+        self.buffer
+            .iter()
+            .map(|line| {
+                // Wide character continuation cells hold no data, so they
+                // don't add anything here.
+                let line = line
+                    .iter()
+                    .map(|cell| cell.data.as_str())
+                    .collect::<String>();
+                if trim {
+                    line.trim_end().to_string()
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 
-    fn cursor_position(&mut self, line: Option<u32>, column: Option<u32>) {
-        let column: i32 = column.map(|a| if a == 0 { 1 } else { a }).unwrap_or(1) as i32 - 1;
-        let mut line: i32 = line.map(|a| if a == 0 { 1 } else { a }).unwrap_or(1) as i32 - 1;
+    /// Returns the `(x, y)` cell where each occurrence of `needle` in the
+    /// displayed text starts, in row-major order. Rows joined by auto
+    /// wrap are searched as one line, so a match may continue on the
+    /// next row. Matching is case-sensitive, see `find_ci`.
+    pub fn find(&self, needle: &str) -> Vec<(u32, u32)> {
+        self.find_with(needle, false)
+    }
 
-        // If origin mode (DECOM) is set, line number is relative to the top scrolling margin.
-        if let Some(margins) = &self.margins {
-            if self.mode.contains(&DECOM) {
-                line += margins.top as i32;
+    /// Like `find`, but ignores case.
+    pub fn find_ci(&self, needle: &str) -> Vec<(u32, u32)> {
+        self.find_with(needle, true)
+    }
 
-                // Cursor is not allowed to move out of the scrolling region.
-                if line < margins.top as i32 || line > margins.bottom as i32 {
-                    return;
+    fn find_with(&self, needle: &str, ignore_case: bool) -> Vec<(u32, u32)> {
+        let fold = |c: char| -> Vec<char> {
+            if ignore_case {
+                c.to_lowercase().collect()
+            } else {
+                vec![c]
+            }
+        };
+        let needle = needle.chars().flat_map(fold).collect::<Vec<_>>();
+        let mut matches = Vec::new();
+        if needle.is_empty() {
+            return matches;
+        }
+
+        // The characters of a logical line, each with its cell.
+        let mut text: Vec<(char, u32, u32)> = Vec::new();
+        let mut search = |text: &[(char, u32, u32)]| {
+            for window in text.windows(needle.len()) {
+                if window.iter().map(|&(c, _, _)| c).eq(needle.iter().copied()) {
+                    matches.push((window[0].1, window[0].2));
+                }
+            }
+        };
+        for y in 0..self.lines {
+            if !self.is_wrapped(y) {
+                search(&text);
+                text.clear();
+            }
+            // Wide character continuation cells hold no data, so they
+            // add nothing here.
+            for x in 0..self.columns {
+                if let Some(cell) = self.visible_cell(x, y) {
+                    text.extend(cell.data.chars().flat_map(fold).map(|c| (c, x, y)));
                 }
             }
         }
+        search(&text);
+        matches
+    }
+
+    /// Returns row `y` as runs of text sharing the same attributes. The
+    /// attributes of each run are returned with empty `data`; wide
+    /// character continuation cells add nothing to the text.
+    pub fn line_runs(&self, y: u32) -> Vec<(String, CharOpts)> {
+        let mut runs: Vec<(String, CharOpts)> = Vec::new();
+        for cell in self.buffer.get(y as usize).into_iter().flatten() {
+            match runs.last_mut() {
+                Some((text, attr)) if attr.same_style(cell) && attr.hyperlink == cell.hyperlink => {
+                    text.push_str(&cell.data);
+                }
+                _ => runs.push((cell.data.clone(), cell.clone_with_data(String::new()))),
+            }
+        }
+        runs
+    }
 
-        self.cursor.x = column as u32;
-        self.cursor.y = line as u32;
-        self.ensure_hbounds();
-        self.ensure_vbounds(None);
+    /// Returns `true` if row `y` continues the row above it, i.e. the
+    /// two form a single logical line which was auto wrapped.
+    pub fn is_wrapped(&self, y: u32) -> bool {
+        self.wrapped.get(y as usize).copied().unwrap_or(false)
     }
 
-    /// Erases display in a specific way.
-    ///
-    /// Character attributes are set to cursor attributes.
-    ///
-    /// # Parameters
-    ///
-    /// - `how`: Defines the way the line should be erased in:
-    ///     - `0`: Erases from cursor to end of screen, including cursor position.
-    ///     - `1`: Erases from beginning of screen to cursor, including cursor position.
-    ///     - `2` and `3`: Erases complete display. All lines are erased and changed to single-width. Cursor does not move.
-    /// - `private`: When `true`, only characters marked as erasable are affected. **Not implemented**.
-    ///
-    /// # Version
-    ///
-    /// This method accepts any number of positional arguments as some `clear` implementations include a `;` after the first parameter causing the stream to assume a `0` second parameter.
-    fn erase_in_display(&mut self, how: Option<u32>, _private: Option<bool>) {
-        let interval: std::ops::Range<u32> = match how {
-            Some(0) => self.cursor.y + 1..self.lines,
-            Some(1) => 0..self.cursor.y,
-            Some(2 | 3) => 0..self.lines,
-            _ => 0..0, // Handle invalid `how` values
-        };
+    /// Serialize the screen to ANSI text which, fed to a fresh terminal
+    /// of the same size, reproduces the display, the cursor position
+    /// and the cursor attributes.
+    pub fn to_ansi(&self) -> String {
+        let mut result = String::new();
+        let default = CharOpts::default();
+        let mut style = &default;
+        let mut link = None;
 
-        self.dirty.extend(interval.clone());
-        for y in interval.clone() {
-            let line = &mut self.buffer.get_mut(&y).expect("can not retrieve line");
-            for x in 0..line.len() {
-                line.insert(x as u32, self.cursor.attr.clone());
+        for (y, line) in self.buffer.iter().enumerate() {
+            if y > 0 {
+                result.push_str("\r\n");
+            }
+            for cell in line {
+                if !cell.same_style(style) {
+                    result.push_str(&format!("\x1B[{}m", cell.sgr_params().join(";")));
+                    style = cell;
+                }
+                if cell.hyperlink.as_ref() != link {
+                    link = cell.hyperlink.as_ref();
+                    result.push_str(&format!("\x1B]8;;{}\x07", link.map_or("", |l| &**l)));
+                }
+                result.push_str(&cell.data);
             }
         }
 
-        if how == Some(0) || how == Some(1) {
-            self.erase_in_line(how, None);
+        let attr = &self.cursor.attr;
+        if !attr.same_style(style) {
+            result.push_str(&format!("\x1B[{}m", attr.sgr_params().join(";")));
+        }
+        if attr.hyperlink.as_ref() != link {
+            result.push_str(&format!(
+                "\x1B]8;;{}\x07",
+                attr.hyperlink.as_deref().unwrap_or("")
+            ));
         }
+        result.push_str(&format!(
+            "\x1B[{};{}H",
+            self.cursor.y + 1,
+            self.cursor.x + 1
+        ));
+        result
     }
 
-    fn erase_in_line(&mut self, how: Option<u32>, _private: Option<bool>) {
-        self.dirty.insert(self.cursor.y);
-
-        let how = how.unwrap_or(0);
-        let interval: Box<dyn Iterator<Item = u32>> = match how {
-            0 => Box::new(self.cursor.x..self.columns),
-            1 => Box::new(0..=self.cursor.x),
-            2 => Box::new(0..self.columns),
-            _ => {
-                panic!("invalid eras_in_line parameter");
-            } // Handle invalid `how` values if necessary
-        };
-
-        let line = self
-            .buffer
-            .get_mut(&self.cursor.y)
-            .expect("can not retrieve line");
-        for x in interval {
-            line.insert(x, self.cursor.attr.clone());
+    /// Returns ANSI text which, fed to a terminal showing `prev`, makes
+    /// it show this screen: only the changed runs of cells are written,
+    /// each after moving the cursor to it, then the cursor position,
+    /// attributes and visibility are updated. Screens of different
+    /// sizes are redrawn in full.
+    pub fn diff_ansi(&self, prev: &Screen) -> String {
+        if (self.columns, self.lines) != (prev.columns, prev.lines) {
+            return format!("\x1B[0m\x1B]8;;\x07\x1B[H\x1B[2J{}", self.to_ansi());
         }
-    }
 
-    /// Insert the indicated number of lines at the line with the cursor.
-    /// Lines displayed at and below the cursor move down. Lines moved
-    /// past the bottom margin are lost.
-    ///
-    /// # Parameters
-    ///
-    /// - `count`: Number of lines to insert.
-    fn insert_lines(&mut self, count: Option<u32>) {
-        let count = count.unwrap_or(1);
-        let Margins { top, bottom } = self
-            .margins
-            .unwrap_or(Margins { top: 0, bottom: self.lines - 1 });
+        let mut result = String::new();
+        let mut style = &prev.cursor.attr;
+        let mut link = prev.cursor.attr.hyperlink.as_ref();
+        let mut moved = self.cursor_xy() != prev.cursor_xy();
+        for y in 0..self.lines {
+            let changed = |x: u32| self.visible_cell(x, y) != prev.visible_cell(x, y);
+            let mut x = 0;
+            while x < self.columns {
+                if !changed(x) {
+                    x += 1;
+                    continue;
+                }
+                // A wide character is written from its leading half.
+                let mut start = x;
+                if self.is_continuation(start, y) {
+                    start -= 1;
+                }
+                let mut end = x;
+                while end < self.columns && changed(end) {
+                    end += 1;
+                }
 
-        // If cursor is outside scrolling margins, do nothing.
-        if top <= self.cursor.y && self.cursor.y <= bottom {
-            self.dirty.extend(self.cursor.y..self.lines);
-            for y in (self.cursor.y as u32..=bottom as u32).rev() {
-                if y + count <= bottom as u32 {
-                    if let Some(line) = self.buffer.remove(&y) {
-                        self.buffer.insert(y + count, line);
+                result.push_str(&format!("\x1B[{};{}H", y + 1, start + 1));
+                moved = true;
+                for cell in (start..end).filter_map(|x| self.visible_cell(x, y)) {
+                    // Continuation cells are covered by the leading half.
+                    if cell.data.is_empty() {
+                        continue;
                     }
-                } else {
-                    self.buffer.remove(&y);
+                    if !cell.same_style(style) {
+                        result.push_str(&format!("\x1B[{}m", cell.sgr_params().join(";")));
+                        style = cell;
+                    }
+                    if cell.hyperlink.as_ref() != link {
+                        link = cell.hyperlink.as_ref();
+                        result.push_str(&format!("\x1B]8;;{}\x07", link.map_or("", |l| &**l)));
+                    }
+                    result.push_str(&cell.data);
                 }
+                x = end;
             }
+        }
 
-            self.cariage_return();
+        let attr = &self.cursor.attr;
+        if !attr.same_style(style) {
+            result.push_str(&format!("\x1B[{}m", attr.sgr_params().join(";")));
+        }
+        if attr.hyperlink.as_ref() != link {
+            result.push_str(&format!(
+                "\x1B]8;;{}\x07",
+                attr.hyperlink.as_deref().unwrap_or("")
+            ));
+        }
+        if moved {
+            result.push_str(&format!(
+                "\x1B[{};{}H",
+                self.cursor.y + 1,
+                self.cursor.x + 1
+            ));
+        }
+        if self.cursor.hidden != prev.cursor.hidden {
+            result.push_str(if self.cursor.hidden {
+                "\x1B[?25l"
+            } else {
+                "\x1B[?25h"
+            });
         }
+        result
     }
 
-    fn delete_lines(&mut self, count: Option<u32>) {
-        let count = count.unwrap_or(1);
-        let Margins { top, bottom } = self
-            .margins
-            .unwrap_or(Margins { top: 0, bottom: self.lines - 1 });
-
-        // If cursor is outside scrolling margins -- do nothing.
-        if top <= self.cursor.y && self.cursor.y <= bottom {
-            self.dirty.extend(self.cursor.y..self.lines);
-            for y in self.cursor.y..=bottom {
-                if y + count <= bottom {
-                    if let Some(line) = self.buffer.remove(&(y + count)) {
-                        self.buffer.insert(y, line);
+    /// Render the screen as a `<pre>` block, with runs of identically
+    /// styled cells wrapped in `<span>`s carrying inline CSS.
+    pub fn to_html(&self) -> String {
+        let mut result = String::from("<pre>");
+        for (y, line) in self.buffer.iter().enumerate() {
+            if y > 0 {
+                result.push('\n');
+            }
+            let mut start = 0;
+            while start < line.len() {
+                let end = start
+                    + line[start..]
+                        .iter()
+                        .take_while(|cell| cell.same_style(&line[start]))
+                        .count();
+                let mut text = String::new();
+                for cell in &line[start..end] {
+                    for c in cell.data.chars() {
+                        match c {
+                            '&' => text.push_str("&amp;"),
+                            '<' => text.push_str("&lt;"),
+                            '>' => text.push_str("&gt;"),
+                            '"' => text.push_str("&quot;"),
+                            c => text.push(c),
+                        }
                     }
+                }
+                let style = line[start].css_style();
+                if style.is_empty() {
+                    result.push_str(&text);
                 } else {
-                    self.buffer.remove(&y);
+                    result.push_str(&format!("<span style=\"{}\">{}</span>", style, text));
                 }
+                start = end;
             }
-
-            self.cariage_return();
         }
+        result.push_str("</pre>");
+        result
     }
 
-    /// Delete the indicated number of characters, starting with the
-    /// character at the cursor position. When a character is deleted,
-    /// all characters to the right of the cursor move left. Character
-    /// attributes move with the characters.
-    ///
-    /// # Parameters
-    /// - `count`: Number of characters to delete.
-    fn delete_characters(&mut self, count: Option<u32>) {
-        self.dirty.insert(self.cursor.y);
-        let count = count.map(|a| if a > 0 { a } else { 1 }).unwrap_or(1);
-
-        let default_char = self.default_char();
-        if let Some(line) = self.buffer.get_mut(&self.cursor.y) {
-            for x in self.cursor.x..self.columns {
-                if x + count <= self.columns {
-                    if let Some(char_opts) = line.remove(&(x + count)) {
-                        line.insert(x, char_opts);
-                    } else {
-                        line.insert(x, default_char.clone());
+    /// Returns each distinct hyperlink on screen, in order of first
+    /// appearance, together with the `(x, y)` cells it covers.
+    pub fn hyperlinks(&self) -> Vec<(String, Vec<(u32, u32)>)> {
+        let mut links: Vec<(String, Vec<(u32, u32)>)> = Vec::new();
+        for (y, line) in self.buffer.iter().enumerate() {
+            for (x, cell) in line.iter().enumerate() {
+                if let Some(uri) = &cell.hyperlink {
+                    let position = (x as u32, y as u32);
+                    match links.iter_mut().find(|(link, _)| **link == **uri) {
+                        Some((_, cells)) => cells.push(position),
+                        None => links.push((uri.to_string(), vec![position])),
                     }
-                } else {
-                    line.remove(&x);
                 }
             }
         }
+        links
     }
 
-    /// Erase the indicated number of characters, starting with the
-    /// character at the cursor position. Character attributes are set
-    /// to cursor attributes. The cursor remains in the same position.
-    ///
-    /// # Parameters
-    /// - `count`: Number of characters to erase.
-    ///
-    /// # Note
-    /// Using cursor attributes for character attributes may seem
-    /// illogical, but if you recall that a terminal emulator emulates
-    /// a typewriter, it starts to make sense. The only way a typewriter
-    /// could erase a character is by typing over it.
-    fn erase_characters(&mut self, count: Option<u32>) {
-        self.dirty.insert(self.cursor.y);
-        let count = count.map(|a| if a > 0 { a } else { 1 }).unwrap_or(1);
+    /// Draw `data` without segmenting it into graphemes or allocating
+    /// per character. Only ASCII text whose mapped characters are all
+    /// single width is handled, in replace mode and without `width_fn`.
+    /// Returns `false`, having drawn nothing, for anything else.
+    fn draw_ascii(&mut self, data: &str) -> bool {
+        let charset = match self.charset {
+            Charset::G0 => &self.g0_charset,
+            Charset::G1 => &self.g1_charset,
+        };
+        if !data.is_ascii()
+            || self.width_fn.is_some()
+            || self.mode.contains(&IRM)
+            || !data.bytes().all(|b| charset[b as usize].width() == Some(1))
+        {
+            return false;
+        }
+
+        let mut bytes = data.as_bytes();
+        while !bytes.is_empty() {
+            if self.pending_wrap {
+                if self.mode.contains(&DECAWM) {
+                    self.dirty.insert(self.cursor.y);
+                    self.cariage_return();
+                    self.linefeed();
+                    self.wrapped[self.cursor.y as usize] = true;
+                }
+                self.pending_wrap = false;
+            }
 
-        if let Some(line) = self.buffer.get_mut(&self.cursor.y) {
-            for x in self.cursor.x..std::cmp::min(self.cursor.x + count, self.columns) {
-                line.insert(x, self.cursor.attr.clone());
+            let x = self.cursor.x as usize;
+            let run = bytes
+                .len()
+                .min(self.columns.saturating_sub(self.cursor.x).max(1) as usize);
+            let charset = match self.charset {
+                Charset::G0 => &self.g0_charset,
+                Charset::G1 => &self.g1_charset,
+            };
+            if let Some(line) = self.buffer.get_mut(self.cursor.y as usize) {
+                for (cell, &b) in line.iter_mut().skip(x).zip(&bytes[..run]) {
+                    cell.assign(&self.cursor.attr, charset[b as usize]);
+                }
             }
+            self.mark_dirty_cells(self.cursor.y, x as u32..(x + run) as u32);
+            self.advance_after_write(run as u32);
+            bytes = &bytes[run..];
         }
+
+        self.dirty.insert(self.cursor.y);
+        true
     }
-    /// Report terminal identity.
-    ///
-    /// # Parameters
-    /// - `mode`: Mode for reporting terminal identity.
-    /// - `private`: When `true`, the method does nothing. This behavior is consistent with the VT220 manual.
+
+    /// Move the cursor right past `width` freshly written columns.
     ///
-    /// # Version
-    /// - Added in version 0.5.0
-    /// - Changed in version 0.7.0: If `private` keyword argument is set, the method does nothing.
-    fn report_device_attributes(&mut self, mode: Option<u32>, private: Option<bool>) {
-        // We only implement "primary" DA which is the only DA request
-        // VT102 understood, see `VT102ID` in `linux/drivers/tty/vt.c`.
-        if mode.unwrap_or(0) == 0 && !private.unwrap_or(false) {
-            self.write_process_input("\x1B[?6c");
+    /// Writing into the last column leaves the cursor there instead of
+    /// moving past the edge; with `DECAWM` set the wrap is deferred until
+    /// the next character is drawn (see `pending_wrap`).
+    fn advance_after_write(&mut self, width: u32) {
+        if self.cursor.x + width >= self.columns {
+            self.cursor.x = self.columns.saturating_sub(1);
+            self.pending_wrap = self.mode.contains(&DECAWM);
+        } else {
+            self.cursor.x += width;
         }
     }
 
-    /// Move cursor to a specific line in the current column.
-    ///
-    /// # Parameters
-    /// - `line`: Line number to move the cursor to.
-    fn cursor_to_line(&mut self, line: Option<u32>) {
-        self.cursor.y = line.unwrap_or(1) - 1;
+    /// Returns `true` if the cell at `x`, `y` is the trailing half of a
+    /// wide character.
+    fn is_continuation(&self, x: u32, y: u32) -> bool {
+        let Some(line) = self.buffer.get(y as usize) else {
+            return false;
+        };
+        x > 0
+            && line
+                .get(x as usize)
+                .is_some_and(|cell| cell.data.is_empty())
+            && grapheme_width(&line[x as usize - 1].data, self.width_fn.as_deref()) == 2
+    }
 
-        // If origin mode (DECOM) is set, line numbers are relative to
-        // the top scrolling margin.
-        if self.mode.contains(&DECOM) {
-            if let Some(margins) = self.margins {
-                self.cursor.y += margins.top;
+    /// Blank the halves of wide characters on row `y` which lost their
+    /// other half, e.g. after part of the row was erased or shifted.
+    fn repair_wide_chars(&mut self, y: u32) {
+        let Some(line) = self.buffer.get_mut(y as usize) else {
+            return;
+        };
+        let is_lead = |cell: &CharOpts| grapheme_width(&cell.data, self.width_fn.as_deref()) == 2;
+        let mut repaired = Vec::new();
+        for x in 0..line.len() {
+            // A wide character in the last column has no room for its
+            // continuation, it is left as is.
+            let orphan = if is_lead(&line[x]) {
+                line.get(x + 1).is_some_and(|next| !next.data.is_empty())
+            } else {
+                line[x].data.is_empty() && (x == 0 || !is_lead(&line[x - 1]))
+            };
+            if orphan {
+                line[x].data = " ".to_string();
+                repaired.push(x as u32);
             }
+        }
+        for x in repaired {
+            self.mark_dirty_cells(y, x..x + 1);
+        }
+    }
 
-            // FIXME: should we also restrict the cursor to the scrolling
-            // region?
+    /// Shifts the lines of the scrolling region one column right or left,
+    /// for DECBI and DECFI. Left and right margins aren't supported, the
+    /// screen edges are used.
+    fn shift_columns(&mut self, right: bool) {
+        let (top, bottom) = self.scroll_region();
+        let default = self.default_char();
+        for y in top..=bottom {
+            self.dirty.insert(y);
+            self.mark_dirty_cells(y, 0..self.columns);
+            if let Some(line) = self.buffer.get_mut(y as usize) {
+                if line.is_empty() {
+                    continue;
+                }
+                if right {
+                    line.rotate_right(1);
+                    line[0] = default.clone();
+                } else {
+                    line.rotate_left(1);
+                    *line.last_mut().unwrap() = default.clone();
+                }
+            }
+            self.repair_wide_chars(y);
         }
+    }
 
-        self.ensure_vbounds(None);
+    /// Removes and returns the `(x, y)` cells changed since the last
+    /// call, for redrawing only what changed. These are the cells in the
+    /// spans of `dirty_ranges`, which are taken along with them.
+    pub fn take_dirty_cells(&mut self) -> HashSet<(u32, u32)> {
+        self.take_dirty_ranges()
+            .into_iter()
+            .flat_map(|(y, (first, last))| (first..=last).map(move |x| (x, y)))
+            .collect()
     }
 
-    /// Clear a horizontal tab stop.
-    ///
-    /// # Parameters
-    /// - `how`: Defines the way the tab stop should be cleared:
-    ///     - `0` or nothing: Clears a horizontal tab stop at the cursor position.
-    ///     - `3`: Clears all horizontal tab stops.
-    fn clear_tab_stop(&mut self, how: Option<u32>) {
-        match how.unwrap_or(0) {
-            0 => {
-                // Clears a horizontal tab stop at cursor position, if it's
-                // present, or silently fails if otherwise.
-                self.tabstops.remove(&self.cursor.x);
-            }
-            3 => {
-                // Clears all horizontal tab stops.
-                self.tabstops.clear();
-            }
-            _ => {
-                // Handle invalid `how` values if necessary.
-            }
+    /// Removes and returns the first and last changed column of each
+    /// row changed since the last call, for repainting only that span.
+    pub fn take_dirty_ranges(&mut self) -> HashMap<u32, (u32, u32)> {
+        core::mem::take(&mut self.dirty_ranges)
+    }
+
+    /// Returns `true` if any line is marked as changed in `dirty`.
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Returns the number of lines marked as changed in `dirty`.
+    pub fn dirty_line_count(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// Forget what changed, once a renderer has drawn it: clears `dirty`
+    /// along with `dirty_ranges`.
+    pub fn reset_dirty(&mut self) {
+        self.dirty.clear();
+        self.dirty_ranges.clear();
+    }
+
+    /// Records the cells of row `y` in `columns` as changed.
+    fn mark_dirty_cells(&mut self, y: u32, columns: core::ops::Range<u32>) {
+        let columns = columns.start..u32::min(columns.end, self.columns);
+        if columns.is_empty() {
+            return;
         }
+        let (first, last) = (columns.start, columns.end - 1);
+        self.dirty_ranges
+            .entry(y)
+            .and_modify(|range| *range = (range.0.min(first), range.1.max(last)))
+            .or_insert((first, last));
     }
 
-    /// Set (enable) a given list of modes.
-    ///
-    /// # Arguments
-    ///
-    /// - `modes`: A list of modes to set, where each mode is a constant from the `pyte::modes` module.
-    ///
-    /// # Example
-    ///
-    /// ```rust, ignore
-    /// let modes = vec![Mode::Insert, Mode::Replace];
-    /// set_modes(modes);
-    /// ```
-    ///
-    /// # Note
-    ///
-    /// Each mode should be a constant from the `modes` module.
-    fn set_mode(&mut self, modes: &[u32], private: bool) {
-        // mode_list = list(modes)
-        // Private mode codes are shifted, to be distinguished from non
-        // private ones.
-        let mut mode_list = Vec::from(modes);
-        if private {
-            mode_list = modes.iter().map(|m| m << 5).collect::<Vec<_>>();
-            if mode_list.iter().any(|m| *m == DECSCNM) {
-                self.dirty.extend(0..self.lines);
-            }
+    /// Records every cell of rows `lines` as changed.
+    fn mark_dirty_rows(&mut self, lines: core::ops::Range<u32>) {
+        for y in lines {
+            self.mark_dirty_cells(y, 0..self.columns);
         }
+    }
 
-        self.mode.extend(mode_list.iter());
+    /// Returns a line of empty characters spanning the screen width.
+    fn blank_line(&self) -> Vec<CharOpts> {
+        vec![self.default_char(); self.columns as usize]
+    }
 
-        // When DECOLM mode is set, the screen is erased and the cursor
-        // moves to the home position.
-        dbg!(mode_list.clone());
-        if mode_list.iter().any(|m| *m == DECCOLM) {
-            dbg!("DECCOLM");
-            self.saved_columns = Some(self.columns);
-            self.resize(None, Some(132));
-            self.erase_in_display(Some(2), None);
-            self.cursor_position(None, None);
+    /// Put the primary screen aside and show a cleared alternate one,
+    /// unless it is already shown.
+    fn enter_alternate_screen(&mut self) {
+        if self.primary_screen.is_some() {
+            return;
         }
+        let blank = vec![self.blank_line(); self.lines as usize];
+        let buffer = core::mem::replace(&mut self.buffer, blank);
+        let wrapped = core::mem::replace(&mut self.wrapped, vec![false; self.lines as usize]);
+        self.primary_screen = Some((buffer, wrapped));
+        self.pending_wrap = false;
+        self.dirty.extend(0..self.lines);
+        self.mark_dirty_rows(0..self.lines);
+    }
 
-        // According to VT520 manual, DECOM should also home the cursor.
-        if mode_list.iter().any(|m| *m == DECOM) {
-            self.cursor_position(None, None);
+    /// Show the primary screen again, fitted to the current size, and
+    /// drop the alternate one.
+    fn leave_alternate_screen(&mut self) {
+        let Some((mut buffer, mut wrapped)) = self.primary_screen.take() else {
+            return;
+        };
+        let default_char = self.default_char();
+        for line in buffer.iter_mut() {
+            line.resize(self.columns as usize, default_char.clone());
+        }
+        buffer.resize(self.lines as usize, self.blank_line());
+        wrapped.resize(self.lines as usize, false);
+        self.buffer = buffer;
+        self.wrapped = wrapped;
+        self.pending_wrap = false;
+        self.dirty.extend(0..self.lines);
+        self.mark_dirty_rows(0..self.lines);
+    }
+
+    /// Scroll lines `top..=bottom` up by `count`, filling the lines
+    /// uncovered at the bottom of the region with `fill`.
+    fn scroll_region_up(&mut self, top: u32, bottom: u32, count: u32, fill: CharOpts) {
+        let (top, bottom) = (top as usize, bottom as usize);
+        let count = usize::min(count as usize, bottom + 1 - top);
+        let blank = vec![fill; self.columns as usize];
+        self.buffer[top..=bottom].rotate_left(count);
+        for line in &mut self.buffer[bottom + 1 - count..=bottom] {
+            line.clone_from(&blank);
+        }
+        self.wrapped[top..=bottom].rotate_left(count);
+        self.wrapped[bottom + 1 - count..=bottom].fill(false);
+        self.mark_dirty_rows(top as u32..bottom as u32 + 1);
+    }
+
+    /// Scroll lines `top..=bottom` down by `count`, filling the lines
+    /// uncovered at the top of the region with `fill`.
+    fn scroll_region_down(&mut self, top: u32, bottom: u32, count: u32, fill: CharOpts) {
+        let (top, bottom) = (top as usize, bottom as usize);
+        let count = usize::min(count as usize, bottom + 1 - top);
+        let blank = vec![fill; self.columns as usize];
+        self.buffer[top..=bottom].rotate_right(count);
+        for line in &mut self.buffer[top..top + count] {
+            line.clone_from(&blank);
         }
+        self.wrapped[top..=bottom].rotate_right(count);
+        self.wrapped[top..top + count].fill(false);
+        self.mark_dirty_rows(top as u32..bottom as u32 + 1);
+    }
 
-        // Mark all displayed characters as reverse.
-        if mode_list.iter().any(|m| *m == DECSCNM) {
-            for line in self.buffer.values_mut() {
-                // line.default = self.default_char;
-                for x in line.iter_mut() {
-                    x.1.reverse = true;
-                }
+    /// Returns the character erased and scrolled in cells are filled
+    /// with: `default_char` with the cursor background when `bce` is on,
+    /// else `default_char` itself.
+    fn erase_char(&self) -> CharOpts {
+        if self.bce {
+            CharOpts {
+                bg: self.cursor.attr.bg.clone(),
+                ..self.default_char()
             }
+        } else {
+            self.default_char()
+        }
+    }
 
-            self.select_graphic_rendition(&[7]); // +reverse.
+    /// Returns an empty character with default foreground and background colors.
+    pub fn default_char(&self) -> CharOpts {
+        CharOpts {
+            data: " ".to_owned(),
+            fg: self.defaults.fg.clone(),
+            bg: self.defaults.bg.clone(),
+            reverse: self.mode.contains(&DECSCNM),
+            ..CharOpts::default()
         }
+    }
+}
 
-        // # Make the cursor visible.
-        if mode_list.iter().any(|m| *m == DECTCEM) {
-            self.cursor.hidden = false;
+impl ParserListener for Screen {
+    /// Fills screen with uppercase E's for screen focus and alignment.
+    fn alignment_display(&mut self) {
+        self.dirty.extend(0..self.lines);
+        self.mark_dirty_rows(0..self.lines);
+        self.wrapped.fill(false);
+        for line in self.buffer.iter_mut() {
+            for cell in line.iter_mut() {
+                cell.data = "E".to_string();
+            }
         }
     }
 
-    /// Reset (disable) a given list of modes.
+    /// Define ``G0`` or ``G1`` charset.
     ///
     /// # Arguments
+    /// * `code` - character set code, should be a character
+    ///   from ``"B0UK"``, otherwise ignored.
     ///
-    /// - `modes`: A list of modes to reset. Each mode should ideally be a constant from the `pyte::modes` module.
+    /// * `mode` - if ``"("`` ``G0`` charset is defined, if
+    ///   ``")"`` we operate on ``G1``.
     ///
-    /// # Example
+    /// **Warning:** User-defined charsets are currently not supported.
+    fn define_charset(&mut self, code: &str, mode: &str) {
+        if MAPS.keys().any(|&a| a == code) {
+            if mode == "(" {
+                self.g0_charset = MAPS[code];
+            } else if mode == ")" {
+                self.g1_charset = MAPS[code];
+            }
+        }
+    }
+
+    /// Reset the terminal to its initial state.
     ///
-    /// ```rust, ignore
-    /// let modes = vec![Mode::Insert, Mode::Replace];
-    /// reset_modes(modes);
-    /// ```
+    /// - Scrolling margins are reset to screen boundaries.
+    /// - Cursor is moved to home location `(0, 0)` and its attributes are set to defaults.
+    /// - Screen is cleared, each character is reset to default char.
+    /// - Tabstops are reset to "every eight columns".
+    /// - All lines are marked as dirty.
     ///
-    /// # Note
+    /// The reporter is left alone, so replies queued before the reset
+    /// but not yet read by the host are not lost.
     ///
-    /// Make sure that each mode is a constant from the `modes` module.
-    fn reset_mode(&mut self, modes: &[u32], is_private: bool) {
-        let mut mode_list = Vec::from(modes);
-        // Private mode codes are shifted, to be distinguished from non
-        // private ones.
-        if is_private {
-            mode_list = modes.iter().map(|m| m << 5).collect::<Vec<_>>();
-            if mode_list.iter().any(|m| *m == DECSCNM) {
-                self.dirty.extend(0..self.lines);
-            }
-        }
+    /// **Warning**
+    /// Neither VT220 nor VT102 manuals mention that terminal modes and tabstops should be reset as well.
+    /// Thanks to `xterm` -- we now know that.
+    fn reset(&mut self) {
+        self.dirty.clear();
+        self.dirty.extend(0..self.lines);
+        self.mark_dirty_rows(0..self.lines);
+        self.margins = None;
 
-        // retain mode mode_list difference
-        self.mode = self
-            .mode
-            .iter()
-            .filter(|&&x| !mode_list.iter().any(|&y| x == y))
-            .cloned()
-            .collect();
+        let was_sync = self.synchronized_output();
+        self.mode = self.defaults.mode.clone();
+        self.sync_changed(was_sync);
+        self.buffer = vec![self.blank_line(); self.lines as usize];
+        self.wrapped = vec![false; self.lines as usize];
+        self.primary_screen = None;
 
-        // Lines below follow the logic in set_mode.
-        if mode_list.iter().any(|m| *m == DECCOLM) {
-            if self.columns == 132 {
-                if let Some(saved_columns) = self.saved_columns {
-                    self.resize(None, Some(saved_columns));
-                    self.saved_columns = None;
-                }
-            }
-            self.erase_in_display(Some(2), None);
-            self.cursor_position(None, None);
-        }
+        self.title = "".to_owned();
+        self.icon_name = "".to_owned();
+        self.default_fg_color = DEFAULT_FG_COLOR.to_owned();
+        self.default_bg_color = DEFAULT_BG_COLOR.to_owned();
+        self.cursor_color = DEFAULT_FG_COLOR.to_owned();
 
-        // According to VT520 manual, DECOM should also home the cursor.
-        if mode_list.iter().any(|m| *m == DECOM) {
-            self.cursor_position(None, None);
-        }
+        self.charset = Charset::G0;
+        self.g0_charset = self.defaults.g0_charset;
+        self.g1_charset = self.defaults.g1_charset;
 
-        // Mark all displayed characters as reverse.
-        if mode_list.iter().any(|m| *m == DECSCNM) {
-            for line in self.buffer.values_mut() {
-                // line.default = self.default_char;
-                for x in line.iter_mut() {
-                    x.1.reverse = false;
-                }
-            }
+        self.reset_tab_stops();
 
-            self.select_graphic_rendition(&[27]); // +reverse.
-        }
+        self.cursor = Cursor {
+            x: 0,
+            y: 0,
+            hidden: false,
+            blink: self.mode.contains(&CURSOR_BLINK),
+            attr: self.default_char(),
+        };
+        self.cursor_position(None, None);
 
-        // Hide the cursor.
-        if mode_list.iter().any(|m| *m == DECTCEM) {
-            self.cursor.hidden = true;
+        self.saved_columns = None;
+        self.pending_wrap = false;
+        self.keypad_application = false;
+        self.modify_other_keys = 0;
+        self.keyboard_flags.clear();
+        self.printer_controller = false;
+        self.auto_print = false;
+        self.view_offset = 0;
+    }
+
+    /// Move the cursor down one line in the same column. If the
+    /// cursor is at the last line, create a new line at the bottom.
+    fn index(&mut self) {
+        self.pending_wrap = false;
+        let Margins { top, bottom } = self
+            .margins
+            .or(Some(Margins { top: 0, bottom: self.lines - 1 }))
+            .expect("unexpected margin found");
+
+        if self.cursor.y == bottom {
+            // Mark all lines as dirty
+            self.dirty.extend(0..self.lines);
+            // Only the primary screen keeps scrollback.
+            if top == 0 && self.scrollback_limit > 0 && self.primary_screen.is_none() {
+                self.push_scrollback(self.buffer[0].clone());
+            }
+            self.scroll_region_up(top, bottom, 1, self.erase_char());
+        } else {
+            self.cursor_down(None);
         }
     }
 
-    /// Set display attributes.
-    ///
-    /// # Parameters
-    /// - `attrs`: A list of display attributes to set.
-    fn select_graphic_rendition(&mut self, attrs: &[u32]) {
-        let mut replace = HashMap::new();
+    // Perform an index and, if LNM is set, a  carriage return.
+    fn linefeed(&mut self) {
+        if self.printer_controller {
+            self.printer_output.push('\n');
+            return;
+        }
+        if self.auto_print {
+            self.print_line(self.cursor.y);
+        }
+        self.index();
+        if let Some(wrapped) = self.wrapped.get_mut(self.cursor.y as usize) {
+            *wrapped = false;
+        }
+        if self.mode.contains(&LNM) {
+            self.cariage_return();
+        }
+    }
 
-        // Fast path for resetting all attributes.
-        if attrs.is_empty() || (attrs.len() == 1 && attrs[0] == 0) {
-            self.cursor.attr = self.default_char();
+    /// Perform a linefeed followed by a carriage return, whether LNM
+    /// is set or not.
+    fn next_line(&mut self) {
+        if self.printer_controller {
+            self.printer_output.push_str("\r\n");
             return;
         }
+        self.linefeed();
+        if !self.mode.contains(&LNM) {
+            self.cariage_return();
+        }
+    }
 
-        let mut attrs_list = attrs.to_vec();
-        attrs_list.reverse();
+    // Move the cursor up one line in the same column. If the cursor
+    // at the first line, create a new line at the top.
+    fn reverse_index(&mut self) {
+        self.pending_wrap = false;
+        let (top, bottom) = match &self.margins {
+            Some(margins) => (margins.top, margins.bottom),
+            None => (0, self.lines - 1),
+        };
 
-        while let Some(attr) = attrs_list.pop() {
-            match attr {
-                0 => {
-                    // Reset all attributes.
-                    replace.extend(self.default_char().to_map());
-                }
-                attr if FG_ANSI.contains_key(&attr) => {
-                    replace.insert("fg".to_string(), FG_ANSI[&attr].clone());
-                }
-                attr if BG_ANSI.contains_key(&attr) => {
-                    replace.insert("bg".to_string(), BG_ANSI[&attr].clone());
-                }
-                attr if TEXT.contains_key(&attr) => {
-                    let attr_str = &TEXT[&attr];
-                    replace.insert(
-                        attr_str[1..].to_string(),
-                        attr_str.starts_with('+').to_string(),
-                    );
-                }
-                attr if FG_AIXTERM.contains_key(&attr) => {
-                    replace.insert("fg".to_string(), FG_AIXTERM[&attr].clone());
-                }
-                attr if BG_AIXTERM.contains_key(&attr) => {
-                    replace.insert("bg".to_string(), BG_AIXTERM[&attr].clone());
-                }
-                attr if attr == FG_256 || attr == BG_256 => {
-                    let key = if attr == FG_256 { "fg" } else { "bg" };
-                    if let Some(n) = attrs_list.pop() {
-                        if n == 5 {
-                            if let Some(m) = attrs_list.pop() {
-                                if m < 16 {
-                                    replace.insert(key.to_string(), FG_BG_256[m as usize].clone());
-                                }
-                            }
-                        } else if n == 2 {
-                            if let (Some(r), Some(g), Some(b)) =
-                                (attrs_list.pop(), attrs_list.pop(), attrs_list.pop())
-                            {
-                                replace.insert(
-                                    key.to_string(),
-                                    format!("{:02x}{:02x}{:02x}", r, g, b),
-                                );
-                            }
-                        } else {
-                            // consider panicing in a strict mode
-                            // panic!("invalid mode for FG BG colors");
-                        }
-                    }
-                }
-                _ => {}
-            }
+        if self.cursor.y == top {
+            // Mark all lines as dirty
+            self.dirty.extend(0..self.lines);
+            self.scroll_region_down(top, bottom, 1, self.erase_char());
+        } else {
+            self.cursor_up(None);
         }
-
-        self.cursor.attr.update_from_map(replace);
     }
 
-    /// Set terminal title.
-    ///
-    /// **Warning:** This is an XTerm extension supported by the Linux terminal.
-    fn set_title(&mut self, title: &str) {
-        self.title = title.to_owned();
+    /// Move the cursor left one column (DECBI). In the first column the
+    /// content of the scrolling region moves right instead, with a blank
+    /// column inserted on the left.
+    fn back_index(&mut self) {
+        self.pending_wrap = false;
+        if self.cursor.x > 0 {
+            self.cursor_back(None);
+        } else {
+            self.shift_columns(true);
+        }
     }
 
-    /// Set icon name
-    ///
-    /// **Warning:** This is an XTerm extension supported by the Linux terminal.
-    fn set_icon_name(&mut self, icon_name: &str) {
-        self.icon_name = icon_name.to_owned();
+    /// Move the cursor right one column (DECFI). In the last column the
+    /// content of the scrolling region moves left instead, with a blank
+    /// column inserted on the right.
+    fn forward_index(&mut self) {
+        self.pending_wrap = false;
+        if self.cursor.x + 1 < self.columns {
+            self.cursor_forward(None);
+        } else {
+            self.shift_columns(false);
+        }
     }
-}
-
-#[cfg(test)]
-mod test {
-    use std::collections::{HashMap, HashSet};
-    use std::sync::{Arc, Mutex};
-
-    use super::{CharOpts, Screen};
-    use crate::graphics::{BG_256, FG_256};
-    use crate::modes::{DECAWM, DECCOLM, DECOM, DECSCNM, DECTCEM, IRM, LNM};
-    use crate::parser::Parser;
-    use crate::parser_listener::ParserListener;
-    use crate::screen::{Charset, Margins};
 
-    /// Macro to create CharOpts with optional color
-    macro_rules! co {
-        (default) => {
-            CharOpts::default()
-        };
-        ($c:literal) => {
-            CharOpts { data: $c.to_string(), ..CharOpts::default() }
-        };
-        ($c:literal, fg = $color:literal) => {
-            CharOpts {
-                data: $c.to_string(),
-                fg: $color.to_string(),
-                ..CharOpts::default()
-            }
-        };
+    // Set a horizontal tab stop at cursor position.
+    fn set_tab_stop(&mut self) {
+        self.tabstops.insert(self.cursor.x);
     }
 
-    macro_rules! cv {
-        ($(co !($($arg:tt)*)),+ $(,)?) => {
-            vec![ $(co!($($arg)*)),+ ]
-        };
+    // Save the cursor state, replacing any saved before.
+    fn save_cursor(&mut self) {
+        self.savepoint = Some(Savepoint {
+            cursor: self.cursor.clone(),
+            g0_charset: self.g0_charset,
+            g1_charset: self.g1_charset,
+            charset: self.charset,
+            origin: self.mode.contains(&DECOM),
+            wrap: self.mode.contains(&DECAWM),
+        })
     }
 
-    pub fn update(screen: &mut Screen, lines: Vec<&str>, colored: Vec<u32>) {
-        for (y, line) in lines.iter().enumerate() {
-            for (x, char) in line.chars().enumerate() {
-                let mut attrs = screen.default_char();
-                if colored.contains(&(y as u32)) {
-                    attrs.fg = "red".to_string();
-                }
-                attrs.data = char.to_string();
-                screen
-                    .buffer
-                    .entry(y as u32)
-                    .or_insert_with(HashMap::new)
-                    .insert(x as u32, attrs);
+    /// Set the current cursor position to the saved one. The savepoint
+    /// is kept, restoring again returns to the same state.
+    fn restore_cursor(&mut self) {
+        self.pending_wrap = false;
+        if let Some(savepoint) = self.savepoint.clone() {
+            self.g0_charset = savepoint.g0_charset;
+            self.g1_charset = savepoint.g1_charset;
+            self.charset = savepoint.charset;
+
+            if savepoint.origin {
+                self.set_mode(&[DECOM], false)
+            }
+            if savepoint.wrap {
+                self.set_mode(&[DECAWM], false)
             }
+
+            // Blinking follows mode 12, it isn't part of the savepoint.
+            self.cursor = Cursor { blink: self.cursor.blink, ..savepoint.cursor };
+            self.ensure_hbounds();
+            self.ensure_vbounds(Some(true));
+        } else {
+            // If nothing was saved, the cursor moves to home position;
+            // origin mode is reset. :todo: DECAWM?
+            self.reset_mode(&[DECOM], false);
+            self.cursor_position(None, None);
         }
     }
 
-    pub fn tolist(screen: &Screen) -> Vec<Vec<CharOpts>> {
-        let mut result = Vec::new();
+    /// Select ``G1`` character set.
+    fn shift_out(&mut self) {
+        self.charset = Charset::G1;
+    }
 
-        for y in 0..screen.lines {
-            let mut line = Vec::new();
-            for x in 0..screen.columns {
-                let char_opts = screen
-                    .buffer
-                    .get(&y)
-                    .and_then(|line| line.get(&x))
-                    .cloned()
-                    .unwrap_or_default();
-                line.push(char_opts);
-            }
-            result.push(line);
+    /// Select ``G0`` character set.
+    fn shift_in(&mut self) {
+        self.charset = Charset::G0;
+    }
+
+    /// Ring the bell by calling `on_bell` with the bell style, the
+    /// actual implementation is left to the end-user.
+    fn bell(&mut self) {
+        if let Some(callback) = self.on_bell.as_mut() {
+            callback(self.bell_style);
         }
+    }
 
-        result
+    /// Switch the keypad to application mode (DECKPAM).
+    fn keypad_application_mode(&mut self) {
+        self.keypad_application = true;
     }
-    #[test]
-    fn initialize_char() {
-        // List of fields in CharOpts struct
-        let fields = vec![
-            "data",
-            "fg",
-            "bg",
-            "bold",
-            "italics",
-            "underscore",
-            "strikethrough",
-            "reverse",
-            "blink",
-        ];
 
-        for field in fields.iter().skip(1) {
-            let mut char_opts = CharOpts::default();
-            match *field {
-                "bold" => char_opts.bold = true,
-                "italics" => char_opts.italics = true,
-                "underscore" => char_opts.underscore = true,
-                "strikethrough" => char_opts.strikethrough = true,
-                "reverse" => char_opts.reverse = true,
-                "blink" => char_opts.blink = true,
-                _ => {}
-            }
+    /// Switch the keypad to numeric mode (DECKPNM).
+    fn keypad_numeric_mode(&mut self) {
+        self.keypad_application = false;
+    }
 
-            match *field {
-                "bold" => assert!(char_opts.bold),
-                "italics" => assert!(char_opts.italics),
-                "underscore" => assert!(char_opts.underscore),
-                "strikethrough" => assert!(char_opts.strikethrough),
-                "reverse" => assert!(char_opts.reverse),
-                "blink" => assert!(char_opts.blink),
-                _ => {}
-            }
+    /// Send the answerback message to the host, if one is set.
+    fn enquiry(&mut self) {
+        if !self.answerback.is_empty() {
+            let answerback = self.answerback.clone();
+            self.write_process_input(&answerback);
         }
     }
 
-    #[test]
-    fn remove_non_existant_attribute() {
-        let mut screen = Screen::new(2, 2);
-
-        let expected = vec![
-            cv![co!(default), co!(default)],
-            cv![co!(default), co!(default)],
-        ];
-
-        assert_eq!(tolist(&screen), expected);
+    /// Move cursor to the left one or keep it in its position if
+    /// it's at the beginning of the line already.
+    fn backspace(&mut self) {
+        self.cursor_back(None);
+    }
 
-        screen.select_graphic_rendition(&[24]); // underline-off.
-        assert_eq!(tolist(&screen), expected);
-        assert!(!screen.cursor.attr.underscore);
+    /// Move to the next tab space, or the end of the screen if there
+    /// aren't anymore left.
+    fn tab(&mut self) {
+        if self.printer_controller {
+            self.printer_output.push('\t');
+            return;
+        }
+        self.pending_wrap = false;
+        let x = self.cursor.x;
+        self.cursor.x = self
+            .tabstops
+            .iter()
+            .copied()
+            .filter(|&stop| stop > x)
+            .min()
+            .unwrap_or(self.columns.saturating_sub(1));
     }
 
-    #[test]
-    fn attributes() {
-        let mut screen = Screen::new(2, 2);
+    /// Move the cursor to the beginning of the current line.
+    fn cariage_return(&mut self) {
+        if self.printer_controller {
+            self.printer_output.push('\r');
+            return;
+        }
+        self.pending_wrap = false;
+        self.cursor.x = 0;
+    }
 
-        let default_char = CharOpts::default();
-        let expected_initial = vec![
-            cv![co!(default), co!(default)],
-            cv![co!(default), co!(default)],
-        ];
+    /// Display decoded characters at the current cursor position and
+    /// advances the cursor if `DECAWM` is set.
+    ///
+    /// # Parameters
+    /// - `data`: Text to display.
+    ///
+    /// # Version
+    /// - Changed in version 0.5.0: Character width is taken into account.
+    ///   Specifically, zero-width and unprintable characters do not affect
+    ///   screen state. Full-width characters are rendered into two consecutive
+    ///   character containers.
+    fn draw(&mut self, data: &str) {
+        if self.printer_controller {
+            self.printer_output.push_str(data);
+            return;
+        }
+        if self.draw_ascii(data) {
+            return;
+        }
+        let data = data
+            .chars()
+            .map(|c| {
+                if c as usize > 255 {
+                    c
+                } else if self.charset == Charset::G1 {
+                    self.g1_charset[c as usize]
+                } else {
+                    self.g0_charset[c as usize]
+                }
+            })
+            .filter_map(|c| {
+                if c.is_control() {
+                    self.replacement_policy.replacement()
+                } else {
+                    Some(c)
+                }
+            })
+            .collect::<String>();
+        let data = if self.normalize && !is_nfc(&data) {
+            data.nfc().collect::<String>()
+        } else {
+            data
+        };
+        let replacement = self.replacement_policy.replacement().map(String::from);
+        let mut data = data.graphemes(true).collect::<Vec<&str>>();
+
+        let mut i = 0;
+        while i < data.len() {
+            let cluster = data[i];
+            let char = cluster.chars().next().unwrap_or_default();
+            let char_width = grapheme_width(cluster, self.width_fn.as_deref());
+
+            // If the previous character went into the last column and auto
+            // wrap mode is (still) enabled, move the cursor to the beginning
+            // of the next line, otherwise replace the character already
+            // displayed there with the newly entered one.
+            if char_width > 0 && self.pending_wrap {
+                if self.mode.contains(&DECAWM) {
+                    self.dirty.insert(self.cursor.y);
+                    self.cariage_return();
+                    self.linefeed();
+                    self.wrapped[self.cursor.y as usize] = true;
+                }
+                self.pending_wrap = false;
+            }
 
-        assert_eq!(tolist(&screen), expected_initial);
+            // Fast path: a run of single-width characters which fits on the
+            // current line is written in one go, reusing the cells' string
+            // allocations instead of cloning the cursor attributes per char.
+            let is_plain = |g: &str| {
+                g.chars().nth(1).is_none() && grapheme_width(g, self.width_fn.as_deref()) == 1
+            };
+            if is_plain(cluster) && self.cursor.x < self.columns && !self.mode.contains(&IRM) {
+                let room = (self.columns - self.cursor.x) as usize;
+                let run = data[i..]
+                    .iter()
+                    .take(room)
+                    .take_while(|g| is_plain(g))
+                    .count();
+                let x = self.cursor.x as usize;
+                if let Some(line) = self.buffer.get_mut(self.cursor.y as usize) {
+                    for (cell, g) in line[x..x + run].iter_mut().zip(&data[i..i + run]) {
+                        cell.assign(&self.cursor.attr, g.chars().next().unwrap_or_default());
+                    }
+                }
+                self.mark_dirty_cells(self.cursor.y, x as u32..(x + run) as u32);
+                self.advance_after_write(run as u32);
+                i += run;
+                continue;
+            }
 
-        screen.select_graphic_rendition(&[1]); // bold.
+            // If Insert mode is set, new characters move old characters to
+            // the right, otherwise terminal is in Replace mode and new
+            // characters replace old characters at cursor position.
+            if self.mode.contains(&IRM) && char_width > 0 {
+                self.insert_characters(Some(char_width as u32));
+            }
 
-        // Still default, since we haven't written anything.
-        assert_eq!(tolist(&screen), expected_initial);
-        assert!(screen.cursor.attr.bold);
+            let (x, y) = (self.cursor.x as usize, self.cursor.y as usize);
+            if char_width > 0 {
+                self.mark_dirty_cells(y as u32, x as u32..(x + char_width) as u32);
+            }
+            if char_width == 1 {
+                if let Some(cell) = self.buffer.get_mut(y).and_then(|l| l.get_mut(x)) {
+                    *cell = self.cursor.attr.clone_with_data(cluster.to_string());
+                }
+            } else if char_width == 2 {
+                if let Some(line) = self.buffer.get_mut(y) {
+                    if let Some(cell) = line.get_mut(x) {
+                        *cell = self.cursor.attr.clone_with_data(cluster.to_string());
+                    }
+                    if let Some(cell) = line.get_mut(x + 1) {
+                        *cell = self.cursor.attr.clone_with_data("".to_string());
+                    }
+                }
+            } else if char_width == 0 && is_combining_mark(char) {
+                // With a pending wrap the cursor still sits on the last
+                // character written.
+                let base = if self.pending_wrap {
+                    Some((x, y))
+                } else if x > 0 {
+                    Some((x - 1, y))
+                } else if y > 0 {
+                    Some((self.columns as usize - 1, y - 1))
+                } else {
+                    None
+                };
+                // The mark belongs to the leading half of a wide character.
+                let base = base.map(|(bx, by)| {
+                    if self.is_continuation(bx as u32, by as u32) {
+                        (bx - 1, by)
+                    } else {
+                        (bx, by)
+                    }
+                });
+                let last =
+                    base.and_then(|(bx, by)| self.buffer.get_mut(by).and_then(|l| l.get_mut(bx)));
+                if let Some(last) = last {
+                    last.data.push_str(cluster);
+                    if self.normalize {
+                        last.data = last.data.nfc().collect::<String>();
+                    }
+                }
+                if let Some((bx, by)) = base {
+                    self.mark_dirty_cells(by as u32, bx as u32..bx as u32 + 1);
+                }
+            } else {
+                // A zero width character which isn't a combining mark,
+                // e.g. U+200B, is replaced as `replacement_policy` says. A
+                // replacement without width of its own is dropped too.
+                match replacement.as_deref() {
+                    Some(replacement)
+                        if grapheme_width(replacement, self.width_fn.as_deref()) > 0 =>
+                    {
+                        data[i] = replacement;
+                    }
+                    _ => i += 1,
+                }
+                continue;
+            }
 
-        screen.draw("f");
-        let expected_after_draw = vec![
-            vec![
-                CharOpts {
-                    data: "f".to_string(),
-                    fg: "default".to_string(),
-                    bg: "default".to_string(),
-                    bold: true,
-                    ..default_char.clone()
-                },
-                default_char.clone(),
-            ],
-            vec![default_char.clone(), default_char.clone()],
-        ];
+            // .. note:: We can't use `cursor_forward()`, because that
+            //           way, we'll never know when to linefeed.
+            if char_width > 0 {
+                self.advance_after_write(char_width as u32);
+            }
+            i += 1;
+        }
 
-        assert_eq!(tolist(&screen), expected_after_draw);
+        self.dirty.insert(self.cursor.y);
     }
 
-    #[test]
-    fn blink() {
-        let mut screen = Screen::new(2, 2);
+    /// Insert the indicated # of blank characters at the cursor
+    /// position. The cursor does not move and remains at the beginning
+    /// of the inserted blank characters. Data on the line is shifted
+    /// forward.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - number of characters to insert.
+    fn insert_characters(&mut self, count: Option<u32>) {
+        self.pending_wrap = false;
+        self.dirty.insert(self.cursor.y);
 
-        let default_char = CharOpts::default();
-        let expected_initial = vec![
-            cv![co!(default), co!(default)],
-            cv![co!(default), co!(default)],
-        ];
+        // As in pyte a count of 0 inserts one character.
+        let count = count.unwrap_or(1).max(1) as usize;
+        let default = self.default_char();
+        let x = self.cursor.x as usize;
+
+        self.mark_dirty_cells(self.cursor.y, x as u32..self.columns);
+        if let Some(line) = self.buffer.get_mut(self.cursor.y as usize) {
+            if x < line.len() {
+                // Cells shifted past the last column are dropped, so the
+                // line keeps its width.
+                let count = usize::min(count, line.len() - x);
+                line[x..].rotate_right(count);
+                for cell in &mut line[x..x + count] {
+                    *cell = default.clone();
+                }
+            }
+        }
+        self.repair_wide_chars(self.cursor.y);
+    }
 
-        assert_eq!(tolist(&screen), expected_initial);
+    fn cursor_up(&mut self, count: Option<u32>) {
+        self.pending_wrap = false;
+        // The top margin only stops a cursor inside the scrolling
+        // region, above it the cursor moves up to the first line.
+        let top = match &self.margins {
+            Some(margins) if self.cursor.y >= margins.top => margins.top,
+            _ => 0,
+        };
+        let count = count.unwrap_or(1);
+        self.cursor.y = self.cursor.y.saturating_sub(count).max(top);
+    }
 
-        screen.select_graphic_rendition(&[5]); // blink.
+    fn cursor_down(&mut self, count: Option<u32>) {
+        self.pending_wrap = false;
+        // Likewise, below the scrolling region the cursor moves down to
+        // the last line.
+        let bottom = match &self.margins {
+            Some(margins) if self.cursor.y <= margins.bottom => margins.bottom,
+            _ => self.lines - 1,
+        };
+        let count = count.unwrap_or(1);
+        self.cursor.y = (self.cursor.y + count).min(bottom);
+    }
 
-        screen.draw("f");
-        let expected_after_draw = vec![
-            vec![
-                CharOpts {
-                    data: "f".to_string(),
-                    fg: "default".to_string(),
-                    bg: "default".to_string(),
-                    blink: true,
-                    ..default_char.clone()
-                },
-                default_char.clone(),
-            ],
-            vec![default_char.clone(), default_char.clone()],
-        ];
+    fn cursor_down1(&mut self, count: Option<u32>) {
+        self.cursor_down(count);
+        self.cariage_return();
+    }
 
-        assert_eq!(tolist(&screen), expected_after_draw);
+    /// Move cursor right the indicated number of columns. Cursor stops
+    /// at the right margin.
+    ///
+    /// A pending wrap is cancelled first, as in xterm, so after a
+    /// character was drawn in the last column the cursor stays there and
+    /// the next character overwrites it rather than wrapping.
+    ///
+    /// # Parameters
+    /// - `count`: Number of columns to skip.
+    fn cursor_forward(&mut self, count: Option<u32>) {
+        self.pending_wrap = false;
+        self.cursor.x += count.unwrap_or(1);
+        self.ensure_hbounds();
+        if self.is_continuation(self.cursor.x, self.cursor.y) {
+            // Step past the trailing half, or back onto the leading half
+            // when the wide character ends the line.
+            if self.cursor.x + 1 < self.columns {
+                self.cursor.x += 1;
+            } else {
+                self.cursor.x -= 1;
+            }
+        }
     }
 
-    #[test]
-    fn colors() {
-        let mut screen = Screen::new(2, 2);
+    /// Move cursor left the indicated # of columns. Cursor stops
+    /// at left margin.
+    ///
+    /// # Arguements
+    ///
+    /// * `count` - number of columns to skip
+    fn cursor_back(&mut self, count: Option<u32>) {
+        self.pending_wrap = false;
+        // With reverse wraparound, moving left past the first column
+        // continues on the last column of the previous line, as many
+        // lines up as the count takes, but not above the first one.
+        if self.mode.contains(&REVERSE_WRAP) {
+            let position = self.cursor.y * self.columns + self.cursor.x.min(self.columns - 1);
+            let position = position.saturating_sub(count.unwrap_or(1));
+            self.cursor.y = position / self.columns;
+            self.cursor.x = position % self.columns;
+            if self.is_continuation(self.cursor.x, self.cursor.y) {
+                self.cursor.x -= 1;
+            }
+            return;
+        }
+        if self.cursor.x >= count.unwrap_or(1) {
+            self.cursor.x -= count.unwrap_or(1);
+        } else {
+            self.cursor.x = 0;
+        }
+        self.ensure_hbounds();
+        if self.is_continuation(self.cursor.x, self.cursor.y) {
+            self.cursor.x -= 1;
+        }
+    }
 
-        screen.select_graphic_rendition(&[30]); // Set foreground color to black.
-        screen.select_graphic_rendition(&[40]); // Set background color to black.
-        assert_eq!(screen.cursor.attr.fg, "black");
-        assert_eq!(screen.cursor.attr.bg, "black");
+    fn cursor_up1(&mut self, count: Option<u32>) {
+        self.cursor_up(count);
+        self.cariage_return();
+    }
 
-        screen.select_graphic_rendition(&[31]); // Set foreground color to red.
-        assert_eq!(screen.cursor.attr.fg, "red");
-        assert_eq!(screen.cursor.attr.bg, "black");
+    fn cursor_to_column(&mut self, character: Option<u32>) {
+        self.pending_wrap = false;
+        self.cursor.x = character.unwrap_or(1) - 1;
+        self.ensure_hbounds();
     }
 
-    #[test]
-    fn colors256() {
-        let mut screen = Screen::new(2, 2);
+    fn cursor_position(&mut self, line: Option<u32>, column: Option<u32>) {
+        self.pending_wrap = false;
+        let column: i32 = column.map(|a| if a == 0 { 1 } else { a }).unwrap_or(1) as i32 - 1;
+        let mut line: i32 = line.map(|a| if a == 0 { 1 } else { a }).unwrap_or(1) as i32 - 1;
 
-        // a) OK-case.
-        screen.select_graphic_rendition(&[FG_256, 5, 0]);
-        screen.select_graphic_rendition(&[BG_256, 5, 15]);
-        assert_eq!(screen.cursor.attr.fg, "000000");
-        assert_eq!(screen.cursor.attr.bg, "ffffff");
-    }
+        // If origin mode (DECOM) is set, line number is relative to the top scrolling margin.
+        if let Some(margins) = &self.margins {
+            if self.mode.contains(&DECOM) {
+                line += margins.top as i32;
 
-    #[test]
-    fn invalid_color() {
-        //consider panicing in this cases
-        let mut screen = Screen::new(2, 2);
-        screen.select_graphic_rendition(&[48, 5, 100500]);
+                // Cursor is not allowed to move out of the scrolling region.
+                if line < margins.top as i32 || line > margins.bottom as i32 {
+                    return;
+                }
+            }
+        }
+
+        self.cursor.x = column as u32;
+        self.cursor.y = line as u32;
+        self.ensure_hbounds();
+        self.ensure_vbounds(None);
     }
 
-    #[test]
-    fn colors256_missing_attrs() {
-        let mut screen = Screen::new(2, 2);
+    /// Erases display in a specific way.
+    ///
+    /// Character attributes are set to cursor attributes.
+    ///
+    /// # Parameters
+    ///
+    /// - `how`: Defines the way the line should be erased in:
+    ///     - `0`: Erases from cursor to end of screen, including cursor position.
+    ///     - `1`: Erases from beginning of screen to cursor, including cursor position.
+    ///     - `2` and `3`: Erases complete display. All lines are erased and changed to single-width. Cursor does not move.
+    /// - `private`: When `true`, only characters marked as erasable are affected. **Not implemented**.
+    ///
+    /// # Version
+    ///
+    /// This method accepts any number of positional arguments as some `clear` implementations include a `;` after the first parameter causing the stream to assume a `0` second parameter.
+    fn erase_in_display(&mut self, how: Option<u32>, _private: Option<bool>) {
+        self.pending_wrap = false;
+        let interval: core::ops::Range<u32> = match how {
+            Some(0) => self.cursor.y + 1..self.lines,
+            Some(1) => 0..self.cursor.y,
+            Some(2 | 3) => 0..self.lines,
+            _ => 0..0, // Handle invalid `how` values
+        };
 
-        // Test from https://github.com/selectel/pyte/issues/115
-        screen.select_graphic_rendition(&[FG_256]);
-        screen.select_graphic_rendition(&[BG_256]);
+        self.dirty.extend(interval.clone());
+        self.mark_dirty_rows(interval.clone());
+        let erased = self.erase_char();
+        for y in interval {
+            if let Some(line) = self.buffer.get_mut(y as usize) {
+                line.fill(erased.clone());
+                self.wrapped[y as usize] = false;
+            }
+        }
 
-        assert_eq!(screen.cursor.attr, CharOpts::default());
+        if how == Some(0) || how == Some(1) {
+            self.erase_in_line(how, None);
+        }
     }
 
-    #[test]
-    fn colors24bit() {
-        let mut screen = Screen::new(2, 2);
+    fn erase_in_line(&mut self, how: Option<u32>, _private: Option<bool>) {
+        self.pending_wrap = false;
+        self.dirty.insert(self.cursor.y);
 
-        // a) OK-case
-        screen.select_graphic_rendition(&[38, 2, 0, 0, 0]);
-        screen.select_graphic_rendition(&[48, 2, 255, 255, 255]);
-        assert_eq!(screen.cursor.attr.fg, "000000");
-        assert_eq!(screen.cursor.attr.bg, "ffffff");
+        let how = how.unwrap_or(0);
+        let columns = self.columns as usize;
+        let x = usize::min(self.cursor.x as usize, columns);
+        let interval = match how {
+            0 => x..columns,
+            1 => 0..usize::min(x + 1, columns),
+            2 => 0..columns,
+            _ => {
+                panic!("invalid eras_in_line parameter");
+            } // Handle invalid `how` values if necessary
+        };
+
+        self.mark_dirty_cells(self.cursor.y, interval.start as u32..interval.end as u32);
+        let erased = self.erase_char();
+        if let Some(line) = self.buffer.get_mut(self.cursor.y as usize) {
+            line[interval].fill(erased);
+            // The row no longer starts with text carried over from above.
+            if how != 0 {
+                self.wrapped[self.cursor.y as usize] = false;
+            }
+        }
+        self.repair_wide_chars(self.cursor.y);
     }
 
-    #[test]
-    fn colors24bit_invalid_color() {
-        // consider panicing in this cases
-        let mut screen = Screen::new(2, 2);
-        screen.select_graphic_rendition(&[48, 2, 255]);
+    /// Insert the indicated number of lines at the line with the cursor.
+    /// Lines displayed at and below the cursor move down. Lines moved
+    /// past the bottom margin are lost.
+    ///
+    /// # Parameters
+    ///
+    /// - `count`: Number of lines to insert.
+    fn insert_lines(&mut self, count: Option<u32>) {
+        let count = count.unwrap_or(1);
+        let Margins { top, bottom } = self
+            .margins
+            .unwrap_or(Margins { top: 0, bottom: self.lines - 1 });
+
+        // If cursor is outside scrolling margins, do nothing.
+        if top <= self.cursor.y && self.cursor.y <= bottom {
+            self.dirty.extend(self.cursor.y..self.lines);
+            let fill = self.erase_char();
+            self.scroll_region_down(self.cursor.y, bottom, count, fill);
+
+            self.cariage_return();
+        }
     }
 
-    #[test]
-    fn colors_aixterm() {
-        let mut screen = Screen::new(2, 2);
+    fn delete_lines(&mut self, count: Option<u32>) {
+        let count = count.unwrap_or(1);
+        let Margins { top, bottom } = self
+            .margins
+            .unwrap_or(Margins { top: 0, bottom: self.lines - 1 });
 
-        // a) foreground color.
-        screen.select_graphic_rendition(&[94]);
-        assert_eq!(screen.cursor.attr.fg, "brightblue");
+        // If cursor is outside scrolling margins -- do nothing.
+        if top <= self.cursor.y && self.cursor.y <= bottom {
+            self.dirty.extend(self.cursor.y..self.lines);
+            let fill = self.erase_char();
+            self.scroll_region_up(self.cursor.y, bottom, count, fill);
 
-        // b) background color.
-        screen.select_graphic_rendition(&[104]);
-        assert_eq!(screen.cursor.attr.bg, "brightblue");
+            self.cariage_return();
+        }
     }
 
-    #[test]
-    fn colors_ignore_invalid() {
-        let mut screen = Screen::new(2, 2);
-        let default_attrs = screen.cursor.attr.clone();
+    /// Scroll the lines between the margins up by `count` (SU), blank
+    /// lines are added at the bottom. The cursor doesn't move. As with
+    /// `index`, lines scrolled off the top of the primary screen go to
+    /// `scrollback`, also when the region ends above the last line.
+    fn scroll_up(&mut self, count: Option<u32>) {
+        let count = count.unwrap_or(1).max(1);
+        let (top, bottom) = self.scroll_region();
+        self.dirty.extend(top..=bottom);
+        if top == 0 && self.scrollback_limit > 0 && self.primary_screen.is_none() {
+            for y in 0..count.min(bottom + 1) {
+                self.push_scrollback(self.buffer[y as usize].clone());
+            }
+        }
+        self.scroll_region_up(top, bottom, count, self.erase_char());
+    }
 
-        screen.select_graphic_rendition(&[100500]);
-        assert_eq!(screen.cursor.attr, default_attrs);
+    /// Scroll the lines between the margins down by `count` (SD), blank
+    /// lines are added at the top. The cursor doesn't move.
+    fn scroll_down(&mut self, count: Option<u32>) {
+        let count = count.unwrap_or(1).max(1);
+        let (top, bottom) = self.scroll_region();
+        self.dirty.extend(top..=bottom);
+        self.scroll_region_down(top, bottom, count, self.erase_char());
+    }
 
-        screen.select_graphic_rendition(&[38, 100500]);
-        assert_eq!(screen.cursor.attr, default_attrs);
+    /// Delete the indicated number of characters, starting with the
+    /// character at the cursor position. When a character is deleted,
+    /// all characters to the right of the cursor move left. Character
+    /// attributes move with the characters.
+    ///
+    /// # Parameters
+    /// - `count`: Number of characters to delete.
+    fn delete_characters(&mut self, count: Option<u32>) {
+        self.pending_wrap = false;
+        self.dirty.insert(self.cursor.y);
+        let count = count.map(|a| if a > 0 { a } else { 1 }).unwrap_or(1);
 
-        screen.select_graphic_rendition(&[48, 100500]);
-        assert_eq!(screen.cursor.attr, default_attrs);
+        let x = self.cursor.x as usize;
+        let erased = self.erase_char();
+        self.mark_dirty_cells(self.cursor.y, x as u32..self.columns);
+        if let Some(line) = self.buffer.get_mut(self.cursor.y as usize) {
+            if x < line.len() {
+                let count = usize::min(count as usize, line.len() - x);
+                line[x..].rotate_left(count);
+                let end = line.len();
+                line[end - count..].fill(erased);
+            }
+        }
+        self.repair_wide_chars(self.cursor.y);
     }
 
-    #[test]
-    fn reset_resets_colors() {
-        let mut screen = Screen::new(2, 2);
-        let expected_initial = vec![
-            cv![co!(default), co!(default)],
-            cv![co!(default), co!(default)],
-        ];
+    /// Erase the indicated number of characters, starting with the
+    /// character at the cursor position. Character attributes are set
+    /// to cursor attributes. The cursor remains in the same position.
+    ///
+    /// # Parameters
+    /// - `count`: Number of characters to erase.
+    ///
+    /// # Note
+    /// Using cursor attributes for character attributes may seem
+    /// illogical, but if you recall that a terminal emulator emulates
+    /// a typewriter, it starts to make sense. The only way a typewriter
+    /// could erase a character is by typing over it.
+    fn erase_characters(&mut self, count: Option<u32>) {
+        self.pending_wrap = false;
+        self.dirty.insert(self.cursor.y);
+        let count = count.map(|a| if a > 0 { a } else { 1 }).unwrap_or(1);
 
-        assert_eq!(tolist(&screen), expected_initial);
+        let x = usize::min(self.cursor.x as usize, self.columns as usize);
+        let end = usize::min(x + count as usize, self.columns as usize);
+        self.mark_dirty_cells(self.cursor.y, x as u32..end as u32);
+        let erased = self.erase_char();
+        if let Some(line) = self.buffer.get_mut(self.cursor.y as usize) {
+            line[x..end].fill(erased);
+        }
+        self.repair_wide_chars(self.cursor.y);
+    }
 
-        screen.select_graphic_rendition(&[30]); // Set foreground color to black.
-        screen.select_graphic_rendition(&[40]); // Set background color to black.
-        assert_eq!(screen.cursor.attr.fg, "black");
-        assert_eq!(screen.cursor.attr.bg, "black");
+    fn set_modify_other_keys(&mut self, level: u32) {
+        self.modify_other_keys = level;
+    }
 
-        screen.select_graphic_rendition(&[0]); // Reset all attributes.
-        assert_eq!(screen.cursor.attr, CharOpts::default());
+    /// Push kitty keyboard flags, dropping the oldest entry once there
+    /// are `KEYBOARD_FLAGS_DEPTH` of them.
+    fn push_keyboard_flags(&mut self, flags: u32) {
+        if self.keyboard_flags.len() >= KEYBOARD_FLAGS_DEPTH {
+            self.keyboard_flags.remove(0);
+        }
+        self.keyboard_flags.push(flags);
     }
 
-    #[test]
-    fn reset_works_between_attributes() {
-        let mut screen = Screen::new(2, 2);
+    /// Pop `count` entries of kitty keyboard flags, all of them if there
+    /// are fewer.
+    fn pop_keyboard_flags(&mut self, count: Option<u32>) {
+        let count = count.unwrap_or(1).max(1) as usize;
+        let len = self.keyboard_flags.len().saturating_sub(count);
+        self.keyboard_flags.truncate(len);
+    }
 
-        let expected_initial = vec![
-            cv![co!(default), co!(default)],
-            cv![co!(default), co!(default)],
-        ];
-
-        assert_eq!(tolist(&screen), expected_initial);
-
-        // Red fg, reset, red bg
-        screen.select_graphic_rendition(&[31, 0, 41]);
-        assert_eq!(screen.cursor.attr.fg, "default");
-        assert_eq!(screen.cursor.attr.bg, "red");
+    /// Report the current kitty keyboard flags, 0 if none were pushed.
+    fn report_keyboard_flags(&mut self) {
+        let flags = self.keyboard_flags.last().cloned().unwrap_or(0);
+        self.write_process_input(&format!("\x1B[?{}u", flags));
     }
 
-    #[test]
-    fn multi_attribs() {
-        let mut screen = Screen::new(2, 2);
-
-        let expected_initial = vec![
-            cv![co!(default), co!(default)],
-            cv![co!(default), co!(default)],
-        ];
-
-        assert_eq!(tolist(&screen), expected_initial);
-
-        screen.select_graphic_rendition(&[1]); // Set bold
-        screen.select_graphic_rendition(&[3]); // Set italics
+    /// Media copy, the printed text is collected in `printer_output`.
+    ///
+    /// # Parameters
+    /// - `mode`: 0 prints the screen, 4 and 5 turn the printer controller
+    ///   off and on. With `private` set, 1 prints the cursor line, 4 and
+    ///   5 turn auto print off and on.
+    fn media_copy(&mut self, mode: Option<u32>, private: Option<bool>) {
+        match (mode.unwrap_or(0), private.unwrap_or(false)) {
+            (0, false) => {
+                for y in 0..self.lines {
+                    self.print_line(y);
+                }
+            }
+            (4, false) => self.printer_controller = false,
+            (5, false) => self.printer_controller = true,
+            (1, true) => self.print_line(self.cursor.y),
+            (4, true) => self.auto_print = false,
+            (5, true) => self.auto_print = true,
+            _ => {}
+        }
+    }
 
-        assert!(screen.cursor.attr.bold);
-        assert!(screen.cursor.attr.italics);
+    fn printer_controller_mode(&self) -> bool {
+        self.printer_controller
     }
 
-    #[test]
-    fn attributes_reset() {
-        let mut screen = Screen::new(2, 2);
-        screen.set_mode(&[LNM], false);
+    /// Report terminal identity.
+    ///
+    /// # Parameters
+    /// - `mode`: Mode for reporting terminal identity.
+    /// - `private`: When `true`, the method does nothing. This behavior is consistent with the VT220 manual.
+    ///
+    /// # Version
+    /// - Added in version 0.5.0
+    /// - Changed in version 0.7.0: If `private` keyword argument is set, the method does nothing.
+    fn report_device_attributes(&mut self, mode: Option<u32>, private: Option<bool>) {
+        // We only implement "primary" DA which is the only DA request
+        // VT102 understood, see `VT102ID` in `linux/drivers/tty/vt.c`.
+        if mode.unwrap_or(0) == 0 && !private.unwrap_or(false) {
+            self.write_process_input("\x1B[?6c");
+        }
+    }
 
-        let default_char = CharOpts::default();
-        let expected_initial = vec![
-            cv![co!(default), co!(default)],
-            cv![co!(default), co!(default)],
+    /// Report whether a mode is set (DECRPM), in reply to a DECRQM
+    /// request.
+    ///
+    /// # Parameters
+    /// - `mode`: Mode to report.
+    /// - `private`: `true` for a DEC private mode (`CSI ? Ps $ p`).
+    fn report_mode(&mut self, mode: u32, private: bool) {
+        let known = [
+            DECCKM,
+            LNM,
+            IRM,
+            DECTCEM,
+            DECSCNM,
+            DECOM,
+            DECAWM,
+            DECCOLM,
+            REVERSE_WRAP,
+            CURSOR_BLINK,
+            ALT_SCREEN,
+            SAVE_CURSOR,
+            ALT_SCREEN_SAVE_CURSOR,
+            SYNCHRONIZED_OUTPUT,
         ];
+        let encoded = if private { mode << 5 } else { mode };
+        // 0 -- not recognized, 1 -- set, 2 -- reset.
+        let state = if self.mode.contains(&encoded) {
+            1
+        } else if known.contains(&encoded) {
+            2
+        } else {
+            0
+        };
+        let prefix = if private { "?" } else { "" };
+        self.write_process_input(&format!("\x1B[{}{};{}$y", prefix, mode, state));
+    }
 
-        assert_eq!(tolist(&screen), expected_initial);
+    /// Report terminal status or cursor position.
+    ///
+    /// # Parameters
+    /// - `mode`: If 5 -- terminal status, 6 -- cursor position,
+    ///   otherwise a noop.
+    /// - `private`: If `Some(true)`, only 6 is answered, with the
+    ///   extended cursor position report (DECXCPR) which adds the page,
+    ///   always 1.
+    fn report_device_status(&mut self, mode: Option<u32>, private: Option<bool>) {
+        let private = private.unwrap_or(false);
+        match mode {
+            Some(5) if !private => self.write_process_input("\x1B[0n"),
+            Some(6) => {
+                let (y, x) = self.cursor_report_position();
+                let report = if private {
+                    format!("\x1B[?{};{};1R", y, x)
+                } else {
+                    format!("\x1B[{};{}R", y, x)
+                };
+                self.write_process_input(&report);
+            }
+            _ => {}
+        }
+    }
 
-        // Set bold attribute and draw "foo"
-        screen.select_graphic_rendition(&[1]);
-        screen.draw("f");
-        screen.draw("o");
-        screen.draw("o");
+    /// Answer xterm's window size reports: `14` -- the text area in
+    /// pixels, see `cell_size`, `18` -- the text area in characters.
+    /// Operations changing the window, and the other reports, are
+    /// ignored.
+    fn window_op(&mut self, params: &[u32]) {
+        let (width, height) = self.cell_size;
+        match params.first() {
+            Some(14) => self.write_process_input(&format!(
+                "\x1B[4;{};{}t",
+                self.lines * height,
+                self.columns * width
+            )),
+            Some(18) => {
+                self.write_process_input(&format!("\x1B[8;{};{}t", self.lines, self.columns))
+            }
+            _ => {}
+        }
+    }
 
-        let bold_char = |c: &str| CharOpts {
-            data: c.to_string(),
-            bold: true,
-            ..CharOpts::default()
+    /// Set or, for `?`, report the default foreground (`10`), background
+    /// (`11`) or cursor (`12`) color. Colors are given as `rgb:r/g/b`
+    /// with one to four hex digits per channel, or `#rgb` and its longer
+    /// forms; others, e.g. names, are ignored.
+    fn set_dynamic_color(&mut self, code: u32, value: &str) {
+        let color = match code {
+            10 => &mut self.default_fg_color,
+            11 => &mut self.default_bg_color,
+            12 => &mut self.cursor_color,
+            _ => return,
         };
+        if value == "?" {
+            let report = format!("\x1B]{};{}\x1B\\", code, color);
+            self.write_process_input(&report);
+        } else if let Some(rgb) = parse_x_color(value) {
+            *color = rgb;
+        }
+    }
 
-        let expected_after_foo = vec![
-            vec![bold_char("f"), bold_char("o")],
-            vec![bold_char("o"), default_char.clone()],
-        ];
+    fn reset_dynamic_color(&mut self, code: u32) {
+        match code {
+            10 => self.default_fg_color = DEFAULT_FG_COLOR.to_owned(),
+            11 => self.default_bg_color = DEFAULT_BG_COLOR.to_owned(),
+            12 => self.cursor_color = DEFAULT_FG_COLOR.to_owned(),
+            _ => {}
+        }
+    }
 
-        assert_eq!(tolist(&screen), expected_after_foo);
+    fn change_attributes_in_rectangle(&mut self, params: &[u32]) {
+        let (rect, attrs) = self.rectangle_params(params);
+        self.change_rectangle_attrs(rect, attrs);
+    }
 
-        // Reset cursor position and attributes, then draw "f"
-        screen.cursor_position(None, None);
-        screen.select_graphic_rendition(&[0]);
-        screen.draw("f");
+    fn reverse_attributes_in_rectangle(&mut self, params: &[u32]) {
+        let (rect, attrs) = self.rectangle_params(params);
+        self.reverse_rectangle_attrs(rect, attrs);
+    }
 
-        let normal_char = |c: &str| CharOpts { data: c.to_string(), ..CharOpts::default() };
+    /// Move cursor to a specific line in the current column.
+    ///
+    /// # Parameters
+    /// - `line`: Line number to move the cursor to.
+    fn cursor_to_line(&mut self, line: Option<u32>) {
+        self.pending_wrap = false;
+        // A 0 parameter is the same as 1.
+        self.cursor.y = line.unwrap_or(1).max(1) - 1;
 
-        let expected_final = vec![
-            vec![normal_char("f"), bold_char("o")],
-            vec![bold_char("o"), default_char],
-        ];
+        // If origin mode (DECOM) is set, line numbers are relative to
+        // the top scrolling margin.
+        if self.mode.contains(&DECOM) {
+            if let Some(margins) = self.margins {
+                self.cursor.y += margins.top;
+            }
+        }
 
-        assert_eq!(tolist(&screen), expected_final);
+        // Keeps the cursor on screen, or in the scrolling region under
+        // DECOM.
+        self.ensure_vbounds(None);
     }
 
-    #[test]
-    fn resize() {
-        // Test initial resize behavior
-        let mut screen = Screen::new(2, 2);
-        screen.set_mode(&[DECOM], false);
-        screen.set_margins(Some(0), Some(1));
-
-        assert_eq!(screen.columns, 2);
-        assert_eq!(screen.lines, 2);
+    /// Clear a horizontal tab stop.
+    ///
+    /// # Parameters
+    /// - `how`: Defines the way the tab stop should be cleared:
+    ///     - `0` or nothing: Clears a horizontal tab stop at the cursor position.
+    ///     - `3`: Clears all horizontal tab stops.
+    ///     - `5`: Clears all tab stops, the same as `3` since there are no
+    ///       vertical ones.
+    ///
+    /// The values clearing vertical (line) tab stops, `1`, `2` and `4`,
+    /// and unknown ones are ignored.
+    fn clear_tab_stop(&mut self, how: Option<u32>) {
+        match how.unwrap_or(0) {
+            0 => {
+                // Clears a horizontal tab stop at cursor position, if it's
+                // present, or silently fails if otherwise.
+                self.tabstops.remove(&self.cursor.x);
+            }
+            3 | 5 => {
+                // Clears all horizontal tab stops.
+                self.tabstops.clear();
+            }
+            _ => {}
+        }
+    }
 
-        let expected_initial = vec![
-            cv![co!(default), co!(default)],
-            cv![co!(default), co!(default)],
-        ];
-        assert_eq!(tolist(&screen), expected_initial);
+    /// Set (enable) a given list of modes.
+    ///
+    /// # Arguments
+    ///
+    /// - `modes`: A list of modes to set, where each mode is a constant from the `pyte::modes` module.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let modes = vec![Mode::Insert, Mode::Replace];
+    /// set_modes(modes);
+    /// ```
+    ///
+    /// # Note
+    ///
+    /// Each mode should be a constant from the `modes` module.
+    fn set_mode(&mut self, modes: &[u32], private: bool) {
+        // mode_list = list(modes)
+        // Private mode codes are shifted, to be distinguished from non
+        // private ones.
+        let mut mode_list = Vec::from(modes);
+        if private {
+            mode_list = modes.iter().map(|m| m << 5).collect::<Vec<_>>();
+            if mode_list.contains(&DECSCNM) {
+                self.dirty.extend(0..self.lines);
+                self.mark_dirty_rows(0..self.lines);
+            }
+        }
 
-        // Test resize to larger dimensions
-        screen.resize(Some(3), Some(3));
-        assert_eq!(screen.columns, 3);
-        assert_eq!(screen.lines, 3);
+        let was_sync = self.synchronized_output();
+        self.mode.extend(mode_list.iter());
+        self.sync_changed(was_sync);
 
-        let expected_larger = vec![
-            cv![co!(default), co!(default), co!(default)],
-            cv![co!(default), co!(default), co!(default)],
-            cv![co!(default), co!(default), co!(default)],
-        ];
-        assert_eq!(tolist(&screen), expected_larger);
-        assert!(screen.mode.contains(&DECOM));
-        assert!(screen.margins.is_none());
+        // When DECOLM mode is set, the screen is erased and the cursor
+        // moves to the home position.
+        if mode_list.contains(&DECCOLM) {
+            self.saved_columns = Some(self.columns);
+            self.resize(None, Some(132));
+            if self.decolm_clears {
+                self.erase_in_display(Some(2), None);
+            }
+            self.cursor_position(None, None);
+        }
 
-        // Test resize back to original size
-        screen.resize(Some(2), Some(2));
-        assert_eq!(screen.columns, 2);
-        assert_eq!(screen.lines, 2);
-        assert_eq!(tolist(&screen), expected_initial);
+        // According to VT520 manual, DECOM should also home the cursor.
+        if mode_list.contains(&DECOM) {
+            self.cursor_position(None, None);
+        }
 
-        // Test quirks:
-        // a) Adding columns to the right
-        let mut screen = Screen::new(2, 2);
-        update(&mut screen, vec!["bo", "sh"], vec![]);
-        screen.resize(Some(2), Some(3));
-        assert_eq!(screen.display(), vec!["bo ".to_string(), "sh ".to_string()]);
+        // Mark all displayed characters as reverse.
+        if mode_list.contains(&DECSCNM) {
+            for line in self.buffer.iter_mut() {
+                for cell in line.iter_mut() {
+                    cell.reverse = true;
+                }
+            }
 
-        // b) Removing columns from the right
-        let mut screen = Screen::new(2, 2);
-        update(&mut screen, vec!["bo", "sh"], vec![]);
-        screen.resize(Some(2), Some(1));
-        assert_eq!(screen.display(), vec!["b".to_string(), "s".to_string()]);
+            self.select_graphic_rendition(&[7]); // +reverse.
+        }
 
-        // c) Adding rows at the bottom
-        let mut screen = Screen::new(2, 2);
-        update(&mut screen, vec!["bo", "sh"], vec![]);
-        screen.resize(Some(3), Some(2));
-        assert_eq!(
-            screen.display(),
-            vec!["bo".to_string(), "sh".to_string(), "  ".to_string()]
-        );
+        // # Make the cursor visible.
+        if mode_list.contains(&DECTCEM) {
+            self.cursor.hidden = false;
+        }
 
-        // d) Removing rows from the top
-        let mut screen = Screen::new(2, 2);
-        update(&mut screen, vec!["bo", "sh"], vec![]);
-        screen.resize(Some(1), Some(2));
-        assert_eq!(screen.display(), vec!["sh".to_string()]);
-    }
+        if mode_list.contains(&CURSOR_BLINK) {
+            self.cursor.blink = true;
+        }
 
-    #[test]
-    fn resize_same() {
-        let mut screen = Screen::new(2, 2);
-        screen.dirty.clear();
-        screen.resize(Some(2), Some(2));
-        assert!(screen.dirty.is_empty());
+        // 1049 saves the cursor before switching, so it is restored to
+        // where it was on the primary screen.
+        if mode_list.contains(&SAVE_CURSOR) || mode_list.contains(&ALT_SCREEN_SAVE_CURSOR) {
+            self.save_cursor();
+        }
+        if mode_list.contains(&ALT_SCREEN) || mode_list.contains(&ALT_SCREEN_SAVE_CURSOR) {
+            self.enter_alternate_screen();
+        }
     }
 
-    #[test]
-    fn set_mode() {
-        // Test DECCOLM mode
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![]);
-        screen.cursor_position(Some(1), Some(1));
-        screen.set_mode(&[DECCOLM], false);
-
-        let default_char = screen.default_char();
-        for line in tolist(&screen) {
-            for char in line {
-                assert_eq!(char, default_char);
-            }
-        }
-        assert_eq!(screen.columns, 132);
-        assert_eq!(screen.cursor.x, 0);
-        assert_eq!(screen.cursor.y, 0);
-        screen.reset_mode(&[DECCOLM], false);
-        assert_eq!(screen.columns, 3);
+    /// Reset (disable) a given list of modes.
+    ///
+    /// # Arguments
+    ///
+    /// - `modes`: A list of modes to reset. Each mode should ideally be a constant from the `pyte::modes` module.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let modes = vec![Mode::Insert, Mode::Replace];
+    /// reset_modes(modes);
+    /// ```
+    ///
+    /// # Note
+    ///
+    /// Make sure that each mode is a constant from the `modes` module.
+    fn reset_mode(&mut self, modes: &[u32], is_private: bool) {
+        let mut mode_list = Vec::from(modes);
+        // Private mode codes are shifted, to be distinguished from non
+        // private ones.
+        if is_private {
+            mode_list = modes.iter().map(|m| m << 5).collect::<Vec<_>>();
+            if mode_list.contains(&DECSCNM) {
+                self.dirty.extend(0..self.lines);
+                self.mark_dirty_rows(0..self.lines);
+            }
+        }
 
-        // Test DECOM mode
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![]);
-        screen.cursor_position(Some(1), Some(1));
-        screen.set_mode(&[DECOM], false);
-        assert_eq!(screen.cursor.x, 0);
-        assert_eq!(screen.cursor.y, 0);
+        // retain mode mode_list difference
+        let was_sync = self.synchronized_output();
+        self.mode.retain(|x| !mode_list.contains(x));
+        self.sync_changed(was_sync);
 
-        // Test DECSCNM mode
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![]);
-        screen.set_mode(&[DECSCNM], false);
-        for line in tolist(&screen) {
-            for char in line {
-                assert!(char.reverse);
+        // Lines below follow the logic in set_mode.
+        if mode_list.contains(&DECCOLM) {
+            if self.columns == 132 {
+                if let Some(saved_columns) = self.saved_columns {
+                    self.resize(None, Some(saved_columns));
+                    self.saved_columns = None;
+                }
             }
-        }
-        let default_char = screen.default_char();
-        assert!(default_char.reverse);
-        screen.reset_mode(&[DECSCNM], false);
-        for line in tolist(&screen) {
-            for char in line {
-                assert!(!char.reverse);
+            if self.decolm_clears {
+                self.erase_in_display(Some(2), None);
             }
+            self.cursor_position(None, None);
         }
-        let default_char = screen.default_char();
-        assert!(!default_char.reverse);
 
-        // Test DECTCEM mode
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![]);
-        screen.cursor.hidden = true;
-        screen.set_mode(&[DECTCEM], false);
-        assert!(!screen.cursor.hidden);
-        screen.reset_mode(&[DECTCEM], false);
-        assert!(screen.cursor.hidden);
-    }
+        // According to VT520 manual, DECOM should also home the cursor.
+        if mode_list.contains(&DECOM) {
+            self.cursor_position(None, None);
+        }
 
-    #[test]
-    fn draw() {
-        // DECAWM on (default)
-        let mut screen = Screen::new(3, 3);
-        screen.set_mode(&[LNM], false);
-        assert!(screen.mode.contains(&DECAWM));
+        // Mark all displayed characters as reverse.
+        if mode_list.contains(&DECSCNM) {
+            for line in self.buffer.iter_mut() {
+                for cell in line.iter_mut() {
+                    cell.reverse = false;
+                }
+            }
 
-        for ch in "abc".chars() {
-            screen.draw(&ch.to_string());
+            self.select_graphic_rendition(&[27]); // +reverse.
         }
 
-        assert_eq!(
-            screen.display(),
-            vec!["abc".to_string(), "   ".to_string(), "   ".to_string()]
-        );
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 3));
-
-        // One more character -- now we got a linefeed!
-        screen.draw("a");
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
+        // Hide the cursor.
+        if mode_list.contains(&DECTCEM) {
+            self.cursor.hidden = true;
+        }
 
-        // DECAWM is off
-        let mut screen = Screen::new(3, 3);
-        screen.reset_mode(&[DECAWM], false);
+        if mode_list.contains(&CURSOR_BLINK) {
+            self.cursor.blink = false;
+        }
 
-        for ch in "abc".chars() {
-            screen.draw(&ch.to_string());
+        if mode_list.contains(&ALT_SCREEN) || mode_list.contains(&ALT_SCREEN_SAVE_CURSOR) {
+            self.leave_alternate_screen();
+        }
+        if mode_list.contains(&SAVE_CURSOR) || mode_list.contains(&ALT_SCREEN_SAVE_CURSOR) {
+            self.restore_cursor();
         }
+    }
 
-        assert_eq!(
-            screen.display(),
-            vec!["abc".to_string(), "   ".to_string(), "   ".to_string()]
-        );
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 3));
+    /// Set display attributes.
+    ///
+    /// # Parameters
+    /// - `attrs`: A list of display attributes to set.
+    fn select_graphic_rendition(&mut self, attrs: &[u32]) {
+        // Fast path for resetting all attributes.
+        if attrs.is_empty() || (attrs.len() == 1 && attrs[0] == 0) {
+            self.cursor.attr = self.default_char();
+            return;
+        }
 
-        // No linefeed is issued on the end of the line ...
-        screen.draw("a");
-        assert_eq!(
-            screen.display(),
-            vec!["aba".to_string(), "   ".to_string(), "   ".to_string()]
-        );
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 3));
+        let replace = self.sgr_changes(attrs);
+        self.cursor.attr.update_from_map(replace);
+    }
 
-        // IRM mode is on, expecting new characters to move the old ones
-        // instead of replacing them
-        screen.set_mode(&[IRM], false);
-        screen.cursor_position(None, None);
-        screen.draw("x");
-        assert_eq!(
-            screen.display(),
-            vec!["xab".to_string(), "   ".to_string(), "   ".to_string()]
-        );
+    /// Set terminal title.
+    ///
+    /// **Warning:** This is an XTerm extension supported by the Linux terminal.
+    fn set_title(&mut self, title: &str) {
+        self.title = title.to_owned();
+        if let Some(callback) = self.on_title_change.as_mut() {
+            callback(title);
+        }
+    }
 
-        screen.cursor_position(None, None);
-        screen.draw("y");
-        assert_eq!(
-            screen.display(),
-            vec!["yxa".to_string(), "   ".to_string(), "   ".to_string()]
-        );
+    /// Set icon name
+    ///
+    /// **Warning:** This is an XTerm extension supported by the Linux terminal.
+    fn set_icon_name(&mut self, icon_name: &str) {
+        self.icon_name = icon_name.to_owned();
+        if let Some(callback) = self.on_icon_name_change.as_mut() {
+            callback(icon_name);
+        }
     }
 
-    #[test]
-    fn draw_russian() {
-        // Test from https://github.com/selectel/pyte/issues/65
-        let screen = Arc::new(Mutex::new(Screen::new(20, 1)));
-        let mut parser = Parser::new(screen.clone());
+    /// Start or end a hyperlink (OSC 8). Characters drawn while a
+    /// hyperlink is active carry its URI; an empty URI ends it.
+    ///
+    /// **Warning:** The `params` part (e.g. ``id=``) is currently ignored.
+    fn set_hyperlink(&mut self, _params: &str, uri: &str) {
+        self.cursor.attr.hyperlink = if uri.is_empty() {
+            None
+        } else {
+            Some(Arc::from(uri))
+        };
+    }
 
-        // Feed the Russian text to the parser
-        parser.feed("Нерусский текст".to_string());
+    /// Set warning bell volume (DECSWBV).
+    ///
+    /// The bell is left to the end-user, so there is nothing to adjust
+    /// here and the request is ignored.
+    fn set_warning_bell_volume(&mut self, _volume: Option<u32>) {}
+}
 
-        assert_eq!(
-            screen.lock().unwrap().display(),
-            vec!["Нерусский текст     ".to_string()]
-        );
-    }
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "std")]
+    use std::sync::{Arc, Mutex};
 
-    #[test]
-    fn draw_multiple_chars() {
-        let mut screen = Screen::new(10, 1);
-        screen.draw("foobar");
-        assert_eq!(screen.cursor.x, 6);
-        assert_eq!(screen.display(), vec!["foobar    ".to_string()]);
-    }
-    #[test]
-    fn draw_utf8() {
-        let screen = Arc::new(Mutex::new(Screen::new(1, 1)));
-        let mut parser = Parser::new(screen.clone());
+    use unicode_width::UnicodeWidthChar;
 
-        // Feed UTF-8 bytes for right double quotation mark (")
-        parser.feed("\u{201D}".to_string()); // Unicode escape for "
+    #[cfg(feature = "std")]
+    use super::BellStyle;
+    use super::{CellChange, CharOpts, DisplayOptions, Rect, Screen, ScreenBuilder};
+    use crate::collections::{HashMap, HashSet};
+    #[cfg(feature = "std")]
+    use crate::error::TermError;
+    use crate::graphics::{BG_256, FG_256};
+    use crate::input::Key;
+    use crate::modes::{
+        ALT_SCREEN,
+        CURSOR_BLINK,
+        DECAWM,
+        DECCOLM,
+        DECOM,
+        DECSCNM,
+        DECTCEM,
+        IRM,
+        LNM,
+        REVERSE_WRAP,
+    };
+    #[cfg(feature = "std")]
+    use crate::parser::Parser;
+    use crate::parser::ReplacementPolicy;
+    use crate::parser_listener::ParserListener;
+    #[cfg(feature = "std")]
+    use crate::reporter::{VecReporter, WriteReporter};
+    use crate::screen::{Charset, Margins};
 
-        assert_eq!(screen.lock().unwrap().display(), vec!["”".to_string()]);
+    /// Macro to create CharOpts with optional color
+    macro_rules! co {
+        (default) => {
+            CharOpts::default()
+        };
+        ($c:literal) => {
+            CharOpts { data: $c.to_string(), ..CharOpts::default() }
+        };
+        ($c:literal, fg = $color:literal) => {
+            CharOpts {
+                data: $c.to_string(),
+                fg: $color.into(),
+                ..CharOpts::default()
+            }
+        };
     }
 
-    #[test]
-    fn draw_width2() {
-        let mut screen = Screen::new(10, 1);
-        screen.draw("コンニチハ"); // Each character takes 2 columns
-        assert_eq!(screen.cursor.x, screen.columns);
-        assert_eq!(screen.display(), vec!["コンニチハ".to_string()]);
+    macro_rules! cv {
+        ($(co !($($arg:tt)*)),+ $(,)?) => {
+            vec![ $(co!($($arg)*)),+ ]
+        };
     }
 
-    #[test]
-    fn draw_width2_line_end() {
-        let mut screen = Screen::new(10, 1);
-        screen.draw(" コンニチハ"); // Space followed by 5 double-width characters
-        assert_eq!(screen.cursor.x, screen.columns);
-        assert_eq!(screen.display(), vec![" コンニチハ".to_string()]);
+    pub fn update(screen: &mut Screen, lines: Vec<&str>, colored: Vec<u32>) {
+        for (y, line) in lines.iter().enumerate() {
+            for (x, char) in line.chars().enumerate() {
+                let mut attrs = screen.default_char();
+                if colored.contains(&(y as u32)) {
+                    attrs.fg = "red".into();
+                }
+                attrs.data = char.to_string();
+                screen.buffer[y][x] = attrs;
+            }
+        }
     }
 
+    pub fn tolist(screen: &Screen) -> Vec<Vec<CharOpts>> {
+        screen.buffer.clone()
+    }
     #[test]
-    fn draw_width0_combining() {
-        let mut screen = Screen::new(4, 2);
-
-        // a) Test with no previous character
-        screen.draw("\u{0308}"); // COMBINING DIAERESIS
-        assert_eq!(
-            screen.display(),
-            vec!["    ".to_string(), "    ".to_string()]
-        );
+    fn initialize_char() {
+        // List of fields in CharOpts struct
+        let fields = vec![
+            "data",
+            "fg",
+            "bg",
+            "bold",
+            "italics",
+            "underscore",
+            "strikethrough",
+            "reverse",
+            "blink",
+        ];
 
-        // Draw "bad"
-        screen.draw("bad");
-
-        // b) Test with previous character on the same line
-        screen.draw("\u{0308}"); // COMBINING DIAERESIS
-        assert_eq!(
-            screen.display(),
-            vec!["bad̈ ".to_string(), "    ".to_string()]
-        );
+        for field in fields.iter().skip(1) {
+            let mut char_opts = CharOpts::default();
+            match *field {
+                "bold" => char_opts.bold = true,
+                "italics" => char_opts.italics = true,
+                "underscore" => char_opts.underscore = true,
+                "strikethrough" => char_opts.strikethrough = true,
+                "reverse" => char_opts.reverse = true,
+                "blink" => char_opts.blink = true,
+                _ => {}
+            }
 
-        // c) Test with previous character on the previous line
-        screen.draw("!");
-        screen.draw("\u{0308}"); // COMBINING DIAERESIS
-        assert_eq!(
-            screen.display(),
-            vec!["bad̈!̈".to_string(), "    ".to_string()]
-        );
+            match *field {
+                "bold" => assert!(char_opts.bold),
+                "italics" => assert!(char_opts.italics),
+                "underscore" => assert!(char_opts.underscore),
+                "strikethrough" => assert!(char_opts.strikethrough),
+                "reverse" => assert!(char_opts.reverse),
+                "blink" => assert!(char_opts.blink),
+                _ => {}
+            }
+        }
     }
 
     #[test]
-    fn draw_width0_irm() {
-        let mut screen = Screen::new(10, 1);
-        screen.set_mode(&[IRM], false); // Enable Insert Mode
+    fn remove_non_existant_attribute() {
+        let mut screen = Screen::new(2, 2);
 
-        // Draw zero width space
-        screen.draw("\u{200B}"); // ZERO WIDTH SPACE
+        let expected = vec![
+            cv![co!(default), co!(default)],
+            cv![co!(default), co!(default)],
+        ];
 
-        // Draw DELETE character
-        screen.draw("\u{0007}"); // DELETE/BELL character
+        assert_eq!(tolist(&screen), expected);
 
-        // Check that screen is still empty (filled with spaces)
-        assert_eq!(screen.display(), vec!["          ".to_string()]); // 10 spaces
+        screen.select_graphic_rendition(&[24]); // underline-off.
+        assert_eq!(tolist(&screen), expected);
+        assert!(!screen.cursor.attr.underscore);
     }
 
     #[test]
-    fn draw_width0_irm_detailed() {
-        let mut screen = Screen::new(10, 1);
+    fn attributes() {
+        let mut screen = Screen::new(2, 2);
 
-        // Initial state check
-        assert_eq!(screen.display(), vec!["          ".to_string()]); // 10 spaces
-        assert_eq!(screen.cursor.x, 0);
+        let default_char = CharOpts::default();
+        let expected_initial = vec![
+            cv![co!(default), co!(default)],
+            cv![co!(default), co!(default)],
+        ];
 
-        // Enable Insert Mode
-        screen.set_mode(&[IRM], false);
-        assert!(screen.mode.contains(&IRM));
+        assert_eq!(tolist(&screen), expected_initial);
 
-        // Draw zero width space and verify no change
-        screen.draw("\u{200B}"); // ZERO WIDTH SPACE
-        assert_eq!(screen.display(), vec!["          ".to_string()]); // Still 10 spaces
-        assert_eq!(screen.cursor.x, 0); // Cursor shouldn't move
+        screen.select_graphic_rendition(&[1]); // bold.
 
-        // Draw DELETE character and verify no change
-        screen.draw("\u{0007}"); // DELETE/BELL character
-        assert_eq!(screen.display(), vec!["          ".to_string()]); // Still 10 spaces
-        assert_eq!(screen.cursor.x, 0); // Cursor shouldn't move
+        // Still default, since we haven't written anything.
+        assert_eq!(tolist(&screen), expected_initial);
+        assert!(screen.cursor.attr.bold);
 
-        // Final state verification
-        assert_eq!(screen.display(), vec![" ".repeat(screen.columns as usize)]);
-        assert!(screen.mode.contains(&IRM)); // IRM should still be enabled
+        screen.draw("f");
+        let expected_after_draw = vec![
+            vec![
+                CharOpts {
+                    data: "f".to_string(),
+                    fg: "default".into(),
+                    bg: "default".into(),
+                    bold: true,
+                    ..default_char.clone()
+                },
+                default_char.clone(),
+            ],
+            vec![default_char.clone(), default_char.clone()],
+        ];
+
+        assert_eq!(tolist(&screen), expected_after_draw);
     }
 
     #[test]
-    fn draw_width0_decawm_off() {
-        let mut screen = Screen::new(10, 1);
+    fn blink() {
+        let mut screen = Screen::new(2, 2);
 
-        // Turn off auto-wrap mode
-        screen.reset_mode(&[DECAWM], false);
-        assert!(!screen.mode.contains(&DECAWM));
+        let default_char = CharOpts::default();
+        let expected_initial = vec![
+            cv![co!(default), co!(default)],
+            cv![co!(default), co!(default)],
+        ];
 
-        // Draw space followed by Japanese characters
-        screen.draw(" コンニチハ");
-        assert_eq!(screen.cursor.x, screen.columns);
+        assert_eq!(tolist(&screen), expected_initial);
 
-        // Draw zero-width characters and verify cursor doesn't move
-        screen.draw("\u{200B}"); // ZERO WIDTH SPACE
-        assert_eq!(screen.cursor.x, screen.columns);
+        screen.select_graphic_rendition(&[5]); // blink.
 
-        screen.draw("\u{0007}"); // DELETE/BELL character
-        assert_eq!(screen.cursor.x, screen.columns);
+        screen.draw("f");
+        let expected_after_draw = vec![
+            vec![
+                CharOpts {
+                    data: "f".to_string(),
+                    fg: "default".into(),
+                    bg: "default".into(),
+                    blink: true,
+                    ..default_char.clone()
+                },
+                default_char.clone(),
+            ],
+            vec![default_char.clone(), default_char.clone()],
+        ];
+
+        assert_eq!(tolist(&screen), expected_after_draw);
     }
 
     #[test]
-    fn draw_width0_decawm_off_detailed() {
-        let mut screen = Screen::new(10, 1);
-
-        // Initial state check
-        assert_eq!(screen.cursor.x, 0);
-        assert!(screen.mode.contains(&DECAWM)); // DECAWM should be on by default
-
-        // Turn off auto-wrap mode
-        screen.reset_mode(&[DECAWM], false);
-        assert!(!screen.mode.contains(&DECAWM));
+    fn colors() {
+        let mut screen = Screen::new(2, 2);
 
-        // Draw space followed by Japanese characters
-        screen.draw(" コンニチハ");
+        screen.select_graphic_rendition(&[30]); // Set foreground color to black.
+        screen.select_graphic_rendition(&[40]); // Set background color to black.
+        assert_eq!(&*screen.cursor.attr.fg, "black");
+        assert_eq!(&*screen.cursor.attr.bg, "black");
 
-        // Verify cursor is at end of line
-        assert_eq!(screen.cursor.x, screen.columns);
-        assert_eq!(screen.display(), vec![" コンニチハ".to_string()]);
+        screen.select_graphic_rendition(&[31]); // Set foreground color to red.
+        assert_eq!(&*screen.cursor.attr.fg, "red");
+        assert_eq!(&*screen.cursor.attr.bg, "black");
+    }
 
-        // Try to draw zero-width space
-        screen.draw("\u{200B}"); // ZERO WIDTH SPACE
-                                 // Verify cursor hasn't moved
-        assert_eq!(screen.cursor.x, screen.columns);
-        assert_eq!(screen.display(), vec![" コンニチハ".to_string()]);
+    #[test]
+    fn colors256() {
+        let mut screen = Screen::new(2, 2);
 
-        // Try to draw DELETE character
-        screen.draw("\u{0007}"); // DELETE/BELL character
-                                 // Verify cursor still hasn't moved
-        assert_eq!(screen.cursor.x, screen.columns);
-        assert_eq!(screen.display(), vec![" コンニチハ".to_string()]);
+        // a) OK-case.
+        screen.select_graphic_rendition(&[FG_256, 5, 0]);
+        screen.select_graphic_rendition(&[BG_256, 5, 15]);
+        assert_eq!(&*screen.cursor.attr.fg, "000000");
+        assert_eq!(&*screen.cursor.attr.bg, "ffffff");
+    }
 
-        // Final state verification
-        assert_eq!(screen.cursor.x, screen.columns);
-        assert!(!screen.mode.contains(&DECAWM));
+    #[test]
+    fn invalid_color() {
+        //consider panicing in this cases
+        let mut screen = Screen::new(2, 2);
+        screen.select_graphic_rendition(&[48, 5, 100500]);
     }
 
     #[test]
-    fn draw_cp437() {
-        let mut screen = Screen::new(5, 1);
-        assert_eq!(screen.charset, Charset::G0);
+    fn colors256_missing_attrs() {
+        let mut screen = Screen::new(2, 2);
 
-        screen.define_charset("U", "(");
-        // In Python this would be: "α ± ε".encode("cp437")
-        // We're simulating feeding CP437 encoded bytes
-        let cp437_text: [u8; 5] = [
-            0xE0, // α
-            0x20, // space
-            0xF1, // ±
-            0x20, // space
-            0xEE, // ε
-        ];
+        // Test from https://github.com/selectel/pyte/issues/115
+        screen.select_graphic_rendition(&[FG_256]);
+        screen.select_graphic_rendition(&[BG_256]);
 
-        for &byte in cp437_text.iter() {
-            screen.draw(&(byte as char).to_string());
-        }
+        assert_eq!(screen.cursor.attr, CharOpts::default());
 
-        assert_eq!(screen.display(), vec!["α ± ε".to_string()]);
+        // The parameters after a lone 38 or 48 still apply.
+        screen.select_graphic_rendition(&[FG_256, 1, BG_256, 4]);
+        assert!(screen.cursor.attr.bold);
+        assert!(screen.cursor.attr.underscore);
+        assert_eq!(&*screen.cursor.attr.fg, "default");
+        assert_eq!(&*screen.cursor.attr.bg, "default");
     }
 
     #[test]
-    fn display_wcwidth() {
-        let mut screen = Screen::new(10, 1);
-        screen.draw("コンニチハ");
+    fn reset_attr() {
+        let mut screen = Screen::new(2, 2);
+        let styled = |screen: &mut Screen| screen.feed("\x1b[1;3;4;7;31;42m");
 
-        assert_eq!(screen.display(), vec!["コンニチハ".to_string()]);
-    }
+        styled(&mut screen);
+        screen.reset_attr();
+        assert_eq!(screen.cursor.attr, screen.default_char());
 
-    #[test]
-    fn draw_with_carriage_return() {
-        let line = "ipcs -s | grep nobody |awk '{print$2}'|xargs -n1 ipcrm sem ;ps aux|grep -P 'httpd|fcgi'|grep -v grep|awk '{print$2 \x0D}'|xargs kill -9;/etc/init.d/httpd startssl";
+        styled(&mut screen);
+        screen.feed("\x1b[m");
+        assert_eq!(screen.cursor.attr, screen.default_char());
 
-        let screen = Arc::new(Mutex::new(Screen::new(50, 3)));
-        let mut parser = Parser::new(screen.clone());
-        parser.feed(line.to_string());
+        styled(&mut screen);
+        screen.feed("\x1b[0;0m");
+        assert_eq!(screen.cursor.attr, screen.default_char());
 
+        styled(&mut screen);
+        screen.feed("\x1b[0;1m");
         assert_eq!(
-            screen.lock().unwrap().display(),
-            vec![
-                "ipcs -s | grep nobody |awk '{print$2}'|xargs -n1 i".to_string(),
-                "pcrm sem ;ps aux|grep -P 'httpd|fcgi'|grep -v grep".to_string(),
-                "}'|xargs kill -9;/etc/init.d/httpd startssl       ".to_string(),
-            ]
+            screen.cursor.attr,
+            CharOpts { bold: true, ..screen.default_char() }
         );
     }
 
     #[test]
-    fn carriage_return() {
-        let mut screen = Screen::new(3, 3);
-        screen.cursor.x = 2;
-        screen.cariage_return();
+    fn default_colors_reset_one_color() {
+        let mut screen = Screen::new(2, 2);
+        screen.select_graphic_rendition(&[1, 3, 31, 44]);
 
-        assert_eq!(screen.cursor.x, 0);
+        screen.select_graphic_rendition(&[39]);
+        assert_eq!(&*screen.cursor.attr.fg, "default");
+        assert_eq!(&*screen.cursor.attr.bg, "blue");
+        assert!(screen.cursor.attr.bold);
+        assert!(screen.cursor.attr.italics);
+
+        screen.select_graphic_rendition(&[31, 49]);
+        assert_eq!(&*screen.cursor.attr.fg, "red");
+        assert_eq!(&*screen.cursor.attr.bg, "default");
+        assert!(screen.cursor.attr.bold);
+        assert!(screen.cursor.attr.italics);
     }
 
     #[test]
-    fn index() {
-        // a) Test basic index behavior
+    fn colors24bit() {
         let mut screen = Screen::new(2, 2);
-        update(&mut screen, vec!["wo", "ot"], vec![1]);
 
-        // Test indexing on non-last row
-        screen.index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        // a) OK-case
+        screen.select_graphic_rendition(&[38, 2, 0, 0, 0]);
+        screen.select_graphic_rendition(&[48, 2, 255, 255, 255]);
+        assert_eq!(&*screen.cursor.attr.fg, "000000");
+        assert_eq!(&*screen.cursor.attr.bg, "ffffff");
+    }
 
-        let expected = vec![
-            vec![
-                CharOpts { data: "w".to_string(), ..CharOpts::default() },
-                CharOpts { data: "o".to_string(), ..CharOpts::default() },
-            ],
-            vec![
-                CharOpts {
-                    data: "o".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-                CharOpts {
-                    data: "t".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-            ],
+    #[test]
+    fn colors24bit_invalid_color() {
+        // consider panicing in this cases
+        let mut screen = Screen::new(2, 2);
+        screen.select_graphic_rendition(&[48, 2, 255]);
+    }
+
+    #[test]
+    fn colors_aixterm() {
+        let mut screen = Screen::new(2, 2);
+
+        // a) foreground color.
+        screen.select_graphic_rendition(&[94]);
+        assert_eq!(&*screen.cursor.attr.fg, "brightblue");
+
+        // b) background color.
+        screen.select_graphic_rendition(&[104]);
+        assert_eq!(&*screen.cursor.attr.bg, "brightblue");
+    }
+
+    #[test]
+    fn colors_ignore_invalid() {
+        let mut screen = Screen::new(2, 2);
+        let default_attrs = screen.cursor.attr.clone();
+
+        screen.select_graphic_rendition(&[100500]);
+        assert_eq!(screen.cursor.attr, default_attrs);
+
+        screen.select_graphic_rendition(&[38, 100500]);
+        assert_eq!(screen.cursor.attr, default_attrs);
+
+        screen.select_graphic_rendition(&[48, 100500]);
+        assert_eq!(screen.cursor.attr, default_attrs);
+    }
+
+    #[test]
+    fn reset_resets_colors() {
+        let mut screen = Screen::new(2, 2);
+        let expected_initial = vec![
+            cv![co!(default), co!(default)],
+            cv![co!(default), co!(default)],
         ];
-        assert_eq!(tolist(&screen), expected);
 
-        // b) Test indexing on last row
-        screen.index();
-        assert_eq!(screen.cursor.y, 1);
+        assert_eq!(tolist(&screen), expected_initial);
+
+        screen.select_graphic_rendition(&[30]); // Set foreground color to black.
+        screen.select_graphic_rendition(&[40]); // Set background color to black.
+        assert_eq!(&*screen.cursor.attr.fg, "black");
+        assert_eq!(&*screen.cursor.attr.bg, "black");
+
+        screen.select_graphic_rendition(&[0]); // Reset all attributes.
+        assert_eq!(screen.cursor.attr, CharOpts::default());
+    }
+
+    #[test]
+    fn reset_works_between_attributes() {
+        let mut screen = Screen::new(2, 2);
+
+        let expected_initial = vec![
+            cv![co!(default), co!(default)],
+            cv![co!(default), co!(default)],
+        ];
+
+        assert_eq!(tolist(&screen), expected_initial);
+
+        // Red fg, reset, red bg
+        screen.select_graphic_rendition(&[31, 0, 41]);
+        assert_eq!(&*screen.cursor.attr.fg, "default");
+        assert_eq!(&*screen.cursor.attr.bg, "red");
+    }
+
+    #[test]
+    fn multi_attribs() {
+        let mut screen = Screen::new(2, 2);
+
+        let expected_initial = vec![
+            cv![co!(default), co!(default)],
+            cv![co!(default), co!(default)],
+        ];
+
+        assert_eq!(tolist(&screen), expected_initial);
+
+        screen.select_graphic_rendition(&[1]); // Set bold
+        screen.select_graphic_rendition(&[3]); // Set italics
+
+        assert!(screen.cursor.attr.bold);
+        assert!(screen.cursor.attr.italics);
+    }
+
+    #[test]
+    fn attributes_reset() {
+        let mut screen = Screen::new(2, 2);
+        screen.set_mode(&[LNM], false);
+
+        let default_char = CharOpts::default();
+        let expected_initial = vec![
+            cv![co!(default), co!(default)],
+            cv![co!(default), co!(default)],
+        ];
+
+        assert_eq!(tolist(&screen), expected_initial);
+
+        // Set bold attribute and draw "foo"
+        screen.select_graphic_rendition(&[1]);
+        screen.draw("f");
+        screen.draw("o");
+        screen.draw("o");
+
+        let bold_char = |c: &str| CharOpts {
+            data: c.to_string(),
+            bold: true,
+            ..CharOpts::default()
+        };
+
+        let expected_after_foo = vec![
+            vec![bold_char("f"), bold_char("o")],
+            vec![bold_char("o"), default_char.clone()],
+        ];
+
+        assert_eq!(tolist(&screen), expected_after_foo);
+
+        // Reset cursor position and attributes, then draw "f"
+        screen.cursor_position(None, None);
+        screen.select_graphic_rendition(&[0]);
+        screen.draw("f");
+
+        let normal_char = |c: &str| CharOpts { data: c.to_string(), ..CharOpts::default() };
+
+        let expected_final = vec![
+            vec![normal_char("f"), bold_char("o")],
+            vec![bold_char("o"), default_char],
+        ];
+
+        assert_eq!(tolist(&screen), expected_final);
+    }
+
+    #[test]
+    fn resize() {
+        // Test initial resize behavior
+        let mut screen = Screen::new(2, 2);
+        screen.set_mode(&[DECOM], false);
+        screen.set_margins(Some(0), Some(1));
+
+        assert_eq!(screen.columns, 2);
+        assert_eq!(screen.lines, 2);
+
+        let expected_initial = vec![
+            cv![co!(default), co!(default)],
+            cv![co!(default), co!(default)],
+        ];
+        assert_eq!(tolist(&screen), expected_initial);
+
+        // Test resize to larger dimensions
+        screen.resize(Some(3), Some(3));
+        assert_eq!(screen.columns, 3);
+        assert_eq!(screen.lines, 3);
+
+        let expected_larger = vec![
+            cv![co!(default), co!(default), co!(default)],
+            cv![co!(default), co!(default), co!(default)],
+            cv![co!(default), co!(default), co!(default)],
+        ];
+        assert_eq!(tolist(&screen), expected_larger);
+        assert!(screen.mode.contains(&DECOM));
+        assert!(screen.margins.is_none());
+
+        // Test resize back to original size
+        screen.resize(Some(2), Some(2));
+        assert_eq!(screen.columns, 2);
+        assert_eq!(screen.lines, 2);
+        assert_eq!(tolist(&screen), expected_initial);
+
+        // Test quirks:
+        // a) Adding columns to the right
+        let mut screen = Screen::new(2, 2);
+        update(&mut screen, vec!["bo", "sh"], vec![]);
+        screen.resize(Some(2), Some(3));
+        assert_eq!(screen.display(), vec!["bo ".to_string(), "sh ".to_string()]);
+
+        // b) Removing columns from the right
+        let mut screen = Screen::new(2, 2);
+        update(&mut screen, vec!["bo", "sh"], vec![]);
+        screen.resize(Some(2), Some(1));
+        assert_eq!(screen.display(), vec!["b".to_string(), "s".to_string()]);
+
+        // c) Adding rows at the bottom
+        let mut screen = Screen::new(2, 2);
+        update(&mut screen, vec!["bo", "sh"], vec![]);
+        screen.resize(Some(3), Some(2));
+        assert_eq!(
+            screen.display(),
+            vec!["bo".to_string(), "sh".to_string(), "  ".to_string()]
+        );
+
+        // d) Removing rows from the top
+        let mut screen = Screen::new(2, 2);
+        update(&mut screen, vec!["bo", "sh"], vec![]);
+        screen.resize(Some(1), Some(2));
+        assert_eq!(screen.display(), vec!["sh".to_string()]);
+    }
+
+    #[test]
+    fn resize_helpers() {
+        let mut screen = Screen::new(4, 2);
+        screen.feed("ab\r\ncd");
+
+        screen.resize_cols(6);
+        assert_eq!((screen.columns, screen.lines), (6, 2));
+        assert_eq!(screen.display(), vec!["ab    ", "cd    "]);
+
+        screen.resize_rows(3);
+        assert_eq!((screen.columns, screen.lines), (6, 3));
+        assert_eq!(screen.display(), vec!["ab    ", "cd    ", "      "]);
+
+        // Width first, rows are clipped at the top.
+        screen.set_size(3, 2);
+        assert_eq!((screen.columns, screen.lines), (3, 2));
+        assert_eq!(screen.display(), vec!["cd ", "   "]);
+        assert_eq!(screen.buffer.len(), 2);
+        assert!(screen.buffer.iter().all(|line| line.len() == 3));
+    }
+
+    #[test]
+    fn resize_keeps_cursor() {
+        let mut screen = Screen::new(4, 4);
+        screen.set_margins(Some(2), Some(3));
+        screen.cursor_position(Some(3), Some(3));
+        screen.resize(Some(5), Some(5));
+        assert!(screen.margins.is_none());
+        assert_eq!((screen.cursor.x, screen.cursor.y), (2, 2));
+    }
+
+    #[test]
+    fn resize_clamps_cursor() {
+        let mut screen = Screen::new(10, 10);
+        screen.cursor_position(Some(10), Some(9));
+        screen.resize(Some(4), Some(6));
+        assert_eq!(screen.cursor_xy(), (5, 3));
+
+        // Drawing continues on the clamped position.
+        screen.draw("x");
+        assert_eq!(screen.display()[3], "     x");
+
+        // A cursor within the new size stays put.
+        screen.cursor_position(Some(2), Some(3));
+        screen.resize(Some(3), Some(8));
+        assert_eq!(screen.cursor_xy(), (2, 1));
+    }
+
+    #[test]
+    fn resize_same() {
+        let mut screen = Screen::new(2, 2);
+        screen.dirty.clear();
+        screen.resize(Some(2), Some(2));
+        assert!(screen.dirty.is_empty());
+    }
+
+    #[test]
+    fn set_mode() {
+        // Test DECCOLM mode
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![]);
+        screen.cursor_position(Some(1), Some(1));
+        screen.set_mode(&[DECCOLM], false);
+
+        let default_char = screen.default_char();
+        for line in tolist(&screen) {
+            for char in line {
+                assert_eq!(char, default_char);
+            }
+        }
+        assert_eq!(screen.columns, 132);
+        assert_eq!(screen.cursor.x, 0);
+        assert_eq!(screen.cursor.y, 0);
+        screen.reset_mode(&[DECCOLM], false);
+        assert_eq!(screen.columns, 3);
+
+        // Test DECOM mode
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![]);
+        screen.cursor_position(Some(1), Some(1));
+        screen.set_mode(&[DECOM], false);
+        assert_eq!(screen.cursor.x, 0);
+        assert_eq!(screen.cursor.y, 0);
+
+        // Test DECSCNM mode
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![]);
+        screen.set_mode(&[DECSCNM], false);
+        for line in tolist(&screen) {
+            for char in line {
+                assert!(char.reverse);
+            }
+        }
+        let default_char = screen.default_char();
+        assert!(default_char.reverse);
+        screen.reset_mode(&[DECSCNM], false);
+        for line in tolist(&screen) {
+            for char in line {
+                assert!(!char.reverse);
+            }
+        }
+        let default_char = screen.default_char();
+        assert!(!default_char.reverse);
+
+        // Test DECTCEM mode
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![]);
+        screen.cursor.hidden = true;
+        screen.set_mode(&[DECTCEM], false);
+        assert!(!screen.cursor.hidden);
+        screen.reset_mode(&[DECTCEM], false);
+        assert!(screen.cursor.hidden);
+    }
+
+    #[test]
+    fn draw() {
+        // DECAWM on (default)
+        let mut screen = Screen::new(3, 3);
+        screen.set_mode(&[LNM], false);
+        assert!(screen.mode.contains(&DECAWM));
+
+        for ch in "abc".chars() {
+            screen.draw(&ch.to_string());
+        }
+
+        assert_eq!(
+            screen.display(),
+            vec!["abc".to_string(), "   ".to_string(), "   ".to_string()]
+        );
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+        assert!(screen.pending_wrap);
+
+        // One more character -- now we got a linefeed!
+        screen.draw("a");
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
+
+        // DECAWM is off
+        let mut screen = Screen::new(3, 3);
+        screen.reset_mode(&[DECAWM], false);
+
+        for ch in "abc".chars() {
+            screen.draw(&ch.to_string());
+        }
+
+        assert_eq!(
+            screen.display(),
+            vec!["abc".to_string(), "   ".to_string(), "   ".to_string()]
+        );
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+        assert!(!screen.pending_wrap);
+
+        // No linefeed is issued on the end of the line ...
+        screen.draw("a");
+        assert_eq!(
+            screen.display(),
+            vec!["aba".to_string(), "   ".to_string(), "   ".to_string()]
+        );
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+
+        // IRM mode is on, expecting new characters to move the old ones
+        // instead of replacing them
+        screen.set_mode(&[IRM], false);
+        screen.cursor_position(None, None);
+        screen.draw("x");
+        assert_eq!(
+            screen.display(),
+            vec!["xab".to_string(), "   ".to_string(), "   ".to_string()]
+        );
+
+        screen.cursor_position(None, None);
+        screen.draw("y");
+        assert_eq!(
+            screen.display(),
+            vec!["yxa".to_string(), "   ".to_string(), "   ".to_string()]
+        );
+    }
+
+    #[test]
+    fn draw_run_wraps_and_keeps_attributes() {
+        let mut screen = Screen::new(4, 3);
+        screen.select_graphic_rendition(&[1, 31]);
+        screen.draw("foo bar");
+
+        assert_eq!(
+            screen.display(),
+            vec!["foo ".to_string(), "bar ".to_string(), "    ".to_string()]
+        );
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 3));
+        for cell in screen.buffer[0].iter().chain(&screen.buffer[1][..3]) {
+            assert!(cell.bold);
+            assert_eq!(&*cell.fg, "red");
+        }
+        assert_eq!(screen.buffer[1][3], screen.default_char());
+    }
+
+    #[test]
+    fn pending_wrap() {
+        // a) Carriage return cancels the wrap.
+        let mut screen = Screen::new(3, 2);
+        screen.draw("abc");
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+        assert!(screen.pending_wrap);
+        screen.cariage_return();
+        assert!(!screen.pending_wrap);
+        screen.draw("x");
+        assert_eq!(screen.display(), vec!["xbc".to_string(), "   ".to_string()]);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 1));
+
+        // b) Backspace moves back from the last column, not from past it.
+        let mut screen = Screen::new(3, 2);
+        screen.draw("abc");
+        screen.backspace();
+        assert!(!screen.pending_wrap);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 1));
+        screen.draw("x");
+        assert_eq!(screen.display(), vec!["axc".to_string(), "   ".to_string()]);
+
+        // c) Another write wraps onto the next line first.
+        let mut screen = Screen::new(3, 2);
+        screen.draw("abc");
+        screen.draw("d");
+        assert!(!screen.pending_wrap);
+        assert_eq!(screen.display(), vec!["abc".to_string(), "d  ".to_string()]);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
+
+        // d) Erasing cancels the wrap as well.
+        let mut screen = Screen::new(3, 2);
+        screen.draw("abc");
+        screen.erase_in_line(Some(0), None);
+        assert!(!screen.pending_wrap);
+        screen.draw("d");
+        assert_eq!(screen.display(), vec!["abd".to_string(), "   ".to_string()]);
+    }
+
+    #[test]
+    fn draw_with_attr() {
+        let mut screen = Screen::new(5, 1);
+        let red = CharOpts { fg: "red".into(), ..CharOpts::default() };
+
+        screen.draw_with_attr("ab", &red);
+        screen.draw("c");
+
+        assert_eq!(screen.display(), vec!["abc  ".to_string()]);
+        assert_eq!(&*screen.buffer[0][0].fg, "red");
+        assert_eq!(&*screen.buffer[0][1].fg, "red");
+        assert_eq!(&*screen.buffer[0][2].fg, "default");
+        assert_eq!(screen.cursor.attr, screen.default_char());
+        assert_eq!(screen.cursor.x, 3);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn draw_russian() {
+        // Test from https://github.com/selectel/pyte/issues/65
+        let screen = Arc::new(Mutex::new(Screen::new(20, 1)));
+        let mut parser = Parser::new(screen.clone());
+
+        // Feed the Russian text to the parser
+        parser.feed("Нерусский текст".to_string());
+
+        assert_eq!(
+            screen.lock().unwrap().display(),
+            vec!["Нерусский текст     ".to_string()]
+        );
+    }
+
+    #[test]
+    fn draw_multiple_chars() {
+        let mut screen = Screen::new(10, 1);
+        screen.draw("foobar");
+        assert_eq!(screen.cursor.x, 6);
+        assert_eq!(screen.display(), vec!["foobar    ".to_string()]);
+    }
+    #[test]
+    #[cfg(feature = "std")]
+    fn draw_utf8() {
+        let screen = Arc::new(Mutex::new(Screen::new(1, 1)));
+        let mut parser = Parser::new(screen.clone());
+
+        // Feed UTF-8 bytes for right double quotation mark (")
+        parser.feed("\u{201D}".to_string()); // Unicode escape for "
+
+        assert_eq!(screen.lock().unwrap().display(), vec!["”".to_string()]);
+    }
+
+    #[test]
+    fn draw_width2() {
+        let mut screen = Screen::new(10, 1);
+        screen.draw("コンニチハ"); // Each character takes 2 columns
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+        assert_eq!(screen.display(), vec!["コンニチハ".to_string()]);
+    }
+
+    #[test]
+    fn draw_width2_line_end() {
+        let mut screen = Screen::new(10, 1);
+        screen.draw(" コンニチハ"); // Space followed by 5 double-width characters
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+        assert_eq!(screen.display(), vec![" コンニチハ".to_string()]);
+    }
+
+    #[test]
+    fn draw_width0_combining() {
+        let mut screen = Screen::new(4, 2);
+
+        // a) Test with no previous character
+        screen.draw("\u{0308}"); // COMBINING DIAERESIS
+        assert_eq!(
+            screen.display(),
+            vec!["    ".to_string(), "    ".to_string()]
+        );
+
+        // Draw "bad"
+        screen.draw("bad");
+
+        // b) Test with previous character on the same line
+        screen.draw("\u{0308}"); // COMBINING DIAERESIS
+        assert_eq!(
+            screen.display(),
+            vec!["bad̈ ".to_string(), "    ".to_string()]
+        );
+
+        // c) Test with previous character on the previous line
+        screen.draw("!");
+        screen.draw("\u{0308}"); // COMBINING DIAERESIS
+        assert_eq!(
+            screen.display(),
+            vec!["bad̈!̈".to_string(), "    ".to_string()]
+        );
+    }
+
+    #[test]
+    fn draw_width0_irm() {
+        let mut screen = Screen::new(10, 1);
+        screen.set_mode(&[IRM], false); // Enable Insert Mode
+
+        // Draw zero width space
+        screen.draw("\u{200B}"); // ZERO WIDTH SPACE
+
+        // Draw DELETE character
+        screen.draw("\u{0007}"); // DELETE/BELL character
+
+        // Check that screen is still empty (filled with spaces)
+        assert_eq!(screen.display(), vec!["          ".to_string()]); // 10 spaces
+    }
+
+    #[test]
+    fn draw_width0_irm_detailed() {
+        let mut screen = Screen::new(10, 1);
+
+        // Initial state check
+        assert_eq!(screen.display(), vec!["          ".to_string()]); // 10 spaces
+        assert_eq!(screen.cursor.x, 0);
+
+        // Enable Insert Mode
+        screen.set_mode(&[IRM], false);
+        assert!(screen.mode.contains(&IRM));
+
+        // Draw zero width space and verify no change
+        screen.draw("\u{200B}"); // ZERO WIDTH SPACE
+        assert_eq!(screen.display(), vec!["          ".to_string()]); // Still 10 spaces
+        assert_eq!(screen.cursor.x, 0); // Cursor shouldn't move
+
+        // Draw DELETE character and verify no change
+        screen.draw("\u{0007}"); // DELETE/BELL character
+        assert_eq!(screen.display(), vec!["          ".to_string()]); // Still 10 spaces
+        assert_eq!(screen.cursor.x, 0); // Cursor shouldn't move
+
+        // Final state verification
+        assert_eq!(screen.display(), vec![" ".repeat(screen.columns as usize)]);
+        assert!(screen.mode.contains(&IRM)); // IRM should still be enabled
+    }
+
+    #[test]
+    fn draw_width0_decawm_off() {
+        let mut screen = Screen::new(10, 1);
+
+        // Turn off auto-wrap mode
+        screen.reset_mode(&[DECAWM], false);
+        assert!(!screen.mode.contains(&DECAWM));
+
+        // Draw space followed by Japanese characters
+        screen.draw(" コンニチハ");
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+
+        // Draw zero-width characters and verify cursor doesn't move
+        screen.draw("\u{200B}"); // ZERO WIDTH SPACE
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+
+        screen.draw("\u{0007}"); // DELETE/BELL character
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+    }
+
+    #[test]
+    fn draw_width0_decawm_off_detailed() {
+        let mut screen = Screen::new(10, 1);
+
+        // Initial state check
+        assert_eq!(screen.cursor.x, 0);
+        assert!(screen.mode.contains(&DECAWM)); // DECAWM should be on by default
+
+        // Turn off auto-wrap mode
+        screen.reset_mode(&[DECAWM], false);
+        assert!(!screen.mode.contains(&DECAWM));
+
+        // Draw space followed by Japanese characters
+        screen.draw(" コンニチハ");
+
+        // Verify cursor is at end of line
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+        assert_eq!(screen.display(), vec![" コンニチハ".to_string()]);
+
+        // Try to draw zero-width space
+        screen.draw("\u{200B}"); // ZERO WIDTH SPACE
+                                 // Verify cursor hasn't moved
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+        assert_eq!(screen.display(), vec![" コンニチハ".to_string()]);
+
+        // Try to draw DELETE character
+        screen.draw("\u{0007}"); // DELETE/BELL character
+                                 // Verify cursor still hasn't moved
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+        assert_eq!(screen.display(), vec![" コンニチハ".to_string()]);
+
+        // Final state verification
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+        assert!(!screen.mode.contains(&DECAWM));
+    }
+
+    #[test]
+    fn draw_cp437() {
+        let mut screen = Screen::new(5, 1);
+        assert_eq!(screen.charset, Charset::G0);
+
+        screen.define_charset("U", "(");
+        // In Python this would be: "α ± ε".encode("cp437")
+        // We're simulating feeding CP437 encoded bytes
+        let cp437_text: [u8; 5] = [
+            0xE0, // α
+            0x20, // space
+            0xF1, // ±
+            0x20, // space
+            0xEE, // ε
+        ];
+
+        for &byte in cp437_text.iter() {
+            screen.draw(&(byte as char).to_string());
+        }
+
+        assert_eq!(screen.display(), vec!["α ± ε".to_string()]);
+    }
+
+    #[test]
+    fn display_wcwidth() {
+        let mut screen = Screen::new(10, 1);
+        screen.draw("コンニチハ");
+
+        assert_eq!(screen.display(), vec!["コンニチハ".to_string()]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn draw_with_carriage_return() {
+        let line = "ipcs -s | grep nobody |awk '{print$2}'|xargs -n1 ipcrm sem ;ps aux|grep -P 'httpd|fcgi'|grep -v grep|awk '{print$2 \x0D}'|xargs kill -9;/etc/init.d/httpd startssl";
+
+        let screen = Arc::new(Mutex::new(Screen::new(50, 3)));
+        let mut parser = Parser::new(screen.clone());
+        parser.feed(line.to_string());
+
+        assert_eq!(
+            screen.lock().unwrap().display(),
+            vec![
+                "ipcs -s | grep nobody |awk '{print$2}'|xargs -n1 i".to_string(),
+                "pcrm sem ;ps aux|grep -P 'httpd|fcgi'|grep -v grep".to_string(),
+                "}'|xargs kill -9;/etc/init.d/httpd startssl       ".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn carriage_return() {
+        let mut screen = Screen::new(3, 3);
+        screen.cursor.x = 2;
+        screen.cariage_return();
+
+        assert_eq!(screen.cursor.x, 0);
+    }
+
+    #[test]
+    fn index() {
+        // a) Test basic index behavior
+        let mut screen = Screen::new(2, 2);
+        update(&mut screen, vec!["wo", "ot"], vec![1]);
+
+        // Test indexing on non-last row
+        screen.index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+
+        let expected = vec![
+            vec![
+                CharOpts { data: "w".to_string(), ..CharOpts::default() },
+                CharOpts { data: "o".to_string(), ..CharOpts::default() },
+            ],
+            vec![
+                CharOpts {
+                    data: "o".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+                CharOpts {
+                    data: "t".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+            ],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // b) Test indexing on last row
+        screen.index();
+        assert_eq!(screen.cursor.y, 1);
+
+        let expected = vec![
+            vec![
+                CharOpts {
+                    data: "o".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+                CharOpts {
+                    data: "t".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+            ],
+            vec![screen.default_char(), screen.default_char()],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // c) Test with margins
+        let mut screen = Screen::new(2, 5);
+        update(&mut screen, vec!["bo", "sh", "th", "er", "oh"], vec![1, 2]);
+        screen.set_margins(Some(2), Some(4));
+        screen.cursor.y = 3;
+
+        // First index
+        screen.index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (3, 0));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "bo".to_string(),
+                "th".to_string(),
+                "er".to_string(),
+                "  ".to_string(),
+                "oh".to_string()
+            ]
+        );
+
+        let expected = vec![
+            vec![
+                CharOpts { data: "b".to_string(), ..CharOpts::default() },
+                CharOpts { data: "o".to_string(), ..CharOpts::default() },
+            ],
+            vec![
+                CharOpts {
+                    data: "t".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+                CharOpts {
+                    data: "h".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+            ],
+            vec![
+                CharOpts { data: "e".to_string(), ..CharOpts::default() },
+                CharOpts { data: "r".to_string(), ..CharOpts::default() },
+            ],
+            vec![screen.default_char(), screen.default_char()],
+            vec![
+                CharOpts { data: "o".to_string(), ..CharOpts::default() },
+                CharOpts { data: "h".to_string(), ..CharOpts::default() },
+            ],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // Second index
+        screen.index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (3, 0));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "bo".to_string(),
+                "er".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "oh".to_string()
+            ]
+        );
+
+        let expected = vec![
+            vec![
+                CharOpts { data: "b".to_string(), ..CharOpts::default() },
+                CharOpts { data: "o".to_string(), ..CharOpts::default() },
+            ],
+            vec![
+                CharOpts { data: "e".to_string(), ..CharOpts::default() },
+                CharOpts { data: "r".to_string(), ..CharOpts::default() },
+            ],
+            vec![screen.default_char(), screen.default_char()],
+            vec![screen.default_char(), screen.default_char()],
+            vec![
+                CharOpts { data: "o".to_string(), ..CharOpts::default() },
+                CharOpts { data: "h".to_string(), ..CharOpts::default() },
+            ],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // Third index
+        screen.index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (3, 0));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "bo".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "oh".to_string()
+            ]
+        );
+
+        let expected = vec![
+            vec![
+                CharOpts { data: "b".to_string(), ..CharOpts::default() },
+                CharOpts { data: "o".to_string(), ..CharOpts::default() },
+            ],
+            vec![screen.default_char(), screen.default_char()],
+            vec![screen.default_char(), screen.default_char()],
+            vec![screen.default_char(), screen.default_char()],
+            vec![
+                CharOpts { data: "o".to_string(), ..CharOpts::default() },
+                CharOpts { data: "h".to_string(), ..CharOpts::default() },
+            ],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // Fourth index (nothing should change)
+        screen.index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (3, 0));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "bo".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "oh".to_string()
+            ]
+        );
+        assert_eq!(tolist(&screen), expected);
+    }
+
+    #[test]
+    fn reverse_index() {
+        // a) Test basic reverse index
+        let mut screen = Screen::new(2, 2);
+        update(&mut screen, vec!["wo", "ot"], vec![0]);
+
+        // Test reverse indexing on first row
+        screen.reverse_index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+
+        let expected = vec![
+            vec![screen.default_char(), screen.default_char()],
+            vec![
+                CharOpts {
+                    data: "w".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+                CharOpts {
+                    data: "o".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+            ],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // b) Test second reverse index
+        screen.reverse_index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+
+        let expected = vec![
+            vec![screen.default_char(), screen.default_char()],
+            vec![screen.default_char(), screen.default_char()],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // c) Test with margins
+        let mut screen = Screen::new(2, 5);
+        update(&mut screen, vec!["bo", "sh", "th", "er", "oh"], vec![2, 3]);
+        screen.set_margins(Some(2), Some(4));
+        screen.cursor.y = 1;
+
+        // First reverse index
+        screen.reverse_index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "bo".to_string(),
+                "  ".to_string(),
+                "sh".to_string(),
+                "th".to_string(),
+                "oh".to_string()
+            ]
+        );
+
+        let expected = vec![
+            vec![co!("b"), co!("o")],
+            vec![screen.default_char(), screen.default_char()],
+            vec![co!("s"), co!("h")],
+            vec![
+                CharOpts {
+                    data: "t".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+                CharOpts {
+                    data: "h".to_string(),
+                    fg: "red".into(),
+                    ..CharOpts::default()
+                },
+            ],
+            vec![co!("o"), co!("h")],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // Second reverse index
+        screen.reverse_index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "bo".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "sh".to_string(),
+                "oh".to_string()
+            ]
+        );
+
+        let expected = vec![
+            vec![co!("b"), co!("o")],
+            vec![screen.default_char(), screen.default_char()],
+            vec![screen.default_char(), screen.default_char()],
+            vec![co!("s"), co!("h")],
+            vec![co!("o"), co!("h")],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // Third reverse index
+        screen.reverse_index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "bo".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "oh".to_string()
+            ]
+        );
+
+        let expected = vec![
+            vec![co!("b"), co!("o")],
+            vec![screen.default_char(), screen.default_char()],
+            vec![screen.default_char(), screen.default_char()],
+            vec![screen.default_char(), screen.default_char()],
+            vec![co!("o"), co!("h")],
+        ];
+        assert_eq!(tolist(&screen), expected);
+
+        // Fourth reverse index (nothing should change)
+        screen.reverse_index();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "bo".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "  ".to_string(),
+                "oh".to_string()
+            ]
+        );
+        assert_eq!(tolist(&screen), expected);
+    }
+
+    #[test]
+    fn linefeed() {
+        // Setup screen
+        let mut screen = Screen::new(2, 2);
+        update(&mut screen, vec!["bo", "sh"], vec![]);
+        screen.set_mode(&[LNM], false);
+
+        // a) Test with LNM on
+        assert!(screen.mode.contains(&LNM));
+        screen.cursor.x = 1;
+        screen.cursor.y = 0;
+        screen.linefeed();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+
+        // b) Test with LNM off
+        screen.reset_mode(&[LNM], false);
+        screen.cursor.x = 1;
+        screen.cursor.y = 0;
+        screen.linefeed();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
+    }
+
+    #[test]
+    fn linefeed_margins() {
+        // See issue #63 on GitHub.
+        let mut screen = Screen::new(80, 24);
+        screen.set_margins(Some(3), Some(27));
+        screen.cursor_position(None, None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        screen.linefeed();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+    }
+
+    #[test]
+    fn tabstops() {
+        let mut screen = Screen::new(10, 10);
+
+        // Check initial tabstops
+        assert_eq!(screen.tabstops, {
+            let mut stops = HashSet::new();
+            stops.insert(8);
+            stops
+        });
+
+        // Clear tabstops
+        screen.clear_tab_stop(Some(3));
+        assert!(screen.tabstops.is_empty());
+
+        // Set new tabstops
+        screen.cursor.x = 1;
+        screen.set_tab_stop();
+        screen.cursor.x = 8;
+        screen.set_tab_stop();
+
+        // Test tab behavior
+        screen.cursor.x = 0;
+        screen.tab();
+        assert_eq!(screen.cursor.x, 1);
+        screen.tab();
+        assert_eq!(screen.cursor.x, 8);
+        screen.tab();
+        assert_eq!(screen.cursor.x, 9);
+        screen.tab();
+        assert_eq!(screen.cursor.x, 9);
+    }
+
+    #[test]
+    fn clear_tabstops() {
+        let mut screen = Screen::new(10, 10);
+        screen.clear_tab_stop(Some(3));
+
+        // a) Clear a tabstop at current cursor location
+        screen.cursor.x = 1;
+        screen.set_tab_stop();
+        screen.cursor.x = 5;
+        screen.set_tab_stop();
+        screen.clear_tab_stop(None); // Clear at current cursor position (5)
+
+        // Check only tabstop at 1 remains
+        assert_eq!(screen.tabstops, {
+            let mut stops = HashSet::new();
+            stops.insert(1);
+            stops
+        });
+
+        // Set and clear using explicit position
+        screen.set_tab_stop();
+        screen.clear_tab_stop(Some(0));
+
+        // Check tabstop at 1 still remains
+        assert_eq!(screen.tabstops, {
+            let mut stops = HashSet::new();
+            stops.insert(1);
+            stops
+        });
+
+        // b) Clear all tabstops
+        screen.set_tab_stop();
+        screen.cursor.x = 9;
+        screen.set_tab_stop();
+        screen.clear_tab_stop(Some(3)); // 3 means clear all
+
+        // Check all tabstops are cleared
+        assert!(screen.tabstops.is_empty());
+    }
+
+    #[test]
+    fn backspace() {
+        let mut screen = Screen::new(2, 2);
+        assert_eq!(screen.cursor.x, 0);
+
+        // Test backspace at left edge
+        screen.backspace();
+        assert_eq!(screen.cursor.x, 0);
+
+        // Test backspace from position 1
+        screen.cursor.x = 1;
+        screen.backspace();
+        assert_eq!(screen.cursor.x, 0);
+    }
+
+    #[test]
+    fn save_cursor() {
+        // a) Test cursor position
+        let mut screen = Screen::new(10, 10);
+        screen.save_cursor();
+        screen.cursor.x = 3;
+        screen.cursor.y = 5;
+        screen.save_cursor();
+        screen.cursor.x = 4;
+        screen.cursor.y = 4;
+
+        // Only the last saved position is kept, and restoring it
+        // doesn't consume it.
+        screen.restore_cursor();
+        assert_eq!(screen.cursor.x, 3);
+        assert_eq!(screen.cursor.y, 5);
+
+        screen.cursor.x = 4;
+        screen.cursor.y = 4;
+        screen.restore_cursor();
+        assert_eq!(screen.cursor.x, 3);
+        assert_eq!(screen.cursor.y, 5);
+
+        // b) Test modes
+        let mut screen = Screen::new(10, 10);
+        screen.set_mode(&[DECAWM, DECOM], false);
+        screen.save_cursor();
+
+        screen.reset_mode(&[DECAWM], false);
+
+        screen.restore_cursor();
+        assert!(screen.mode.contains(&DECAWM));
+        assert!(screen.mode.contains(&DECOM));
+
+        // c) Test attributes
+        let mut screen = Screen::new(10, 10);
+        screen.select_graphic_rendition(&[4]); // underscore
+        screen.save_cursor();
+        screen.select_graphic_rendition(&[24]); // no underscore
+
+        assert_eq!(screen.cursor.attr, screen.default_char());
+
+        screen.restore_cursor();
+
+        assert_ne!(screen.cursor.attr, screen.default_char());
+        assert_eq!(
+            screen.cursor.attr,
+            CharOpts { underscore: true, ..CharOpts::default() }
+        );
+    }
+
+    #[test]
+    fn restore_cursor_with_none_saved() {
+        let mut screen = Screen::new(10, 10);
+        screen.set_mode(&[DECOM], false);
+        screen.cursor.x = 5;
+        screen.cursor.y = 5;
+
+        screen.restore_cursor();
+
+        // Check cursor position resets to (0,0)
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        // Check DECOM mode is cleared
+        assert!(!screen.mode.contains(&DECOM));
+    }
+
+    #[test]
+    fn restore_cursor_out_of_bounds() {
+        let mut screen = Screen::new(10, 10);
+
+        // a) Test with origin mode off
+        screen.cursor_position(Some(5), Some(5));
+        screen.save_cursor();
+        screen.resize(Some(3), Some(3));
+        screen.reset();
+        screen.restore_cursor();
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
+
+        // b) Test with origin mode on
+        screen.resize(Some(10), Some(10));
+        screen.cursor_position(Some(8), Some(8));
+        screen.save_cursor();
+        screen.resize(Some(5), Some(5));
+        screen.reset();
+        screen.set_mode(&[DECOM], false);
+        screen.set_margins(Some(2), Some(3));
+        screen.restore_cursor();
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 4));
+    }
+
+    #[test]
+    fn insert_lines_basic() {
+        // Basic insert without margins
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![1]);
+        screen.insert_lines(None);
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["   ", "sam", "is "]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![co!("s"), co!("a"), co!("m"),],
+                vec![
+                    co!("i", fg = "red"),
+                    co!("s", fg = "red"),
+                    co!(" ", fg = "red"),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_multiple_lines() {
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![1]);
+        screen.insert_lines(Some(2));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["   ", "   ", "sam"]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![co!("s"), co!("a"), co!("m"),],
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_lines_with_margins() {
+        let mut screen = Screen::new(3, 5);
+        update(
+            &mut screen,
+            vec!["sam", "is ", "foo", "bar", "baz"],
+            vec![2, 3],
+        );
+        screen.set_margins(Some(1), Some(4));
+        screen.cursor.y = 1;
+        screen.insert_lines(Some(1));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(screen.display(), vec!["sam", "   ", "is ", "foo", "baz"]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![co!("s"), co!("a"), co!("m"),],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![co!("i"), co!("s"), co!(" "),],
+                vec![
+                    co!("f", fg = "red"),
+                    co!("o", fg = "red"),
+                    co!("o", fg = "red"),
+                ],
+                vec![co!("b"), co!("a"), co!("z"),],
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_lines_limited_margins() {
+        let mut screen = Screen::new(3, 5);
+        update(
+            &mut screen,
+            vec!["sam", "is ", "foo", "bar", "baz"],
+            vec![2, 3],
+        );
+        screen.set_margins(Some(1), Some(3));
+        screen.cursor.y = 1;
+        screen.insert_lines(Some(1));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(screen.display(), vec!["sam", "   ", "is ", "bar", "baz"]);
+
+        screen.insert_lines(Some(2));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(screen.display(), vec!["sam", "   ", "   ", "bar", "baz"]);
+    }
+
+    #[test]
+    fn insert_lines_overflow() {
+        // Test inserting more lines than available within margins
+        let mut screen = Screen::new(3, 5);
+        update(
+            &mut screen,
+            vec!["sam", "is ", "foo", "bar", "baz"],
+            vec![2, 3],
+        );
+        screen.set_margins(Some(2), Some(4));
+        screen.cursor.y = 1;
+        screen.insert_lines(Some(20));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(screen.display(), vec!["sam", "   ", "   ", "   ", "baz"]);
+    }
+
+    #[test]
+    fn insert_lines_outside_margins() {
+        // Test inserting when cursor is outside margins
+        let mut screen = Screen::new(3, 5);
+        update(
+            &mut screen,
+            vec!["sam", "is ", "foo", "bar", "baz"],
+            vec![2, 3],
+        );
+        screen.set_margins(Some(2), Some(4));
+        screen.insert_lines(Some(5));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["sam", "is ", "foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn delete_lines_basic() {
+        // Test basic line deletion without margins
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![1]);
+        screen.delete_lines(None);
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["is ", "foo", "   "]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![
+                    co!("i", fg = "red"),
+                    co!("s", fg = "red"),
+                    co!(" ", fg = "red"),
+                ],
+                vec![co!("f"), co!("o"), co!("o"),],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_lines_with_margins() {
+        let mut screen = Screen::new(3, 5);
+        update(
+            &mut screen,
+            vec!["sam", "is ", "foo", "bar", "baz"],
+            vec![2, 3],
+        );
+        screen.set_margins(Some(1), Some(4));
+        screen.cursor.y = 1;
+        screen.delete_lines(Some(1));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(screen.display(), vec!["sam", "foo", "bar", "   ", "baz"]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![co!("s"), co!("a"), co!("m"),],
+                vec![
+                    co!("f", fg = "red"),
+                    co!("o", fg = "red"),
+                    co!("o", fg = "red"),
+                ],
+                vec![
+                    co!("b", fg = "red"),
+                    co!("a", fg = "red"),
+                    co!("r", fg = "red"),
+                ],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![co!("b"), co!("a"), co!("z"),],
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_multiple_lines_with_margins() {
+        let mut screen = Screen::new(3, 5);
+        update(
+            &mut screen,
+            vec!["sam", "is ", "foo", "bar", "baz"],
+            vec![2, 3],
+        );
+        screen.set_margins(Some(1), Some(4));
+        screen.cursor.y = 1;
+        screen.delete_lines(Some(2));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(screen.display(), vec!["sam", "bar", "   ", "   ", "baz"]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![co!("s"), co!("a"), co!("m"),],
+                vec![
+                    co!("b", fg = "red"),
+                    co!("a", fg = "red"),
+                    co!("r", fg = "red"),
+                ],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![co!("b"), co!("a"), co!("z"),],
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_lines_overflow() {
+        let mut screen = Screen::new(3, 5);
+        update(
+            &mut screen,
+            vec!["sam", "is ", "foo", "bar", "baz"],
+            vec![2, 3],
+        );
+        screen.set_margins(Some(1), Some(4));
+        screen.cursor.y = 1;
+        screen.delete_lines(Some(5));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        assert_eq!(screen.display(), vec!["sam", "   ", "   ", "   ", "baz"]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![co!("s"), co!("a"), co!("m"),],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![co!("b"), co!("a"), co!("z"),],
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_lines_outside_margins() {
+        let mut screen = Screen::new(3, 5);
+        update(
+            &mut screen,
+            vec!["sam", "is ", "foo", "bar", "baz"],
+            vec![2, 3],
+        );
+        screen.set_margins(Some(2), Some(4));
+        screen.cursor.y = 0;
+        screen.delete_lines(Some(5));
+
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["sam", "is ", "foo", "bar", "baz"]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![co!("s"), co!("a"), co!("m"),],
+                vec![co!("i"), co!("s"), co!(" "),],
+                vec![
+                    co!("f", fg = "red"),
+                    co!("o", fg = "red"),
+                    co!("o", fg = "red"),
+                ],
+                vec![
+                    co!("b", fg = "red"),
+                    co!("a", fg = "red"),
+                    co!("r", fg = "red"),
+                ],
+                vec![co!("b"), co!("a"), co!("z"),],
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_lines_default_count() {
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![1]);
+
+        // First deletion
+        screen.delete_lines(None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["is ", "foo", "   "]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![
+                    co!("i", fg = "red"),
+                    co!("s", fg = "red"),
+                    co!(" ", fg = "red"),
+                ],
+                vec![co!("f"), co!("o"), co!("o"),],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+            ]
+        );
+
+        // Second deletion
+        screen.delete_lines(None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["foo", "   ", "   "]);
+        assert_eq!(
+            tolist(&screen),
+            vec![
+                vec![co!("f"), co!("o"), co!("o"),],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+                vec![
+                    screen.default_char(),
+                    screen.default_char(),
+                    screen.default_char()
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_characters_normal() {
+        let mut screen = Screen::new(3, 4);
+        update(&mut screen, vec!["sam", "is ", "foo", "bar"], vec![0]);
+
+        // Save cursor position
+        let cursor_x = screen.cursor.x;
+        let cursor_y = screen.cursor.y;
+
+        screen.insert_characters(Some(2));
+
+        // Check cursor hasn't moved
+        assert_eq!((screen.cursor.y, screen.cursor.x), (cursor_y, cursor_x));
+
+        // Check first line
+        assert_eq!(
+            tolist(&screen)[0],
+            vec![
+                screen.default_char(),
+                screen.default_char(),
+                co!("s", fg = "red"),
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_characters_middle() {
+        let mut screen = Screen::new(3, 4);
+        update(&mut screen, vec!["sam", "is ", "foo", "bar"], vec![0]);
+
+        screen.cursor.y = 2;
+        screen.cursor.x = 1;
+        screen.insert_characters(Some(1));
+
+        assert_eq!(tolist(&screen)[2], cv![co!("f"), co!(default), co!("o")]);
+    }
+
+    #[test]
+    fn insert_characters_overflow() {
+        let mut screen = Screen::new(3, 4);
+        update(&mut screen, vec!["sam", "is ", "foo", "bar"], vec![0]);
+
+        screen.cursor.y = 3;
+        screen.cursor.x = 1;
+        screen.insert_characters(Some(10));
+
+        assert_eq!(
+            tolist(&screen)[3],
+            vec![
+                CharOpts { data: "b".to_string(), ..CharOpts::default() },
+                screen.default_char(),
+                screen.default_char(),
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_characters_edges() {
+        // a) At the last column only that cell is blanked.
+        let mut screen = Screen::new(3, 1);
+        update(&mut screen, vec!["sam"], vec![]);
+        screen.cursor.x = 2;
+        screen.insert_characters(Some(2));
+        assert_eq!(screen.display(), vec!["sa "]);
+        assert_eq!(screen.buffer[0].len(), 3);
+
+        // b) At column 0 with a count larger than the line.
+        let mut screen = Screen::new(3, 1);
+        update(&mut screen, vec!["sam"], vec![]);
+        screen.insert_characters(Some(5));
+        assert_eq!(screen.display(), vec!["   "]);
+        assert_eq!(screen.buffer[0].len(), 3);
+
+        // c) A 0 count inserts one.
+        let mut screen = Screen::new(3, 1);
+        update(&mut screen, vec!["sam"], vec![]);
+        screen.feed("\x1b[0@");
+        assert_eq!(screen.display(), vec![" sa"]);
+    }
+
+    #[test]
+    fn insert_characters_default_count() {
+        // Test with no count (should default to 1)
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![0]);
+
+        screen.cursor_position(None, None);
+        screen.insert_characters(None);
+
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![co!(default), co!("s", fg = "red"), co!("a", fg = "red"),]
+        );
+
+        // Test with explicit count of 1
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![0]);
+
+        screen.cursor_position(None, None);
+        screen.insert_characters(Some(1));
+
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![co!(default), co!("s", fg = "red"), co!("a", fg = "red")]
+        );
+    }
+
+    #[test]
+    fn delete_characters() {
+        // Basic case
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![0]);
+        screen.delete_characters(Some(2));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["m  ", "is ", "foo"]);
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![co!("m", fg = "red"), co!(default), co!(default)]
+        );
+
+        // Delete at position (2,2)
+        screen.cursor.y = 2;
+        screen.cursor.x = 2;
+        screen.delete_characters(None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
+        assert_eq!(screen.display(), vec!["m  ", "is ", "fo "]);
+
+        // Delete at position (1,1)
+        screen.cursor.y = 1;
+        screen.cursor.x = 1;
+        screen.delete_characters(Some(0));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
+        assert_eq!(screen.display(), vec!["m  ", "i  ", "fo "]);
+
+        // Extreme cases
+        // 1. Delete from middle of line
+        let mut screen = Screen::new(5, 1);
+        update(&mut screen, vec!["12345"], vec![0]);
+        screen.cursor.x = 1;
+        screen.delete_characters(Some(3));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 1));
+        assert_eq!(screen.display(), vec!["15   "]);
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!("1", fg = "red"),
+                co!("5", fg = "red"),
+                co!(default),
+                co!(default),
+                co!(default)
+            ]
+        );
+
+        // 2. Delete more than available
+        let mut screen = Screen::new(5, 1);
+        update(&mut screen, vec!["12345"], vec![0]);
+        screen.cursor.x = 2;
+        screen.delete_characters(Some(10));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+        assert_eq!(screen.display(), vec!["12   "]);
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!("1", fg = "red"),
+                co!("2", fg = "red"),
+                co!(default),
+                co!(default),
+                co!(default)
+            ]
+        );
 
-        let expected = vec![
-            vec![
-                CharOpts {
-                    data: "o".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-                CharOpts {
-                    data: "t".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-            ],
-            vec![screen.default_char(), screen.default_char()],
-        ];
-        assert_eq!(tolist(&screen), expected);
+        // 3. Delete from start
+        let mut screen = Screen::new(5, 1);
+        update(&mut screen, vec!["12345"], vec![0]);
+        screen.delete_characters(Some(4));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["5    "]);
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!("5", fg = "red"),
+                co!(default),
+                co!(default),
+                co!(default),
+                co!(default)
+            ]
+        );
+    }
 
-        // c) Test with margins
-        let mut screen = Screen::new(2, 5);
-        update(&mut screen, vec!["bo", "sh", "th", "er", "oh"], vec![1, 2]);
-        screen.set_margins(Some(2), Some(4));
-        screen.cursor.y = 3;
+    #[test]
+    fn erase_characters() {
+        // Basic case
+        let mut screen = Screen::new(3, 3);
+        update(&mut screen, vec!["sam", "is ", "foo"], vec![0]);
 
-        // First index
-        screen.index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (3, 0));
+        screen.erase_characters(Some(2));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["  m", "is ", "foo"]);
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![co!(default), co!(default), co!("m", fg = "red")]
+        );
+
+        screen.cursor.y = 2;
+        screen.cursor.x = 2;
+        screen.erase_characters(None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
+        assert_eq!(screen.display(), vec!["  m", "is ", "fo "]);
+
+        screen.cursor.y = 1;
+        screen.cursor.x = 1;
+        screen.erase_characters(Some(0));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
+        assert_eq!(screen.display(), vec!["  m", "i  ", "fo "]);
+
+        // Extreme cases
+        // 1. Erase from middle
+        let mut screen = Screen::new(5, 1);
+        update(&mut screen, vec!["12345"], vec![0]);
+        screen.cursor.x = 1;
+        screen.erase_characters(Some(3));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 1));
+        assert_eq!(screen.display(), vec!["1   5"]);
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!("1", fg = "red"),
+                co!(default),
+                co!(default),
+                co!(default),
+                co!("5", fg = "red")
+            ]
+        );
+
+        // 2. Erase more than available
+        let mut screen = Screen::new(5, 1);
+        update(&mut screen, vec!["12345"], vec![0]);
+        screen.cursor.x = 2;
+        screen.erase_characters(Some(10));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+        assert_eq!(screen.display(), vec!["12   "]);
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!("1", fg = "red"),
+                co!("2", fg = "red"),
+                co!(default),
+                co!(default),
+                co!(default)
+            ]
+        );
+
+        // 3. Erase from start
+        let mut screen = Screen::new(5, 1);
+        update(&mut screen, vec!["12345"], vec![0]);
+        screen.erase_characters(Some(4));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+        assert_eq!(screen.display(), vec!["    5"]);
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!(default),
+                co!(default),
+                co!(default),
+                co!(default),
+                co!("5", fg = "red")
+            ]
+        );
+    }
+
+    #[test]
+    fn erase_in_line() {
+        // Initial setup
+        let mut screen = Screen::new(5, 5);
+        update(
+            &mut screen,
+            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
+            vec![0],
+        );
+        screen.cursor_position(Some(1), Some(3));
+
+        // a) erase from cursor to the end of line
+        screen.erase_in_line(Some(0), None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
         assert_eq!(
             screen.display(),
             vec![
-                "bo".to_string(),
-                "th".to_string(),
-                "er".to_string(),
-                "  ".to_string(),
-                "oh".to_string()
+                "sa   ".to_string(),
+                "s foo".to_string(),
+                "but a".to_string(),
+                "re yo".to_string(),
+                "u?   ".to_string(),
+            ]
+        );
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!("s", fg = "red"),
+                co!("a", fg = "red"),
+                co!(default),
+                co!(default),
+                co!(default)
             ]
         );
 
-        let expected = vec![
-            vec![
-                CharOpts { data: "b".to_string(), ..CharOpts::default() },
-                CharOpts { data: "o".to_string(), ..CharOpts::default() },
-            ],
-            vec![
-                CharOpts {
-                    data: "t".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-                CharOpts {
-                    data: "h".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-            ],
-            vec![
-                CharOpts { data: "e".to_string(), ..CharOpts::default() },
-                CharOpts { data: "r".to_string(), ..CharOpts::default() },
-            ],
-            vec![screen.default_char(), screen.default_char()],
+        // b) erase from the beginning of the line to the cursor
+        let mut screen = Screen::new(5, 5);
+        update(
+            &mut screen,
+            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
+            vec![0],
+        );
+        screen.cursor_position(Some(1), Some(3));
+        screen.erase_in_line(Some(1), None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+        assert_eq!(
+            screen.display(),
             vec![
-                CharOpts { data: "o".to_string(), ..CharOpts::default() },
-                CharOpts { data: "h".to_string(), ..CharOpts::default() },
-            ],
-        ];
-        assert_eq!(tolist(&screen), expected);
+                "    i".to_string(),
+                "s foo".to_string(),
+                "but a".to_string(),
+                "re yo".to_string(),
+                "u?   ".to_string(),
+            ]
+        );
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!(default),
+                co!(default),
+                co!(default),
+                co!(" ", fg = "red"),
+                co!("i", fg = "red")
+            ]
+        );
 
-        // Second index
-        screen.index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (3, 0));
+        // c) erase the entire line
+        let mut screen = Screen::new(5, 5);
+        update(
+            &mut screen,
+            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
+            vec![0],
+        );
+        screen.cursor_position(Some(1), Some(3));
+        screen.erase_in_line(Some(2), None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
         assert_eq!(
             screen.display(),
             vec![
-                "bo".to_string(),
-                "er".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "oh".to_string()
+                "     ".to_string(),
+                "s foo".to_string(),
+                "but a".to_string(),
+                "re yo".to_string(),
+                "u?   ".to_string(),
+            ]
+        );
+        assert_eq!(
+            tolist(&screen)[0],
+            cv![
+                co!(default),
+                co!(default),
+                co!(default),
+                co!(default),
+                co!(default)
             ]
         );
+    }
 
-        let expected = vec![
-            vec![
-                CharOpts { data: "b".to_string(), ..CharOpts::default() },
-                CharOpts { data: "o".to_string(), ..CharOpts::default() },
-            ],
-            vec![
-                CharOpts { data: "e".to_string(), ..CharOpts::default() },
-                CharOpts { data: "r".to_string(), ..CharOpts::default() },
-            ],
-            vec![screen.default_char(), screen.default_char()],
-            vec![screen.default_char(), screen.default_char()],
-            vec![
-                CharOpts { data: "o".to_string(), ..CharOpts::default() },
-                CharOpts { data: "h".to_string(), ..CharOpts::default() },
-            ],
-        ];
-        assert_eq!(tolist(&screen), expected);
+    #[test]
+    fn erase_in_display() {
+        // Initial setup
+        let mut screen = Screen::new(5, 5);
+        update(
+            &mut screen,
+            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
+            vec![2, 3],
+        );
+        screen.cursor_position(Some(3), Some(3));
 
-        // Third index
-        screen.index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (3, 0));
+        // a) erase from cursor to the end of display
+        screen.erase_in_display(Some(0), None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
         assert_eq!(
             screen.display(),
             vec![
-                "bo".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "oh".to_string()
+                "sam i".to_string(),
+                "s foo".to_string(),
+                "bu   ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
             ]
         );
-
-        let expected = vec![
-            vec![
-                CharOpts { data: "b".to_string(), ..CharOpts::default() },
-                CharOpts { data: "o".to_string(), ..CharOpts::default() },
-            ],
-            vec![screen.default_char(), screen.default_char()],
-            vec![screen.default_char(), screen.default_char()],
-            vec![screen.default_char(), screen.default_char()],
+        assert_eq!(
+            tolist(&screen)[2..],
             vec![
-                CharOpts { data: "o".to_string(), ..CharOpts::default() },
-                CharOpts { data: "h".to_string(), ..CharOpts::default() },
-            ],
-        ];
-        assert_eq!(tolist(&screen), expected);
+                cv![
+                    co!("b", fg = "red"),
+                    co!("u", fg = "red"),
+                    co!(default),
+                    co!(default),
+                    co!(default)
+                ],
+                cv![
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(default)
+                ],
+                cv![
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(default)
+                ]
+            ]
+        );
 
-        // Fourth index (nothing should change)
-        screen.index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (3, 0));
+        // b) erase from beginning of display to cursor
+        let mut screen = Screen::new(5, 5);
+        update(
+            &mut screen,
+            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
+            vec![2, 3],
+        );
+        screen.cursor_position(Some(3), Some(3));
+        screen.erase_in_display(Some(1), None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
         assert_eq!(
             screen.display(),
             vec![
-                "bo".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "oh".to_string()
+                "     ".to_string(),
+                "     ".to_string(),
+                "    a".to_string(),
+                "re yo".to_string(),
+                "u?   ".to_string(),
             ]
         );
-        assert_eq!(tolist(&screen), expected);
-    }
-
-    #[test]
-    fn reverse_index() {
-        // a) Test basic reverse index
-        let mut screen = Screen::new(2, 2);
-        update(&mut screen, vec!["wo", "ot"], vec![0]);
-
-        // Test reverse indexing on first row
-        screen.reverse_index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-
-        let expected = vec![
-            vec![screen.default_char(), screen.default_char()],
+        assert_eq!(
+            tolist(&screen)[..3],
             vec![
-                CharOpts {
-                    data: "w".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-                CharOpts {
-                    data: "o".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-            ],
-        ];
-        assert_eq!(tolist(&screen), expected);
-
-        // b) Test second reverse index
-        screen.reverse_index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-
-        let expected = vec![
-            vec![screen.default_char(), screen.default_char()],
-            vec![screen.default_char(), screen.default_char()],
-        ];
-        assert_eq!(tolist(&screen), expected);
-
-        // c) Test with margins
-        let mut screen = Screen::new(2, 5);
-        update(&mut screen, vec!["bo", "sh", "th", "er", "oh"], vec![2, 3]);
-        screen.set_margins(Some(2), Some(4));
-        screen.cursor.y = 1;
+                cv![
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(default)
+                ],
+                cv![
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(default)
+                ],
+                cv![
+                    co!(default),
+                    co!(default),
+                    co!(default),
+                    co!(" ", fg = "red"),
+                    co!("a", fg = "red")
+                ],
+            ]
+        );
 
-        // First reverse index
-        screen.reverse_index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        // c) erase whole display
+        screen.erase_in_display(Some(2), None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
         assert_eq!(
             screen.display(),
             vec![
-                "bo".to_string(),
-                "  ".to_string(),
-                "sh".to_string(),
-                "th".to_string(),
-                "oh".to_string()
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
             ]
         );
-
-        let expected = vec![
-            vec![co!("b"), co!("o")],
-            vec![screen.default_char(), screen.default_char()],
-            vec![co!("s"), co!("h")],
-            vec![
-                CharOpts {
-                    data: "t".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-                CharOpts {
-                    data: "h".to_string(),
-                    fg: "red".to_string(),
-                    ..CharOpts::default()
-                },
-            ],
-            vec![co!("o"), co!("h")],
+        let empty_line = cv![
+            co!(default),
+            co!(default),
+            co!(default),
+            co!(default),
+            co!(default)
         ];
-        assert_eq!(tolist(&screen), expected);
-
-        // Second reverse index
-        screen.reverse_index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
         assert_eq!(
-            screen.display(),
+            tolist(&screen),
             vec![
-                "bo".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "sh".to_string(),
-                "oh".to_string()
+                empty_line.clone(),
+                empty_line.clone(),
+                empty_line.clone(),
+                empty_line.clone(),
+                empty_line.clone()
             ]
         );
 
-        let expected = vec![
-            vec![co!("b"), co!("o")],
-            vec![screen.default_char(), screen.default_char()],
-            vec![screen.default_char(), screen.default_char()],
-            vec![co!("s"), co!("h")],
-            vec![co!("o"), co!("h")],
-        ];
-        assert_eq!(tolist(&screen), expected);
+        // d) erase with private mode
+        let mut screen = Screen::new(5, 5);
+        update(
+            &mut screen,
+            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
+            vec![2, 3],
+        );
+        screen.erase_in_display(Some(3), Some(true));
+        assert_eq!(
+            screen.display(),
+            vec![
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+            ]
+        );
 
-        // Third reverse index
-        screen.reverse_index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        // e) erase with mode 3
+        let mut screen = Screen::new(5, 5);
+        update(
+            &mut screen,
+            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
+            vec![2, 3],
+        );
+        screen.erase_in_display(Some(3), None);
         assert_eq!(
             screen.display(),
             vec![
-                "bo".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "oh".to_string()
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
             ]
         );
 
-        let expected = vec![
-            vec![co!("b"), co!("o")],
-            vec![screen.default_char(), screen.default_char()],
-            vec![screen.default_char(), screen.default_char()],
-            vec![screen.default_char(), screen.default_char()],
-            vec![co!("o"), co!("h")],
-        ];
-        assert_eq!(tolist(&screen), expected);
-
-        // Fourth reverse index (nothing should change)
-        screen.reverse_index();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        // f) erase with mode 3 and private mode
+        let mut screen = Screen::new(5, 5);
+        update(
+            &mut screen,
+            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
+            vec![2, 3],
+        );
+        screen.erase_in_display(Some(3), Some(true));
         assert_eq!(
             screen.display(),
             vec![
-                "bo".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "  ".to_string(),
-                "oh".to_string()
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
             ]
         );
-        assert_eq!(tolist(&screen), expected);
     }
 
     #[test]
-    fn linefeed() {
-        // Setup screen
-        let mut screen = Screen::new(2, 2);
-        update(&mut screen, vec!["bo", "sh"], vec![]);
-        screen.set_mode(&[LNM], false);
+    fn cursor_up() {
+        let mut screen = Screen::new(10, 10);
 
-        // a) Test with LNM on
-        assert!(screen.mode.contains(&LNM));
-        screen.cursor.x = 1;
-        screen.cursor.y = 0;
-        screen.linefeed();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
+        // Moving the cursor up at the top doesn't do anything
+        screen.cursor_up(Some(1));
+        assert_eq!(screen.cursor.y, 0);
 
-        // b) Test with LNM off
-        screen.reset_mode(&[LNM], false);
-        screen.cursor.x = 1;
-        screen.cursor.y = 0;
-        screen.linefeed();
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
+        screen.cursor.y = 1;
+
+        // Moving the cursor past the top moves it to the top
+        screen.cursor_up(Some(10));
+        assert_eq!(screen.cursor.y, 0);
+
+        screen.cursor.y = 5;
+        // Can move the cursor more than one up
+        screen.cursor_up(Some(3));
+        assert_eq!(screen.cursor.y, 2);
     }
 
     #[test]
-    fn linefeed_margins() {
-        // See issue #63 on GitHub.
-        let mut screen = Screen::new(80, 24);
-        screen.set_margins(Some(3), Some(27));
-        screen.cursor_position(None, None);
+    fn cursor_down() {
+        let mut screen = Screen::new(10, 10);
+
+        // Moving the cursor down at the bottom doesn't do anything
+        screen.cursor.y = 9;
+        screen.cursor_down(Some(1));
+        assert_eq!(screen.cursor.y, 9);
+
+        screen.cursor.y = 8;
+
+        // Moving the cursor past the bottom moves it to the bottom
+        screen.cursor_down(Some(10));
+        assert_eq!(screen.cursor.y, 9);
+
+        screen.cursor.y = 5;
+        // Can move the cursor more than one down
+        screen.cursor_down(Some(3));
+        assert_eq!(screen.cursor.y, 8);
+    }
+
+    #[test]
+    fn cursor_back() {
+        let mut screen = Screen::new(10, 10);
+
+        // Moving the cursor left at the margin doesn't do anything
+        screen.cursor.x = 0;
+        screen.cursor_back(Some(1));
+        assert_eq!(screen.cursor.x, 0);
+
+        screen.cursor.x = 3;
+
+        // Moving the cursor past the left margin moves it to the left margin
+        screen.cursor_back(Some(10));
+        assert_eq!(screen.cursor.x, 0);
+
+        screen.cursor.x = 5;
+        // Can move the cursor more than one back
+        screen.cursor_back(Some(3));
+        assert_eq!(screen.cursor.x, 2);
+    }
+
+    #[test]
+    fn cursor_back_last_column() {
+        let mut screen = Screen::new(13, 1);
+        screen.draw("Hello, world!");
+        assert_eq!(screen.cursor.x, screen.columns - 1);
+
+        screen.cursor_back(Some(5));
+        assert_eq!(screen.cursor.x, (screen.columns - 1) - 5);
+    }
+
+    #[test]
+    fn cursor_back_reverse_wrap() {
+        let mut screen = Screen::new(3, 2);
+        screen.set_mode(&[45], true);
+        assert!(screen.mode.contains(&REVERSE_WRAP));
+
+        // At the top-left corner there is nowhere to wrap to.
+        screen.backspace();
         assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        screen.linefeed();
+
+        screen.cursor_position(Some(2), Some(1));
+        screen.backspace();
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+
+        // A count carries on across line boundaries.
+        screen.cursor_position(Some(2), Some(1));
+        screen.cursor_back(Some(2));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 1));
+        screen.cursor_position(Some(2), Some(2));
+        screen.cursor_back(Some(3));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 1));
+        screen.cursor_back(Some(9));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
+
+        // Without the mode the cursor stays at the left margin.
+        screen.reset_mode(&[45], true);
+        screen.cursor_position(Some(2), Some(1));
+        screen.backspace();
         assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
     }
 
     #[test]
-    fn tabstops() {
+    fn cursor_forward() {
         let mut screen = Screen::new(10, 10);
 
-        // Check initial tabstops
-        assert_eq!(screen.tabstops, {
-            let mut stops = HashSet::new();
-            stops.insert(8);
-            stops
-        });
-
-        // Clear tabstops
-        screen.clear_tab_stop(Some(3));
-        assert!(screen.tabstops.is_empty());
+        // Moving the cursor right at the margin doesn't do anything
+        screen.cursor.x = 9;
+        screen.cursor_forward(Some(1));
+        assert_eq!(screen.cursor.x, 9);
 
-        // Set new tabstops
-        screen.cursor.x = 1;
-        screen.set_tab_stop();
+        // Moving the cursor past the right margin moves it to the right margin
         screen.cursor.x = 8;
-        screen.set_tab_stop();
+        screen.cursor_forward(Some(10));
+        assert_eq!(screen.cursor.x, 9);
+
+        // Can move the cursor more than one forward
+        screen.cursor.x = 5;
+        screen.cursor_forward(Some(3));
+        assert_eq!(screen.cursor.x, 8);
+    }
+
+    #[test]
+    fn cursor_position() {
+        let mut screen = Screen::new(10, 10);
+
+        // a) testing that we expect 1-indexed values
+        screen.cursor_position(Some(5), Some(10));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (4, 9));
+
+        // b) but (0, 0) is also accepted and should be the same as (1, 1)
+        screen.cursor_position(Some(0), Some(10));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 9));
+
+        // c) moving outside the margins constrains to within the screen bounds
+        screen.cursor_position(Some(100), Some(5));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (9, 4));
+
+        screen.cursor_position(Some(5), Some(100));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (4, 9));
+
+        // d) DECOM on
+        screen.set_margins(Some(5), Some(9));
+        screen.set_mode(&[DECOM], false);
+        screen.cursor_position(None, None);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (4, 0));
+
+        screen.cursor_position(Some(2), Some(0));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (5, 0));
+
+        // Note that cursor position doesn't change
+        screen.cursor_position(Some(10), Some(0));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (5, 0));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn unicode() {
+        let screen = Arc::new(Mutex::new(Screen::new(4, 2)));
+        let mut parser = Parser::new(screen.clone());
+
+        parser.feed("тест".to_string());
+
+        assert_eq!(
+            screen.lock().unwrap().display(),
+            vec!["тест".to_string(), "    ".to_string()]
+        );
+    }
+
+    #[test]
+    fn alignment_display() {
+        let mut screen = Screen::new(5, 5);
+        screen.set_mode(&[LNM], false);
+
+        // Draw initial content
+        screen.draw("a");
+        screen.linefeed();
+        screen.linefeed();
+        screen.draw("b");
+
+        assert_eq!(
+            screen.display(),
+            vec![
+                "a    ".to_string(),
+                "     ".to_string(),
+                "b    ".to_string(),
+                "     ".to_string(),
+                "     ".to_string(),
+            ]
+        );
+
+        // Test alignment display
+        screen.alignment_display();
 
-        // Test tab behavior
-        screen.cursor.x = 0;
-        screen.tab();
-        assert_eq!(screen.cursor.x, 1);
-        screen.tab();
-        assert_eq!(screen.cursor.x, 8);
-        screen.tab();
-        assert_eq!(screen.cursor.x, 9);
-        screen.tab();
-        assert_eq!(screen.cursor.x, 9);
+        assert_eq!(
+            screen.display(),
+            vec![
+                "EEEEE".to_string(),
+                "EEEEE".to_string(),
+                "EEEEE".to_string(),
+                "EEEEE".to_string(),
+                "EEEEE".to_string(),
+            ]
+        );
     }
 
     #[test]
-    fn clear_tabstops() {
+    fn set_margins() {
         let mut screen = Screen::new(10, 10);
-        screen.clear_tab_stop(Some(3));
-
-        // a) Clear a tabstop at current cursor location
-        screen.cursor.x = 1;
-        screen.set_tab_stop();
-        screen.cursor.x = 5;
-        screen.set_tab_stop();
-        screen.clear_tab_stop(None); // Clear at current cursor position (5)
-
-        // Check only tabstop at 1 remains
-        assert_eq!(screen.tabstops, {
-            let mut stops = HashSet::new();
-            stops.insert(1);
-            stops
-        });
 
-        // Set and clear using explicit position
-        screen.set_tab_stop();
-        screen.clear_tab_stop(Some(0));
+        assert!(screen.margins.is_none());
 
-        // Check tabstop at 1 still remains
-        assert_eq!(screen.tabstops, {
-            let mut stops = HashSet::new();
-            stops.insert(1);
-            stops
-        });
+        // a) ok-case
+        screen.set_margins(Some(1), Some(5));
+        assert_eq!(screen.margins, Some(Margins { top: 0, bottom: 4 }));
 
-        // b) Clear all tabstops
-        screen.set_tab_stop();
-        screen.cursor.x = 9;
-        screen.set_tab_stop();
-        screen.clear_tab_stop(Some(3)); // 3 means clear all
+        // b) one of the margins is out of bounds
+        screen.set_margins(Some(100), Some(10));
+        assert_ne!(screen.margins, Some(Margins { top: 99, bottom: 9 }));
+        assert_eq!(screen.margins, Some(Margins { top: 0, bottom: 4 }));
 
-        // Check all tabstops are cleared
-        assert!(screen.tabstops.is_empty());
+        // c) no margins provided -- reset to full screen
+        screen.set_margins(None, None);
+        assert!(screen.margins.is_none());
     }
 
     #[test]
-    fn backspace() {
-        let mut screen = Screen::new(2, 2);
-        assert_eq!(screen.cursor.x, 0);
+    fn set_margins_zero() {
+        // See https://github.com/selectel/pyte/issues/61
+        let mut screen = Screen::new(80, 24);
 
-        // Test backspace at left edge
-        screen.backspace();
-        assert_eq!(screen.cursor.x, 0);
+        screen.set_margins(Some(1), Some(5));
+        assert_eq!(screen.margins, Some(Margins { top: 0, bottom: 4 }));
 
-        // Test backspace from position 1
-        screen.cursor.x = 1;
-        screen.backspace();
-        assert_eq!(screen.cursor.x, 0);
+        screen.set_margins(Some(0), None);
+        assert!(screen.margins.is_none());
     }
 
     #[test]
-    fn save_cursor() {
-        // a) Test cursor position
-        let mut screen = Screen::new(10, 10);
-        screen.save_cursor();
-        screen.cursor.x = 3;
-        screen.cursor.y = 5;
-        screen.save_cursor();
-        screen.cursor.x = 4;
-        screen.cursor.y = 4;
+    fn index_outside_margins() {
+        let mut screen = Screen::new(2, 6);
+        screen.feed("a\r\nb\r\nc\r\nd\r\ne\r\nf");
+        screen.set_margins(Some(2), Some(3));
 
-        // Restore and check last saved position
-        screen.restore_cursor();
-        assert_eq!(screen.cursor.x, 3);
+        // Below the region LF moves down without scrolling and stops at
+        // the last line.
+        screen.cursor_position(Some(5), Some(1));
+        screen.linefeed();
+        assert_eq!(screen.cursor.y, 5);
+        screen.linefeed();
         assert_eq!(screen.cursor.y, 5);
+        assert_eq!(screen.display(), vec!["a ", "b ", "c ", "d ", "e ", "f "]);
 
-        // Restore and check initial position
-        screen.restore_cursor();
-        assert_eq!(screen.cursor.x, 0);
+        // Above it RI moves up to the first line.
+        screen.cursor_position(Some(1), Some(1));
+        screen.cursor_down(None);
+        assert_eq!(screen.cursor.y, 1);
+        screen.cursor.y = 0;
+        screen.reverse_index();
         assert_eq!(screen.cursor.y, 0);
+        screen.cursor_up(Some(3));
+        assert_eq!(screen.cursor.y, 0);
+        assert_eq!(screen.display(), vec!["a ", "b ", "c ", "d ", "e ", "f "]);
 
-        // b) Test modes
-        let mut screen = Screen::new(10, 10);
-        screen.set_mode(&[DECAWM, DECOM], false);
-        screen.save_cursor();
-
-        screen.reset_mode(&[DECAWM], false);
-
-        screen.restore_cursor();
-        assert!(screen.mode.contains(&DECAWM));
-        assert!(screen.mode.contains(&DECOM));
-
-        // c) Test attributes
-        let mut screen = Screen::new(10, 10);
-        screen.select_graphic_rendition(&[4]); // underscore
-        screen.save_cursor();
-        screen.select_graphic_rendition(&[24]); // no underscore
-
-        assert_eq!(screen.cursor.attr, screen.default_char());
-
-        screen.restore_cursor();
-
-        assert_ne!(screen.cursor.attr, screen.default_char());
-        assert_eq!(
-            screen.cursor.attr,
-            CharOpts { underscore: true, ..CharOpts::default() }
-        );
+        // Inside it the margins still stop the cursor.
+        screen.cursor_position(Some(3), Some(1));
+        screen.cursor_down(Some(5));
+        assert_eq!(screen.cursor.y, 2);
     }
 
     #[test]
-    fn restore_cursor_with_none_saved() {
+    fn set_margins_homes_cursor() {
         let mut screen = Screen::new(10, 10);
         screen.set_mode(&[DECOM], false);
-        screen.cursor.x = 5;
-        screen.cursor.y = 5;
+        screen.cursor_position(Some(5), Some(5));
 
-        screen.restore_cursor();
+        // Under DECOM home is the top left of the scrolling region.
+        screen.set_margins(Some(3), Some(6));
+        assert_eq!((screen.cursor.x, screen.cursor.y), (0, 2));
 
-        // Check cursor position resets to (0,0)
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        // Check DECOM mode is cleared
-        assert!(!screen.mode.contains(&DECOM));
+        screen.cursor_position(Some(2), Some(4));
+        assert_eq!((screen.cursor.x, screen.cursor.y), (3, 3));
+
+        // Resetting the margins homes it too.
+        screen.set_margins(None, None);
+        assert_eq!((screen.cursor.x, screen.cursor.y), (0, 0));
+
+        screen.reset_mode(&[DECOM], false);
+        screen.cursor_position(Some(5), Some(5));
+        screen.set_margins(Some(3), Some(6));
+        assert_eq!((screen.cursor.x, screen.cursor.y), (0, 0));
     }
 
     #[test]
-    fn restore_cursor_out_of_bounds() {
+    fn hide_cursor() {
         let mut screen = Screen::new(10, 10);
 
-        // a) Test with origin mode off
-        screen.cursor_position(Some(5), Some(5));
-        screen.save_cursor();
-        screen.resize(Some(3), Some(3));
-        screen.reset();
-        screen.restore_cursor();
-
-        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
+        // DECTCEM is set by default
+        assert!(screen.mode.contains(&DECTCEM));
+        assert!(!screen.cursor.hidden);
 
-        // b) Test with origin mode on
-        screen.resize(Some(10), Some(10));
-        screen.cursor_position(Some(8), Some(8));
-        screen.save_cursor();
-        screen.resize(Some(5), Some(5));
-        screen.reset();
-        screen.set_mode(&[DECOM], false);
-        screen.set_margins(Some(2), Some(3));
-        screen.restore_cursor();
+        // a) resetting DECTCEM hides the cursor
+        screen.reset_mode(&[DECTCEM], false);
+        assert!(screen.cursor.hidden);
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 4));
+        // b) setting DECTCEM shows the cursor again
+        screen.set_mode(&[DECTCEM], false);
+        assert!(!screen.cursor.hidden);
     }
 
     #[test]
-    fn insert_lines_basic() {
-        // Basic insert without margins
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![1]);
-        screen.insert_lines(None);
+    #[cfg(feature = "std")]
+    fn hyperlinks() {
+        let screen = Arc::new(Mutex::new(Screen::new(6, 2)));
+        let mut parser = Parser::new(screen.clone());
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["   ", "sam", "is "]);
+        parser.feed("\x1b]8;;http://a.example\x07ab\x1b]8;;\x07 ".to_string());
+        parser.feed("\x1b]8;id=1;http://b.example\x07c\r\nd\x1b]8;;\x07e".to_string());
+
+        let screen = screen.lock().unwrap();
         assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![co!("s"), co!("a"), co!("m"),],
-                vec![
-                    co!("i", fg = "red"),
-                    co!("s", fg = "red"),
-                    co!(" ", fg = "red"),
-                ],
-            ]
+            screen.display(),
+            vec!["ab c  ".to_string(), "de    ".to_string()]
         );
-    }
-
-    #[test]
-    fn insert_multiple_lines() {
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![1]);
-        screen.insert_lines(Some(2));
-
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["   ", "   ", "sam"]);
         assert_eq!(
-            tolist(&screen),
+            screen.hyperlinks(),
             vec![
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![co!("s"), co!("a"), co!("m"),],
+                ("http://a.example".to_string(), vec![(0, 0), (1, 0)]),
+                ("http://b.example".to_string(), vec![(3, 0), (0, 1)]),
             ]
         );
     }
 
     #[test]
-    fn insert_lines_with_margins() {
-        let mut screen = Screen::new(3, 5);
-        update(
-            &mut screen,
-            vec!["sam", "is ", "foo", "bar", "baz"],
-            vec![2, 3],
-        );
-        screen.set_margins(Some(1), Some(4));
-        screen.cursor.y = 1;
-        screen.insert_lines(Some(1));
+    fn screen_set_icon_name_title() {
+        let mut screen = Screen::new(10, 1);
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
-        assert_eq!(screen.display(), vec!["sam", "   ", "is ", "foo", "baz"]);
-        assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![co!("s"), co!("a"), co!("m"),],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![co!("i"), co!("s"), co!(" "),],
-                vec![
-                    co!("f", fg = "red"),
-                    co!("o", fg = "red"),
-                    co!("o", fg = "red"),
-                ],
-                vec![co!("b"), co!("a"), co!("z"),],
-            ]
-        );
+        let text = "±";
+        screen.set_icon_name(text);
+        assert_eq!(screen.icon_name, text);
+
+        screen.set_title(text);
+        assert_eq!(screen.title, text);
     }
 
     #[test]
-    fn insert_lines_limited_margins() {
-        let mut screen = Screen::new(3, 5);
-        update(
-            &mut screen,
-            vec!["sam", "is ", "foo", "bar", "baz"],
-            vec![2, 3],
-        );
-        screen.set_margins(Some(1), Some(3));
-        screen.cursor.y = 1;
-        screen.insert_lines(Some(1));
+    #[cfg(feature = "std")]
+    fn report_device_status() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 10);
+        screen.set_reporter(reporter.clone());
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
-        assert_eq!(screen.display(), vec!["sam", "   ", "is ", "bar", "baz"]);
+        // a) noop
+        screen.report_device_status(Some(42), None);
+        assert!(reporter.is_empty());
 
-        screen.insert_lines(Some(2));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
-        assert_eq!(screen.display(), vec!["sam", "   ", "   ", "bar", "baz"]);
-    }
+        // b) terminal status
+        screen.report_device_status(Some(5), None);
+        assert_eq!(reporter.take(), vec!["\x1B[0n".to_string()]);
 
-    #[test]
-    fn insert_lines_overflow() {
-        // Test inserting more lines than available within margins
-        let mut screen = Screen::new(3, 5);
-        update(
-            &mut screen,
-            vec!["sam", "is ", "foo", "bar", "baz"],
-            vec![2, 3],
-        );
-        screen.set_margins(Some(2), Some(4));
-        screen.cursor.y = 1;
-        screen.insert_lines(Some(20));
+        // c) cursor position, DECOM off
+        screen.cursor_to_column(Some(5));
+        screen.report_device_status(Some(6), None);
+        assert_eq!(reporter.take(), vec!["\x1B[1;5R".to_string()]);
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
-        assert_eq!(screen.display(), vec!["sam", "   ", "   ", "   ", "baz"]);
+        // d) cursor position, DECOM on
+        screen.cursor_position(None, None);
+        screen.set_margins(Some(5), Some(9));
+        screen.set_mode(&[DECOM], false);
+        screen.cursor_to_line(Some(5));
+        screen.report_device_status(Some(6), None);
+        assert_eq!(reporter.take(), vec!["\x1B[5;1R".to_string()]);
     }
 
     #[test]
-    fn insert_lines_outside_margins() {
-        // Test inserting when cursor is outside margins
-        let mut screen = Screen::new(3, 5);
-        update(
-            &mut screen,
-            vec!["sam", "is ", "foo", "bar", "baz"],
-            vec![2, 3],
-        );
-        screen.set_margins(Some(2), Some(4));
-        screen.insert_lines(Some(5));
+    #[cfg(feature = "std")]
+    fn extended_cursor_position_report() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 10);
+        screen.set_reporter(reporter.clone());
+        screen.feed("\x1b[3;7H");
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["sam", "is ", "foo", "bar", "baz"]);
+        screen.feed("\x1b[?6n");
+        assert_eq!(reporter.take(), vec!["\x1B[?3;7;1R".to_string()]);
+        screen.feed("\x1b[6n");
+        assert_eq!(reporter.take(), vec!["\x1B[3;7R".to_string()]);
+
+        // Relative to the scrolling region under DECOM.
+        screen.set_margins(Some(2), Some(9));
+        screen.set_mode(&[DECOM], false);
+        screen.feed("\x1b[4;2H\x1b[?6n");
+        assert_eq!(reporter.take(), vec!["\x1B[?4;2;1R".to_string()]);
+
+        // Only the cursor position has an extended form.
+        screen.feed("\x1b[?5n");
+        assert!(reporter.is_empty());
     }
 
     #[test]
-    fn delete_lines_basic() {
-        // Test basic line deletion without margins
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![1]);
-        screen.delete_lines(None);
+    #[cfg(feature = "std")]
+    fn window_size_reports() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(80, 24);
+        screen.set_reporter(reporter.clone());
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["is ", "foo", "   "]);
-        assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![
-                    co!("i", fg = "red"),
-                    co!("s", fg = "red"),
-                    co!(" ", fg = "red"),
-                ],
-                vec![co!("f"), co!("o"), co!("o"),],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-            ]
-        );
+        screen.feed("\x1b[18t");
+        assert_eq!(reporter.take(), vec!["\x1B[8;24;80t".to_string()]);
+
+        screen.feed("\x1b[14t");
+        assert_eq!(reporter.take(), vec!["\x1B[4;384;640t".to_string()]);
+
+        screen.cell_size = (9, 18);
+        screen.feed("\x1b[14t");
+        assert_eq!(reporter.take(), vec!["\x1B[4;432;720t".to_string()]);
+
+        // Resizing the window isn't a report, nor is the private form.
+        screen.feed("\x1b[8;40;100t\x1b[1t\x1b[?18t");
+        assert!(reporter.is_empty());
+        assert_eq!((screen.columns, screen.lines), (80, 24));
     }
 
     #[test]
-    fn delete_lines_with_margins() {
-        let mut screen = Screen::new(3, 5);
-        update(
-            &mut screen,
-            vec!["sam", "is ", "foo", "bar", "baz"],
-            vec![2, 3],
-        );
-        screen.set_margins(Some(1), Some(4));
-        screen.cursor.y = 1;
-        screen.delete_lines(Some(1));
+    #[cfg(feature = "std")]
+    fn dynamic_colors() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 1);
+        screen.set_reporter(reporter.clone());
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
-        assert_eq!(screen.display(), vec!["sam", "foo", "bar", "   ", "baz"]);
+        screen.feed("\x1b]11;?\x07");
         assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![co!("s"), co!("a"), co!("m"),],
-                vec![
-                    co!("f", fg = "red"),
-                    co!("o", fg = "red"),
-                    co!("o", fg = "red"),
-                ],
-                vec![
-                    co!("b", fg = "red"),
-                    co!("a", fg = "red"),
-                    co!("r", fg = "red"),
-                ],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![co!("b"), co!("a"), co!("z"),],
-            ]
+            reporter.take(),
+            vec!["\x1B]11;rgb:0000/0000/0000\x1B\\".to_string()]
         );
-    }
 
-    #[test]
-    fn delete_multiple_lines_with_margins() {
-        let mut screen = Screen::new(3, 5);
-        update(
-            &mut screen,
-            vec!["sam", "is ", "foo", "bar", "baz"],
-            vec![2, 3],
+        screen.feed("\x1b]11;rgb:ffff/ffff/dddd\x1b\\\x1b]11;?\x1b\\");
+        assert_eq!(screen.default_bg_color, "rgb:ffff/ffff/dddd");
+        assert_eq!(
+            reporter.take(),
+            vec!["\x1B]11;rgb:ffff/ffff/dddd\x1B\\".to_string()]
         );
-        screen.set_margins(Some(1), Some(4));
-        screen.cursor.y = 1;
-        screen.delete_lines(Some(2));
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
-        assert_eq!(screen.display(), vec!["sam", "bar", "   ", "   ", "baz"]);
+        // Further values set the following colors, all of them kept in
+        // the `rgb:` form.
+        screen.feed("\x1b]10;#f00;rgb:8/80/800;?\x07");
+        assert_eq!(screen.default_fg_color, "rgb:f000/0000/0000");
+        assert_eq!(screen.default_bg_color, "rgb:8888/8080/8008");
         assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![co!("s"), co!("a"), co!("m"),],
-                vec![
-                    co!("b", fg = "red"),
-                    co!("a", fg = "red"),
-                    co!("r", fg = "red"),
-                ],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![co!("b"), co!("a"), co!("z"),],
-            ]
+            reporter.take(),
+            vec!["\x1B]12;rgb:ffff/ffff/ffff\x1B\\".to_string()]
         );
+        screen.feed("\x1b]11;#123456\x07");
+        assert_eq!(screen.default_bg_color, "rgb:1200/3400/5600");
+        assert_eq!((screen.title.as_str(), screen.icon_name.as_str()), ("", ""));
+
+        // Colors which can't be parsed are ignored.
+        for color in [
+            "red",
+            "#12345",
+            "rgb:1/2",
+            "rgb:12345/0/0",
+            "rgb:x/0/0",
+            "#ééé",
+        ] {
+            screen.feed(&format!("\x1b]11;{}\x07", color));
+            assert_eq!(screen.default_bg_color, "rgb:1200/3400/5600", "{}", color);
+        }
+
+        // `OSC 110`, `OSC 111` and `OSC 112` restore the defaults.
+        screen.feed("\x1b]10;#000\x07\x1b]111\x07");
+        assert_eq!(screen.default_bg_color, "rgb:0000/0000/0000");
+        assert_eq!(screen.default_fg_color, "rgb:0000/0000/0000");
+        screen.feed("\x1b]110\x07\x1b]12;#000\x07\x1b]112;\x07");
+        assert_eq!(screen.default_fg_color, "rgb:ffff/ffff/ffff");
+        assert_eq!(screen.cursor_color, "rgb:ffff/ffff/ffff");
+        assert_eq!((screen.title.as_str(), screen.icon_name.as_str()), ("", ""));
+        screen.feed("\x1b]11;#123456\x07");
+
+        screen.feed("\x1bc");
+        assert_eq!(screen.default_bg_color, "rgb:0000/0000/0000");
     }
 
     #[test]
-    fn delete_lines_overflow() {
-        let mut screen = Screen::new(3, 5);
-        update(
-            &mut screen,
-            vec!["sam", "is ", "foo", "bar", "baz"],
-            vec![2, 3],
-        );
-        screen.set_margins(Some(1), Some(4));
-        screen.cursor.y = 1;
-        screen.delete_lines(Some(5));
+    #[cfg(feature = "std")]
+    fn feed_with_output() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 2);
+        screen.set_reporter(reporter.clone());
 
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 0));
-        assert_eq!(screen.display(), vec!["sam", "   ", "   ", "   ", "baz"]);
+        assert_eq!(screen.feed_with_output("\x1b[c"), "\x1B[?6c");
         assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![co!("s"), co!("a"), co!("m"),],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![co!("b"), co!("a"), co!("z"),],
-            ]
+            screen.feed_with_output("ab\x1b[5n\x1b[6n"),
+            "\x1B[0n\x1B[1;3R"
         );
+        assert_eq!(screen.feed_with_output("cd"), "");
+        assert_eq!(screen.display(), vec!["abcd      ", "          "]);
+
+        // The replies went to the caller only, the reporter is back.
+        assert!(reporter.is_empty());
+        screen.feed("\x1b[c");
+        assert_eq!(reporter.take(), vec!["\x1B[?6c".to_string()]);
     }
 
     #[test]
-    fn delete_lines_outside_margins() {
-        let mut screen = Screen::new(3, 5);
-        update(
-            &mut screen,
-            vec!["sam", "is ", "foo", "bar", "baz"],
-            vec![2, 3],
-        );
-        screen.set_margins(Some(2), Some(4));
-        screen.cursor.y = 0;
-        screen.delete_lines(Some(5));
+    #[cfg(feature = "std")]
+    fn reset_keeps_queued_replies() {
+        let reporter = VecReporter::new();
+        let screen = Arc::new(Mutex::new(Screen::new(10, 3)));
+        screen.lock().unwrap().set_reporter(reporter.clone());
+        let mut parser = Parser::new(screen.clone());
 
+        parser.feed("ab\r\nc\x1b[6n".to_string());
+        parser.feed("\x1bc".to_string());
+
+        assert_eq!(reporter.take(), vec!["\x1B[2;2R".to_string()]);
+        let screen = screen.lock().unwrap();
         assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["sam", "is ", "foo", "bar", "baz"]);
-        assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![co!("s"), co!("a"), co!("m"),],
-                vec![co!("i"), co!("s"), co!(" "),],
-                vec![
-                    co!("f", fg = "red"),
-                    co!("o", fg = "red"),
-                    co!("o", fg = "red"),
-                ],
-                vec![
-                    co!("b", fg = "red"),
-                    co!("a", fg = "red"),
-                    co!("r", fg = "red"),
-                ],
-                vec![co!("b"), co!("a"), co!("z"),],
-            ]
-        );
+        assert_eq!(screen.display(), vec!["          ".to_string(); 3]);
     }
 
     #[test]
-    fn delete_lines_default_count() {
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![1]);
+    fn to_ansi() {
+        let mut screen = Screen::new(3, 2);
+        screen.select_graphic_rendition(&[1, 31]);
+        screen.draw("ab");
+        screen.select_graphic_rendition(&[0]);
+        screen.linefeed();
+        screen.cariage_return();
+        screen.select_graphic_rendition(&[48, 2, 1, 2, 3]);
+        screen.draw("c");
 
-        // First deletion
-        screen.delete_lines(None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["is ", "foo", "   "]);
         assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![
-                    co!("i", fg = "red"),
-                    co!("s", fg = "red"),
-                    co!(" ", fg = "red"),
-                ],
-                vec![co!("f"), co!("o"), co!("o"),],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-            ]
+            screen.to_ansi(),
+            "\x1B[0;1;31mab\x1B[0m \r\n\x1B[0;48;2;1;2;3mc\x1B[0m  \x1B[0;48;2;1;2;3m\x1B[2;2H"
         );
+    }
 
-        // Second deletion
-        screen.delete_lines(None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["foo", "   ", "   "]);
+    #[test]
+    #[cfg(feature = "std")]
+    fn to_ansi_round_trip() {
+        let source = Arc::new(Mutex::new(Screen::new(8, 3)));
+        let mut parser = Parser::new(source.clone());
+        parser.feed("\x1b[1;4;32mgreen\x1b[0m \x1b[7mrev\r\n".to_string());
+        parser.feed("\x1b[38;5;9;103mコ\x1b[0m\x1b]8;;http://a.example\x07ln".to_string());
+        parser.feed("\x1b]8;;\x07\x1b[95mx\x1b[3;3H".to_string());
+        let source = source.lock().unwrap();
+
+        let copy = Arc::new(Mutex::new(Screen::new(8, 3)));
+        Parser::new(copy.clone()).feed(source.to_ansi());
+        let copy = copy.lock().unwrap();
+
+        assert_eq!(copy.buffer, source.buffer);
+        assert_eq!(copy.cursor.attr, source.cursor.attr);
         assert_eq!(
-            tolist(&screen),
-            vec![
-                vec![co!("f"), co!("o"), co!("o"),],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-                vec![
-                    screen.default_char(),
-                    screen.default_char(),
-                    screen.default_char()
-                ],
-            ]
+            (copy.cursor.y, copy.cursor.x),
+            (source.cursor.y, source.cursor.x)
         );
     }
 
     #[test]
-    fn insert_characters_normal() {
-        let mut screen = Screen::new(3, 4);
-        update(&mut screen, vec!["sam", "is ", "foo", "bar"], vec![0]);
-
-        // Save cursor position
-        let cursor_x = screen.cursor.x;
-        let cursor_y = screen.cursor.y;
-
-        screen.insert_characters(Some(2));
-
-        // Check cursor hasn't moved
-        assert_eq!((screen.cursor.y, screen.cursor.x), (cursor_y, cursor_x));
+    fn to_html() {
+        let mut screen = Screen::new(4, 2);
+        screen.select_graphic_rendition(&[1, 31]);
+        screen.draw("ab");
+        screen.select_graphic_rendition(&[0]);
+        screen.draw("<");
+        screen.linefeed();
+        screen.cariage_return();
+        screen.select_graphic_rendition(&[4, 48, 2, 1, 2, 3]);
+        screen.draw("c");
 
-        // Check first line
         assert_eq!(
-            tolist(&screen)[0],
-            vec![
-                screen.default_char(),
-                screen.default_char(),
-                co!("s", fg = "red"),
-            ]
+            screen.to_html(),
+            "<pre><span style=\"color:#cd0000;font-weight:bold\">ab</span>&lt; \n\
+             <span style=\"background-color:#010203;text-decoration:underline\">c</span>   </pre>"
         );
     }
 
     #[test]
-    fn insert_characters_middle() {
-        let mut screen = Screen::new(3, 4);
-        update(&mut screen, vec!["sam", "is ", "foo", "bar"], vec![0]);
-
-        screen.cursor.y = 2;
-        screen.cursor.x = 1;
-        screen.insert_characters(Some(1));
+    fn text() {
+        let mut screen = Screen::new(6, 3);
+        screen.draw("ab");
+        screen.linefeed();
+        screen.cariage_return();
+        screen.draw("コン x");
 
-        assert_eq!(tolist(&screen)[2], cv![co!("f"), co!(default), co!("o")]);
+        assert_eq!(screen.text(), "ab\nコン x\n");
+        assert_eq!(screen.text_with(false), "ab    \nコン x\n      ");
     }
 
     #[test]
-    fn insert_characters_overflow() {
+    fn wrapped_lines() {
         let mut screen = Screen::new(3, 4);
-        update(&mut screen, vec!["sam", "is ", "foo", "bar"], vec![0]);
+        screen.draw("abcdefg");
+        assert_eq!(
+            (0..4).map(|y| screen.is_wrapped(y)).collect::<Vec<_>>(),
+            vec![false, true, true, false]
+        );
 
-        screen.cursor.y = 3;
-        screen.cursor.x = 1;
-        screen.insert_characters(Some(10));
+        // An explicit line feed starts a new logical line.
+        screen.cariage_return();
+        screen.linefeed();
+        screen.draw("h");
+        assert!(!screen.is_wrapped(3));
 
+        // Scrolling moves the flags along with the rows.
+        screen.index();
         assert_eq!(
-            tolist(&screen)[3],
-            vec![
-                CharOpts { data: "b".to_string(), ..CharOpts::default() },
-                screen.default_char(),
-                screen.default_char(),
-            ]
+            (0..4).map(|y| screen.is_wrapped(y)).collect::<Vec<_>>(),
+            vec![true, true, false, false]
         );
+
+        // Erasing a row clears its flag.
+        screen.cursor_position(Some(2), Some(1));
+        screen.erase_in_line(Some(2), None);
+        assert!(!screen.is_wrapped(1));
+        screen.erase_in_display(Some(2), None);
+        assert!(!screen.is_wrapped(0));
     }
 
     #[test]
-    fn insert_characters_default_count() {
-        // Test with no count (should default to 1)
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![0]);
-
-        screen.cursor_position(None, None);
-        screen.insert_characters(None);
+    fn resize_reflow() {
+        let mut screen = Screen::new(4, 3);
+        screen.select_graphic_rendition(&[31]);
+        screen.draw("abcdef");
+        screen.select_graphic_rendition(&[0]);
+        screen.cariage_return();
+        screen.linefeed();
+        screen.draw("gh");
+        assert_eq!(screen.display(), vec!["abcd", "ef  ", "gh  "]);
 
+        // Narrower: the wrapped line is re-wrapped and "gh" is untouched.
+        screen.resize_reflow(3, 3);
+        assert_eq!(screen.display(), vec!["abc", "def", "gh "]);
         assert_eq!(
-            tolist(&screen)[0],
-            cv![co!(default), co!("s", fg = "red"), co!("a", fg = "red"),]
+            (0..3).map(|y| screen.is_wrapped(y)).collect::<Vec<_>>(),
+            vec![false, true, false]
         );
+        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
+        assert_eq!(&*screen.buffer[1][2].fg, "red");
+        assert_eq!(&*screen.buffer[2][0].fg, "default");
 
-        // Test with explicit count of 1
+        // Too many rows: the oldest one is dropped at the top.
+        screen.resize_reflow(2, 3);
+        assert_eq!(screen.display(), vec!["def", "gh "]);
+        assert!(!screen.is_wrapped(0));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 2));
+
+        // Wider: rows joined by auto wrap become one row again.
         let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![0]);
+        screen.draw("abcdefg");
+        screen.resize_reflow(2, 8);
+        assert_eq!(screen.display(), vec!["abcdefg ", "        "]);
+        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 7));
+        assert!(!screen.pending_wrap);
 
-        screen.cursor_position(None, None);
-        screen.insert_characters(Some(1));
+        // A wide character is moved to the next row instead of split.
+        let mut screen = Screen::new(4, 2);
+        screen.draw("abコ");
+        screen.resize_reflow(3, 3);
+        assert_eq!(screen.display(), vec!["ab ", "コ ", "   "]);
+        assert!(screen.is_wrapped(1));
+        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 2));
+        assert!(!screen.pending_wrap);
+    }
 
+    #[test]
+    fn line_runs() {
+        let mut screen = Screen::new(6, 1);
+        screen.select_graphic_rendition(&[31]);
+        screen.draw("コa");
+        screen.select_graphic_rendition(&[42]);
+        screen.draw("b");
+
+        let red = CharOpts {
+            data: String::new(),
+            fg: "red".into(),
+            ..CharOpts::default()
+        };
+        let red_on_green = CharOpts { bg: "green".into(), ..red.clone() };
+        let blank = CharOpts { data: String::new(), ..CharOpts::default() };
         assert_eq!(
-            tolist(&screen)[0],
-            cv![co!(default), co!("s", fg = "red"), co!("a", fg = "red")]
+            screen.line_runs(0),
+            vec![
+                ("コa".to_string(), red),
+                ("b".to_string(), red_on_green),
+                ("  ".to_string(), blank),
+            ]
         );
+        assert!(screen.line_runs(1).is_empty());
     }
 
     #[test]
-    fn delete_characters() {
-        // Basic case
+    fn is_mode_set() {
         let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![0]);
-        screen.delete_characters(Some(2));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["m  ", "is ", "foo"]);
+        assert!(screen.is_mode_set(7, true));
+        assert!(!screen.is_mode_set(7, false));
+        assert!(!screen.is_mode_set(4, false));
+
+        screen.set_mode(&[4], false);
+        screen.set_mode(&[6], true);
+        assert!(screen.is_mode_set(4, false));
+        assert!(screen.is_mode_set(6, true));
+
+        let modes = screen.modes().collect::<HashSet<_>>();
+        assert_eq!(modes, HashSet::from([DECAWM, DECTCEM, IRM, DECOM]));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn report_mode() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(3, 3);
+        screen.set_reporter(reporter.clone());
+
+        screen.report_mode(7, true);
+        screen.report_mode(4, false);
+        screen.set_mode(&[4], false);
+        screen.report_mode(4, false);
+        screen.report_mode(1234, true);
         assert_eq!(
-            tolist(&screen)[0],
-            cv![co!("m", fg = "red"), co!(default), co!(default)]
+            reporter.take(),
+            vec!["\x1B[?7;1$y", "\x1B[4;2$y", "\x1B[4;1$y", "\x1B[?1234;0$y"]
         );
+    }
 
-        // Delete at position (2,2)
-        screen.cursor.y = 2;
-        screen.cursor.x = 2;
-        screen.delete_characters(None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
-        assert_eq!(screen.display(), vec!["m  ", "is ", "fo "]);
+    #[test]
+    fn wide_char_halves() {
+        // a) erasing the continuation
+        let mut screen = Screen::new(6, 1);
+        screen.draw("コン");
+        screen.cursor_to_column(Some(2));
+        screen.erase_characters(Some(1));
+        assert_eq!(screen.display(), vec!["  ン  ".to_string()]);
+
+        // b) deleting the leading half
+        let mut screen = Screen::new(6, 1);
+        screen.draw("コン");
+        screen.cursor_to_column(Some(1));
+        screen.delete_characters(Some(1));
+        assert_eq!(screen.display(), vec![" ン   ".to_string()]);
+
+        // c) inserting in the middle
+        let mut screen = Screen::new(6, 1);
+        screen.draw("コン");
+        screen.cursor_to_column(Some(2));
+        screen.insert_characters(Some(1));
+        assert_eq!(screen.display(), vec!["   ン ".to_string()]);
 
-        // Delete at position (1,1)
-        screen.cursor.y = 1;
-        screen.cursor.x = 1;
-        screen.delete_characters(Some(0));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
-        assert_eq!(screen.display(), vec!["m  ", "i  ", "fo "]);
+        // d) erasing to the end of line from the continuation
+        let mut screen = Screen::new(6, 1);
+        screen.draw("コン");
+        screen.cursor_to_column(Some(4));
+        screen.erase_in_line(Some(0), None);
+        assert_eq!(screen.display(), vec!["コ    ".to_string()]);
 
-        // Extreme cases
-        // 1. Delete from middle of line
+        // No empty cell is left without its leading half.
+        for screen_line in screen.buffer.iter() {
+            for (x, cell) in screen_line.iter().enumerate() {
+                if cell.data.is_empty() {
+                    assert_eq!(screen_line[x - 1].data, "コ");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cursor_skips_wide_char_halves() {
+        let mut screen = Screen::new(10, 1);
+        screen.draw("コンニチハ");
+        assert_eq!(screen.cursor.x, 9);
+
+        screen.cursor_to_column(Some(1));
+        screen.cursor_forward(Some(1));
+        assert_eq!(screen.cursor.x, 2);
+        screen.cursor_forward(Some(3));
+        assert_eq!(screen.cursor.x, 6);
+        screen.cursor_forward(Some(2));
+        assert_eq!(screen.cursor.x, 8);
+
+        // Landing on the last continuation cell falls back onto its lead.
+        screen.cursor_forward(Some(1));
+        assert_eq!(screen.cursor.x, 8);
+        screen.cursor_forward(Some(100));
+        assert_eq!(screen.cursor.x, 8);
+
+        screen.cursor_back(Some(1));
+        assert_eq!(screen.cursor.x, 6);
+        screen.cursor_back(Some(3));
+        assert_eq!(screen.cursor.x, 2);
+        screen.cursor_back(Some(100));
+        assert_eq!(screen.cursor.x, 0);
+
+        // Drawing at the new position replaces whole characters.
+        screen.cursor_forward(Some(1));
+        screen.draw("ab");
+        assert_eq!(screen.display(), vec!["コabニチハ".to_string()]);
+    }
+
+    #[test]
+    fn draw_combining_onto_wide_char() {
+        // a) right after a wide character
+        let mut screen = Screen::new(4, 2);
+        screen.draw("コ\u{0308}");
+        assert_eq!(screen.buffer[0][0].data, "コ\u{0308}");
+        assert_eq!(screen.buffer[0][1].data, "");
+        assert_eq!(screen.cursor.x, 2);
+
+        // b) a wide character in the last columns, wrap pending
+        let mut screen = Screen::new(4, 2);
+        screen.draw("abコ\u{0308}");
+        assert_eq!(screen.buffer[0][2].data, "コ\u{0308}");
+        assert_eq!(screen.buffer[0][3].data, "");
+
+        // c) at the start of the next line
+        let mut screen = Screen::new(4, 2);
+        screen.draw("abコ");
+        screen.cursor_position(Some(2), Some(1));
+        screen.draw("\u{0308}");
+        assert_eq!(screen.buffer[0][2].data, "コ\u{0308}");
+        assert_eq!(screen.buffer[0][3].data, "");
+        assert_eq!(screen.buffer[1][0].data, " ");
+    }
+
+    #[test]
+    fn draw_grapheme_clusters() {
+        // a) a family emoji joined with ZWJ takes a single wide cell
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
         let mut screen = Screen::new(5, 1);
-        update(&mut screen, vec!["12345"], vec![0]);
-        screen.cursor.x = 1;
-        screen.delete_characters(Some(3));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 1));
-        assert_eq!(screen.display(), vec!["15   "]);
-        assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!("1", fg = "red"),
-                co!("5", fg = "red"),
-                co!(default),
-                co!(default),
-                co!(default)
-            ]
-        );
+        screen.draw(&format!("{}a", family));
+        assert_eq!(screen.buffer[0][0].data, family);
+        assert_eq!(screen.buffer[0][1].data, "");
+        assert_eq!(screen.buffer[0][2].data, "a");
+        assert_eq!(screen.cursor.x, 3);
 
-        // 2. Delete more than available
+        // b) a flag is a pair of regional indicators
+        let flag = "\u{1F1F9}\u{1F1F7}";
         let mut screen = Screen::new(5, 1);
-        update(&mut screen, vec!["12345"], vec![0]);
-        screen.cursor.x = 2;
-        screen.delete_characters(Some(10));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
-        assert_eq!(screen.display(), vec!["12   "]);
-        assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!("1", fg = "red"),
-                co!("2", fg = "red"),
-                co!(default),
-                co!(default),
-                co!(default)
-            ]
-        );
+        screen.draw(&format!("{}a", flag));
+        assert_eq!(screen.buffer[0][0].data, flag);
+        assert_eq!(screen.buffer[0][1].data, "");
+        assert_eq!(screen.buffer[0][2].data, "a");
+        assert_eq!(screen.cursor.x, 3);
 
-        // 3. Delete from start
+        // c) a skin tone modifier stays with its base
+        let wave = "\u{1F44B}\u{1F3FD}";
         let mut screen = Screen::new(5, 1);
-        update(&mut screen, vec!["12345"], vec![0]);
-        screen.delete_characters(Some(4));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["5    "]);
+        screen.draw(wave);
+        assert_eq!(screen.buffer[0][0].data, wave);
+        assert_eq!(screen.cursor.x, 2);
+
+        // d) combining marks still compose within a single draw
+        let mut screen = Screen::new(5, 1);
+        screen.draw("e\u{0301}x");
+        assert_eq!(screen.buffer[0][0].data, "\u{e9}");
+        assert_eq!(screen.buffer[0][1].data, "x");
+        assert_eq!(screen.cursor.x, 2);
+    }
+
+    #[test]
+    fn custom_width_fn() {
+        let mut screen = Screen::new(6, 2);
+        // Treat ambiguous width Greek letters as wide.
+        screen.set_width_fn(|c| match c {
+            'α'..='ω' => 2,
+            _ => 1,
+        });
+        screen.draw("αβγ");
         assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!("5", fg = "red"),
-                co!(default),
-                co!(default),
-                co!(default),
-                co!(default)
-            ]
+            screen.display(),
+            vec!["αβγ".to_string(), "      ".to_string()]
         );
+        assert_eq!(screen.buffer[0][1].data, "");
+        assert_eq!(screen.cursor.x, 5);
+        assert!(screen.pending_wrap);
+
+        // The next wide character goes to the next line.
+        screen.draw("δa");
+        assert_eq!(screen.display()[1], "δa   ");
+        assert_eq!(screen.cursor.x, 3);
+
+        // Cursor movement steps over the trailing halves.
+        screen.cursor_back(Some(2));
+        assert_eq!(screen.cursor.x, 0);
+        screen.cursor_forward(Some(1));
+        assert_eq!(screen.cursor.x, 2);
     }
 
     #[test]
-    fn erase_characters() {
-        // Basic case
-        let mut screen = Screen::new(3, 3);
-        update(&mut screen, vec!["sam", "is ", "foo"], vec![0]);
+    fn feed() {
+        let mut screen = Screen::new(10, 2);
+        screen.feed("\x1b[2Jfoo\x1b[2;3Hbar");
+        assert_eq!(screen.display(), vec!["foo       ", "  bar     "]);
 
-        screen.erase_characters(Some(2));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["  m", "is ", "foo"]);
-        assert_eq!(
-            tolist(&screen)[0],
-            cv![co!(default), co!(default), co!("m", fg = "red")]
-        );
+        // A sequence split across feeds.
+        screen.feed("\x1b[1");
+        screen.feed(";2Hx");
+        assert_eq!(screen.display(), vec!["fxo       ", "  bar     "]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn enquiry() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 1);
+        screen.set_reporter(reporter.clone());
+
+        // a) no answerback by default
+        screen.feed("\x05");
+        assert!(reporter.is_empty());
+
+        // b) answerback
+        screen.set_answerback("memterm");
+        screen.feed("\x05");
+        assert_eq!(reporter.take(), vec!["memterm".to_string()]);
+        assert_eq!(screen.display(), vec!["          "]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn decid() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 1);
+        screen.set_reporter(reporter.clone());
+
+        screen.feed("\x1bZ");
+        assert_eq!(reporter.take(), vec!["\x1b[?6c".to_string()]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn device_attributes_prefixes() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 1);
+        screen.set_reporter(reporter.clone());
+
+        // Only primary DA is answered.
+        screen.feed("\x1b[>c\x1b[=c\x1b[>0c");
+        assert!(reporter.is_empty());
+        screen.feed("\x1b[c");
+        assert_eq!(reporter.take(), vec!["\x1b[?6c".to_string()]);
+    }
+
+    #[test]
+    fn keypad_mode() {
+        let mut screen = Screen::new(10, 1);
+        assert!(!screen.keypad_application);
+
+        screen.feed("\x1b=");
+        assert!(screen.keypad_application);
+        screen.feed("\x1b>");
+        assert!(!screen.keypad_application);
+
+        // Reset goes back to numeric mode.
+        screen.feed("\x1b=\x1bc");
+        assert!(!screen.keypad_application);
+    }
+
+    #[test]
+    fn cursor_keys_mode() {
+        let mut screen = Screen::new(10, 1);
+        assert!(!screen.cursor_keys_application());
+        assert_eq!(screen.encode_key(Key::Up), "\x1B[A");
+        assert_eq!(screen.encode_key(Key::End), "\x1B[F");
+
+        screen.feed("\x1b[?1h");
+        assert!(screen.cursor_keys_application());
+        assert_eq!(screen.encode_key(Key::Up), "\x1BOA");
+        assert_eq!(screen.encode_key(Key::Left), "\x1BOD");
+        assert_eq!(screen.encode_key(Key::Home), "\x1BOH");
+
+        screen.feed("\x1b[?1l");
+        assert_eq!(screen.encode_key(Key::Down), "\x1B[B");
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut screen = Screen::new(10, 4);
+        screen.feed("\x1b]2;title\x07\x1b[1;31mhello\r\nworld");
+        screen.set_margins(Some(2), Some(3));
+        screen.feed("\x1b[?6h\x1b[2;4H\x1b7");
+        let saved = (screen.cursor.x, screen.cursor.y);
+        let snapshot = screen.snapshot();
+        let display = screen.display();
 
-        screen.cursor.y = 2;
-        screen.cursor.x = 2;
-        screen.erase_characters(None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
-        assert_eq!(screen.display(), vec!["  m", "is ", "fo "]);
+        screen.set_margins(None, None);
+        screen
+            .feed("\x1b[?6l\x1b[0m\x1b]2;other\x07\x1b[H\x1b[2Jfoo\nbar\x1bM\x1bD\x1bD\x1bD\x1bD");
+        screen.resize(Some(3), Some(6));
+        assert_ne!(screen.snapshot(), snapshot);
 
-        screen.cursor.y = 1;
-        screen.cursor.x = 1;
-        screen.erase_characters(Some(0));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (1, 1));
-        assert_eq!(screen.display(), vec!["  m", "i  ", "fo "]);
+        screen.dirty.clear();
+        screen.restore(snapshot.clone());
+        assert_eq!(screen.snapshot(), snapshot);
+        assert_eq!(screen.display(), display);
+        assert_eq!(screen.title, "title");
+        assert_eq!(screen.margins, Some(Margins { top: 1, bottom: 2 }));
+        assert!(screen.mode.contains(&DECOM));
+        assert_eq!(&*screen.cursor.attr.fg, "red");
+        assert_eq!(screen.dirty, HashSet::from([0, 1, 2, 3]));
 
-        // Extreme cases
-        // 1. Erase from middle
-        let mut screen = Screen::new(5, 1);
-        update(&mut screen, vec!["12345"], vec![0]);
-        screen.cursor.x = 1;
-        screen.erase_characters(Some(3));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 1));
-        assert_eq!(screen.display(), vec!["1   5"]);
-        assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!("1", fg = "red"),
-                co!(default),
-                co!(default),
-                co!(default),
-                co!("5", fg = "red")
-            ]
-        );
+        // The saved cursor comes back too.
+        screen.feed("\x1b[H\x1b8");
+        assert_eq!((screen.cursor.x, screen.cursor.y), saved);
+    }
 
-        // 2. Erase more than available
-        let mut screen = Screen::new(5, 1);
-        update(&mut screen, vec!["12345"], vec![0]);
-        screen.cursor.x = 2;
-        screen.erase_characters(Some(10));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
-        assert_eq!(screen.display(), vec!["12   "]);
+    #[test]
+    fn screen_eq() {
+        // a) the same contents, one drawn and erased
+        let mut screen = Screen::new(5, 2);
+        let mut other = Screen::new(5, 2);
+        assert!(screen == other);
+        screen.feed("\x1b[31mabc\x1b[0m\x1b[2K\x1b[H");
+        assert!(screen == other);
+        assert!(screen.diff(&other).is_empty());
+
+        // b) a cell missing from the buffer is a default one
+        other.buffer[1].pop();
+        assert!(screen == other);
+
+        // c) a different cell
+        other.feed("\x1b[2;2Hx\x1b[H");
+        assert!(screen != other);
         assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!("1", fg = "red"),
-                co!("2", fg = "red"),
-                co!(default),
-                co!(default),
-                co!(default)
-            ]
+            screen.diff(&other),
+            vec![CellChange {
+                x: 1,
+                y: 1,
+                old: screen.default_char(),
+                new: CharOpts { data: "x".to_string(), ..screen.default_char() },
+            }]
         );
 
-        // 3. Erase from start
-        let mut screen = Screen::new(5, 1);
-        update(&mut screen, vec!["12345"], vec![0]);
-        screen.erase_characters(Some(4));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 0));
-        assert_eq!(screen.display(), vec!["    5"]);
-        assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!(default),
-                co!(default),
-                co!(default),
-                co!(default),
-                co!("5", fg = "red")
-            ]
-        );
+        // d) the cursor and modes are compared too
+        let mut other = Screen::new(5, 2);
+        other.cursor_forward(None);
+        assert!(screen != other);
+        let mut other = Screen::new(5, 2);
+        other.set_mode(&[4], false);
+        assert!(screen != other);
     }
 
     #[test]
-    fn erase_in_line() {
-        // Initial setup
-        let mut screen = Screen::new(5, 5);
-        update(
-            &mut screen,
-            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
-            vec![0],
-        );
-        screen.cursor_position(Some(1), Some(3));
-
-        // a) erase from cursor to the end of line
-        screen.erase_in_line(Some(0), None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+    fn dirty_cells() {
+        let sorted = |cells: HashSet<(u32, u32)>| {
+            let mut cells = cells.into_iter().collect::<Vec<_>>();
+            cells.sort_by_key(|&(x, y)| (y, x));
+            cells
+        };
+        let mut screen = Screen::new(5, 3);
+        assert_eq!(screen.take_dirty_cells().len(), 15);
+        assert!(screen.take_dirty_cells().is_empty());
+
+        // a) draw
+        screen.draw("ab");
+        assert_eq!(sorted(screen.take_dirty_cells()), vec![(0, 0), (1, 0)]);
+        screen.draw("コ");
+        assert_eq!(sorted(screen.take_dirty_cells()), vec![(2, 0), (3, 0)]);
+
+        // b) erase and delete
+        screen.cursor_to_column(Some(2));
+        screen.erase_characters(Some(2));
         assert_eq!(
-            screen.display(),
-            vec![
-                "sa   ".to_string(),
-                "s foo".to_string(),
-                "but a".to_string(),
-                "re yo".to_string(),
-                "u?   ".to_string(),
-            ]
+            sorted(screen.take_dirty_cells()),
+            vec![(1, 0), (2, 0), (3, 0)]
         );
+        screen.delete_characters(Some(1));
         assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!("s", fg = "red"),
-                co!("a", fg = "red"),
-                co!(default),
-                co!(default),
-                co!(default)
-            ]
-        );
-
-        // b) erase from the beginning of the line to the cursor
-        let mut screen = Screen::new(5, 5);
-        update(
-            &mut screen,
-            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
-            vec![0],
+            sorted(screen.take_dirty_cells()),
+            vec![(1, 0), (2, 0), (3, 0), (4, 0)]
         );
-        screen.cursor_position(Some(1), Some(3));
         screen.erase_in_line(Some(1), None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
-        assert_eq!(
-            screen.display(),
-            vec![
-                "    i".to_string(),
-                "s foo".to_string(),
-                "but a".to_string(),
-                "re yo".to_string(),
-                "u?   ".to_string(),
-            ]
-        );
-        assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!(default),
-                co!(default),
-                co!(default),
-                co!(" ", fg = "red"),
-                co!("i", fg = "red")
-            ]
-        );
+        assert_eq!(sorted(screen.take_dirty_cells()), vec![(0, 0), (1, 0)]);
 
-        // c) erase the entire line
-        let mut screen = Screen::new(5, 5);
-        update(
-            &mut screen,
-            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
-            vec![0],
-        );
-        screen.cursor_position(Some(1), Some(3));
-        screen.erase_in_line(Some(2), None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 2));
+        // c) cursor movement alone changes nothing
+        screen.cursor_position(Some(2), Some(2));
+        screen.linefeed();
+        assert!(screen.take_dirty_cells().is_empty());
+
+        // d) scrolling marks the scrolled region
+        screen.set_margins(Some(2), Some(3));
+        screen.cursor_position(Some(2), Some(1));
+        screen.reverse_index();
+        assert_eq!(screen.take_dirty_cells().len(), 10);
+        assert!(screen.take_dirty_cells().is_empty());
+        screen.insert_lines(Some(1));
         assert_eq!(
-            screen.display(),
-            vec![
-                "     ".to_string(),
-                "s foo".to_string(),
-                "but a".to_string(),
-                "re yo".to_string(),
-                "u?   ".to_string(),
-            ]
+            sorted(screen.take_dirty_cells()),
+            (1..3)
+                .flat_map(|y| (0..5).map(move |x| (x, y)))
+                .collect::<Vec<_>>()
         );
+
+        // e) the cells are those of the row spans, taken along with them
+        screen.cursor_position(Some(1), Some(1));
+        screen.draw("x");
+        screen.cursor_to_column(Some(4));
+        screen.draw("y");
         assert_eq!(
-            tolist(&screen)[0],
-            cv![
-                co!(default),
-                co!(default),
-                co!(default),
-                co!(default),
-                co!(default)
-            ]
+            sorted(screen.take_dirty_cells()),
+            vec![(0, 0), (1, 0), (2, 0), (3, 0)]
         );
+        assert!(screen.take_dirty_ranges().is_empty());
     }
 
     #[test]
-    fn erase_in_display() {
-        // Initial setup
-        let mut screen = Screen::new(5, 5);
-        update(
-            &mut screen,
-            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
-            vec![2, 3],
-        );
-        screen.cursor_position(Some(3), Some(3));
+    fn scroll_view() {
+        let mut screen = Screen::new(3, 2);
+        screen.feed("a\r\nb\r\nc\r\nd");
+        assert_eq!(screen.display(), vec!["c  ", "d  "]);
+        assert_eq!(screen.scrollback.len(), 2);
+
+        screen.scroll_view_up(1);
+        assert_eq!(screen.display_view(), vec!["b  ", "c  "]);
+        // Scrolling stops at the oldest row.
+        screen.scroll_view_up(5);
+        assert_eq!(screen.view_offset, 2);
+        assert_eq!(screen.display_view(), vec!["a  ", "b  "]);
+
+        // Output goes to the live screen.
+        screen.feed("x");
+        assert_eq!(screen.display(), vec!["c  ", "dx "]);
+        assert_eq!(screen.display_view(), vec!["a  ", "b  "]);
+
+        screen.scroll_view_down(1);
+        assert_eq!(screen.display_view(), vec!["b  ", "c  "]);
+
+        // The view stays on the same rows as new ones scroll off.
+        screen.feed("\r\ne");
+        assert_eq!(screen.view_offset, 2);
+        assert_eq!(screen.display_view(), vec!["b  ", "c  "]);
+        // Until they drop out of the history.
+        screen.scrollback_limit = 3;
+        screen.feed("\r\nf\r\ng");
+        assert_eq!(screen.view_offset, 3);
+        assert_eq!(screen.display_view(), vec!["c  ", "dx "]);
+
+        // A lowered limit applies from the next row on.
+        screen.scrollback_limit = 1;
+        screen.feed("\r\nh");
+        assert_eq!(screen.scrollback.len(), 1);
+        assert_eq!(screen.view_offset, 1);
+        assert_eq!(screen.display_view(), vec!["f  ", "g  "]);
+
+        screen.scroll_view_down(5);
+        assert_eq!(screen.display_view(), screen.display());
+    }
 
-        // a) erase from cursor to the end of display
-        screen.erase_in_display(Some(0), None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
-        assert_eq!(
-            screen.display(),
-            vec![
-                "sam i".to_string(),
-                "s foo".to_string(),
-                "bu   ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-            ]
-        );
-        assert_eq!(
-            tolist(&screen)[2..],
-            vec![
-                cv![
-                    co!("b", fg = "red"),
-                    co!("u", fg = "red"),
-                    co!(default),
-                    co!(default),
-                    co!(default)
-                ],
-                cv![
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(default)
-                ],
-                cv![
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(default)
-                ]
-            ]
-        );
+    #[test]
+    fn scroll_region_and_tab_stops() {
+        let mut screen = Screen::new(20, 5);
+        assert_eq!(screen.scroll_region(), (0, 4));
+        screen.set_margins(Some(2), Some(4));
+        assert_eq!(screen.scroll_region(), (1, 3));
 
-        // b) erase from beginning of display to cursor
-        let mut screen = Screen::new(5, 5);
-        update(
-            &mut screen,
-            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
-            vec![2, 3],
-        );
-        screen.cursor_position(Some(3), Some(3));
-        screen.erase_in_display(Some(1), None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
-        assert_eq!(
-            screen.display(),
-            vec![
-                "     ".to_string(),
-                "     ".to_string(),
-                "    a".to_string(),
-                "re yo".to_string(),
-                "u?   ".to_string(),
-            ]
+        assert_eq!(screen.tab_stops(), vec![8, 16]);
+        screen.cursor_to_column(Some(4));
+        screen.set_tab_stop();
+        assert_eq!(screen.tab_stops(), vec![3, 8, 16]);
+        assert!(screen.has_tab_stop(3));
+        assert!(!screen.has_tab_stop(4));
+    }
+
+    #[test]
+    fn back_and_forward_index() {
+        let mut screen = Screen::new(4, 3);
+        screen.feed("abcd\r\nefgh\r\nijkl");
+        screen.set_margins(Some(1), Some(2));
+
+        // Away from the margins only the cursor moves.
+        screen.cursor_position(Some(2), Some(2));
+        screen.feed("\x1b6");
+        assert_eq!((screen.cursor.x, screen.cursor.y), (0, 1));
+        screen.feed("\x1b9");
+        assert_eq!((screen.cursor.x, screen.cursor.y), (1, 1));
+        assert_eq!(screen.display(), vec!["abcd", "efgh", "ijkl"]);
+
+        // At the left edge the scrolling region moves right.
+        screen.cursor_to_column(Some(1));
+        screen.feed("\x1b6");
+        assert_eq!(screen.cursor.x, 0);
+        assert_eq!(screen.display(), vec![" abc", " efg", "ijkl"]);
+
+        // At the right edge it moves left.
+        screen.cursor_to_column(Some(4));
+        screen.feed("\x1b9\x1b9");
+        assert_eq!(screen.cursor.x, 3);
+        assert_eq!(screen.display(), vec!["bc  ", "fg  ", "ijkl"]);
+    }
+
+    #[test]
+    fn clear() {
+        let mut screen = Screen::new(3, 2);
+        screen.feed("abc\r\nde\x1b[1m");
+        screen.take_dirty_cells();
+        screen.clear_line();
+        assert_eq!(screen.display(), vec!["abc", "   "]);
+        assert_eq!(screen.take_dirty_cells().len(), 3);
+
+        screen.feed("f");
+        screen.clear();
+        assert_eq!(screen.display(), vec!["   ", "   "]);
+        assert!(screen.dirty.contains(&0));
+        assert_eq!((screen.cursor.x, screen.cursor.y), (2, 1));
+        assert!(screen.cursor.attr.bold);
+    }
+
+    #[test]
+    fn set_attr() {
+        let mut screen = Screen::new(3, 1);
+        screen.set_fg("ff8000");
+        screen.set_bold(true);
+        screen.feed("a");
+        screen.set_attr(CharOpts {
+            underscore: true,
+            bg: "blue".into(),
+            ..screen.default_char()
+        });
+        screen.feed("b");
+        assert!(screen.current_attr().underscore);
+
+        let a = &screen.buffer[0][0];
+        assert_eq!((a.data.as_str(), &*a.fg, a.bold), ("a", "ff8000", true));
+        let b = &screen.buffer[0][1];
+        assert_eq!((b.data.as_str(), &*b.fg, &*b.bg), ("b", "default", "blue"));
+        assert!(b.underscore && !b.bold);
+    }
+
+    #[test]
+    fn char_opts_hash() {
+        let screen = Screen::new(2, 1);
+        let mut set = HashSet::new();
+        set.insert(screen.default_char());
+        set.insert(screen.default_char());
+        set.insert(CharOpts { bold: true, ..screen.default_char() });
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn builder() {
+        let mut screen = ScreenBuilder::new(20, 2)
+            .tab_width(4)
+            .default_fg("green")
+            .default_bg("000080")
+            .autowrap(false)
+            .build();
+        assert_eq!(screen.tab_stops(), vec![4, 8, 12, 16]);
+        assert!(!screen.mode.contains(&DECAWM));
+        assert!(screen.mode.contains(&DECTCEM));
+        assert_eq!(
+            (&*screen.buffer[0][0].fg, &*screen.buffer[0][0].bg),
+            ("green", "000080")
         );
+
+        // Resetting colors returns to the configured defaults.
+        screen.feed("\x1b[31;44ma\x1b[39mb\x1b[0mc");
+        let colors: Vec<_> = screen.buffer[0][..3]
+            .iter()
+            .map(|cell| (cell.fg.to_string(), cell.bg.to_string()))
+            .collect();
         assert_eq!(
-            tolist(&screen)[..3],
+            colors,
             vec![
-                cv![
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(default)
-                ],
-                cv![
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(default)
-                ],
-                cv![
-                    co!(default),
-                    co!(default),
-                    co!(default),
-                    co!(" ", fg = "red"),
-                    co!("a", fg = "red")
-                ],
+                ("red".to_string(), "blue".to_string()),
+                ("green".to_string(), "blue".to_string()),
+                ("green".to_string(), "000080".to_string()),
             ]
         );
 
-        // c) erase whole display
-        screen.erase_in_display(Some(2), None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (2, 2));
+        // So does RIS.
+        screen.feed("\x1bc");
+        assert_eq!(screen.tab_stops(), vec![4, 8, 12, 16]);
+        assert!(!screen.mode.contains(&DECAWM));
+        assert_eq!(&*screen.cursor.attr.fg, "green");
+    }
+
+    #[test]
+    fn set_tab_width() {
+        let mut screen = Screen::new(14, 1);
+        screen.set_tab_width(4, false);
+        assert_eq!(screen.tab_stops(), vec![8]);
+
+        screen.set_tab_width(4, true);
+        let mut stops = Vec::new();
+        for _ in 0..4 {
+            screen.tab();
+            stops.push(screen.cursor.x);
+        }
+        assert_eq!(stops, vec![4, 8, 12, 13]);
+
+        // RIS keeps the new width.
+        screen.set_tab_width(6, false);
+        screen.reset();
+        assert_eq!(screen.tab_stops(), vec![6, 12]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn try_feed() {
+        struct ClosedPipe;
+
+        impl std::io::Write for ClosedPipe {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut screen = Screen::new(10, 1);
+        screen.set_reporter(WriteReporter::new(ClosedPipe));
+        assert!(screen.try_feed("ab").is_ok());
+        match screen.try_feed("c\x1b[6nd") {
+            Err(TermError::Output(error)) => {
+                assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe)
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+        // The input after the failed reply is still applied.
+        assert_eq!(screen.display(), vec!["abcd      "]);
+        // feed ignores the error.
+        screen.feed("\x1b[6n");
+
+        screen.set_reporter(WriteReporter::new(Vec::new()));
+        screen.parser.set_strict(true);
+        assert!(matches!(
+            screen.try_feed("\x1b[5y"),
+            Err(TermError::Parse(errors)) if errors.len() == 1
+        ));
+        assert!(screen.try_feed("\x1b[6n").is_ok());
+
+        // Errors from an earlier feed aren't this call's.
+        screen.feed("\x1b[5y");
+        assert!(screen.try_feed("ab").is_ok());
+
+        // Nor are ones from a call which failed writing a reply.
+        screen.set_reporter(WriteReporter::new(ClosedPipe));
+        assert!(matches!(
+            screen.try_feed("\x1b[5y\x1b[6n"),
+            Err(TermError::Output(_))
+        ));
+        screen.set_reporter(WriteReporter::new(Vec::new()));
+        assert!(screen.try_feed("ab").is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn title_callbacks() {
+        let titles = Arc::new(Mutex::new(Vec::new()));
+        let icon_names = Arc::new(Mutex::new(Vec::new()));
+        let mut screen = Screen::new(10, 1);
+        screen.set_on_title_change({
+            let titles = titles.clone();
+            move |title| titles.lock().unwrap().push(title.to_owned())
+        });
+        screen.set_on_icon_name_change({
+            let icon_names = icon_names.clone();
+            move |icon_name| icon_names.lock().unwrap().push(icon_name.to_owned())
+        });
+
+        screen.feed("\x1b]2;vim\x07\x1b]0;htop\x1b\\");
+        assert_eq!(*titles.lock().unwrap(), vec!["vim", "htop"]);
+        assert_eq!(*icon_names.lock().unwrap(), vec!["htop"]);
+        assert_eq!(screen.title, "htop");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn resize_callback() {
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let mut screen = Screen::new(80, 24);
+        screen.set_on_resize({
+            let sizes = sizes.clone();
+            move |columns, lines| sizes.lock().unwrap().push((columns, lines))
+        });
+
+        screen.resize(Some(30), Some(100));
+        // No change, no callback.
+        screen.resize(Some(30), Some(100));
+        screen.resize_reflow(20, 100);
+        // DECCOLM resizes to 132 columns and back.
+        screen.feed("\x1b[?3h\x1b[?3l");
         assert_eq!(
-            screen.display(),
-            vec![
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-            ]
+            *sizes.lock().unwrap(),
+            vec![(100, 30), (100, 20), (132, 20), (100, 20)]
         );
-        let empty_line = cv![
-            co!(default),
-            co!(default),
-            co!(default),
-            co!(default),
-            co!(default)
-        ];
+    }
+
+    #[test]
+    fn empty_sgr_parameters() {
+        let mut screen = Screen::new(4, 1);
+        // Empty slots are 0, so a trailing one resets what came before.
+        screen.feed("\x1b[;31ma\x1b[1;mb\x1b[1;;4mc\x1b[;md");
+        let cells = &screen.buffer[0];
+        assert_eq!((&*cells[0].fg, cells[0].bold), ("red", false));
+        assert_eq!((&*cells[1].fg, cells[1].bold), ("default", false));
+        assert!(cells[2].underscore && !cells[2].bold);
         assert_eq!(
-            tolist(&screen),
-            vec![
-                empty_line.clone(),
-                empty_line.clone(),
-                empty_line.clone(),
-                empty_line.clone(),
-                empty_line.clone()
-            ]
+            cells[3],
+            CharOpts { data: "d".into(), ..screen.default_char() }
         );
+    }
 
-        // d) erase with private mode
-        let mut screen = Screen::new(5, 5);
-        update(
-            &mut screen,
-            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
-            vec![2, 3],
+    #[test]
+    fn long_sgr_parameters() {
+        let mut screen = Screen::new(2, 1);
+        screen.feed(&format!("\x1b[00031ma\x1b[{}1mb", "0".repeat(50)));
+        assert_eq!(&*screen.buffer[0][0].fg, "red");
+        assert!(screen.buffer[0][1].bold);
+    }
+
+    #[test]
+    fn scroll_up_down() {
+        let mut screen = Screen::new(1, 5);
+        screen.feed("a\r\nb\r\nc\r\nd\r\ne\x1b[4;1H");
+
+        screen.feed("\x1b[S");
+        assert_eq!(screen.display(), vec!["b", "c", "d", "e", " "]);
+        screen.feed("\x1b[2T");
+        assert_eq!(screen.display(), vec![" ", " ", "b", "c", "d"]);
+        assert_eq!((screen.cursor.x, screen.cursor.y), (0, 3));
+
+        // Only the scrolling region moves.
+        screen.set_margins(Some(2), Some(4));
+        screen.feed("\x1b[S");
+        assert_eq!(screen.display(), vec![" ", "b", "c", " ", "d"]);
+        screen.feed("\x1b[9T");
+        assert_eq!(screen.display(), vec![" ", " ", " ", " ", "d"]);
+    }
+
+    #[test]
+    fn scroll_up_scrollback() {
+        let mut screen = Screen::new(1, 3);
+        screen.feed("a\r\nb\r\nc\x1b[2S");
+        assert_eq!(screen.display(), vec!["c", " ", " "]);
+        let scrollback = screen.scrollback.iter().map(|line| line[0].data.as_str());
+        assert_eq!(scrollback.collect::<Vec<_>>(), vec!["a", "b"]);
+
+        // From a scrolling region at the top, only its rows.
+        screen.set_margins(Some(1), Some(2));
+        screen.feed("y\x1b[9S");
+        let scrollback = screen.scrollback.iter().map(|line| line[0].data.as_str());
+        assert_eq!(scrollback.collect::<Vec<_>>(), vec!["a", "b", "y", " "]);
+        // As does a line feed at its bottom.
+        screen.feed("\x1b[2Hz\n");
+        assert_eq!(screen.scrollback.len(), 5);
+
+        // Not from a region below the top or the alternate screen.
+        screen.set_margins(Some(2), Some(3));
+        screen.feed("\x1b[S");
+        screen.set_margins(None, None);
+        screen.feed("\x1b[?1049hx\x1b[S");
+        assert_eq!(screen.scrollback.len(), 5);
+        screen.feed("\x1b[?1049l\x1b[9S");
+        assert_eq!(screen.scrollback.len(), 8);
+        assert_eq!(screen.display(), vec![" ", " ", " "]);
+    }
+
+    #[test]
+    fn cursor_visible() {
+        let mut screen = Screen::new(10, 10);
+        assert!(screen.cursor_visible());
+        screen.feed("\x1b[?25l\x1b[3;5H");
+        assert!(!screen.cursor_visible());
+        assert_eq!(screen.cursor_xy(), (4, 2));
+        screen.feed("\x1b[?25h");
+        assert!(screen.cursor_visible());
+    }
+
+    #[test]
+    fn cursor_to_line_bounds() {
+        let mut screen = Screen::new(10, 10);
+        screen.feed("\x1b[5;3H\x1b[0d");
+        assert_eq!(screen.cursor_xy(), (2, 0));
+        screen.feed("\x1b[99d");
+        assert_eq!(screen.cursor_xy(), (2, 9));
+
+        // Under DECOM lines count from the top margin and stay in the
+        // region.
+        screen.set_margins(Some(3), Some(6));
+        screen.set_mode(&[DECOM], false);
+        screen.feed("\x1b[0d");
+        assert_eq!(screen.cursor.y, 2);
+        screen.feed("\x1b[2d");
+        assert_eq!(screen.cursor.y, 3);
+        screen.feed("\x1b[99d");
+        assert_eq!(screen.cursor.y, 5);
+    }
+
+    #[test]
+    fn decolm_keeps_content() {
+        let mut screen = Screen::new(4, 2);
+        screen.decolm_clears = false;
+        screen.feed("ab\r\ncd");
+
+        screen.feed("\x1b[?3h");
+        assert_eq!(screen.columns, 132);
+        assert_eq!(&screen.display()[0][..4], "ab  ");
+        assert_eq!(screen.cursor_xy(), (0, 0));
+
+        screen.feed("\x1b[?3l");
+        assert_eq!(screen.display(), vec!["ab  ", "cd  "]);
+
+        // By default the screen is cleared.
+        screen.decolm_clears = true;
+        screen.feed("\x1b[?3h\x1b[?3l");
+        assert_eq!(screen.display(), vec!["    ", "    "]);
+    }
+
+    #[test]
+    fn tab_stop_at_column_zero() {
+        let mut screen = Screen::new(10, 1);
+        screen.clear_tab_stop(Some(3));
+        screen.set_tab_stop();
+        screen.cursor_to_column(Some(5));
+        screen.set_tab_stop();
+        assert_eq!(screen.tab_stops(), vec![0, 4]);
+
+        // From the stop at column 0 the tab moves on to the next one.
+        screen.cursor_to_column(Some(1));
+        screen.tab();
+        assert_eq!(screen.cursor.x, 4);
+        // No stops further right, the tab stops at the last column.
+        screen.tab();
+        assert_eq!(screen.cursor.x, 9);
+        screen.tab();
+        assert_eq!(screen.cursor.x, 9);
+    }
+
+    #[test]
+    fn resize_tab_stops() {
+        let mut screen = Screen::new(20, 1);
+        screen.cursor_to_column(Some(4));
+        screen.set_tab_stop();
+        assert_eq!(screen.tab_stops(), vec![3, 8, 16]);
+
+        // Stops past the edge are dropped, tab stops at the last column.
+        screen.resize(None, Some(12));
+        assert_eq!(screen.tab_stops(), vec![3, 8]);
+        screen.cursor_to_column(Some(10));
+        screen.tab();
+        assert_eq!(screen.cursor.x, 11);
+
+        // New columns get the default stops, custom ones are kept.
+        screen.resize(None, Some(30));
+        assert_eq!(screen.tab_stops(), vec![3, 8, 16, 24]);
+        screen.tab();
+        assert_eq!(screen.cursor.x, 16);
+
+        screen.resize_reflow(1, 5);
+        assert_eq!(screen.tab_stops(), vec![3]);
+    }
+
+    #[test]
+    fn draw_ascii_matches_general_path() {
+        // A `width_fn` disables the ASCII fast path.
+        let mut fast = Screen::new(5, 3);
+        let mut slow = Screen::new(5, 3);
+        slow.set_width_fn(|c| UnicodeWidthChar::width(c).unwrap_or(0) as u8);
+
+        for screen in [&mut fast, &mut slow] {
+            screen.feed("\x1b[1mhello world\x1b[0m");
+            // Line drawing characters from the VT100 set in G1.
+            screen.shift_out();
+            screen.draw("qx");
+            screen.shift_in();
+            screen.draw("abwrap");
+        }
+        assert_eq!(fast.buffer, slow.buffer);
+        assert_eq!(fast.wrapped, slow.wrapped);
+        assert_eq!(fast.cursor_xy(), slow.cursor_xy());
+        assert_eq!(fast.pending_wrap, slow.pending_wrap);
+        assert_eq!(fast.display(), vec![" worl", "d─│ab", "wrap "]);
+    }
+
+    #[test]
+    fn cells() {
+        let mut screen = Screen::new(4, 2);
+        screen.feed("\x1b[31ma\x1b[0m\u{4e2d}b\r\n\x1b[44mcd");
+
+        let cells = screen.cells().collect::<Vec<_>>();
+        // The trailing half of the wide character is skipped.
+        assert_eq!(cells.len(), 7);
+        assert_eq!(
+            cells
+                .iter()
+                .map(|&(x, y, _)| (x, y))
+                .take(4)
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (1, 0), (3, 0), (0, 1)]
         );
-        screen.erase_in_display(Some(3), Some(true));
+
+        let (x, y, cell) = cells[0];
+        assert_eq!((x, y, cell.data.as_str(), &*cell.fg), (0, 0, "a", "red"));
+        let (x, y, cell) = cells[1];
+        assert_eq!((x, y, cell.data.as_str()), (1, 0, "\u{4e2d}"));
+        let (x, y, cell) = cells[4];
+        assert_eq!((x, y, cell.data.as_str(), &*cell.bg), (1, 1, "d", "blue"));
+    }
+
+    #[test]
+    fn media_copy() {
+        let mut screen = Screen::new(10, 3);
+        screen.feed("top\r\n");
+
+        // Printer controller mode passes text to the printer only.
+        screen.feed("\x1b[5iline one\r\n\tline two\x1b[4iafter");
+        assert_eq!(screen.take_printer_output(), "line one\r\n\tline two");
         assert_eq!(
             screen.display(),
-            vec![
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-            ]
+            vec!["top       ", "after     ", "          "]
         );
+        assert_eq!(screen.cursor_xy(), (5, 1));
 
-        // e) erase with mode 3
-        let mut screen = Screen::new(5, 5);
-        update(
-            &mut screen,
-            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
-            vec![2, 3],
+        // So do sequences, split or not, and the printer gets them as
+        // they are.
+        screen.feed("\x1b[5i\x1b[H\x1b[2J\x1b[1mx\x1bEy\x1b[4");
+        screen.feed("\x1b[4i");
+        assert_eq!(
+            screen.take_printer_output(),
+            "\x1b[H\x1b[2J\x1b[1mx\x1bEy\x1b[4"
         );
-        screen.erase_in_display(Some(3), None);
         assert_eq!(
             screen.display(),
-            vec![
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-            ]
+            vec!["top       ", "after     ", "          "]
         );
+        assert_eq!(screen.cursor.attr, screen.default_char());
+        screen.feed("\u{9b}5iz\u{9b}4i");
+        assert_eq!(screen.take_printer_output(), "z");
+        screen.printer_controller = true;
+        screen.next_line();
+        assert_eq!(screen.take_printer_output(), "\r\n");
+        screen.printer_controller = false;
 
-        // f) erase with mode 3 and private mode
-        let mut screen = Screen::new(5, 5);
-        update(
-            &mut screen,
-            vec!["sam i", "s foo", "but a", "re yo", "u?   "],
-            vec![2, 3],
-        );
-        screen.erase_in_display(Some(3), Some(true));
+        // Print screen and the cursor line.
+        screen.feed("\x1b[i\x1b[?1i");
+        assert_eq!(screen.take_printer_output(), "top\nafter\n\nafter\n");
+
+        // Auto print prints each line a linefeed leaves.
+        screen.feed("\x1b[?5i\r\nnext\n\x1b[?4i\n");
+        assert_eq!(screen.take_printer_output(), "after\nnext\n");
+        assert!(screen.printer_output.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn keyboard_flags() {
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 1);
+        screen.set_reporter(reporter.clone());
+
+        screen.feed("\x1b[?u");
+        assert_eq!(reporter.take(), vec!["\x1B[?0u".to_string()]);
+
+        screen.feed("\x1b[>1u\x1b[>11u\x1b[?u");
+        assert_eq!(screen.keyboard_flags, vec![1, 11]);
+        assert_eq!(reporter.take(), vec!["\x1B[?11u".to_string()]);
+
+        // A missing count pops one entry.
+        screen.feed("\x1b[<u\x1b[?u");
+        assert_eq!(screen.keyboard_flags, vec![1]);
+        assert_eq!(reporter.take(), vec!["\x1B[?1u".to_string()]);
+
+        screen.feed("\x1b[>3u\x1b[<5u\x1b[?u");
+        assert!(screen.keyboard_flags.is_empty());
+        assert_eq!(reporter.take(), vec!["\x1B[?0u".to_string()]);
+
+        // The stack is bounded, the oldest entries go first.
+        for flags in 0..20 {
+            screen.feed(&format!("\x1b[>{}u", flags));
+        }
+        assert_eq!(screen.keyboard_flags, (4..20).collect::<Vec<_>>());
+
+        screen.reset();
+        assert!(screen.keyboard_flags.is_empty());
+        assert_eq!(screen.cursor.attr, screen.default_char());
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        let mut screen = Screen::new(10, 1);
+        screen.feed("\x1b[>4;2m");
+        assert_eq!(screen.modify_other_keys, 2);
+        // It isn't SGR 4;2, underline and faint.
+        assert_eq!(screen.cursor.attr, screen.default_char());
+
+        screen.feed("\x1b[>4m");
+        assert_eq!(screen.modify_other_keys, 0);
+        screen.feed("\x1b[>4;1m\x1b[>4n");
+        assert_eq!(screen.modify_other_keys, 0);
+
+        screen.feed("\x1b[>4;2m");
+        screen.reset();
+        assert_eq!(screen.modify_other_keys, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn synchronized_output() {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let reporter = VecReporter::new();
+        let mut screen = Screen::new(10, 1);
+        screen.set_reporter(reporter.clone());
+        screen.set_on_sync_change({
+            let changes = changes.clone();
+            move |on| changes.lock().unwrap().push(on)
+        });
+
+        // Supported and off.
+        screen.feed("\x1b[?2026$p");
+        assert_eq!(reporter.take(), vec!["\x1b[?2026;2$y".to_string()]);
+
+        screen.feed("\x1b[?2026h");
+        assert!(screen.synchronized_output());
+        // Setting it again doesn't begin another frame.
+        screen.feed("frame\x1b[?2026h\x1b[?2026l");
+        assert!(!screen.synchronized_output());
+        assert_eq!(*changes.lock().unwrap(), vec![true, false]);
+
+        // A reset ends the frame.
+        screen.feed("\x1b[?2026h");
+        screen.reset();
+        assert!(!screen.synchronized_output());
+        assert_eq!(*changes.lock().unwrap(), vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn hpb_vpb() {
+        let mut screen = Screen::new(10, 10);
+        screen.feed("\x1b[6;6H\x1b[3j");
+        assert_eq!(screen.cursor_xy(), (2, 5));
+        screen.feed("\x1b[2k");
+        assert_eq!(screen.cursor_xy(), (2, 3));
+
+        // Both stop at the edges of the screen.
+        screen.feed("\x1b[20j\x1b[20k");
+        assert_eq!(screen.cursor_xy(), (0, 0));
+    }
+
+    #[test]
+    fn next_line() {
+        let mut screen = Screen::new(4, 2);
+        assert!(!screen.mode.contains(&LNM));
+        screen.feed("ab\x1bEc");
+        assert_eq!(screen.cursor_xy(), (1, 1));
+        assert_eq!(screen.display(), vec!["ab  ", "c   "]);
+
+        // At the bottom it scrolls like a linefeed.
+        screen.feed("\x1bE");
+        assert_eq!(screen.cursor_xy(), (0, 1));
+        assert_eq!(screen.display(), vec!["c   ", "    "]);
+    }
+
+    #[test]
+    fn erase_in_display_above_sparse_rows() {
+        let mut screen = Screen::new(6, 4);
+        // A few styled cells scattered over the rows above the cursor.
+        screen.feed("\x1b[1;6H\x1b[1;31mx\x1b[2;2Hy\x1b[3;1Hz\x1b[3;4Hw\x1b[0m");
+        screen.feed("\x1b[3;3H\x1b[1J");
+
+        for y in 0..2 {
+            for x in 0..6 {
+                assert_eq!(screen.buffer[y][x], screen.default_char(), "({}, {})", x, y);
+            }
+        }
+        // The cursor row is erased up to and including the cursor.
         assert_eq!(
             screen.display(),
-            vec![
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-            ]
+            vec!["      ", "      ", "   w  ", "      "]
         );
+        assert!(screen.buffer[2][..3]
+            .iter()
+            .all(|cell| *cell == screen.default_char()));
+        assert!(screen.buffer[2][3].bold);
     }
 
     #[test]
-    fn cursor_up() {
-        let mut screen = Screen::new(10, 10);
+    fn save_cursor_mode() {
+        let mut screen = Screen::new(4, 2);
+        screen.feed("ab\x1b[?1048h\x1b[2;4Hc");
+        assert_eq!(screen.cursor_xy(), (3, 1));
 
-        // Moving the cursor up at the top doesn't do anything
-        screen.cursor_up(Some(1));
-        assert_eq!(screen.cursor.y, 0);
+        screen.feed("\x1b[?1048l");
+        assert_eq!(screen.cursor_xy(), (2, 0));
+        assert!(screen.primary_screen.is_none());
+        assert_eq!(screen.display(), vec!["ab  ", "   c"]);
+    }
 
-        screen.cursor.y = 1;
+    #[test]
+    fn alternate_screen() {
+        let mut screen = Screen::new(4, 2);
+        screen.feed("ab\x1b[?1047h");
+        assert_eq!(screen.display(), vec!["    ", "    "]);
+        // The cursor stays where it was.
+        assert_eq!(screen.cursor_xy(), (2, 0));
 
-        // Moving the cursor past the top moves it to the top
-        screen.cursor_up(Some(10));
-        assert_eq!(screen.cursor.y, 0);
+        screen.feed("cd\x1b[2;1H");
+        assert_eq!(screen.display(), vec!["  cd", "    "]);
+        screen.feed("\x1b[?1047l");
+        assert_eq!(screen.display(), vec!["ab  ", "    "]);
+        assert_eq!(screen.cursor_xy(), (0, 1));
 
-        screen.cursor.y = 5;
-        // Can move the cursor more than one up
-        screen.cursor_up(Some(3));
-        assert_eq!(screen.cursor.y, 2);
+        // The alternate screen is cleared each time it is shown.
+        screen.feed("\x1b[?1047h");
+        assert_eq!(screen.display(), vec!["    ", "    "]);
     }
 
     #[test]
-    fn cursor_down() {
-        let mut screen = Screen::new(10, 10);
+    fn alternate_screen_save_cursor() {
+        let mut screen = Screen::new(4, 3);
+        screen.scrollback_limit = 10;
+        screen.feed("ab\r\ncd\x1b[?1049h");
+        assert_eq!(screen.display(), vec!["    ", "    ", "    "]);
 
-        // Moving the cursor down at the bottom doesn't do anything
-        screen.cursor.y = 9;
-        screen.cursor_down(Some(1));
-        assert_eq!(screen.cursor.y, 9);
+        // Scrolling the alternate screen doesn't add to the scrollback.
+        screen.feed("\x1b[1;1Hx\n\n\n\ny");
+        assert!(screen.scrollback.is_empty());
+        assert!(screen.is_mode_set(1049, true));
 
-        screen.cursor.y = 8;
+        screen.feed("\x1b[?1049l");
+        assert!(!screen.is_mode_set(1049, true));
+        assert_eq!(screen.display(), vec!["ab  ", "cd  ", "    "]);
+        assert_eq!(screen.cursor_xy(), (2, 1));
 
-        // Moving the cursor past the bottom moves it to the bottom
-        screen.cursor_down(Some(10));
-        assert_eq!(screen.cursor.y, 9);
+        // Resetting without the alternate screen shown only restores
+        // the cursor.
+        screen.feed("\x1b[3;3H\x1b[?1049l");
+        assert_eq!(screen.display(), vec!["ab  ", "cd  ", "    "]);
+        assert_eq!(screen.cursor_xy(), (2, 1));
+    }
 
-        screen.cursor.y = 5;
-        // Can move the cursor more than one down
-        screen.cursor_down(Some(3));
-        assert_eq!(screen.cursor.y, 8);
+    #[test]
+    fn alternate_screen_resize() {
+        let mut screen = Screen::new(4, 2);
+        screen.feed("abcd\x1b[?1049h");
+        screen.resize(Some(3), Some(2));
+        screen.feed("\x1b[?1049l");
+        assert_eq!(screen.display(), vec!["ab", "  ", "  "]);
+
+        screen.set_mode(&[ALT_SCREEN], false);
+        screen.reset();
+        assert!(screen.primary_screen.is_none());
+        assert_eq!(screen.buffer.len(), 3);
     }
 
     #[test]
-    fn cursor_back() {
-        let mut screen = Screen::new(10, 10);
+    fn replacement_policy() {
+        let input = b"a\xffb\x01c\xe2\x82d";
+        let cases = [
+            (None, "a\u{FFFD}bc\u{FFFD}d"),
+            (
+                Some(ReplacementPolicy::Fffd),
+                "a\u{FFFD}b\u{FFFD}c\u{FFFD}d",
+            ),
+            (Some(ReplacementPolicy::Skip), "abcd"),
+            (Some(ReplacementPolicy::Custom('?')), "a?b?c?d"),
+        ];
+        for (policy, expected) in cases {
+            let mut screen = Screen::new(8, 1);
+            if let Some(policy) = policy {
+                screen.set_replacement_policy(policy);
+            }
+            screen.feed_bytes(input);
+            assert_eq!(
+                screen.display(),
+                vec![format!("{:8}", expected)],
+                "{:?}",
+                policy
+            );
+        }
 
-        // Moving the cursor left at the margin doesn't do anything
-        screen.cursor.x = 0;
-        screen.cursor_back(Some(1));
-        assert_eq!(screen.cursor.x, 0);
+        // So are zero width characters which aren't combining marks, the
+        // text after them is still drawn.
+        for (policy, expected) in [
+            (ReplacementPolicy::Skip, "ab  "),
+            (ReplacementPolicy::Fffd, "a\u{FFFD}b "),
+            (ReplacementPolicy::Custom('?'), "a?b "),
+            (ReplacementPolicy::Custom('\u{200B}'), "ab  "),
+            (ReplacementPolicy::Custom('\x01'), "ab  "),
+        ] {
+            let mut screen = Screen::new(4, 1);
+            screen.set_replacement_policy(policy);
+            screen.feed("a\u{200B}b");
+            assert_eq!(screen.display(), vec![expected], "{:?}", policy);
+        }
 
-        screen.cursor.x = 3;
+        // Input in another encoding is replaced the same way.
+        let mut screen = Screen::new(4, 1);
+        screen.parser.set_encoding(encoding_rs::SHIFT_JIS);
+        screen.set_replacement_policy(ReplacementPolicy::Custom('?'));
+        screen.feed_bytes(b"a\xfdb");
+        assert_eq!(screen.display(), vec!["a?b "]);
 
-        // Moving the cursor past the left margin moves it to the left margin
-        screen.cursor_back(Some(10));
+        // A byte which can't follow a lead byte is decoded on its own.
+        screen.feed_bytes(b"\r\x81 \xe0\r");
+        assert_eq!(screen.display(), vec!["? ? "]);
         assert_eq!(screen.cursor.x, 0);
-
-        screen.cursor.x = 5;
-        // Can move the cursor more than one back
-        screen.cursor_back(Some(3));
-        assert_eq!(screen.cursor.x, 2);
     }
 
     #[test]
-    fn cursor_back_last_column() {
-        let mut screen = Screen::new(13, 1);
-        screen.draw("Hello, world!");
-        assert_eq!(screen.cursor.x, screen.columns);
+    #[cfg(feature = "std")]
+    fn bell_style() {
+        let rung = Arc::new(Mutex::new(Vec::new()));
+        let mut screen = Screen::new(4, 1);
+        screen.set_on_bell({
+            let rung = rung.clone();
+            move |style| rung.lock().unwrap().push(style)
+        });
 
-        screen.cursor_back(Some(5));
-        assert_eq!(screen.cursor.x, (screen.columns - 1) - 5);
+        screen.feed("\x07");
+        screen.set_bell_style(BellStyle::Visual);
+        screen.feed("a\x07");
+        // The style is a frontend setting, a reset keeps it.
+        screen.reset();
+        screen.feed("\x07");
+
+        assert_eq!(
+            *rung.lock().unwrap(),
+            vec![BellStyle::Audible, BellStyle::Visual, BellStyle::Visual]
+        );
+        assert_eq!(screen.display(), vec!["    "]);
     }
 
     #[test]
-    fn cursor_forward() {
-        let mut screen = Screen::new(10, 10);
+    fn cursor_blink() {
+        let mut screen = Screen::new(4, 1);
+        assert!(!screen.cursor.blink);
 
-        // Moving the cursor right at the margin doesn't do anything
-        screen.cursor.x = 9;
-        screen.cursor_forward(Some(1));
-        assert_eq!(screen.cursor.x, 9);
+        screen.feed("\x1b[1;5m\x1b[?12h");
+        assert!(screen.cursor.blink);
+        assert!(screen.is_mode_set(12, true));
+        // Text blink is a separate attribute.
+        screen.feed("\x1b[25m");
+        assert!(screen.cursor.blink);
+        assert!(!screen.cursor.attr.blink);
 
-        // Moving the cursor past the right margin moves it to the right margin
-        screen.cursor.x = 8;
-        screen.cursor_forward(Some(10));
-        assert_eq!(screen.cursor.x, 9);
+        // Restoring the cursor keeps it blinking.
+        screen.feed("\x1b7\x1b[?12l");
+        assert!(!screen.cursor.blink);
+        screen.feed("\x1b[?12h\x1b8");
+        assert!(screen.cursor.blink);
 
-        // Can move the cursor more than one forward
-        screen.cursor.x = 5;
-        screen.cursor_forward(Some(3));
-        assert_eq!(screen.cursor.x, 8);
+        screen.feed("\x1b[?12l");
+        assert!(!screen.cursor.blink);
+        assert!(!screen.is_mode_set(12, true));
+
+        let screen = ScreenBuilder::new(4, 1).modes(&[CURSOR_BLINK]).build();
+        assert!(screen.cursor.blink);
     }
 
     #[test]
-    fn cursor_position() {
-        let mut screen = Screen::new(10, 10);
-
-        // a) testing that we expect 1-indexed values
-        screen.cursor_position(Some(5), Some(10));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (4, 9));
-
-        // b) but (0, 0) is also accepted and should be the same as (1, 1)
-        screen.cursor_position(Some(0), Some(10));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (0, 9));
+    fn char_at_column() {
+        let mut screen = Screen::new(6, 2);
+        screen.feed("a\u{754c}e\u{301}\x1b[1mb");
+
+        let (x, cell) = screen.char_at_column(0, 2).unwrap();
+        assert_eq!((x, cell.data.as_str()), (1, "\u{754c}"));
+        let (x, cell) = screen.char_at_column(0, 1).unwrap();
+        assert_eq!((x, cell.data.as_str()), (1, "\u{754c}"));
+
+        // The combining mark shares the cell of its base.
+        let (x, cell) = screen.char_at_column(0, 3).unwrap();
+        assert_eq!((x, cell.data.as_str()), (3, "\u{e9}"));
+        let (x, cell) = screen.char_at_column(0, 4).unwrap();
+        assert_eq!((x, cell.data.as_str()), (4, "b"));
+        assert!(cell.bold);
 
-        // c) moving outside the margins constrains to within the screen bounds
-        screen.cursor_position(Some(100), Some(5));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (9, 4));
+        assert_eq!(
+            screen.char_at_column(1, 0),
+            Some((0, screen.default_char()))
+        );
+        assert_eq!(screen.char_at_column(0, 6), None);
+        assert_eq!(screen.char_at_column(2, 0), None);
+    }
 
-        screen.cursor_position(Some(5), Some(100));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (4, 9));
+    #[test]
+    fn find() {
+        let mut screen = Screen::new(6, 4);
+        screen.feed("Foo fo\r\n\u{754c}foo\r\nabcdefoo x");
+
+        assert_eq!(screen.find("foo"), vec![(2, 1), (5, 2)]);
+        assert_eq!(screen.find_ci("FOO"), vec![(0, 0), (2, 1), (5, 2)]);
+        // The wide character takes two columns, but is one character.
+        assert_eq!(screen.find("\u{754c}f"), vec![(0, 1)]);
+        // Rows are only joined where the text wrapped.
+        assert_eq!(screen.find("fo\u{754c}"), vec![]);
+        assert_eq!(screen.find("efoo x"), vec![(4, 2)]);
+        // Every occurrence is found.
+        assert_eq!(
+            screen.find("o"),
+            vec![(1, 0), (2, 0), (5, 0), (3, 1), (4, 1), (0, 3), (1, 3)]
+        );
+        assert_eq!(screen.find(""), vec![]);
+    }
 
-        // d) DECOM on
-        screen.set_margins(Some(5), Some(9));
-        screen.set_mode(&[DECOM], false);
-        screen.cursor_position(None, None);
-        assert_eq!((screen.cursor.y, screen.cursor.x), (4, 0));
+    #[test]
+    fn cursor_accessors() {
+        let mut screen = Screen::new(10, 10);
+        screen.feed("\x1b[4;7H");
+        assert_eq!(screen.cursor_xy(), (6, 3));
+        assert_eq!((screen.cursor_col(), screen.cursor_row()), (6, 3));
+        assert_eq!(screen.cursor_report_position(), (4, 7));
 
-        screen.cursor_position(Some(2), Some(0));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (5, 0));
+        // In origin mode lines are reported from the top margin.
+        screen.set_margins(Some(3), Some(8));
+        screen.feed("\x1b[?6h\x1b[2;5H");
+        assert_eq!(screen.cursor_xy(), (4, 3));
+        assert_eq!(screen.cursor_report_position(), (2, 5));
+    }
 
-        // Note that cursor position doesn't change
-        screen.cursor_position(Some(10), Some(0));
-        assert_eq!((screen.cursor.y, screen.cursor.x), (5, 0));
+    #[test]
+    fn delete_characters_cursor_attributes() {
+        let mut screen = Screen::new(5, 1);
+        screen.feed("abcde\x1b[2G\x1b[44m\x1b[2P");
+        assert_eq!(screen.display(), vec!["ade  "]);
+        for x in 0..3 {
+            assert_eq!(&*screen.buffer[0][x].bg, "default");
+        }
+        for x in 3..5 {
+            assert_eq!(screen.buffer[0][x].data, " ");
+            assert_eq!(&*screen.buffer[0][x].bg, "blue");
+        }
     }
 
     #[test]
-    fn unicode() {
-        let screen = Arc::new(Mutex::new(Screen::new(4, 2)));
-        let mut parser = Parser::new(screen.clone());
+    fn insert_delete_lines_cursor_attributes() {
+        let mut screen = Screen::new(3, 4);
+        screen.feed("a\r\nb\r\nc\r\nd\x1b[2;1H\x1b[41m\x1b[1L");
+        assert_eq!(screen.display(), vec!["a  ", "   ", "b  ", "c  "]);
+        assert!(screen.buffer[1].iter().all(|cell| &*cell.bg == "red"));
+        assert!(screen.buffer[2].iter().all(|cell| &*cell.bg == "default"));
+
+        screen.feed("\x1b[42m\x1b[2M");
+        assert_eq!(screen.display(), vec!["a  ", "c  ", "   ", "   "]);
+        assert!(screen.buffer[1].iter().all(|cell| &*cell.bg == "default"));
+        for y in 2..4 {
+            assert!(screen.buffer[y].iter().all(|cell| &*cell.bg == "green"));
+        }
+    }
 
-        parser.feed("тест".to_string());
+    #[test]
+    fn bce() {
+        // Each sequence leaves blank cells at (0, 0) or (2, 2).
+        let cases = [
+            "\x1b[2J",
+            "\x1b[K",
+            "\x1b[2X",
+            "\x1b[3P",
+            "\x1b[1L",
+            "\x1b[1M",
+            "\x1b[1S",
+            "\x1b[1T",
+            "\x1b[3;1H\n",
+            "\x1bM",
+        ];
+        for bce in [true, false] {
+            for sequence in cases {
+                let mut screen = Screen::new(3, 3);
+                screen.bce = bce;
+                // Only the background color is used, not the other
+                // attributes.
+                screen.feed("abc\r\ndef\r\nghi\x1b[H\x1b[1;4;7;9;44m");
+                screen.feed(sequence);
+
+                let blank = if screen.buffer[0][0].data == " " {
+                    &screen.buffer[0][0]
+                } else {
+                    &screen.buffer[2][2]
+                };
+                assert_eq!(blank.data, " ", "{:?}", sequence);
+                let expected = if bce {
+                    CharOpts { bg: "blue".into(), ..screen.default_char() }
+                } else {
+                    screen.default_char()
+                };
+                assert_eq!(*blank, expected, "{:?} with bce {}", sequence, bce);
+            }
+        }
+    }
 
+    #[test]
+    fn diff_ansi() {
+        let start = "\x1b[1;32mgreen\x1b[0m text\r\n\u{754c}\u{754c} wide\r\nlast line";
+        let mut prev = Screen::new(12, 3);
+        prev.feed(start);
+        let mut next = Screen::new(12, 3);
+        next.feed(start);
+        next.feed("\x1b[1;8HX\x1b[2;3H\x1b[41m\u{4e16}\x1b[0m\x1b[3;1H\x1b[4m");
+
+        let diff = next.diff_ansi(&prev);
         assert_eq!(
-            screen.lock().unwrap().display(),
-            vec!["тест".to_string(), "    ".to_string()]
+            diff,
+            "\x1b[1;8HX\x1b[2;3H\x1b[0;41m\u{4e16}\x1b[0;4m\x1b[3;1H"
         );
+
+        let mut replay = Screen::new(12, 3);
+        replay.feed(start);
+        replay.feed(&diff);
+        assert!(replay == next);
+        assert_eq!(replay.cursor.attr, next.cursor.attr);
+
+        // Nothing changed, nothing to write.
+        assert_eq!(next.diff_ansi(&replay), "");
+
+        // Changing the trailing half of a wide character rewrites it
+        // from its leading half.
+        let mut narrowed = Screen::new(12, 3);
+        narrowed.feed(start);
+        narrowed.feed("\x1b[2;2Hx\x1b[?25l");
+        let diff = narrowed.diff_ansi(&prev);
+        let mut replay = Screen::new(12, 3);
+        replay.feed(start);
+        replay.feed(&diff);
+        assert!(replay == narrowed);
+        assert!(replay.cursor.hidden);
     }
 
     #[test]
-    fn alignment_display() {
-        let mut screen = Screen::new(5, 5);
-        screen.set_mode(&[LNM], false);
+    fn set_tab_stops() {
+        let mut screen = Screen::new(20, 1);
+        screen.set_tab_stops(&[3, 12, 7, 25]);
+        assert_eq!(screen.tab_stops(), vec![3, 7, 12]);
 
-        // Draw initial content
-        screen.draw("a");
-        screen.linefeed();
-        screen.linefeed();
-        screen.draw("b");
+        screen.tab();
+        assert_eq!(screen.cursor.x, 3);
+        screen.tab();
+        assert_eq!(screen.cursor.x, 7);
+        screen.tab();
+        assert_eq!(screen.cursor.x, 12);
+        screen.tab();
+        assert_eq!(screen.cursor.x, 19);
+
+        screen.back_tab();
+        assert_eq!(screen.cursor.x, 12);
+        screen.back_tab();
+        assert_eq!(screen.cursor.x, 7);
+
+        // TBC at the cursor only clears that stop.
+        screen.feed("\x1b[0g");
+        assert_eq!(screen.tab_stops(), vec![3, 12]);
+        screen.back_tab();
+        assert_eq!(screen.cursor.x, 3);
+        screen.back_tab();
+        assert_eq!(screen.cursor.x, 0);
+
+        // Vertical and unknown TBC values are ignored.
+        screen.feed("\x1b[1g\x1b[2g\x1b[4g\x1b[9g");
+        assert_eq!(screen.tab_stops(), vec![3, 12]);
+
+        screen.feed("\x1b[5g");
+        assert!(screen.tab_stops().is_empty());
+        screen.tab();
+        assert_eq!(screen.cursor.x, 19);
+        screen.back_tab();
+        assert_eq!(screen.cursor.x, 0);
+    }
 
+    #[test]
+    fn dirty_ranges() {
+        let sorted = |ranges: HashMap<u32, (u32, u32)>| {
+            let mut ranges = ranges.into_iter().collect::<Vec<_>>();
+            ranges.sort_unstable();
+            ranges
+        };
+        let mut screen = Screen::new(10, 4);
         assert_eq!(
-            screen.display(),
-            vec![
-                "a    ".to_string(),
-                "     ".to_string(),
-                "b    ".to_string(),
-                "     ".to_string(),
-                "     ".to_string(),
-            ]
+            sorted(screen.take_dirty_ranges()),
+            (0..4).map(|y| (y, (0, 9))).collect::<Vec<_>>()
         );
 
-        // Test alignment display
-        screen.alignment_display();
+        // A small edit only covers the cells it touched.
+        screen.cursor_position(Some(2), Some(4));
+        screen.draw("ab");
+        assert_eq!(sorted(screen.take_dirty_ranges()), vec![(1, (3, 4))]);
+
+        // Edits of the same row are merged.
+        screen.cursor_to_column(Some(8));
+        screen.erase_characters(Some(2));
+        screen.cursor_to_column(Some(2));
+        screen.draw("x");
+        assert_eq!(sorted(screen.take_dirty_ranges()), vec![(1, (1, 8))]);
+
+        // Inserting shifts the rest of the row.
+        screen.insert_characters(Some(1));
+        assert_eq!(sorted(screen.take_dirty_ranges()), vec![(1, (2, 9))]);
+        assert!(screen.take_dirty_ranges().is_empty());
 
+        // Scrolling covers whole rows.
+        screen.set_margins(Some(2), Some(3));
+        screen.cursor_position(Some(3), Some(1));
+        screen.index();
         assert_eq!(
-            screen.display(),
-            vec![
-                "EEEEE".to_string(),
-                "EEEEE".to_string(),
-                "EEEEE".to_string(),
-                "EEEEE".to_string(),
-                "EEEEE".to_string(),
-            ]
+            sorted(screen.take_dirty_ranges()),
+            vec![(1, (0, 9)), (2, (0, 9))]
+        );
+        screen.scroll_up(Some(1));
+        assert_eq!(
+            sorted(screen.take_dirty_ranges()),
+            vec![(1, (0, 9)), (2, (0, 9))]
         );
     }
 
     #[test]
-    fn set_margins() {
-        let mut screen = Screen::new(10, 10);
+    fn cursor_forward_pending_wrap() {
+        let mut screen = Screen::new(5, 2);
+        screen.feed("abcde");
+        assert_eq!((screen.cursor.x, screen.cursor.y), (4, 0));
+        assert!(screen.pending_wrap);
+
+        // The cursor stays on the last column and the wrap is cancelled.
+        screen.feed("\x1b[1C");
+        assert_eq!((screen.cursor.x, screen.cursor.y), (4, 0));
+        assert!(!screen.pending_wrap);
+
+        screen.feed("X");
+        assert_eq!(screen.display(), vec!["abcdX", "     "]);
+        assert_eq!((screen.cursor.x, screen.cursor.y), (4, 0));
+        assert!(screen.pending_wrap);
+
+        // A larger count doesn't go past the edge either, and drawing
+        // after the overwrite wraps as usual.
+        screen.feed("\x1b[10CYZ");
+        assert_eq!(screen.display(), vec!["abcdY", "Z    "]);
+        assert_eq!((screen.cursor.x, screen.cursor.y), (1, 1));
+        assert!(screen.wrapped[1]);
+    }
 
-        assert!(screen.margins.is_none());
+    #[test]
+    fn normalize() {
+        // a) Kept as received when not normalizing.
+        let mut screen = Screen::new(4, 1);
+        screen.normalize = false;
+        screen.draw("e\u{301}\u{212b}");
+        assert_eq!(screen.buffer[0][0].data, "e\u{301}");
+        assert_eq!(screen.buffer[0][1].data, "\u{212b}");
+        screen.draw("\u{30a}");
+        assert_eq!(screen.buffer[0][1].data, "\u{212b}\u{30a}");
+
+        // b) Composed, and singletons replaced, by default.
+        let mut screen = Screen::new(4, 1);
+        screen.draw("e\u{301}\u{212b}");
+        assert_eq!(screen.buffer[0][0].data, "\u{e9}");
+        assert_eq!(screen.buffer[0][1].data, "\u{c5}");
+        assert_eq!(screen.cursor.x, 2);
 
-        // a) ok-case
-        screen.set_margins(Some(1), Some(5));
-        assert_eq!(screen.margins, Some(Margins { top: 0, bottom: 4 }));
+        // c) Also a mark drawn separately from its base.
+        screen.draw("a");
+        screen.draw("\u{30a}");
+        assert_eq!(screen.buffer[0][2].data, "\u{e5}");
+        assert_eq!(screen.display(), vec!["\u{e9}\u{c5}\u{e5} "]);
+    }
 
-        // b) one of the margins is out of bounds
-        screen.set_margins(Some(100), Some(10));
-        assert_ne!(screen.margins, Some(Margins { top: 99, bottom: 9 }));
-        assert_eq!(screen.margins, Some(Margins { top: 0, bottom: 4 }));
+    #[test]
+    fn rectangle_attrs() {
+        let mut screen = Screen::new(6, 3);
+        screen.feed("abcdef\r\nghijkl\r\nmnopqr");
+        let cells = |screen: &Screen, check: fn(&CharOpts) -> bool| {
+            screen
+                .buffer
+                .iter()
+                .map(|line| {
+                    line.iter()
+                        .map(|cell| if check(cell) { 'x' } else { '.' })
+                        .collect()
+                })
+                .collect::<Vec<String>>()
+        };
 
-        // c) no margins provided -- reset to full screen
-        screen.set_margins(None, None);
-        assert!(screen.margins.is_none());
+        // a) DECCARA makes rows 1-2, columns 2-4 bold and red.
+        screen.feed("\x1b[1;2;2;4;1;31$r");
+        assert_eq!(
+            cells(&screen, |cell| cell.bold),
+            vec![".xxx..", ".xxx..", "......"]
+        );
+        assert_eq!(
+            cells(&screen, |cell| &*cell.fg == "red"),
+            vec![".xxx..", ".xxx..", "......"]
+        );
+        assert_eq!(screen.display(), vec!["abcdef", "ghijkl", "mnopqr"]);
+        assert!(!screen.cursor.attr.bold);
+
+        // b) DECRARA toggles reverse, the rectangle is clamped.
+        screen.feed("\x1b[2;3;9;9;7$t");
+        assert_eq!(
+            cells(&screen, |cell| cell.reverse),
+            vec!["......", "..xxxx", "..xxxx"]
+        );
+        screen.reverse_rectangle_attrs(Rect { top: 1, left: 0, bottom: 1, right: 5 }, &[7, 1]);
+        assert_eq!(
+            cells(&screen, |cell| cell.reverse),
+            vec!["......", "xx....", "..xxxx"]
+        );
+        assert_eq!(
+            cells(&screen, |cell| cell.bold),
+            vec![".xxx..", "x...xx", "......"]
+        );
+
+        // c) Without parameters all attributes are turned off everywhere.
+        screen.feed("\x1b[$r");
+        let blank = screen.default_char();
+        assert!(screen
+            .buffer
+            .iter()
+            .flatten()
+            .all(|cell| cell.same_style(&blank)));
+        assert_eq!(screen.display(), vec!["abcdef", "ghijkl", "mnopqr"]);
     }
 
     #[test]
-    fn set_margins_zero() {
-        // See https://github.com/selectel/pyte/issues/61
-        let mut screen = Screen::new(80, 24);
-
-        screen.set_margins(Some(1), Some(5));
-        assert_eq!(screen.margins, Some(Margins { top: 0, bottom: 4 }));
+    fn display_fixed_width() {
+        let width = |line: &str| line.chars().map(|c| c.width().unwrap_or(0)).sum::<usize>();
 
-        screen.set_margins(Some(0), None);
-        assert!(screen.margins.is_none());
+        // The last wide character has no room for its trailing half.
+        let mut screen = Screen::new(10, 1);
+        screen.draw(" コンニチハ");
+        assert_eq!(screen.buffer[0][9].data, "ハ");
+        assert_eq!(width(&screen.display()[0]), 11);
+        screen.display_fixed_width = true;
+        assert_eq!(screen.display(), vec![" コンニチ "]);
+        assert_eq!(width(&screen.display()[0]), 10);
+
+        // Cut off by a narrower screen.
+        let mut screen = Screen::new(4, 1);
+        screen.display_fixed_width = true;
+        screen.draw("ab\u{754c}");
+        screen.resize(None, Some(3));
+        assert_eq!(screen.display(), vec!["ab "]);
+
+        // A wide character whose trailing half was overwritten doesn't
+        // hide the next cell.
+        let mut screen = Screen::new(4, 1);
+        screen.display_fixed_width = true;
+        screen.buffer[0][0].data = "\u{754c}".to_string();
+        screen.buffer[0][1].data = "x".to_string();
+        assert_eq!(screen.display(), vec![" x  "]);
+
+        // Complete wide characters are shown as usual.
+        let mut screen = Screen::new(5, 1);
+        screen.display_fixed_width = true;
+        screen.draw("abc\u{754c}");
+        assert_eq!(screen.display(), vec!["abc\u{754c}"]);
+        assert_eq!(width(&screen.display()[0]), 5);
     }
 
     #[test]
-    fn hide_cursor() {
-        let mut screen = Screen::new(10, 10);
+    fn reset_dirty() {
+        let mut screen = Screen::new(5, 3);
+        assert!(screen.is_dirty());
+        assert_eq!(screen.dirty_line_count(), 3);
 
-        // DECTCEM is set by default
-        assert!(screen.mode.contains(&DECTCEM));
-        assert!(!screen.cursor.hidden);
+        screen.reset_dirty();
+        assert!(!screen.is_dirty());
+        assert_eq!(screen.dirty_line_count(), 0);
+        assert!(screen.take_dirty_cells().is_empty());
+        assert!(screen.take_dirty_ranges().is_empty());
 
-        // a) resetting DECTCEM hides the cursor
-        screen.reset_mode(&[DECTCEM], false);
-        assert!(screen.cursor.hidden);
+        screen.draw("ab");
+        assert!(screen.is_dirty());
+        assert_eq!(screen.dirty_line_count(), 1);
+        assert!(screen.dirty.contains(&0));
 
-        // b) setting DECTCEM shows the cursor again
-        screen.set_mode(&[DECTCEM], false);
-        assert!(!screen.cursor.hidden);
+        screen.cursor_position(Some(3), Some(1));
+        screen.draw("c");
+        assert_eq!(screen.dirty_line_count(), 2);
+
+        screen.reset_dirty();
+        assert!(!screen.is_dirty());
+        assert!(screen.take_dirty_cells().is_empty());
     }
 
     #[test]
-    fn screen_set_icon_name_title() {
-        let mut screen = Screen::new(10, 1);
+    fn display_with_concealed() {
+        let mut screen = Screen::new(6, 1);
+        screen.feed("a\x1b[8mpw\x1b[28mb");
+        assert!(screen.buffer[0][1].conceal);
+        assert!(!screen.buffer[0][3].conceal);
+
+        // Shown by default, like `display`.
+        assert_eq!(screen.display(), vec!["apwb  "]);
+        assert_eq!(
+            screen.display_with(DisplayOptions::default()),
+            vec!["apwb  "]
+        );
 
-        let text = "±";
-        screen.set_icon_name(text);
-        assert_eq!(screen.icon_name, text);
+        let hidden = DisplayOptions { hide_concealed: true, ..DisplayOptions::default() };
+        assert_eq!(screen.display_with(hidden), vec!["a  b  "]);
+    }
 
-        screen.set_title(text);
-        assert_eq!(screen.title, text);
+    #[test]
+    fn display_with_blink_and_wide_chars() {
+        let mut screen = Screen::new(6, 1);
+        screen.feed("\x1b[5m\u{754c}\x1b[25mx\u{754c}");
+        assert_eq!(screen.display(), vec!["\u{754c}x\u{754c} "]);
+
+        // The blinking wide character still takes two columns.
+        let off = DisplayOptions { blink_off: true, ..DisplayOptions::default() };
+        assert_eq!(screen.display_with(off), vec!["  x\u{754c} "]);
+
+        let continuation = DisplayOptions {
+            wide_continuation: Some('.'),
+            ..DisplayOptions::default()
+        };
+        assert_eq!(
+            screen.display_with(continuation),
+            vec!["\u{754c}.x\u{754c}. "]
+        );
+        let both = DisplayOptions { blink_off: true, ..continuation };
+        assert_eq!(screen.display_with(both), vec![" .x\u{754c}. "]);
     }
 }
@@ -0,0 +1,114 @@
+use crate::reporter::VecReporter;
+use crate::screen::{CharOpts, Screen};
+
+/// A screen together with the parser feeding it and the replies it sends
+/// back to the host, e.g. for device attribute and status requests.
+///
+/// Input is taken as bytes and decoded as UTF-8, a character split
+/// across two `feed` calls is completed by the second one.
+pub struct Terminal {
+    screen: Screen,
+    output: VecReporter,
+}
+
+impl Terminal {
+    pub fn new(columns: u32, lines: u32) -> Self {
+        let output = VecReporter::new();
+        let mut screen = Screen::new(columns, lines);
+        screen.set_reporter(output.clone());
+        Self { screen, output }
+    }
+
+    /// Decode `data` and apply it to the screen, see
+    /// `StreamParser::feed_bytes`.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.screen.feed_bytes(data);
+    }
+
+    /// Resize the screen, see `Screen::resize`.
+    pub fn resize(&mut self, columns: u32, lines: u32) {
+        self.screen.resize(Some(lines), Some(columns));
+    }
+
+    /// A list of screen lines as unicode strings.
+    pub fn display(&self) -> Vec<String> {
+        self.screen.display()
+    }
+
+    /// Returns the character at column `x` of line `y`, or `None` if it
+    /// is off screen.
+    pub fn get_cell(&self, x: u32, y: u32) -> Option<&CharOpts> {
+        self.screen.buffer.get(y as usize)?.get(x as usize)
+    }
+
+    /// Removes and returns the replies to send back to the host, oldest
+    /// first.
+    pub fn drain_output(&mut self) -> String {
+        self.output.take().concat()
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn screen_mut(&mut self) -> &mut Screen {
+        &mut self.screen
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Terminal;
+
+    #[test]
+    fn captured_session() {
+        let mut terminal = Terminal::new(20, 4);
+        let session: &[&[u8]] = &[
+            b"\x1b[H\x1b[2J$ ls\r\n",
+            b"Cargo.toml  \x1b[1;34msrc\x1b[0m\r\n",
+            // "ü" split between two reads.
+            b"$ echo gr\xc3",
+            b"\xbcn\r\ngr\xc3\xbcn\r\n$ ",
+            // The shell asks for the cursor position and device
+            // attributes.
+            b"\x1b[6n\x1b[c",
+        ];
+        for chunk in session {
+            terminal.feed(chunk);
+        }
+
+        assert_eq!(
+            terminal.display(),
+            vec![
+                "Cargo.toml  src     ",
+                "$ echo grün         ",
+                "grün                ",
+                "$                   ",
+            ]
+        );
+        // The screen scrolled, the prompt is on the last line.
+        let cell = terminal.get_cell(12, 0).unwrap();
+        assert_eq!(cell.data, "s");
+        assert!(cell.bold);
+        assert_eq!(&*cell.fg, "blue");
+        assert!(terminal.get_cell(20, 1).is_none());
+
+        assert_eq!(terminal.drain_output(), "\x1b[4;3R\x1b[?6c");
+        assert_eq!(terminal.drain_output(), "");
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut terminal = Terminal::new(5, 1);
+        terminal.feed(b"a\xffb");
+        assert_eq!(terminal.display(), vec!["a\u{FFFD}b  "]);
+    }
+
+    #[test]
+    fn resize() {
+        let mut terminal = Terminal::new(5, 2);
+        terminal.resize(8, 3);
+        assert_eq!(terminal.screen().columns, 8);
+        assert_eq!(terminal.screen().lines, 3);
+    }
+}